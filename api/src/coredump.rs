@@ -0,0 +1,546 @@
+//! Core dumps of processes killed by signals.
+//!
+//! The dump is an ELF file of type `ET_CORE` in the format of Linux, so that
+//! debuggers can load it along with the executable. It has a `PT_LOAD`
+//! segment for each user memory area, and a `PT_NOTE` segment with the
+//! registers of the threads and information about the process.
+//!
+//! Only the registers of the dumping thread are exact. The other threads keep
+//! running during the dump, their general purpose registers are those saved
+//! when they last entered the kernel, and their floating-point registers are
+//! not dumped.
+
+use alloc::{
+    string::{String, ToString},
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
+use core::mem::size_of;
+
+use axerrno::LinuxResult;
+use axfs::api::File;
+use axhal::{
+    arch::TrapFrame,
+    paging::{MappingFlags, PageSize},
+};
+use axio::{Seek, SeekFrom, Write};
+use axmm::AddrSpace;
+use axprocess::Thread;
+use axsignal::{SignalInfo, SignalSet};
+use axtask::{TaskExtRef, current};
+use linux_raw_sys::{
+    elf_uapi::{
+        EI_CLASS, EI_DATA, EI_OSABI, EI_VERSION, ELFCLASS64, ELFDATA2LSB, ELFMAG, ELFOSABI_NONE,
+        ET_CORE, EV_CURRENT, Elf64_Ehdr, Elf64_Nhdr, Elf64_Phdr, NT_AUXV, NT_FILE, NT_PRFPREG,
+        NT_PRPSINFO, NT_PRSTATUS, NT_SIGINFO, PF_R, PF_W, PF_X, PT_LOAD, PT_NOTE,
+    },
+    general::{AT_NULL, RLIMIT_CORE, kernel_sigset_t},
+};
+use memory_addr::{PAGE_SIZE_4K, align_up, align_up_4k};
+use starry_core::{
+    realtime::realtime,
+    task::{ThreadData, time_stat_output},
+};
+
+use crate::regs::{GeneralRegs, current_fp_regs, general_regs};
+
+/// The bit set in the wait status of a process which dumped core.
+pub const WCOREFLAG: i32 = 0x80;
+
+/// Where the name of the core file is configured, see `core(5)`.
+const CORE_PATTERN_PATH: &str = "/proc/sys/kernel/core_pattern";
+
+#[cfg(target_arch = "x86_64")]
+const ELF_MACHINE: u32 = linux_raw_sys::elf_uapi::EM_X86_64;
+#[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+const ELF_MACHINE: u32 = linux_raw_sys::elf_uapi::EM_RISCV;
+#[cfg(target_arch = "aarch64")]
+const ELF_MACHINE: u32 = linux_raw_sys::elf_uapi::EM_AARCH64;
+#[cfg(target_arch = "loongarch64")]
+const ELF_MACHINE: u32 = linux_raw_sys::elf_uapi::EM_LOONGARCH;
+
+/// `struct elf_prstatus`, the status of a thread.
+#[repr(C)]
+struct ElfPrstatus {
+    si_signo: i32,
+    si_code: i32,
+    si_errno: i32,
+    pr_cursig: u16,
+    _pad0: u16,
+    pr_sigpend: u64,
+    pr_sighold: u64,
+    pr_pid: i32,
+    pr_ppid: i32,
+    pr_pgrp: i32,
+    pr_sid: i32,
+    pr_utime: [i64; 2],
+    pr_stime: [i64; 2],
+    pr_cutime: [i64; 2],
+    pr_cstime: [i64; 2],
+    pr_reg: GeneralRegs,
+    pr_fpvalid: i32,
+    _pad1: i32,
+}
+
+/// `struct elf_prpsinfo`, the information about the process.
+#[repr(C)]
+struct ElfPrpsinfo {
+    pr_state: u8,
+    pr_sname: u8,
+    pr_zomb: u8,
+    pr_nice: i8,
+    _pad0: u32,
+    pr_flag: u64,
+    pr_uid: u32,
+    pr_gid: u32,
+    pr_pid: i32,
+    pr_ppid: i32,
+    pr_pgrp: i32,
+    pr_sid: i32,
+    pr_fname: [u8; 16],
+    pr_psargs: [u8; 80],
+}
+
+/// View a `repr(C)` structure without implicit padding as bytes.
+fn as_bytes<T>(value: &T) -> &[u8] {
+    // SAFETY: all the bytes of `T` are initialized.
+    unsafe { core::slice::from_raw_parts(value as *const T as *const u8, size_of::<T>()) }
+}
+
+fn sigset_bits(set: SignalSet) -> u64 {
+    let mut sigset: kernel_sigset_t = unsafe { core::mem::zeroed() };
+    set.to_ctype(&mut sigset);
+    sigset.sig[0] as _
+}
+
+/// A note of the `PT_NOTE` segment, whose name is always `CORE`.
+struct Note {
+    ty: u32,
+    desc: Vec<u8>,
+}
+
+impl Note {
+    const NAME: &[u8] = b"CORE\0";
+
+    fn new(ty: u32, desc: &[u8]) -> Self {
+        Self {
+            ty,
+            desc: desc.to_vec(),
+        }
+    }
+
+    /// Append the note to `buf`, with the name and descriptor aligned to 4
+    /// bytes.
+    fn write_to(&self, buf: &mut Vec<u8>) {
+        let header = Elf64_Nhdr {
+            n_namesz: Self::NAME.len() as _,
+            n_descsz: self.desc.len() as _,
+            n_type: self.ty,
+        };
+        buf.extend_from_slice(as_bytes(&header));
+        buf.extend_from_slice(Self::NAME);
+        buf.resize(align_up(buf.len(), 4), 0);
+        buf.extend_from_slice(&self.desc);
+        buf.resize(align_up(buf.len(), 4), 0);
+    }
+}
+
+/// Build the `NT_PRSTATUS` note of `thread`, whose user registers are `tf`.
+fn prstatus(thread: &Thread, tf: &TrapFrame, sig: Option<&SignalInfo>, fpvalid: bool) -> Note {
+    let process = thread.process();
+    let group = process.group();
+    let signal = &thread.data::<ThreadData>().unwrap().signal;
+    let mut status = ElfPrstatus {
+        si_signo: 0,
+        si_code: 0,
+        si_errno: 0,
+        pr_cursig: 0,
+        _pad0: 0,
+        pr_sigpend: sigset_bits(signal.pending()),
+        pr_sighold: sigset_bits(signal.blocked()),
+        pr_pid: thread.tid() as _,
+        pr_ppid: process.parent().map_or(0, |it| it.pid()) as _,
+        pr_pgrp: group.pgid() as _,
+        pr_sid: group.session().sid() as _,
+        pr_utime: [0; 2],
+        pr_stime: [0; 2],
+        pr_cutime: [0; 2],
+        pr_cstime: [0; 2],
+        pr_reg: general_regs(tf),
+        pr_fpvalid: fpvalid as _,
+        _pad1: 0,
+    };
+    if let Some(sig) = sig {
+        status.si_signo = sig.signo() as _;
+        status.si_code = sig.code();
+        status.pr_cursig = sig.signo() as _;
+        // The times are only known for the current thread.
+        let (utime_sec, utime_usec, stime_sec, stime_usec) = time_stat_output();
+        status.pr_utime = [utime_sec as _, (utime_usec % 1_000_000) as _];
+        status.pr_stime = [stime_sec as _, (stime_usec % 1_000_000) as _];
+    }
+    Note::new(NT_PRSTATUS, as_bytes(&status))
+}
+
+/// The arguments and the auxiliary vector on the initial user stack of the
+/// process.
+struct StackInfo {
+    args: Vec<u8>,
+    auxv: Vec<u8>,
+}
+
+/// Read the arguments and the auxiliary vector the program was started with,
+/// from the initial user stack at `sp`, which holds `argc`, the `argv` and
+/// `envp` arrays and then the auxiliary vector.
+fn read_stack_info(aspace: &AddrSpace, sp: usize) -> StackInfo {
+    let mut info = StackInfo {
+        args: Vec::new(),
+        auxv: Vec::new(),
+    };
+    let stack_top = axconfig::plat::USER_STACK_TOP;
+    if sp == 0 || sp >= stack_top {
+        return info;
+    }
+    let mut stack = vec![0; stack_top - sp];
+    if aspace
+        .read(sp.into(), PageSize::Size4K, &mut stack)
+        .is_err()
+    {
+        return info;
+    }
+    const WORD: usize = size_of::<usize>();
+    let word = |index: usize| -> Option<usize> {
+        let bytes = stack.get(index * WORD..(index + 1) * WORD)?;
+        Some(usize::from_ne_bytes(bytes.try_into().unwrap()))
+    };
+    let string = |addr: usize| -> &[u8] {
+        let bytes = addr
+            .checked_sub(sp)
+            .and_then(|offset| stack.get(offset..))
+            .unwrap_or_default();
+        let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+        &bytes[..len]
+    };
+
+    let argc = word(0).unwrap_or(0);
+    for i in 1..=argc {
+        let Some(arg) = word(i) else {
+            return info;
+        };
+        if !info.args.is_empty() {
+            info.args.push(b' ');
+        }
+        info.args.extend_from_slice(string(arg));
+    }
+    // Skip the `argv` and `envp` arrays with their terminating null pointers.
+    let mut index = argc + 2;
+    while word(index).is_some_and(|it| it != 0) {
+        index += 1;
+    }
+    index += 1;
+    while let Some(entry) = stack.get(index * WORD..(index + 2) * WORD) {
+        info.auxv.extend_from_slice(entry);
+        if word(index) == Some(AT_NULL as usize) {
+            break;
+        }
+        index += 2;
+    }
+    info
+}
+
+/// Build the notes of the process, starting with those of the current
+/// thread as expected by debuggers.
+fn build_notes(tf: &TrapFrame, sig: &SignalInfo, stack: &StackInfo) -> Vec<u8> {
+    let curr = current();
+    let thread = &curr.task_ext().thread;
+    let process = thread.process();
+    let group = process.group();
+
+    let fp_regs = current_fp_regs(tf);
+    let mut notes = vec![prstatus(thread, tf, Some(sig), fp_regs.is_some())];
+
+    let mut psinfo = ElfPrpsinfo {
+        pr_state: 0,
+        pr_sname: b'R',
+        pr_zomb: 0,
+        pr_nice: 0,
+        _pad0: 0,
+        pr_flag: 0,
+        pr_uid: 0,
+        pr_gid: 0,
+        pr_pid: process.pid() as _,
+        pr_ppid: process.parent().map_or(0, |it| it.pid()) as _,
+        pr_pgrp: group.pgid() as _,
+        pr_sid: group.session().sid() as _,
+        pr_fname: [0; 16],
+        pr_psargs: [0; 80],
+    };
+    let name = curr.name();
+    let len = name.len().min(psinfo.pr_fname.len() - 1);
+    psinfo.pr_fname[..len].copy_from_slice(&name.as_bytes()[..len]);
+    let len = stack.args.len().min(psinfo.pr_psargs.len() - 1);
+    psinfo.pr_psargs[..len].copy_from_slice(&stack.args[..len]);
+    notes.push(Note::new(NT_PRPSINFO, as_bytes(&psinfo)));
+
+    notes.push(Note::new(NT_SIGINFO, as_bytes(&sig.0)));
+    notes.push(Note::new(NT_AUXV, &stack.auxv));
+    // The count of file mappings and the page size. Files are mapped by
+    // copying their contents to anonymous memory, so there are no entries.
+    let mut files = Vec::new();
+    files.extend_from_slice(&0u64.to_ne_bytes());
+    files.extend_from_slice(&(PAGE_SIZE_4K as u64).to_ne_bytes());
+    notes.push(Note::new(NT_FILE, &files));
+    if let Some(fp_regs) = &fp_regs {
+        notes.push(Note::new(NT_PRFPREG, as_bytes(fp_regs)));
+    }
+
+    for other in process.threads() {
+        if Arc::ptr_eq(&other, thread) {
+            continue;
+        }
+        let Some(data) = other.data::<ThreadData>() else {
+            continue;
+        };
+        // SAFETY: the thread has not exited, as it is still in the process.
+        let tf = unsafe { &*data.user_trap_frame() };
+        notes.push(prstatus(&other, tf, None, false));
+    }
+
+    let mut buf = Vec::new();
+    for note in notes {
+        note.write_to(&mut buf);
+    }
+    buf
+}
+
+/// The core file being written, which is truncated at `RLIMIT_CORE`.
+struct CoreFile {
+    file: File,
+    pos: u64,
+    limit: u64,
+}
+
+impl CoreFile {
+    /// Advance by up to `len` bytes, returning how many fit below the limit.
+    fn reserve(&mut self, len: usize) -> usize {
+        let len = (len as u64).min(self.limit.saturating_sub(self.pos));
+        self.pos += len;
+        len as usize
+    }
+
+    fn write(&mut self, buf: &[u8]) -> LinuxResult {
+        let len = self.reserve(buf.len());
+        self.file.write_all(&buf[..len])?;
+        Ok(())
+    }
+
+    /// Leave a hole of `len` bytes, which reads as zeros.
+    fn skip(&mut self, len: usize) -> LinuxResult {
+        self.reserve(len);
+        self.file.seek(SeekFrom::Start(self.pos))?;
+        Ok(())
+    }
+
+    /// Whether the file reached the limit, so that the rest is not written.
+    fn is_full(&self) -> bool {
+        self.pos >= self.limit
+    }
+}
+
+/// Write the core file of the current process to `file`.
+fn write_core(file: &mut CoreFile, tf: &TrapFrame, sig: &SignalInfo) -> LinuxResult {
+    let curr = current();
+    let proc_data = curr.task_ext().process_data();
+    let aspace = proc_data.aspace();
+    let aspace = aspace.lock();
+
+    let stack = read_stack_info(&aspace, proc_data.get_user_stack());
+    let notes = build_notes(tf, sig, &stack);
+    let areas = aspace
+        .areas()
+        .filter(|(_, flags)| flags.contains(MappingFlags::USER))
+        .collect::<Vec<_>>();
+
+    let phnum = areas.len() + 1;
+    let notes_offset = size_of::<Elf64_Ehdr>() + phnum * size_of::<Elf64_Phdr>();
+    let mut offset = align_up_4k(notes_offset + notes.len());
+
+    let mut e_ident = [0; 16];
+    e_ident[..4].copy_from_slice(&ELFMAG[..4]);
+    e_ident[EI_CLASS as usize] = ELFCLASS64 as _;
+    e_ident[EI_DATA as usize] = ELFDATA2LSB as _;
+    e_ident[EI_VERSION as usize] = EV_CURRENT as _;
+    e_ident[EI_OSABI as usize] = ELFOSABI_NONE as _;
+    let ehdr = Elf64_Ehdr {
+        e_ident,
+        e_type: ET_CORE as _,
+        e_machine: ELF_MACHINE as _,
+        e_version: EV_CURRENT,
+        e_entry: 0,
+        e_phoff: size_of::<Elf64_Ehdr>() as _,
+        e_shoff: 0,
+        e_flags: 0,
+        e_ehsize: size_of::<Elf64_Ehdr>() as _,
+        e_phentsize: size_of::<Elf64_Phdr>() as _,
+        e_phnum: phnum as _,
+        e_shentsize: 0,
+        e_shnum: 0,
+        e_shstrndx: 0,
+    };
+    file.write(as_bytes(&ehdr))?;
+
+    let note_phdr = Elf64_Phdr {
+        p_type: PT_NOTE,
+        p_flags: 0,
+        p_offset: notes_offset as _,
+        p_vaddr: 0,
+        p_paddr: 0,
+        p_filesz: notes.len() as _,
+        p_memsz: 0,
+        p_align: 4,
+    };
+    file.write(as_bytes(&note_phdr))?;
+    for (range, flags) in &areas {
+        let mut p_flags = 0;
+        if flags.contains(MappingFlags::READ) {
+            p_flags |= PF_R;
+        }
+        if flags.contains(MappingFlags::WRITE) {
+            p_flags |= PF_W;
+        }
+        if flags.contains(MappingFlags::EXECUTE) {
+            p_flags |= PF_X;
+        }
+        // Areas which cannot be read are not dumped.
+        let filesz = if p_flags & PF_R != 0 { range.size() } else { 0 };
+        let phdr = Elf64_Phdr {
+            p_type: PT_LOAD,
+            p_flags,
+            p_offset: offset as _,
+            p_vaddr: range.start.as_usize() as _,
+            p_paddr: 0,
+            p_filesz: filesz as _,
+            p_memsz: range.size() as _,
+            p_align: PAGE_SIZE_4K as _,
+        };
+        file.write(as_bytes(&phdr))?;
+        offset += filesz;
+    }
+
+    file.write(&notes)?;
+    file.skip(align_up_4k(file.pos as usize) - file.pos as usize)?;
+
+    let mut page = [0; PAGE_SIZE_4K];
+    'dump: for (range, flags) in &areas {
+        if !flags.contains(MappingFlags::READ) {
+            continue;
+        }
+        let mut vaddr = range.start;
+        while vaddr < range.end {
+            if file.is_full() {
+                break 'dump;
+            }
+            // The pages which have never been accessed are left as holes.
+            if aspace.read(vaddr, PageSize::Size4K, &mut page).is_ok() {
+                file.write(&page)?;
+            } else {
+                file.skip(PAGE_SIZE_4K)?;
+            }
+            vaddr += PAGE_SIZE_4K;
+        }
+    }
+    // Extend the file over a final hole.
+    file.file.set_len(file.pos)?;
+    Ok(())
+}
+
+/// Expand the `core_pattern` setting into the path of the core file, see
+/// `core(5)`.
+///
+/// Returns `None` if core dumps are disabled by an empty pattern, or piped
+/// to a program, which is not supported.
+fn core_file_path(sig: &SignalInfo) -> Option<String> {
+    let pattern = axfs::api::read_to_string(CORE_PATTERN_PATH).unwrap_or_else(|_| "core".into());
+    let pattern = pattern.trim_end_matches('\n');
+    if pattern.is_empty() {
+        return None;
+    }
+    if pattern.starts_with('|') {
+        warn!("Piping core dumps to {:?} is not supported", pattern);
+        return None;
+    }
+
+    let curr = current();
+    let thread = &curr.task_ext().thread;
+    let mut path = String::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            path.push(c);
+            continue;
+        }
+        // Unknown specifiers are dropped.
+        match chars.next() {
+            Some('%') => path.push('%'),
+            Some('p' | 'P') => path += &thread.process().pid().to_string(),
+            Some('i' | 'I') => path += &thread.tid().to_string(),
+            Some('u' | 'g') => path.push('0'),
+            Some('s') => path += &(sig.signo() as u8).to_string(),
+            Some('t') => path += &realtime().as_secs().to_string(),
+            Some('c') => {
+                let limit = curr.task_ext().process_data().rlimits.read()[RLIMIT_CORE].current;
+                path += &limit.to_string()
+            }
+            Some('e') => path += curr.name(),
+            Some('E') => {
+                path += &curr
+                    .task_ext()
+                    .process_data()
+                    .exe_path
+                    .read()
+                    .replace('/', "!")
+            }
+            _ => {}
+        }
+    }
+    Some(path)
+}
+
+/// Dump the core of the current process, which is killed by `sig`, unless
+/// `RLIMIT_CORE` or `core_pattern` disable it.
+///
+/// `tf` holds the user registers of the current thread. Returns whether the
+/// core file has been written, which is truncated if it would exceed
+/// `RLIMIT_CORE`.
+pub fn dump_core(tf: &TrapFrame, sig: &SignalInfo) -> bool {
+    let curr = current();
+    let limit = curr.task_ext().process_data().rlimits.read()[RLIMIT_CORE].current;
+    if limit == 0 {
+        return false;
+    }
+    let Some(path) = core_file_path(sig) else {
+        return false;
+    };
+    info!("Dumping core of {:?} to {}", curr.task_ext().thread, path);
+
+    let file = match File::create(&path) {
+        Ok(file) => file,
+        Err(err) => {
+            warn!("Failed to create core file {}: {:?}", path, err);
+            return false;
+        }
+    };
+    let mut file = CoreFile {
+        file,
+        pos: 0,
+        limit,
+    };
+    match write_core(&mut file, tf, sig) {
+        Ok(()) => true,
+        Err(err) => {
+            warn!("Failed to write core file {}: {:?}", path, err);
+            false
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use core::{
+    any::Any,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+};
+
+use alloc::sync::Arc;
+use axerrno::{LinuxError, LinuxResult};
+use axio::PollState;
+use axsync::Mutex;
+use axtask::WaitQueue;
+
+use super::{FileLike, Kstat};
+use crate::signal::have_signals;
+
+/// The maximum value of the counter of an eventfd.
+///
+/// `u64::MAX` itself is never reached by writes, and means that the counter
+/// overflowed.
+const EVENTFD_MAX: u64 = u64::MAX - 1;
+
+/// An event notification counter, created by `eventfd`.
+pub struct EventFd {
+    count: Mutex<u64>,
+    /// Whether reads decrement the counter by one rather than reset it
+    semaphore: bool,
+    /// The readers and writers waiting for the counter to change
+    wq: WaitQueue,
+    /// The number of times the counter changed
+    changes: AtomicU64,
+    nonblocking: AtomicBool,
+}
+
+impl EventFd {
+    pub fn new(initval: u64, semaphore: bool) -> Self {
+        Self {
+            count: Mutex::new(initval),
+            semaphore,
+            wq: WaitQueue::new(),
+            changes: AtomicU64::new(0),
+            nonblocking: AtomicBool::new(false),
+        }
+    }
+
+    /// Wait until `f` succeeds, which is called with the counter until it
+    /// returns `Some`.
+    fn wait_for<R>(&self, mut f: impl FnMut(&mut u64) -> Option<R>) -> LinuxResult<R> {
+        loop {
+            let mut count = self.count.lock();
+            let changes = self.changes.load(Ordering::Acquire);
+            if let Some(result) = f(&mut count) {
+                self.changes.fetch_add(1, Ordering::Release);
+                drop(count);
+                self.wq.notify_all(false);
+                return Ok(result);
+            }
+            drop(count);
+            if self.nonblocking.load(Ordering::Acquire) {
+                return Err(LinuxError::EAGAIN);
+            }
+            if have_signals() {
+                return Err(LinuxError::EINTR);
+            }
+            self.wq
+                .wait_until_interruptible(|| self.changes.load(Ordering::Acquire) != changes);
+        }
+    }
+}
+
+impl FileLike for EventFd {
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        if buf.len() < size_of::<u64>() {
+            return Err(LinuxError::EINVAL);
+        }
+        let value = self.wait_for(|count| {
+            if *count == 0 {
+                return None;
+            }
+            Some(if self.semaphore {
+                *count -= 1;
+                1
+            } else {
+                core::mem::take(count)
+            })
+        })?;
+        buf[..size_of::<u64>()].copy_from_slice(&value.to_ne_bytes());
+        Ok(size_of::<u64>())
+    }
+
+    fn write(&self, buf: &[u8]) -> LinuxResult<usize> {
+        let Some(value) = buf.first_chunk::<{ size_of::<u64>() }>() else {
+            return Err(LinuxError::EINVAL);
+        };
+        let value = u64::from_ne_bytes(*value);
+        if value == u64::MAX {
+            return Err(LinuxError::EINVAL);
+        }
+        self.wait_for(|count| {
+            // Block until the counter can hold the value without overflowing.
+            (*count <= EVENTFD_MAX - value).then(|| *count += value)
+        })?;
+        Ok(size_of::<u64>())
+    }
+
+    fn stat(&self) -> LinuxResult<Kstat> {
+        Ok(Kstat {
+            ino: self as *const _ as usize as u64,
+            mode: 0o600,
+            ..Default::default()
+        })
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+
+    fn poll(&self) -> LinuxResult<PollState> {
+        let count = *self.count.lock();
+        // An overflowed counter is reported as an error, i.e. `POLLERR`.
+        if count == u64::MAX {
+            return Err(LinuxError::EOVERFLOW);
+        }
+        Ok(PollState {
+            readable: count > 0,
+            writable: count < EVENTFD_MAX,
+        })
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> LinuxResult {
+        self.nonblocking.store(nonblocking, Ordering::Release);
+        Ok(())
+    }
+}
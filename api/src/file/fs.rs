@@ -5,9 +5,10 @@ use axerrno::{LinuxError, LinuxResult};
 use axfs::fops::DirEntry;
 use axio::PollState;
 use axsync::{Mutex, MutexGuard};
-use linux_raw_sys::general::S_IFDIR;
+use linux_raw_sys::general::{S_IFCHR, S_IFMT};
 
 use super::{FileLike, Kstat, get_file_like};
+use crate::terminal::open_terminal;
 
 /// File wrapper for `axfs::fops::File`.
 pub struct File {
@@ -32,6 +33,16 @@ impl File {
     pub fn get_inner(&self) -> MutexGuard<axfs::fops::File> {
         self.inner.lock()
     }
+
+    /// Open the terminal the file refers to, or return `None` if it is not a
+    /// terminal device. See [`open_terminal`].
+    pub fn open_terminal(&self, flags: u32) -> LinuxResult<Option<Arc<dyn FileLike>>> {
+        let stat = self.stat()?;
+        if stat.mode & S_IFMT != S_IFCHR {
+            return Ok(None);
+        }
+        open_terminal(stat.rdev, flags)
+    }
 }
 
 impl FileLike for File {
@@ -44,17 +55,11 @@ impl FileLike for File {
     }
 
     fn stat(&self) -> LinuxResult<Kstat> {
-        let metadata = self.get_inner().get_attr()?;
-        let ty = metadata.file_type() as u8;
-        let perm = metadata.perm().bits() as u32;
-
-        Ok(Kstat {
-            mode: ((ty as u32) << 12) | perm,
-            size: metadata.size(),
-            blocks: metadata.blocks(),
-            blksize: 512,
-            ..Default::default()
-        })
+        let inner = self.get_inner();
+        Ok(Kstat::from_attr(
+            &inner.get_attr()?,
+            &inner.get_inode_attr()?,
+        ))
     }
 
     fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
@@ -115,10 +120,11 @@ impl FileLike for Directory {
     }
 
     fn stat(&self) -> LinuxResult<Kstat> {
-        Ok(Kstat {
-            mode: S_IFDIR | 0o755u32, // rwxr-xr-x
-            ..Default::default()
-        })
+        let inner = self.get_inner();
+        Ok(Kstat::from_attr(
+            &inner.get_attr()?,
+            &inner.get_inode_attr()?,
+        ))
     }
 
     fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
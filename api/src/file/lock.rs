@@ -0,0 +1,553 @@
+//! Advisory file locks.
+//!
+//! Three kinds of locks are supported, all of them keyed by the inode:
+//!
+//! - BSD locks taken by `flock`, which cover the whole file and belong to the
+//!   open file description.
+//! - POSIX record locks taken by `fcntl(F_SETLK)`, which cover a byte range and
+//!   belong to the process. They are released when the process closes any
+//!   descriptor referring to the file.
+//! - Open file description locks taken by `fcntl(F_OFD_SETLK)`, which cover a
+//!   byte range like POSIX locks but belong to the open file description.
+//!
+//! POSIX and OFD locks share the same byte-range space and conflict with each
+//! other, while BSD locks are independent of them.
+
+use core::{
+    fmt::Write,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use alloc::{
+    collections::{btree_map::BTreeMap, btree_set::BTreeSet},
+    string::String,
+    sync::Arc,
+    vec::Vec,
+};
+use axerrno::{LinuxError, LinuxResult};
+use axio::SeekFrom;
+use axprocess::Pid;
+use axtask::{TaskExtRef, WaitQueue, current};
+use linux_raw_sys::general::{
+    F_RDLCK, F_UNLCK, F_WRLCK, LOCK_EX, LOCK_NB, LOCK_SH, LOCK_UN, O_ACCMODE, O_RDONLY, O_WRONLY,
+    SEEK_CUR, SEEK_END, SEEK_SET, flock,
+};
+use spin::Mutex;
+
+use super::{File, OpenFile};
+use crate::signal::have_signals;
+
+/// The end offset of a lock reaching the end of the file.
+const OFFSET_MAX: u64 = i64::MAX as u64;
+
+/// Identifies an inode as `(dev, ino)`.
+type InodeKey = (u64, u64);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LockOwner {
+    /// POSIX record locks belong to a process.
+    Process(Pid),
+    /// BSD and OFD locks belong to an open file description.
+    OpenFile(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LockKind {
+    Read,
+    Write,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LockClass {
+    Flock,
+    Posix,
+    Ofd,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct FileLock {
+    class: LockClass,
+    owner: LockOwner,
+    kind: LockKind,
+    /// First byte covered by the lock.
+    start: u64,
+    /// Last byte covered by the lock, [`OFFSET_MAX`] if it reaches EOF.
+    end: u64,
+    /// The process which took the lock.
+    pid: Pid,
+}
+
+impl FileLock {
+    fn overlaps(&self, start: u64, end: u64) -> bool {
+        self.start <= end && start <= self.end
+    }
+
+    fn conflicts(&self, other: &FileLock) -> bool {
+        self.owner != other.owner
+            && (self.kind == LockKind::Write || other.kind == LockKind::Write)
+            && self.overlaps(other.start, other.end)
+    }
+}
+
+#[derive(Default)]
+struct InodeLocks {
+    flocks: Vec<FileLock>,
+    /// POSIX and OFD record locks.
+    records: Vec<FileLock>,
+}
+
+impl InodeLocks {
+    fn is_empty(&self) -> bool {
+        self.flocks.is_empty() && self.records.is_empty()
+    }
+
+    /// Remove the range `[start, end]` from the record locks of `owner`,
+    /// splitting the locks partially covered. Returns whether anything was
+    /// unlocked.
+    fn unlock_records(&mut self, owner: LockOwner, start: u64, end: u64) -> bool {
+        let mut changed = false;
+        let mut remaining = Vec::with_capacity(self.records.len());
+        for lock in self.records.drain(..) {
+            if lock.owner != owner || !lock.overlaps(start, end) {
+                remaining.push(lock);
+                continue;
+            }
+            changed = true;
+            if lock.start < start {
+                remaining.push(FileLock {
+                    end: start - 1,
+                    ..lock
+                });
+            }
+            if lock.end > end {
+                remaining.push(FileLock {
+                    start: end + 1,
+                    ..lock
+                });
+            }
+        }
+        self.records = remaining;
+        changed
+    }
+
+    /// Insert a record lock, replacing the locks of the same owner in its
+    /// range and merging it with adjacent locks of the same kind.
+    fn insert_record(&mut self, mut new: FileLock) {
+        self.unlock_records(new.owner, new.start, new.end);
+        self.records.retain(|lock| {
+            let adjacent = lock.end.checked_add(1) == Some(new.start)
+                || new.end.checked_add(1) == Some(lock.start);
+            if lock.owner == new.owner && lock.kind == new.kind && adjacent {
+                new.start = new.start.min(lock.start);
+                new.end = new.end.max(lock.end);
+                false
+            } else {
+                true
+            }
+        });
+        self.records.push(new);
+    }
+}
+
+struct LockTable {
+    inodes: BTreeMap<InodeKey, InodeLocks>,
+    /// For each process blocked on a POSIX lock, the owner of the lock it is
+    /// waiting for.
+    blocked_on: BTreeMap<Pid, LockOwner>,
+}
+
+impl LockTable {
+    /// Whether `pid` waiting for a lock held by `owner` would deadlock.
+    ///
+    /// The chain of the processes waiting for each other is walked to its
+    /// end, or until it loops without going through `pid`.
+    fn would_deadlock(&self, pid: Pid, mut owner: LockOwner) -> bool {
+        let mut visited = BTreeSet::new();
+        loop {
+            let LockOwner::Process(holder) = owner else {
+                return false;
+            };
+            if holder == pid {
+                return true;
+            }
+            if !visited.insert(holder) {
+                return false;
+            }
+            match self.blocked_on.get(&holder) {
+                Some(next) => owner = *next,
+                None => return false,
+            }
+        }
+    }
+
+    /// Drop the entry of `key` if it holds no locks any more.
+    fn cleanup(&mut self, key: InodeKey) {
+        if self.inodes.get(&key).is_some_and(InodeLocks::is_empty) {
+            self.inodes.remove(&key);
+        }
+    }
+}
+
+static LOCK_TABLE: Mutex<LockTable> = Mutex::new(LockTable {
+    inodes: BTreeMap::new(),
+    blocked_on: BTreeMap::new(),
+});
+
+/// Bumped each time a lock is released, to wake up the blocked lockers.
+static LOCK_GENERATION: AtomicU64 = AtomicU64::new(0);
+static LOCK_WAIT_QUEUE: WaitQueue = WaitQueue::new();
+
+fn current_pid() -> Pid {
+    current().task_ext().thread.process().pid()
+}
+
+fn inode_key(file: &OpenFile) -> LinuxResult<InodeKey> {
+    let stat = file.file().stat()?;
+    Ok((stat.dev, stat.ino))
+}
+
+fn wake_waiters() {
+    LOCK_GENERATION.fetch_add(1, Ordering::AcqRel);
+    LOCK_WAIT_QUEUE.notify_all(true);
+}
+
+/// Wait until some lock is released after `generation` was observed.
+///
+/// Returns `EINTR` if a signal arrives in the meantime.
+fn wait_for_release(generation: u64) -> LinuxResult {
+    let released = || LOCK_GENERATION.load(Ordering::Acquire) != generation;
+    loop {
+        if released() {
+            return Ok(());
+        }
+        if have_signals() {
+            return Err(LinuxError::EINTR);
+        }
+        LOCK_WAIT_QUEUE.wait_until_interruptible(released);
+    }
+}
+
+/// Apply or remove a BSD lock, as done by `flock`.
+pub fn apply_flock(file: &Arc<OpenFile>, operation: u32) -> LinuxResult {
+    let kind = match operation & !LOCK_NB {
+        LOCK_SH => Some(LockKind::Read),
+        LOCK_EX => Some(LockKind::Write),
+        LOCK_UN => None,
+        _ => return Err(LinuxError::EINVAL),
+    };
+    let nonblock = operation & LOCK_NB != 0;
+    let owner = LockOwner::OpenFile(Arc::as_ptr(file) as usize);
+    let key = inode_key(file)?;
+
+    loop {
+        let mut table = LOCK_TABLE.lock();
+        let locks = table.inodes.entry(key).or_default();
+        let existing = locks.flocks.iter().position(|lock| lock.owner == owner);
+        match (existing, kind) {
+            (Some(idx), Some(kind)) if locks.flocks[idx].kind == kind => return Ok(()),
+            // Converting a lock is not atomic: the old one is dropped first.
+            (Some(idx), _) => {
+                locks.flocks.remove(idx);
+                wake_waiters();
+            }
+            (None, _) => {}
+        }
+        let Some(kind) = kind else {
+            table.cleanup(key);
+            return Ok(());
+        };
+
+        let new = FileLock {
+            class: LockClass::Flock,
+            owner,
+            kind,
+            start: 0,
+            end: OFFSET_MAX,
+            pid: current_pid(),
+        };
+        if !locks.flocks.iter().any(|lock| lock.conflicts(&new)) {
+            locks.flocks.push(new);
+            return Ok(());
+        }
+        if nonblock {
+            table.cleanup(key);
+            return Err(LinuxError::EAGAIN);
+        }
+        let generation = LOCK_GENERATION.load(Ordering::Acquire);
+        drop(table);
+        wait_for_release(generation)?;
+    }
+}
+
+/// A record lock request decoded from `struct flock`.
+struct RecordRequest {
+    /// The requested lock kind, `None` for `F_UNLCK`.
+    kind: Option<LockKind>,
+    start: u64,
+    end: u64,
+}
+
+impl RecordRequest {
+    fn parse(file: &OpenFile, lock: &flock) -> LinuxResult<Self> {
+        let kind = match lock.l_type as u32 {
+            F_RDLCK => Some(LockKind::Read),
+            F_WRLCK => Some(LockKind::Write),
+            F_UNLCK => None,
+            _ => return Err(LinuxError::EINVAL),
+        };
+        let base = match lock.l_whence as u32 {
+            SEEK_SET => 0,
+            SEEK_CUR => match file.file().clone().into_any().downcast::<File>() {
+                Ok(file) => file.get_inner().seek(SeekFrom::Current(0))? as i64,
+                Err(_) => 0,
+            },
+            SEEK_END => file.file().stat()?.size as i64,
+            _ => return Err(LinuxError::EINVAL),
+        };
+        let start = base
+            .checked_add(lock.l_start)
+            .ok_or(LinuxError::EOVERFLOW)?;
+        let len = lock.l_len;
+        let (start, end) = if len > 0 {
+            let end = start.checked_add(len - 1).ok_or(LinuxError::EOVERFLOW)?;
+            (start, end)
+        } else if len < 0 {
+            (start.checked_add(len).ok_or(LinuxError::EINVAL)?, start - 1)
+        } else {
+            (start, OFFSET_MAX as i64)
+        };
+        if start < 0 || end < start {
+            return Err(LinuxError::EINVAL);
+        }
+        Ok(Self {
+            kind,
+            start: start as u64,
+            end: end as u64,
+        })
+    }
+}
+
+fn record_owner(file: &Arc<OpenFile>, ofd: bool) -> LockOwner {
+    if ofd {
+        LockOwner::OpenFile(Arc::as_ptr(file) as usize)
+    } else {
+        LockOwner::Process(current_pid())
+    }
+}
+
+/// Find a lock which would prevent the lock described by `lock`, as done by
+/// `F_GETLK` and `F_OFD_GETLK`.
+///
+/// `lock` is overwritten with the conflicting lock, or its type is set to
+/// `F_UNLCK` if there is none.
+pub fn get_record_lock(file: &Arc<OpenFile>, ofd: bool, lock: &mut flock) -> LinuxResult {
+    if ofd && lock.l_pid != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let request = RecordRequest::parse(file, lock)?;
+    let Some(kind) = request.kind else {
+        return Err(LinuxError::EINVAL);
+    };
+    let probe = FileLock {
+        class: if ofd {
+            LockClass::Ofd
+        } else {
+            LockClass::Posix
+        },
+        owner: record_owner(file, ofd),
+        kind,
+        start: request.start,
+        end: request.end,
+        pid: current_pid(),
+    };
+    let key = inode_key(file)?;
+
+    let table = LOCK_TABLE.lock();
+    let conflict = table
+        .inodes
+        .get(&key)
+        .and_then(|locks| locks.records.iter().find(|it| it.conflicts(&probe)));
+    match conflict {
+        Some(conflict) => {
+            lock.l_type = match conflict.kind {
+                LockKind::Read => F_RDLCK,
+                LockKind::Write => F_WRLCK,
+            } as _;
+            lock.l_whence = SEEK_SET as _;
+            lock.l_start = conflict.start as _;
+            lock.l_len = if conflict.end == OFFSET_MAX {
+                0
+            } else {
+                (conflict.end - conflict.start + 1) as _
+            };
+            lock.l_pid = match conflict.class {
+                LockClass::Posix => conflict.pid as _,
+                _ => -1,
+            };
+        }
+        None => lock.l_type = F_UNLCK as _,
+    }
+    Ok(())
+}
+
+/// Acquire or release a record lock, as done by `F_SETLK`, `F_SETLKW` and
+/// their OFD counterparts.
+///
+/// If `wait` is set, blocks until the lock can be taken; otherwise fails with
+/// `EAGAIN` on conflict.
+pub fn set_record_lock(file: &Arc<OpenFile>, ofd: bool, lock: &flock, wait: bool) -> LinuxResult {
+    if ofd && lock.l_pid != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let request = RecordRequest::parse(file, lock)?;
+    let access = file.flags() & O_ACCMODE;
+    match request.kind {
+        Some(LockKind::Read) if access == O_WRONLY => return Err(LinuxError::EBADF),
+        Some(LockKind::Write) if access == O_RDONLY => return Err(LinuxError::EBADF),
+        _ => {}
+    }
+    let owner = record_owner(file, ofd);
+    let pid = current_pid();
+    let key = inode_key(file)?;
+
+    loop {
+        let mut table = LOCK_TABLE.lock();
+        table.blocked_on.remove(&pid);
+        let Some(kind) = request.kind else {
+            if let Some(locks) = table.inodes.get_mut(&key) {
+                if locks.unlock_records(owner, request.start, request.end) {
+                    wake_waiters();
+                }
+            }
+            table.cleanup(key);
+            return Ok(());
+        };
+
+        let new = FileLock {
+            class: if ofd {
+                LockClass::Ofd
+            } else {
+                LockClass::Posix
+            },
+            owner,
+            kind,
+            start: request.start,
+            end: request.end,
+            pid,
+        };
+        let locks = table.inodes.entry(key).or_default();
+        let Some(blocker) = locks.records.iter().find(|it| it.conflicts(&new)).copied() else {
+            // Downgrading a write lock may let others in.
+            locks.insert_record(new);
+            wake_waiters();
+            return Ok(());
+        };
+        if !wait {
+            table.cleanup(key);
+            return Err(LinuxError::EAGAIN);
+        }
+        if !ofd {
+            if table.would_deadlock(pid, blocker.owner) {
+                table.cleanup(key);
+                return Err(LinuxError::EDEADLK);
+            }
+            table.blocked_on.insert(pid, blocker.owner);
+        }
+        let generation = LOCK_GENERATION.load(Ordering::Acquire);
+        drop(table);
+        if let Err(err) = wait_for_release(generation) {
+            LOCK_TABLE.lock().blocked_on.remove(&pid);
+            return Err(err);
+        }
+    }
+}
+
+/// Release the POSIX locks held by the current process on the file, as
+/// required when any of its descriptors referring to the file is closed.
+pub(super) fn release_posix_locks(file: &OpenFile) {
+    if LOCK_TABLE.lock().inodes.is_empty() {
+        return;
+    }
+    let Ok(key) = inode_key(file) else {
+        return;
+    };
+    let owner = LockOwner::Process(current_pid());
+    let mut table = LOCK_TABLE.lock();
+    if let Some(locks) = table.inodes.get_mut(&key) {
+        if locks.unlock_records(owner, 0, OFFSET_MAX) {
+            wake_waiters();
+        }
+    }
+    table.cleanup(key);
+}
+
+/// Release the BSD and OFD locks held by an open file description that is
+/// going away.
+pub(super) fn release_open_file_locks(file: &OpenFile) {
+    let owner = LockOwner::OpenFile(file as *const OpenFile as usize);
+    let mut table = LOCK_TABLE.lock();
+    let mut changed = false;
+    table.inodes.retain(|_, locks| {
+        let count = locks.flocks.len() + locks.records.len();
+        locks.flocks.retain(|lock| lock.owner != owner);
+        locks.records.retain(|lock| lock.owner != owner);
+        changed |= locks.flocks.len() + locks.records.len() != count;
+        !locks.is_empty()
+    });
+    if changed {
+        wake_waiters();
+    }
+}
+
+/// Render the held locks in the format of `/proc/locks`.
+fn proc_locks() -> Vec<u8> {
+    let table = LOCK_TABLE.lock();
+    let mut out = String::new();
+    let locks = table.inodes.iter().flat_map(|(key, locks)| {
+        locks
+            .flocks
+            .iter()
+            .chain(&locks.records)
+            .map(move |it| (key, it))
+    });
+    for (id, ((dev, ino), lock)) in locks.enumerate() {
+        let class = match lock.class {
+            LockClass::Flock => "FLOCK",
+            LockClass::Posix => "POSIX",
+            LockClass::Ofd => "OFDLCK",
+        };
+        let kind = match lock.kind {
+            LockKind::Read => "READ ",
+            LockKind::Write => "WRITE",
+        };
+        let pid = match lock.class {
+            LockClass::Ofd => -1,
+            _ => lock.pid as i64,
+        };
+        let _ = write!(
+            out,
+            "{}: {:<6} ADVISORY  {} {} {:02x}:{:02x}:{} {} ",
+            id + 1,
+            class,
+            kind,
+            pid,
+            super::dev_major(*dev),
+            super::dev_minor(*dev),
+            ino,
+            lock.start,
+        );
+        let _ = if lock.end == OFFSET_MAX {
+            writeln!(out, "EOF")
+        } else {
+            writeln!(out, "{}", lock.end)
+        };
+    }
+    out.into_bytes()
+}
+
+#[ctor_bare::register_ctor]
+fn init_proc_locks() {
+    if let Err(err) = axfs::api::create_generated_file("/proc/locks", proc_locks) {
+        warn!("failed to create /proc/locks: {:?}", err);
+    }
+}
@@ -1,65 +1,150 @@
+mod eventfd;
 mod fs;
+mod lock;
 mod net;
+mod pidfd;
 mod pipe;
-mod stdio;
+mod pty;
+mod signalfd;
+mod timerfd;
+mod tty;
 
-use core::{any::Any, ffi::c_int};
+use core::{
+    any::Any,
+    ffi::c_int,
+    sync::atomic::{AtomicU32, Ordering},
+};
 
 use alloc::{sync::Arc, vec::Vec};
 use axerrno::{LinuxError, LinuxResult};
+use axfs::fops::{FileAttr, InodeAttr};
+use axhal::time::TimeValue;
 use axio::PollState;
 use axns::{ResArc, def_resource};
 use flatten_objects::FlattenObjects;
-use linux_raw_sys::general::{stat, statx};
+use linux_raw_sys::general::{
+    FASYNC, O_ACCMODE, O_APPEND, O_CLOEXEC, O_DIRECT, O_NOATIME, O_NONBLOCK, O_RDWR,
+    STATX_BASIC_STATS, STATX_BTIME, stat, statx, statx_timestamp,
+};
 use spin::RwLock;
 
+use crate::terminal::{CONSOLE, Terminal};
+
 pub use self::{
+    eventfd::EventFd,
     fs::{Directory, File},
+    lock::{apply_flock, get_record_lock, set_record_lock},
     net::Socket,
+    pidfd::PidFd,
     pipe::Pipe,
+    pty::PtyMaster,
+    signalfd::SignalFd,
+    timerfd::TimerFd,
+    tty::Tty,
 };
 
 pub const AX_FILE_LIMIT: usize = 1024;
 
 #[derive(Debug, Clone, Copy)]
 pub struct Kstat {
+    dev: u64,
     ino: u64,
     nlink: u32,
     uid: u32,
     gid: u32,
     mode: u32,
+    rdev: u64,
     size: u64,
     blocks: u64,
     blksize: u32,
+    atime: TimeValue,
+    mtime: TimeValue,
+    ctime: TimeValue,
+    btime: Option<TimeValue>,
 }
 
 impl Default for Kstat {
     fn default() -> Self {
         Self {
+            dev: 0,
             ino: 1,
             nlink: 1,
             uid: 1,
             gid: 1,
             mode: 0,
+            rdev: 0,
             size: 0,
             blocks: 0,
             blksize: 4096,
+            atime: TimeValue::ZERO,
+            mtime: TimeValue::ZERO,
+            ctime: TimeValue::ZERO,
+            btime: None,
+        }
+    }
+}
+
+impl Kstat {
+    /// Build the status of a filesystem node from its attributes.
+    pub fn from_attr(attr: &FileAttr, inode: &InodeAttr) -> Self {
+        let ty = attr.file_type() as u8;
+        let perm = attr.perm().bits() as u32;
+        Self {
+            dev: inode.dev,
+            ino: inode.ino,
+            nlink: inode.nlink,
+            mode: ((ty as u32) << 12) | perm,
+            rdev: inode.rdev,
+            size: attr.size(),
+            blocks: attr.blocks(),
+            blksize: 512,
+            atime: inode.atime,
+            mtime: inode.mtime,
+            ctime: inode.ctime,
+            btime: inode.btime,
+            ..Default::default()
         }
     }
 }
 
+/// Get the major number of a device ID.
+const fn dev_major(dev: u64) -> u32 {
+    (((dev >> 32) & 0xffff_f000) | ((dev >> 8) & 0xfff)) as u32
+}
+
+/// Get the minor number of a device ID.
+const fn dev_minor(dev: u64) -> u32 {
+    (((dev >> 12) & 0xffff_ff00) | (dev & 0xff)) as u32
+}
+
+fn statx_time(time: TimeValue) -> statx_timestamp {
+    statx_timestamp {
+        tv_sec: time.as_secs() as _,
+        tv_nsec: time.subsec_nanos(),
+        __reserved: 0,
+    }
+}
+
 impl From<Kstat> for stat {
     fn from(value: Kstat) -> Self {
         // SAFETY: valid for stat
         let mut stat: stat = unsafe { core::mem::zeroed() };
+        stat.st_dev = value.dev as _;
         stat.st_ino = value.ino as _;
         stat.st_nlink = value.nlink as _;
         stat.st_mode = value.mode as _;
         stat.st_uid = value.uid as _;
         stat.st_gid = value.gid as _;
+        stat.st_rdev = value.rdev as _;
         stat.st_size = value.size as _;
         stat.st_blksize = value.blksize as _;
         stat.st_blocks = value.blocks as _;
+        stat.st_atime = value.atime.as_secs() as _;
+        stat.st_atime_nsec = value.atime.subsec_nanos() as _;
+        stat.st_mtime = value.mtime.as_secs() as _;
+        stat.st_mtime_nsec = value.mtime.subsec_nanos() as _;
+        stat.st_ctime = value.ctime.as_secs() as _;
+        stat.st_ctime_nsec = value.ctime.subsec_nanos() as _;
 
         stat
     }
@@ -69,8 +154,8 @@ impl From<Kstat> for statx {
     fn from(value: Kstat) -> Self {
         // SAFETY: valid for statx
         let mut statx: statx = unsafe { core::mem::zeroed() };
+        statx.stx_mask = STATX_BASIC_STATS;
         statx.stx_blksize = value.blksize as _;
-        statx.stx_attributes = value.mode as _;
         statx.stx_nlink = value.nlink as _;
         statx.stx_uid = value.uid as _;
         statx.stx_gid = value.gid as _;
@@ -78,6 +163,17 @@ impl From<Kstat> for statx {
         statx.stx_ino = value.ino as _;
         statx.stx_size = value.size as _;
         statx.stx_blocks = value.blocks as _;
+        statx.stx_atime = statx_time(value.atime);
+        statx.stx_mtime = statx_time(value.mtime);
+        statx.stx_ctime = statx_time(value.ctime);
+        if let Some(btime) = value.btime {
+            statx.stx_mask |= STATX_BTIME;
+            statx.stx_btime = statx_time(btime);
+        }
+        statx.stx_rdev_major = dev_major(value.rdev);
+        statx.stx_rdev_minor = dev_minor(value.rdev);
+        statx.stx_dev_major = dev_major(value.dev);
+        statx.stx_dev_minor = dev_minor(value.dev);
 
         statx
     }
@@ -92,6 +188,17 @@ pub trait FileLike: Send + Sync {
     fn poll(&self) -> LinuxResult<PollState>;
     fn set_nonblocking(&self, nonblocking: bool) -> LinuxResult;
 
+    /// The terminal this file refers to, if it is a terminal device.
+    fn terminal(&self) -> Option<Arc<Terminal>> {
+        None
+    }
+
+    /// Handle `ioctl` requests specific to the file. Files which are not
+    /// devices accept none.
+    fn ioctl(&self, _op: u32, _arg: usize) -> LinuxResult<isize> {
+        Err(LinuxError::ENOTTY)
+    }
+
     fn from_fd(fd: c_int) -> LinuxResult<Arc<Self>>
     where
         Self: Sized + 'static,
@@ -102,21 +209,108 @@ pub trait FileLike: Send + Sync {
             .map_err(|_| LinuxError::EINVAL)
     }
 
-    fn add_to_fd_table(self) -> LinuxResult<c_int>
+    fn add_to_fd_table_with_flags(self, flags: u32) -> LinuxResult<c_int>
     where
         Self: Sized + 'static,
     {
-        add_file_like(Arc::new(self))
+        add_file_like_with_flags(Arc::new(self), flags)
+    }
+}
+
+/// Status flags that can be changed by `fcntl(F_SETFL)`.
+const SETFL_MASK: u32 = O_APPEND | FASYNC | O_DIRECT | O_NOATIME | O_NONBLOCK;
+
+/// An open file description.
+///
+/// It is created by each `open` (or `pipe`, `socket`, ...) and shared by all
+/// the file descriptors duplicated from it by `dup`, `fcntl(F_DUPFD)` or
+/// `fork`. The file offset lives in the underlying file object, so together
+/// with the status flags stored here it is shared between those descriptors.
+pub struct OpenFile {
+    file: Arc<dyn FileLike>,
+    flags: AtomicU32,
+}
+
+impl OpenFile {
+    /// Create a new open file description with the given open flags.
+    ///
+    /// Creation flags such as `O_CREAT` and `O_CLOEXEC` are not kept.
+    pub fn new(file: Arc<dyn FileLike>, flags: u32) -> Self {
+        Self {
+            file,
+            flags: AtomicU32::new(flags & (O_ACCMODE | SETFL_MASK)),
+        }
+    }
+
+    /// Get the underlying file object.
+    pub fn file(&self) -> &Arc<dyn FileLike> {
+        &self.file
+    }
+
+    /// Get the access mode and status flags, as returned by `F_GETFL`.
+    pub fn flags(&self) -> u32 {
+        self.flags.load(Ordering::Acquire)
+    }
+
+    /// Update the status flags, as done by `F_SETFL`.
+    ///
+    /// The access mode and the flags not in [`SETFL_MASK`] are ignored.
+    pub fn set_status_flags(&self, flags: u32) -> LinuxResult {
+        let flags = flags & SETFL_MASK;
+        self.file.set_nonblocking(flags & O_NONBLOCK != 0)?;
+        if let Ok(file) = self.file.clone().into_any().downcast::<File>() {
+            file.get_inner().set_append(flags & O_APPEND != 0);
+        }
+        let _ = self
+            .flags
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |old| {
+                Some((old & !SETFL_MASK) | flags)
+            });
+        Ok(())
+    }
+}
+
+impl Drop for OpenFile {
+    fn drop(&mut self) {
+        lock::release_open_file_locks(self);
+    }
+}
+
+/// An entry of the file descriptor table.
+///
+/// The open file description is shared by duplicated descriptors, while the
+/// close-on-exec flag belongs to each descriptor.
+#[derive(Clone)]
+pub struct FileDescriptor {
+    pub file: Arc<OpenFile>,
+    pub cloexec: bool,
+}
+
+impl FileDescriptor {
+    /// Create a descriptor referring to a new open file description.
+    pub fn new(file: Arc<dyn FileLike>, flags: u32) -> Self {
+        Self {
+            file: Arc::new(OpenFile::new(file, flags)),
+            cloexec: flags & O_CLOEXEC != 0,
+        }
+    }
+
+    /// Release the resources tied to this descriptor once it is removed from
+    /// the table.
+    pub fn close(self) {
+        lock::release_posix_locks(&self.file);
     }
 }
 
 def_resource! {
-    pub static FD_TABLE: ResArc<RwLock<FlattenObjects<Arc<dyn FileLike>, AX_FILE_LIMIT>>> = ResArc::new();
+    pub static FD_TABLE: ResArc<RwLock<FlattenObjects<FileDescriptor, AX_FILE_LIMIT>>> = ResArc::new();
 }
 
 impl FD_TABLE {
     /// Return a copy of the inner table.
-    pub fn copy_inner(&self) -> RwLock<FlattenObjects<Arc<dyn FileLike>, AX_FILE_LIMIT>> {
+    ///
+    /// The new table refers to the same open file descriptions.
+    pub fn copy_inner(&self) -> RwLock<FlattenObjects<FileDescriptor, AX_FILE_LIMIT>> {
         let table = self.read();
         let mut new_table = FlattenObjects::new();
         for id in table.ids() {
@@ -128,14 +322,32 @@ impl FD_TABLE {
     pub fn clear(&self) {
         let mut table = self.write();
         let ids = table.ids().collect::<Vec<_>>();
-        for id in ids {
-            let _ = table.remove(id);
-        }
+        let closed = ids
+            .into_iter()
+            .filter_map(|id| table.remove(id))
+            .collect::<Vec<_>>();
+        drop(table);
+        closed.into_iter().for_each(FileDescriptor::close);
+    }
+
+    /// Close all the file descriptors with the close-on-exec flag set.
+    pub fn close_on_exec(&self) {
+        let mut table = self.write();
+        let ids = table
+            .ids()
+            .filter(|&id| table.get(id).is_some_and(|fd| fd.cloexec))
+            .collect::<Vec<_>>();
+        let closed = ids
+            .into_iter()
+            .filter_map(|id| table.remove(id))
+            .collect::<Vec<_>>();
+        drop(table);
+        closed.into_iter().for_each(FileDescriptor::close);
     }
 }
 
-/// Get a file-like object by `fd`.
-pub fn get_file_like(fd: c_int) -> LinuxResult<Arc<dyn FileLike>> {
+/// Get a file descriptor table entry by `fd`.
+pub fn get_file_descriptor(fd: c_int) -> LinuxResult<FileDescriptor> {
     FD_TABLE
         .read()
         .get(fd as usize)
@@ -143,9 +355,33 @@ pub fn get_file_like(fd: c_int) -> LinuxResult<Arc<dyn FileLike>> {
         .ok_or(LinuxError::EBADF)
 }
 
-/// Add a file to the file descriptor table.
-pub fn add_file_like(f: Arc<dyn FileLike>) -> LinuxResult<c_int> {
-    Ok(FD_TABLE.write().add(f).map_err(|_| LinuxError::EMFILE)? as c_int)
+/// Get an open file description by `fd`.
+pub fn get_open_file(fd: c_int) -> LinuxResult<Arc<OpenFile>> {
+    Ok(get_file_descriptor(fd)?.file)
+}
+
+/// Get a file-like object by `fd`.
+pub fn get_file_like(fd: c_int) -> LinuxResult<Arc<dyn FileLike>> {
+    Ok(get_open_file(fd)?.file.clone())
+}
+
+/// Add a file to the file descriptor table, with the given open flags.
+///
+/// The flags include the access mode, which the creator of the file sets as
+/// Linux does for it, e.g. `O_RDONLY` for the read end of a pipe.
+pub fn add_file_like_with_flags(f: Arc<dyn FileLike>, flags: u32) -> LinuxResult<c_int> {
+    add_file_descriptor(FileDescriptor::new(f, flags), 0)
+}
+
+/// Add a file descriptor to the table, using the lowest available number
+/// greater than or equal to `min_fd`.
+pub fn add_file_descriptor(fd: FileDescriptor, min_fd: usize) -> LinuxResult<c_int> {
+    let mut table = FD_TABLE.write();
+    let id = (min_fd..AX_FILE_LIMIT)
+        .find(|&id| !table.is_assigned(id))
+        .ok_or(LinuxError::EMFILE)?;
+    table.add_at(id, fd).map_err(|_| LinuxError::EMFILE)?;
+    Ok(id as c_int)
 }
 
 /// Close a file by `fd`.
@@ -154,21 +390,27 @@ pub fn close_file_like(fd: c_int) -> LinuxResult {
         .write()
         .remove(fd as usize)
         .ok_or(LinuxError::EBADF)?;
-    debug!("close_file_like <= count: {}", Arc::strong_count(&f));
+    debug!("close_file_like <= count: {}", Arc::strong_count(&f.file));
+    f.close();
     Ok(())
 }
 
 #[ctor_bare::register_ctor]
 fn init_stdio() {
+    // The standard streams share one open file description, as if the
+    // console was opened once and duplicated.
+    let console = Arc::new(OpenFile::new(
+        Arc::new(Tty::new(CONSOLE.clone()).expect("failed to open the console")),
+        O_RDWR,
+    ));
     let mut fd_table = flatten_objects::FlattenObjects::new();
-    fd_table
-        .add_at(0, Arc::new(stdio::stdin()) as _)
-        .unwrap_or_else(|_| panic!()); // stdin
-    fd_table
-        .add_at(1, Arc::new(stdio::stdout()) as _)
-        .unwrap_or_else(|_| panic!()); // stdout
-    fd_table
-        .add_at(2, Arc::new(stdio::stdout()) as _)
-        .unwrap_or_else(|_| panic!()); // stderr
+    // stdin, stdout and stderr
+    for fd in 0..3 {
+        let desc = FileDescriptor {
+            file: console.clone(),
+            cloexec: false,
+        };
+        fd_table.add_at(fd, desc).unwrap_or_else(|_| panic!());
+    }
     FD_TABLE.init_new(spin::RwLock::new(fd_table));
 }
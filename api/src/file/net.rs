@@ -87,6 +87,7 @@ impl FileLike for Socket {
     fn stat(&self) -> LinuxResult<Kstat> {
         // not really implemented
         Ok(Kstat {
+            ino: self as *const Self as usize as u64,
             mode: S_IFSOCK | 0o777u32, // rwxrwxrwx
             blksize: 4096,
             ..Default::default()
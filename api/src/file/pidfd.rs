@@ -0,0 +1,86 @@
+use core::{
+    any::Any,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use alloc::sync::{Arc, Weak};
+use axerrno::{LinuxError, LinuxResult};
+use axio::PollState;
+use axprocess::{Pid, Process};
+
+use super::{FileLike, Kstat};
+
+/// A file descriptor referring to a process, created by `pidfd_open` or
+/// `clone(CLONE_PIDFD)`.
+///
+/// Unlike a PID, it cannot refer to another process once the one it was
+/// created for is gone.
+pub struct PidFd {
+    pid: Pid,
+    process: Weak<Process>,
+    nonblocking: AtomicBool,
+}
+
+impl PidFd {
+    pub fn new(process: &Arc<Process>) -> Self {
+        Self {
+            pid: process.pid(),
+            process: Arc::downgrade(process),
+            nonblocking: AtomicBool::new(false),
+        }
+    }
+
+    /// The ID of the process, which may have been reaped.
+    pub fn pid(&self) -> Pid {
+        self.pid
+    }
+
+    /// The process, or `ESRCH` if it was reaped.
+    pub fn process(&self) -> LinuxResult<Arc<Process>> {
+        self.process.upgrade().ok_or(LinuxError::ESRCH)
+    }
+
+    /// Whether the process has exited, whether or not it was reaped.
+    pub fn exited(&self) -> bool {
+        self.process.upgrade().is_none_or(|it| it.is_zombie())
+    }
+
+    /// Whether `waitid(P_PIDFD)` returns `EAGAIN` rather than blocking.
+    pub fn nonblocking(&self) -> bool {
+        self.nonblocking.load(Ordering::Acquire)
+    }
+}
+
+impl FileLike for PidFd {
+    fn read(&self, _buf: &mut [u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EINVAL)
+    }
+
+    fn write(&self, _buf: &[u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EINVAL)
+    }
+
+    fn stat(&self) -> LinuxResult<Kstat> {
+        Ok(Kstat {
+            ino: self as *const _ as usize as u64,
+            mode: 0o600,
+            ..Default::default()
+        })
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+
+    fn poll(&self) -> LinuxResult<PollState> {
+        Ok(PollState {
+            readable: self.exited(),
+            writable: false,
+        })
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> LinuxResult {
+        self.nonblocking.store(nonblocking, Ordering::Release);
+        Ok(())
+    }
+}
@@ -1,12 +1,17 @@
-use core::any::Any;
+use core::{
+    any::Any,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+};
 
 use alloc::sync::Arc;
 use axerrno::{LinuxError, LinuxResult};
 use axio::PollState;
 use axsync::Mutex;
+use axtask::WaitQueue;
 use linux_raw_sys::general::S_IFIFO;
 
 use super::{FileLike, Kstat};
+use crate::signal::have_signals;
 
 #[derive(Copy, Clone, PartialEq)]
 enum RingBufferStatus {
@@ -74,21 +79,52 @@ impl PipeRingBuffer {
     }
 }
 
+/// The state shared by the two ends of a pipe.
+struct PipeInner {
+    buffer: Mutex<PipeRingBuffer>,
+    /// Whether one of the ends was closed
+    closed: AtomicBool,
+    /// The readers and writers waiting for the other end
+    wq: WaitQueue,
+    /// The number of times the buffer changed or an end was closed
+    changes: AtomicU64,
+}
+
+impl PipeInner {
+    /// Wake up the other end after the buffer changed or an end was closed.
+    fn notify(&self) {
+        self.changes.fetch_add(1, Ordering::AcqRel);
+        self.wq.notify_all(false);
+    }
+
+    /// Wait until the buffer changes or an end is closed, after `changes`
+    /// was read, or a signal is sent to the current thread.
+    fn wait(&self, changes: u64) {
+        self.wq
+            .wait_until_interruptible(|| self.changes.load(Ordering::Acquire) != changes);
+    }
+}
+
 pub struct Pipe {
     readable: bool,
-    buffer: Arc<Mutex<PipeRingBuffer>>,
+    inner: Arc<PipeInner>,
 }
 
 impl Pipe {
     pub fn new() -> (Pipe, Pipe) {
-        let buffer = Arc::new(Mutex::new(PipeRingBuffer::new()));
+        let inner = Arc::new(PipeInner {
+            buffer: Mutex::new(PipeRingBuffer::new()),
+            closed: AtomicBool::new(false),
+            wq: WaitQueue::new(),
+            changes: AtomicU64::new(0),
+        });
         let read_end = Pipe {
             readable: true,
-            buffer: buffer.clone(),
+            inner: inner.clone(),
         };
         let write_end = Pipe {
             readable: false,
-            buffer,
+            inner,
         };
         (read_end, write_end)
     }
@@ -102,7 +138,14 @@ impl Pipe {
     }
 
     pub fn closed(&self) -> bool {
-        Arc::strong_count(&self.buffer) == 1
+        self.inner.closed.load(Ordering::Acquire)
+    }
+}
+
+impl Drop for Pipe {
+    fn drop(&mut self) {
+        self.inner.closed.store(true, Ordering::Release);
+        self.inner.notify();
     }
 }
 
@@ -116,20 +159,26 @@ impl FileLike for Pipe {
         }
 
         loop {
-            let mut ring_buffer = self.buffer.lock();
+            let changes = self.inner.changes.load(Ordering::Acquire);
+            let mut ring_buffer = self.inner.buffer.lock();
             let read_size = ring_buffer.available_read().min(buf.len());
             if read_size == 0 {
                 if self.closed() {
                     return Ok(0);
                 }
+                if have_signals() {
+                    return Err(LinuxError::EINTR);
+                }
                 drop(ring_buffer);
                 // Data not ready, wait for write end
-                axtask::yield_now(); // TODO: use synconize primitive
+                self.inner.wait(changes);
                 continue;
             }
             for c in buf.iter_mut().take(read_size) {
                 *c = ring_buffer.read_byte();
             }
+            drop(ring_buffer);
+            self.inner.notify();
             return Ok(read_size);
         }
     }
@@ -148,29 +197,41 @@ impl FileLike for Pipe {
         let mut write_size = 0usize;
         let total_len = buf.len();
         loop {
-            let mut ring_buffer = self.buffer.lock();
+            let changes = self.inner.changes.load(Ordering::Acquire);
+            let mut ring_buffer = self.inner.buffer.lock();
             let loop_write = ring_buffer.available_write();
             if loop_write == 0 {
                 if self.closed() {
                     return Ok(write_size);
                 }
+                if have_signals() {
+                    return if write_size > 0 {
+                        Ok(write_size)
+                    } else {
+                        Err(LinuxError::EINTR)
+                    };
+                }
                 drop(ring_buffer);
                 // Buffer is full, wait for read end to consume
-                axtask::yield_now(); // TODO: use synconize primitive
+                self.inner.wait(changes);
                 continue;
             }
-            for _ in 0..loop_write {
-                if write_size == total_len {
-                    return Ok(write_size);
-                }
+            for _ in 0..loop_write.min(total_len - write_size) {
                 ring_buffer.write_byte(buf[write_size]);
                 write_size += 1;
             }
+            drop(ring_buffer);
+            self.inner.notify();
+            if write_size == total_len {
+                return Ok(write_size);
+            }
         }
     }
 
     fn stat(&self) -> LinuxResult<Kstat> {
         Ok(Kstat {
+            // both ends share the same inode
+            ino: Arc::as_ptr(&self.inner) as usize as u64,
             mode: S_IFIFO | 0o600u32, // rw-------
             ..Default::default()
         })
@@ -181,7 +242,7 @@ impl FileLike for Pipe {
     }
 
     fn poll(&self) -> LinuxResult<PollState> {
-        let buf = self.buffer.lock();
+        let buf = self.inner.buffer.lock();
         Ok(PollState {
             readable: self.readable() && buf.available_read() > 0,
             writable: self.writable() && buf.available_write() > 0,
@@ -0,0 +1,79 @@
+use core::{
+    any::Any,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use alloc::sync::Arc;
+use axerrno::LinuxResult;
+use axio::PollState;
+use linux_raw_sys::general::{O_ACCMODE, O_CLOEXEC, O_NOCTTY, O_NONBLOCK, S_IFCHR};
+
+use super::{FileLike, Kstat, Tty, add_file_like_with_flags};
+use crate::terminal::{PTMX_RDEV, Pty};
+
+const TIOCGPTPEER: u32 = 0x5441;
+
+/// The master side of a pseudo-terminal, opened from `/dev/ptmx`.
+pub struct PtyMaster {
+    pty: Pty,
+    nonblocking: AtomicBool,
+}
+
+impl PtyMaster {
+    pub fn new(pty: Pty) -> Self {
+        Self {
+            pty,
+            nonblocking: AtomicBool::new(false),
+        }
+    }
+
+    /// Open the slave as a new file, as done by `TIOCGPTPEER`.
+    fn open_peer(&self, flags: u32) -> LinuxResult<isize> {
+        let flags = flags & (O_ACCMODE | O_CLOEXEC | O_NOCTTY | O_NONBLOCK);
+        let tty = Tty::new(self.pty.slave().clone())?;
+        tty.set_nonblocking(flags & O_NONBLOCK != 0)?;
+        Ok(add_file_like_with_flags(Arc::new(tty), flags)? as _)
+    }
+}
+
+impl FileLike for PtyMaster {
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        self.pty.read(buf, self.nonblocking.load(Ordering::Acquire))
+    }
+
+    fn write(&self, buf: &[u8]) -> LinuxResult<usize> {
+        self.pty
+            .write(buf, self.nonblocking.load(Ordering::Acquire))
+    }
+
+    fn stat(&self) -> LinuxResult<Kstat> {
+        Ok(Kstat {
+            mode: S_IFCHR | 0o666u32, // rw-rw-rw-
+            rdev: PTMX_RDEV,
+            ..Default::default()
+        })
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+
+    fn poll(&self) -> LinuxResult<PollState> {
+        Ok(PollState {
+            readable: self.pty.readable(),
+            writable: self.pty.writable(),
+        })
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> LinuxResult {
+        self.nonblocking.store(nonblocking, Ordering::Release);
+        Ok(())
+    }
+
+    fn ioctl(&self, op: u32, arg: usize) -> LinuxResult<isize> {
+        if op == TIOCGPTPEER {
+            return self.open_peer(arg as u32);
+        }
+        self.pty.ioctl(op, arg)
+    }
+}
@@ -0,0 +1,212 @@
+use core::{
+    any::Any,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use alloc::sync::Arc;
+use axerrno::{LinuxError, LinuxResult};
+use axio::PollState;
+use axsignal::{SignalInfo, SignalSet, Signo};
+use axsync::Mutex;
+use axtask::{TaskExtRef, current};
+use linux_raw_sys::general::SI_TIMER;
+
+use super::{FileLike, Kstat};
+use crate::signal::{have_signals, wait_for_signal};
+
+/// A signal read from a signalfd, as in `struct signalfd_siginfo`.
+#[repr(C)]
+#[derive(Default)]
+struct SignalfdSiginfo {
+    ssi_signo: u32,
+    ssi_errno: i32,
+    ssi_code: i32,
+    ssi_pid: u32,
+    ssi_uid: u32,
+    ssi_fd: i32,
+    ssi_tid: u32,
+    ssi_band: u32,
+    ssi_overrun: u32,
+    ssi_trapno: u32,
+    ssi_status: i32,
+    ssi_int: i32,
+    ssi_ptr: u64,
+    ssi_utime: u64,
+    ssi_stime: u64,
+    ssi_addr: u64,
+    ssi_addr_lsb: u16,
+    __pad2: u16,
+    ssi_syscall: i32,
+    ssi_call_addr: u64,
+    ssi_arch: u32,
+    __pad: [u8; 28],
+}
+
+impl From<&SignalInfo> for SignalfdSiginfo {
+    fn from(sig: &SignalInfo) -> Self {
+        let signo = sig.signo();
+        let code = sig.code();
+        // SAFETY: the fields read are the ones in use for the signal and its
+        // code, and the rest of `siginfo` is zeroed.
+        unsafe {
+            let info = &sig.0.__bindgen_anon_1.__bindgen_anon_1;
+            let fields = &info._sifields;
+            let mut result = Self {
+                ssi_signo: signo as _,
+                ssi_errno: info.si_errno,
+                ssi_code: code,
+                ..Default::default()
+            };
+            if code == SI_TIMER {
+                result.ssi_tid = fields._timer._tid as _;
+                result.ssi_overrun = fields._timer._overrun as _;
+                result.ssi_int = fields._timer._sigval.sival_int;
+                result.ssi_ptr = fields._timer._sigval.sival_ptr as _;
+                return result;
+            }
+            // The codes of the kernel are positive, and those of the users
+            // are not.
+            match signo {
+                Signo::SIGCHLD if code > 0 => {
+                    let sigchld = &fields._sigchld;
+                    result.ssi_pid = sigchld._pid as _;
+                    result.ssi_uid = sigchld._uid;
+                    result.ssi_status = sigchld._status;
+                    result.ssi_utime = sigchld._utime as _;
+                    result.ssi_stime = sigchld._stime as _;
+                }
+                Signo::SIGSEGV | Signo::SIGBUS | Signo::SIGILL | Signo::SIGFPE | Signo::SIGTRAP
+                    if code > 0 =>
+                {
+                    result.ssi_addr = fields._sigfault._addr as _;
+                }
+                Signo::SIGIO if code > 0 => {
+                    result.ssi_band = fields._sigpoll._band as _;
+                    result.ssi_fd = fields._sigpoll._fd;
+                }
+                _ => {
+                    result.ssi_pid = fields._rt._pid as _;
+                    result.ssi_uid = fields._rt._uid;
+                    result.ssi_int = fields._rt._sigval.sival_int;
+                    result.ssi_ptr = fields._rt._sigval.sival_ptr as _;
+                }
+            }
+            result
+        }
+    }
+}
+
+/// A file descriptor receiving signals, created by `signalfd`.
+///
+/// Reading it dequeues the pending signals of the reading thread and its
+/// process which are in its mask. Like Linux, the signals must be blocked,
+/// or they are delivered as usual.
+pub struct SignalFd {
+    mask: Mutex<SignalSet>,
+    nonblocking: AtomicBool,
+}
+
+impl SignalFd {
+    pub fn new(mask: SignalSet) -> Self {
+        Self {
+            mask: Mutex::new(Self::sanitize(mask)),
+            nonblocking: AtomicBool::new(false),
+        }
+    }
+
+    /// Change the signals received through the file descriptor.
+    pub fn set_mask(&self, mask: SignalSet) {
+        *self.mask.lock() = Self::sanitize(mask);
+    }
+
+    fn sanitize(mut mask: SignalSet) -> SignalSet {
+        // `SIGKILL` and `SIGSTOP` cannot be received this way.
+        mask.remove(Signo::SIGKILL);
+        mask.remove(Signo::SIGSTOP);
+        mask
+    }
+
+    /// Dequeue a pending signal in the mask.
+    fn dequeue(&self) -> Option<SignalInfo> {
+        let curr = current();
+        let signal = &curr.task_ext().thread_data().signal;
+        let mask = *self.mask.lock() & signal.blocked();
+        // Do not wait at all if no signal is pending.
+        if signal.pending().dequeue(&mask).is_none() {
+            return None;
+        }
+        signal.wait_timeout(mask, Some(Duration::ZERO))
+    }
+}
+
+impl FileLike for SignalFd {
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        const SIZE: usize = size_of::<SignalfdSiginfo>();
+        if buf.len() < SIZE {
+            return Err(LinuxError::EINVAL);
+        }
+        let first = loop {
+            if let Some(sig) = self.dequeue() {
+                break sig;
+            }
+            if self.nonblocking.load(Ordering::Acquire) {
+                return Err(LinuxError::EAGAIN);
+            }
+            if have_signals() {
+                return Err(LinuxError::EINTR);
+            }
+            // Any signal sent to the thread or its process wakes it up.
+            wait_for_signal();
+        };
+
+        // Read as many of the pending signals as the buffer can hold.
+        let mut read = 0;
+        let mut next = Some(first);
+        while let Some(sig) = next {
+            let info = SignalfdSiginfo::from(&sig);
+            // SAFETY: `SignalfdSiginfo` is plain old data.
+            let bytes =
+                unsafe { core::slice::from_raw_parts(&info as *const _ as *const u8, SIZE) };
+            buf[read..read + SIZE].copy_from_slice(bytes);
+            read += SIZE;
+            next = if buf.len() - read >= SIZE {
+                self.dequeue()
+            } else {
+                None
+            };
+        }
+        Ok(read)
+    }
+
+    fn write(&self, _buf: &[u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EINVAL)
+    }
+
+    fn stat(&self) -> LinuxResult<Kstat> {
+        Ok(Kstat {
+            ino: self as *const _ as usize as u64,
+            mode: 0o600,
+            ..Default::default()
+        })
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+
+    fn poll(&self) -> LinuxResult<PollState> {
+        let curr = current();
+        let signal = &curr.task_ext().thread_data().signal;
+        let mask = *self.mask.lock() & signal.blocked();
+        Ok(PollState {
+            readable: signal.pending().dequeue(&mask).is_some(),
+            writable: false,
+        })
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> LinuxResult {
+        self.nonblocking.store(nonblocking, Ordering::Release);
+        Ok(())
+    }
+}
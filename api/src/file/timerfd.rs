@@ -0,0 +1,232 @@
+use core::{
+    any::Any,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    time::Duration,
+};
+
+use alloc::sync::Arc;
+use axerrno::{LinuxError, LinuxResult};
+use axhal::time::{TimeValue, monotonic_time};
+use axio::PollState;
+use axsync::Mutex;
+use axtask::WaitQueue;
+use starry_core::{
+    itimer::ITimer,
+    realtime::{realtime, realtime_set_count, realtime_set_wait_queue},
+};
+
+use super::{FileLike, Kstat};
+use crate::signal::have_signals;
+
+struct TimerFdState {
+    /// Whether the deadline is a realtime rather than a monotonic time.
+    ///
+    /// Like Linux, only absolute timers of `CLOCK_REALTIME` follow the
+    /// changes of the realtime clock.
+    realtime: bool,
+    interval: Duration,
+    deadline: Option<TimeValue>,
+    /// The expirations not read yet
+    ticks: u64,
+    /// The realtime set count when the timer was armed, with
+    /// `TFD_TIMER_CANCEL_ON_SET`
+    cancel_on_set: Option<u64>,
+}
+
+impl TimerFdState {
+    fn now(&self) -> TimeValue {
+        if self.realtime {
+            realtime()
+        } else {
+            monotonic_time()
+        }
+    }
+
+    /// Count the expirations until now, and reload the interval.
+    fn update(&mut self) {
+        let Some(deadline) = self.deadline else {
+            return;
+        };
+        let now = self.now();
+        if now < deadline {
+            return;
+        }
+        if self.interval.is_zero() {
+            self.ticks += 1;
+            self.deadline = None;
+        } else {
+            let count = (now - deadline).as_nanos() / self.interval.as_nanos() + 1;
+            self.ticks += count as u64;
+            self.deadline = Some(deadline + self.interval * count as u32);
+        }
+    }
+
+    /// Whether the realtime clock was set since the timer was armed with
+    /// `TFD_TIMER_CANCEL_ON_SET`.
+    fn canceled(&self) -> bool {
+        self.cancel_on_set
+            .is_some_and(|count| count != realtime_set_count())
+    }
+
+    fn get(&self) -> ITimer {
+        ITimer {
+            interval: self.interval,
+            value: self.deadline.map_or(Duration::ZERO, |deadline| {
+                deadline.saturating_sub(self.now())
+            }),
+        }
+    }
+}
+
+/// A timer notifying its expirations through a file descriptor, created by
+/// `timerfd_create`.
+pub struct TimerFd {
+    /// Whether the timer measures `CLOCK_REALTIME` rather than a monotonic
+    /// clock
+    realtime_clock: bool,
+    state: Mutex<TimerFdState>,
+    /// The readers waiting for the timer to expire
+    wq: WaitQueue,
+    /// The number of times the timer was set
+    generation: AtomicU64,
+    nonblocking: AtomicBool,
+}
+
+impl TimerFd {
+    pub fn new(realtime_clock: bool) -> Self {
+        Self {
+            realtime_clock,
+            state: Mutex::new(TimerFdState {
+                realtime: false,
+                interval: Duration::ZERO,
+                deadline: None,
+                ticks: 0,
+                cancel_on_set: None,
+            }),
+            wq: WaitQueue::new(),
+            generation: AtomicU64::new(0),
+            nonblocking: AtomicBool::new(false),
+        }
+    }
+
+    /// The current setting of the timer, with the time until it expires.
+    pub fn get(&self) -> ITimer {
+        let mut state = self.state.lock();
+        state.update();
+        state.get()
+    }
+
+    /// Arm the timer with `timer`, or disarm it if its value is zero, and
+    /// return the previous setting.
+    ///
+    /// If `absolute` is set, the value is the time of the clock of the timer
+    /// at which it expires. `cancel_on_set` makes reads fail with
+    /// `ECANCELED` once the realtime clock is set, for absolute realtime
+    /// timers.
+    pub fn set(&self, timer: ITimer, absolute: bool, cancel_on_set: bool) -> ITimer {
+        let mut state = self.state.lock();
+        state.update();
+        let old = state.get();
+        let was_realtime = state.realtime;
+
+        let realtime = self.realtime_clock && absolute;
+        state.realtime = realtime;
+        state.interval = timer.interval;
+        state.deadline = (!timer.value.is_zero()).then(|| {
+            if absolute {
+                timer.value
+            } else {
+                state.now() + timer.value
+            }
+        });
+        state.ticks = 0;
+        state.cancel_on_set = (realtime && cancel_on_set).then(realtime_set_count);
+        drop(state);
+        self.generation.fetch_add(1, Ordering::Release);
+        self.wq.notify_all(false);
+        if was_realtime {
+            realtime_set_wait_queue().notify_all(false);
+        }
+        old
+    }
+}
+
+impl FileLike for TimerFd {
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        if buf.len() < size_of::<u64>() {
+            return Err(LinuxError::EINVAL);
+        }
+        loop {
+            let sets = realtime_set_count();
+            let generation = self.generation.load(Ordering::Acquire);
+            let mut state = self.state.lock();
+            if state.canceled() {
+                // Each setting of the clock is reported once.
+                state.ticks = 0;
+                state.cancel_on_set = Some(realtime_set_count());
+                return Err(LinuxError::ECANCELED);
+            }
+            state.update();
+            if state.ticks > 0 {
+                buf[..size_of::<u64>()].copy_from_slice(&state.ticks.to_ne_bytes());
+                state.ticks = 0;
+                return Ok(size_of::<u64>());
+            }
+            if self.nonblocking.load(Ordering::Acquire) {
+                return Err(LinuxError::EAGAIN);
+            }
+            if have_signals() {
+                return Err(LinuxError::EINTR);
+            }
+            // The realtime clock may be set while waiting, which moves the
+            // deadlines of realtime timers.
+            let wq = if state.realtime {
+                realtime_set_wait_queue()
+            } else {
+                &self.wq
+            };
+            let timeout = state
+                .deadline
+                .map(|deadline| deadline.saturating_sub(state.now()));
+            drop(state);
+            let changed = || {
+                self.generation.load(Ordering::Acquire) != generation
+                    || realtime_set_count() != sets
+            };
+            match timeout {
+                Some(timeout) => wq.wait_timeout_until_interruptible(timeout, changed),
+                None => wq.wait_until_interruptible(changed),
+            };
+        }
+    }
+
+    fn write(&self, _buf: &[u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EINVAL)
+    }
+
+    fn stat(&self) -> LinuxResult<Kstat> {
+        Ok(Kstat {
+            ino: self as *const _ as usize as u64,
+            mode: 0o600,
+            ..Default::default()
+        })
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+
+    fn poll(&self) -> LinuxResult<PollState> {
+        let mut state = self.state.lock();
+        state.update();
+        Ok(PollState {
+            readable: state.ticks > 0 || state.canceled(),
+            writable: false,
+        })
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> LinuxResult {
+        self.nonblocking.store(nonblocking, Ordering::Release);
+        Ok(())
+    }
+}
@@ -0,0 +1,79 @@
+use core::{
+    any::Any,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use alloc::sync::Arc;
+use axerrno::LinuxResult;
+use axio::PollState;
+use linux_raw_sys::general::S_IFCHR;
+
+use super::{FileLike, Kstat};
+use crate::terminal::Terminal;
+
+/// An opened terminal, such as the standard streams or `/dev/tty`.
+pub struct Tty {
+    terminal: Arc<Terminal>,
+    nonblocking: AtomicBool,
+}
+
+impl Tty {
+    /// Open `terminal`.
+    pub fn new(terminal: Arc<Terminal>) -> LinuxResult<Self> {
+        terminal.open()?;
+        Ok(Self {
+            terminal,
+            nonblocking: AtomicBool::new(false),
+        })
+    }
+}
+
+impl Drop for Tty {
+    fn drop(&mut self) {
+        self.terminal.release();
+    }
+}
+
+impl FileLike for Tty {
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        self.terminal
+            .read(buf, self.nonblocking.load(Ordering::Acquire))
+    }
+
+    fn write(&self, buf: &[u8]) -> LinuxResult<usize> {
+        self.terminal
+            .write(buf, self.nonblocking.load(Ordering::Acquire))
+    }
+
+    fn stat(&self) -> LinuxResult<Kstat> {
+        Ok(Kstat {
+            mode: S_IFCHR | 0o620u32, // rw--w----
+            rdev: self.terminal.rdev(),
+            ..Default::default()
+        })
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+
+    fn poll(&self) -> LinuxResult<PollState> {
+        Ok(PollState {
+            readable: self.terminal.readable(),
+            writable: true,
+        })
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> LinuxResult {
+        self.nonblocking.store(nonblocking, Ordering::Release);
+        Ok(())
+    }
+
+    fn terminal(&self) -> Option<Arc<Terminal>> {
+        Some(self.terminal.clone())
+    }
+
+    fn ioctl(&self, op: u32, arg: usize) -> LinuxResult<isize> {
+        self.terminal.ioctl(op, arg)
+    }
+}
@@ -7,27 +7,20 @@ use alloc::ffi::CString;
 use axerrno::{LinuxError, LinuxResult};
 use axfs::fops::DirEntry;
 use linux_raw_sys::general::{
-    AT_FDCWD, AT_REMOVEDIR, DT_BLK, DT_CHR, DT_DIR, DT_FIFO, DT_LNK, DT_REG, DT_SOCK, DT_UNKNOWN,
-    linux_dirent64,
+    AT_EMPTY_PATH, AT_FDCWD, AT_REMOVEDIR, AT_SYMLINK_FOLLOW, DT_BLK, DT_CHR, DT_DIR, DT_FIFO,
+    DT_LNK, DT_REG, DT_SOCK, DT_UNKNOWN, O_NONBLOCK, linux_dirent64,
 };
 
 use crate::{
-    file::{Directory, FileLike},
-    path::{HARDLINK_MANAGER, handle_file_path},
+    file::{Directory, FD_TABLE, FileLike, get_open_file},
+    path::{PathFlags, handle_file_path, resolve_path_with_flags},
     ptr::{UserConstPtr, UserPtr, nullable},
 };
 
-use axtask::TaskExtRef;
-use axtask::current;
-
-// Terminal control constants
-const TCGETS: usize = 0x5401;
-const TCSETS: usize = 0x5402;
-const TCSETSW: usize = 0x5403;
-const TCSETSF: usize = 0x5404;
-const TIOCGPGRP: usize = 0x540F;
-const TIOCSPGRP: usize = 0x5410;
-const TIOCGWINSZ: usize = 0x5413;
+// Generic file ioctl constants
+const FIONCLEX: usize = 0x5450;
+const FIOCLEX: usize = 0x5451;
+const FIONBIO: usize = 0x5421;
 
 /// The ioctl() system call manipulates the underlying device parameters
 /// of special files.
@@ -41,62 +34,28 @@ pub fn sys_ioctl(fd: i32, op: usize, argp: UserPtr<c_void>) -> LinuxResult<isize
     debug!("sys_ioctl <= fd: {}, op: 0x{:x}", fd, op);
 
     // 获取文件描述符
-    let current = current();
-    // let file = get_file_like(fd as _)
-    //     .map_err(|_| LinuxError::EBADF)?;
+    let file = get_open_file(fd)?;
 
-    // 检查是否是 tty 设备
     match op {
-        TCGETS | TCSETS | TCSETSW | TCSETSF => {
-            // 对于 tty 设备的终端控制操作，我们简单返回成功
-            // 这里可以实现更复杂的终端属性设置
-            debug!("Terminal control ioctl: 0x{:x}", op);
-            Ok(0)
-        }
-        TIOCGPGRP => {
-            // 获取前台进程组ID
-            debug!("Get foreground process group");
-            // 对于简化实现，返回当前进程的进程组ID
-            // 这样可以避免 SIGTTIN 信号的发送
-            let pgid = current.task_ext().thread.process().group().pgid();
-            if !argp.is_null() {
-                let argp_mut = argp.address().as_mut_ptr() as *mut i32;
-                unsafe { *argp_mut = pgid as i32 };
-            }
-            Ok(0)
-        }
-        TIOCSPGRP => {
-            // 设置前台进程组ID
-            debug!("Set foreground process group");
-            // 对于简化实现，我们忽略这个操作
+        FIONBIO => {
+            // Like `F_SETFL`, the flag is set in the open file description.
+            let nonblocking =
+                *UserConstPtr::<c_int>::from(argp.address().as_usize()).get_as_ref()? != 0;
+            let flags = file.flags();
+            file.set_status_flags(if nonblocking {
+                flags | O_NONBLOCK
+            } else {
+                flags & !O_NONBLOCK
+            })?;
             Ok(0)
         }
-        TIOCGWINSZ => {
-            // 获取终端窗口大小
-            debug!("Get window size");
-            // 返回默认的窗口大小
-            #[repr(C)]
-            struct Winsize {
-                ws_row: u16,
-                ws_col: u16,
-                ws_xpixel: u16,
-                ws_ypixel: u16,
-            }
-            if !argp.is_null() {
-                // let winsize = argp.cast::<Winsize>().get_as_mut()?;
-                let winsize_ptr = argp.address().as_mut_ptr() as *mut Winsize;
-                let winsize = unsafe { &mut *winsize_ptr };
-                winsize.ws_row = 24; // 默认24行
-                winsize.ws_col = 80; // 默认80列
-                winsize.ws_xpixel = 0;
-                winsize.ws_ypixel = 0;
-            }
+        FIOCLEX | FIONCLEX => {
+            let mut table = FD_TABLE.write();
+            let fd = table.get_mut(fd as usize).ok_or(LinuxError::EBADF)?;
+            fd.cloexec = op == FIOCLEX;
             Ok(0)
         }
-        _ => {
-            debug!("Unsupported ioctl operation: 0x{:x}", op);
-            Ok(0) // 对于不支持的操作，返回成功以避免程序崩溃
-        }
+        _ => file.file().ioctl(op as u32, argp.address().as_usize()),
     }
 }
 
@@ -104,7 +63,8 @@ pub fn sys_chdir(path: UserConstPtr<c_char>) -> LinuxResult<isize> {
     let path = path.get_as_str()?;
     debug!("sys_chdir <= {:?}", path);
 
-    axfs::api::set_current_dir(path)?;
+    let path = resolve_path_with_flags(AT_FDCWD, path, PathFlags::new())?;
+    axfs::api::set_current_dir(path.as_str())?;
     Ok(0)
 }
 
@@ -142,9 +102,13 @@ pub enum FileType {
 impl From<axfs::api::FileType> for FileType {
     fn from(ft: axfs::api::FileType) -> Self {
         match ft {
-            ft if ft.is_dir() => FileType::Dir,
-            ft if ft.is_file() => FileType::Reg,
-            _ => FileType::Unknown,
+            axfs::api::FileType::Fifo => FileType::Fifo,
+            axfs::api::FileType::CharDevice => FileType::Chr,
+            axfs::api::FileType::Dir => FileType::Dir,
+            axfs::api::FileType::BlockDevice => FileType::Blk,
+            axfs::api::FileType::File => FileType::Reg,
+            axfs::api::FileType::SymLink => FileType::Lnk,
+            axfs::api::FileType::Socket => FileType::Socket,
         }
     }
 }
@@ -256,16 +220,20 @@ pub fn sys_linkat(
         old_dirfd, old_path, new_dirfd, new_path, flags
     );
 
-    if flags != 0 {
-        warn!("Unsupported flags: {flags}");
+    let flags = flags as u32;
+    if flags & !(AT_SYMLINK_FOLLOW | AT_EMPTY_PATH) != 0 {
+        return Err(LinuxError::EINVAL);
     }
 
     // handle old path
-    let old_path = handle_file_path(old_dirfd, old_path)?;
+    let old_path = resolve_path_with_flags(old_dirfd, old_path, PathFlags::from_link_flags(flags))?;
     // handle new path
     let new_path = handle_file_path(new_dirfd, new_path)?;
 
-    HARDLINK_MANAGER.create_link(&new_path, &old_path)?;
+    if !axfs::api::same_filesystem(old_path.as_str(), new_path.as_str())? {
+        return Err(LinuxError::EXDEV);
+    }
+    axfs::api::hard_link(old_path.as_str(), new_path.as_str())?;
 
     Ok(0)
 }
@@ -299,9 +267,7 @@ pub fn sys_unlinkat(dirfd: c_int, path: UserConstPtr<c_char>, flags: u32) -> Lin
             return Err(LinuxError::EISDIR);
         } else {
             debug!("unlink file: {:?}", path);
-            HARDLINK_MANAGER
-                .remove_link(&path)
-                .ok_or(LinuxError::ENOENT)?;
+            axfs::api::remove_file(path.as_str())?;
         }
     }
     Ok(0)
@@ -0,0 +1,16 @@
+use axerrno::{LinuxError, LinuxResult};
+use linux_raw_sys::general::{EFD_CLOEXEC, EFD_NONBLOCK, EFD_SEMAPHORE, O_RDWR};
+
+use crate::file::{EventFd, FileLike};
+
+pub fn sys_eventfd2(initval: u32, flags: u32) -> LinuxResult<isize> {
+    debug!("sys_eventfd2 <= initval: {}, flags: {:#x}", initval, flags);
+    if flags & !(EFD_CLOEXEC | EFD_NONBLOCK | EFD_SEMAPHORE) != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let eventfd = EventFd::new(initval as _, flags & EFD_SEMAPHORE != 0);
+    eventfd.set_nonblocking(flags & EFD_NONBLOCK != 0)?;
+    eventfd
+        .add_to_fd_table_with_flags(O_RDWR | (flags & (EFD_CLOEXEC | EFD_NONBLOCK)))
+        .map(|fd| fd as _)
+}
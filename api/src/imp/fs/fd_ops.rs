@@ -3,17 +3,29 @@ use core::{
     panic,
 };
 
-use alloc::{string::ToString, format};
+use alloc::string::ToString;
 use axerrno::{AxError, LinuxError, LinuxResult};
 use axfs::fops::OpenOptions;
+use axhal::time::TimeValue;
 use linux_raw_sys::general::{
-    __kernel_mode_t, AT_FDCWD, F_DUPFD, F_DUPFD_CLOEXEC, F_GETFL, F_SETFL, O_APPEND, O_CREAT, O_DIRECTORY, O_NONBLOCK, O_PATH, O_RDONLY, O_TRUNC, O_WRONLY, __kernel_timespec
+    __kernel_mode_t, __kernel_timespec, AT_EMPTY_PATH, AT_FDCWD, AT_SYMLINK_NOFOLLOW, F_DUPFD,
+    F_DUPFD_CLOEXEC, F_GETFD, F_GETFL, F_GETLK, F_OFD_GETLK, F_OFD_SETLK, F_OFD_SETLKW, F_SETFD,
+    F_SETFL, F_SETLK, F_SETLKW, FD_CLOEXEC, O_APPEND, O_CLOEXEC, O_CREAT, O_DIRECTORY, O_NOFOLLOW,
+    O_PATH, O_RDONLY, O_TMPFILE, O_TRUNC, O_WRONLY, RESOLVE_BENEATH, RESOLVE_IN_ROOT,
+    RESOLVE_NO_MAGICLINKS, RESOLVE_NO_SYMLINKS, RESOLVE_NO_XDEV, UTIME_NOW, UTIME_OMIT, flock,
+    open_how,
 };
+use memory_addr::PAGE_SIZE_4K;
 
 use crate::{
-    file::{Directory, FD_TABLE, File, FileLike, add_file_like, close_file_like, get_file_like},
+    file::{
+        AX_FILE_LIMIT, Directory, FD_TABLE, File, FileDescriptor, FileLike, add_file_descriptor,
+        add_file_like_with_flags, apply_flock, close_file_like, get_file_descriptor, get_file_like,
+        get_open_file, get_record_lock, set_record_lock,
+    },
     path::{resolve_path_with_flags, PathFlags},
-    ptr::{UserConstPtr, UserPtr},
+    ptr::{UserConstPtr, UserPtr, nullable},
+    time::TimeValueLike,
 };
 
 const O_EXEC: u32 = O_PATH;
@@ -49,20 +61,14 @@ fn flags_to_options(flags: c_int, _mode: __kernel_mode_t) -> OpenOptions {
     options
 }
 
-/// Open or create a file.
-/// fd: file descriptor
-/// filename: file path to be opened or created
-/// flags: open flags
-/// mode: see man 7 inode
-/// return new file descriptor if succeed, or return -1.
-pub fn sys_openat(
+fn do_openat(
     dirfd: c_int,
-    path: UserConstPtr<c_char>,
-    flags: i32,
+    path: &str,
+    flags: u32,
     mode: __kernel_mode_t,
+    resolve: u32,
 ) -> LinuxResult<isize> {
-    let path = path.get_as_str()?;
-    let opts = flags_to_options(flags, mode);
+    let opts = flags_to_options(flags as _, mode);
     debug!("sys_openat <= {} {} {:?}", dirfd, path, opts);
 
     let dir = if path.starts_with('/') || dirfd == AT_FDCWD {
@@ -70,7 +76,14 @@ pub fn sys_openat(
     } else {
         Some(Directory::from_fd(dirfd)?)
     };
-    let real_path = resolve_path_with_flags(dirfd, path, PathFlags::new())?;
+    let path_flags = PathFlags::from_open_flags(flags).with_resolve(resolve);
+    let real_path = resolve_path_with_flags(dirfd, path, path_flags)?;
+    if flags & O_NOFOLLOW != 0
+        && flags & O_PATH == 0
+        && axfs::api::is_symlink(real_path.as_str()).unwrap_or(false)
+    {
+        return Err(LinuxError::ELOOP);
+    }
 
     if !opts.has_directory() {
         match dir.as_ref().map_or_else(
@@ -79,7 +92,11 @@ pub fn sys_openat(
         ) {
             Err(AxError::IsADirectory) => {}
             r => {
-                let fd = File::new(r?, real_path.to_string()).add_to_fd_table()?;
+                let file = File::new(r?, real_path.to_string());
+                let fd = match file.open_terminal(flags)? {
+                    Some(terminal) => add_file_like_with_flags(terminal, flags)?,
+                    None => file.add_to_fd_table_with_flags(flags)?,
+                };
                 return Ok(fd as _);
             }
         }
@@ -92,10 +109,74 @@ pub fn sys_openat(
         )?,
         real_path.to_string(),
     )
-    .add_to_fd_table()?;
+    .add_to_fd_table_with_flags(flags)?;
     Ok(fd as _)
 }
 
+/// Open or create a file.
+/// fd: file descriptor
+/// filename: file path to be opened or created
+/// flags: open flags
+/// mode: see man 7 inode
+/// return new file descriptor if succeed, or return -1.
+pub fn sys_openat(
+    dirfd: c_int,
+    path: UserConstPtr<c_char>,
+    flags: i32,
+    mode: __kernel_mode_t,
+) -> LinuxResult<isize> {
+    let path = path.get_as_str()?;
+    do_openat(dirfd, path, flags as u32, mode, 0)
+}
+
+/// Open a file like `openat`, with extended resolution flags in `how`.
+pub fn sys_openat2(
+    dirfd: c_int,
+    path: UserConstPtr<c_char>,
+    how: UserConstPtr<open_how>,
+    size: usize,
+) -> LinuxResult<isize> {
+    let path = path.get_as_str()?;
+    if size < size_of::<open_how>() {
+        return Err(LinuxError::EINVAL);
+    }
+    if size > PAGE_SIZE_4K {
+        return Err(LinuxError::E2BIG);
+    }
+    // The fields of newer versions of the structure must be zero.
+    let bytes = UserConstPtr::<u8>::from(how.address().as_usize()).get_as_slice(size)?;
+    if bytes[size_of::<open_how>()..].iter().any(|&it| it != 0) {
+        return Err(LinuxError::E2BIG);
+    }
+    let how = how.get_as_ref()?;
+    debug!(
+        "sys_openat2 <= flags: {:#x}, mode: {:o}, resolve: {:#x}",
+        how.flags, how.mode, how.resolve
+    );
+
+    const SUPPORTED_RESOLVE: u64 = (RESOLVE_NO_XDEV
+        | RESOLVE_NO_MAGICLINKS
+        | RESOLVE_NO_SYMLINKS
+        | RESOLVE_BENEATH
+        | RESOLVE_IN_ROOT) as u64;
+    if how.flags > u32::MAX as u64
+        || how.resolve & !SUPPORTED_RESOLVE != 0
+        || how.resolve & (RESOLVE_BENEATH | RESOLVE_IN_ROOT) as u64
+            == (RESOLVE_BENEATH | RESOLVE_IN_ROOT) as u64
+        || (how.flags as u32 & (O_CREAT | O_TMPFILE) == 0 && how.mode != 0)
+    {
+        return Err(LinuxError::EINVAL);
+    }
+
+    do_openat(
+        dirfd,
+        path,
+        how.flags as u32,
+        how.mode as _,
+        how.resolve as u32,
+    )
+}
+
 /// Open a file by `filename` and insert it into the file descriptor table.
 ///
 /// Return its index in the file table (`fd`). Return `EMFILE` if it already
@@ -114,65 +195,106 @@ pub fn sys_close(fd: c_int) -> LinuxResult<isize> {
     Ok(0)
 }
 
-fn dup_fd(old_fd: c_int) -> LinuxResult<isize> {
-    let f = get_file_like(old_fd)?;
-    let new_fd = add_file_like(f)?;
+/// Duplicate `old_fd` to the lowest available descriptor not less than
+/// `min_fd`. The new descriptor shares the open file description.
+fn dup_fd(old_fd: c_int, min_fd: usize, cloexec: bool) -> LinuxResult<isize> {
+    let f = FileDescriptor {
+        cloexec,
+        ..get_file_descriptor(old_fd)?
+    };
+    let new_fd = add_file_descriptor(f, min_fd)?;
     Ok(new_fd as _)
 }
 
 pub fn sys_dup(old_fd: c_int) -> LinuxResult<isize> {
     debug!("sys_dup <= {}", old_fd);
-    dup_fd(old_fd)
+    dup_fd(old_fd, 0, false)
 }
 
-pub fn sys_dup2(old_fd: c_int, new_fd: c_int) -> LinuxResult<isize> {
-    debug!("sys_dup2 <= old_fd: {}, new_fd: {}", old_fd, new_fd);
+fn dup_to(old_fd: c_int, new_fd: c_int, cloexec: bool) -> LinuxResult<isize> {
+    if new_fd < 0 || new_fd as usize >= AX_FILE_LIMIT {
+        return Err(LinuxError::EBADF);
+    }
     let mut fd_table = FD_TABLE.write();
-    let f = fd_table
-        .get(old_fd as _)
-        .cloned()
-        .ok_or(LinuxError::EBADF)?;
-
-    if old_fd != new_fd {
-        fd_table.remove(new_fd as _);
-        fd_table
-            .add_at(new_fd as _, f)
-            .unwrap_or_else(|_| panic!("new_fd should be valid"));
+    let f = FileDescriptor {
+        cloexec,
+        ..fd_table
+            .get(old_fd as _)
+            .cloned()
+            .ok_or(LinuxError::EBADF)?
+    };
+    let replaced = fd_table.remove(new_fd as _);
+    fd_table
+        .add_at(new_fd as _, f)
+        .unwrap_or_else(|_| panic!("new_fd should be valid"));
+    drop(fd_table);
+    if let Some(replaced) = replaced {
+        replaced.close();
     }
 
     Ok(new_fd as _)
 }
 
+pub fn sys_dup2(old_fd: c_int, new_fd: c_int) -> LinuxResult<isize> {
+    debug!("sys_dup2 <= old_fd: {}, new_fd: {}", old_fd, new_fd);
+    if old_fd == new_fd {
+        // dup2 does nothing but checks the validity of `old_fd`
+        get_file_descriptor(old_fd)?;
+        return Ok(new_fd as _);
+    }
+    dup_to(old_fd, new_fd, false)
+}
+
+pub fn sys_dup3(old_fd: c_int, new_fd: c_int, flags: c_int) -> LinuxResult<isize> {
+    debug!(
+        "sys_dup3 <= old_fd: {}, new_fd: {}, flags: {:#x}",
+        old_fd, new_fd, flags
+    );
+    let flags = flags as u32;
+    if flags & !O_CLOEXEC != 0 || old_fd == new_fd {
+        return Err(LinuxError::EINVAL);
+    }
+    dup_to(old_fd, new_fd, flags & O_CLOEXEC != 0)
+}
+
 pub fn sys_fcntl(fd: c_int, cmd: c_int, arg: usize) -> LinuxResult<isize> {
     debug!("sys_fcntl <= fd: {} cmd: {} arg: {}", fd, cmd, arg);
 
     match cmd as u32 {
-        F_DUPFD => dup_fd(fd),
-        F_DUPFD_CLOEXEC => {
-            warn!("sys_fcntl: treat F_DUPFD_CLOEXEC as F_DUPFD");
-            dup_fd(fd)
-        }
-        F_GETFL => {
-            // 获取文件状态标志
-            // 对于简单实现，返回基本的读写标志
-            if fd == 0 || fd == 1 || fd == 2 {
-                // 标准输入/输出/错误流
-                match fd {
-                    0 => Ok(O_RDONLY as isize), // 标准输入只读
-                    1 | 2 => Ok(O_WRONLY as isize), // 标准输出/错误只写
-                    _ => unreachable!(),
-                }
-            } else {
-                // 对于普通文件，返回读写标志
-                // 这里可以根据实际的文件打开模式返回更精确的标志
-                Ok((O_RDONLY | O_WRONLY) as isize) // 简化为读写
+        F_DUPFD | F_DUPFD_CLOEXEC => {
+            if arg >= AX_FILE_LIMIT {
+                return Err(LinuxError::EINVAL);
             }
+            dup_fd(fd, arg, cmd as u32 == F_DUPFD_CLOEXEC)
         }
+        F_GETFD => {
+            let cloexec = get_file_descriptor(fd)?.cloexec;
+            Ok(if cloexec { FD_CLOEXEC as _ } else { 0 })
+        }
+        F_SETFD => {
+            let mut fd_table = FD_TABLE.write();
+            let f = fd_table.get_mut(fd as _).ok_or(LinuxError::EBADF)?;
+            f.cloexec = arg & FD_CLOEXEC as usize != 0;
+            Ok(0)
+        }
+        F_GETFL => Ok(get_file_descriptor(fd)?.file.flags() as _),
         F_SETFL => {
-            if fd == 0 || fd == 1 || fd == 2 {
-                return Ok(0);
-            }
-            get_file_like(fd)?.set_nonblocking(arg & (O_NONBLOCK as usize) > 0)?;
+            get_file_descriptor(fd)?.file.set_status_flags(arg as u32)?;
+            Ok(0)
+        }
+        F_GETLK | F_OFD_GETLK => {
+            let file = get_open_file(fd)?;
+            let lock = UserPtr::<flock>::from(arg).get_as_mut()?;
+            get_record_lock(&file, cmd as u32 == F_OFD_GETLK, lock)?;
+            Ok(0)
+        }
+        F_SETLK | F_SETLKW | F_OFD_SETLK | F_OFD_SETLKW => {
+            let cmd = cmd as u32;
+            let file = get_open_file(fd)?;
+            let lock = UserPtr::<flock>::from(arg).get_as_mut()?;
+            let ofd = cmd == F_OFD_SETLK || cmd == F_OFD_SETLKW;
+            let wait = cmd == F_SETLKW || cmd == F_OFD_SETLKW;
+            set_record_lock(&file, ofd, lock, wait)?;
             Ok(0)
         }
         _ => {
@@ -182,6 +304,12 @@ pub fn sys_fcntl(fd: c_int, cmd: c_int, arg: usize) -> LinuxResult<isize> {
     }
 }
 
+pub fn sys_flock(fd: c_int, operation: c_int) -> LinuxResult<isize> {
+    debug!("sys_flock <= fd: {}, operation: {:#x}", fd, operation);
+    apply_flock(&get_open_file(fd)?, operation as u32)?;
+    Ok(0)
+}
+
 pub fn sys_fchmodat(
     dirfd: c_int,
     path: UserConstPtr<c_char>,
@@ -213,8 +341,8 @@ pub fn sys_renameat2(
         old_dirfd, old_path, new_dirfd, new_path, flags
     );
 
-    let old_binding = resolve_path_with_flags(old_dirfd, old_path, PathFlags::new())?;
-    let new_binding = resolve_path_with_flags(new_dirfd, new_path, PathFlags::new())?;
+    let old_binding = resolve_path_with_flags(old_dirfd, old_path, PathFlags::no_follow())?;
+    let new_binding = resolve_path_with_flags(new_dirfd, new_path, PathFlags::no_follow())?;
 
     let flags = flags as u32;
 
@@ -234,68 +362,71 @@ pub fn sys_renameat2(
     Ok(0)
 }
 
+/// Convert a timestamp of `utimensat` to the time to set, handling the
+/// special values `UTIME_NOW` and `UTIME_OMIT`.
+fn utime_to_time_value(time: __kernel_timespec) -> LinuxResult<Option<TimeValue>> {
+    match time.tv_nsec as u32 {
+        UTIME_NOW => Ok(Some(starry_core::realtime::realtime())),
+        UTIME_OMIT => Ok(None),
+        _ if time.tv_sec < 0 || !(0..1_000_000_000).contains(&time.tv_nsec) => {
+            Err(LinuxError::EINVAL)
+        }
+        _ => Ok(Some(time.to_time_value())),
+    }
+}
+
+/// Change the access and modification times of a file.
+///
+/// If `times` is null, both times are set to the current time. If `path` is
+/// null, the times of the file referred to by `dirfd` are changed, which is
+/// how `futimens` is implemented.
 pub fn sys_utimensat(
     dirfd: c_int,
     path: UserConstPtr<c_char>,
-    _times: UserPtr<__kernel_timespec>,
+    times: UserConstPtr<__kernel_timespec>,
     flags: c_int,
 ) -> LinuxResult<isize> {
-    let path = path.get_as_str()?;
-    debug!("sys_utimensat <= dirfd: {} path: {} flags: {}", dirfd, path, flags);
+    let path = nullable!(path.get_as_str())?;
+    debug!(
+        "sys_utimensat <= dirfd: {} path: {:?} flags: {}",
+        dirfd, path, flags
+    );
 
-    // 对于 utimensat，我们需要手动构建绝对路径，因为文件可能不存在
-    let absolute_path = if path.starts_with('/') {
-        path.to_string()
-    } else {
-        // 处理相对路径
-        if dirfd == AT_FDCWD {
-            // 在 StarryOS 中，我们知道 busybox 是从 /musl/ 目录运行的
-            // 所以相对路径应该相对于 /musl/
-            format!("/musl/{}", path)
-        } else {
-            // 使用指定的目录文件描述符
-            match Directory::from_fd(dirfd) {
-                Ok(dir) => format!("{}/{}", dir.path(), path),
-                Err(_) => return Err(LinuxError::EBADF),
-            }
+    let flags = flags as u32;
+    if flags & !(AT_SYMLINK_NOFOLLOW | AT_EMPTY_PATH) != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+
+    let (atime, mtime) = match nullable!(times.get_as_slice(2))? {
+        Some(times) => (
+            utime_to_time_value(times[0])?,
+            utime_to_time_value(times[1])?,
+        ),
+        None => {
+            let now = starry_core::realtime::realtime();
+            (Some(now), Some(now))
         }
     };
 
-    debug!("utimensat: absolute path: {}", absolute_path);
-
-    // 检查文件是否存在
-    if axfs::api::absolute_path_exists(&absolute_path) {
-        debug!("utimensat: file already exists: {}", absolute_path);
-    } else {
-        debug!("utimensat: file does not exist, attempting to create: {}", absolute_path);
-        
-        // 首先检查是否可以使用 axfs API 创建文件
-        match axfs::api::File::create(&absolute_path) {
-            Ok(_) => {
-                debug!("utimensat: file created successfully with axfs API: {}", absolute_path);
+    match path {
+        Some(path) if !path.is_empty() || flags & AT_EMPTY_PATH == 0 => {
+            let path = resolve_path_with_flags(dirfd, path, PathFlags::from_at_flags(flags))?;
+            if atime.is_some() || mtime.is_some() {
+                axfs::api::set_times(path.as_str(), atime, mtime)?;
             }
-            Err(e) => {
-                debug!("utimensat: failed to create file with axfs API: {:?}, trying fops", e);
-                // 如果 axfs API 失败，尝试使用 fops
-                let mut opts = OpenOptions::new();
-                opts.create(true);
-                opts.write(true);
-                match axfs::fops::File::open(&absolute_path, &opts) {
-                    Ok(_) => {
-                        debug!("utimensat: file created with fops: {}", absolute_path);
-                    }
-                    Err(e) => {
-                        debug!("utimensat: failed to create file with fops: {:?}", e);
-                        return Err(LinuxError::EACCES);
-                    }
-                }
+        }
+        _ => {
+            let f = get_file_like(dirfd)?.into_any();
+            if atime.is_none() && mtime.is_none() {
+                return Ok(0);
+            }
+            // other kinds of files have no timestamps to keep
+            if let Some(file) = f.downcast_ref::<File>() {
+                file.get_inner().set_times(atime, mtime)?;
+            } else if let Some(dir) = f.downcast_ref::<Directory>() {
+                dir.get_inner().set_times(atime, mtime)?;
             }
         }
     }
-
-    // TODO: 实际设置文件的访问和修改时间
-    // 目前只是一个基本实现，足以支持 touch 命令
-    
-    debug!("utimensat: operation completed successfully");
     Ok(0)
 }
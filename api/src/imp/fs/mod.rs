@@ -1,4 +1,5 @@
 mod ctl;
+mod eventfd;
 mod fd_ops;
 mod io;
 mod mount;
@@ -6,6 +7,7 @@ mod pipe;
 mod stat;
 
 pub use self::ctl::*;
+pub use self::eventfd::*;
 pub use self::fd_ops::*;
 pub use self::io::*;
 pub use self::mount::*;
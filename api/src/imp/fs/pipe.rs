@@ -1,6 +1,7 @@
 use core::ffi::c_int;
 
-use axerrno::LinuxResult;
+use axerrno::{LinuxError, LinuxResult};
+use linux_raw_sys::general::{O_CLOEXEC, O_NONBLOCK, O_RDONLY, O_WRONLY};
 
 use crate::{
     file::{FileLike, Pipe, close_file_like},
@@ -8,16 +9,21 @@ use crate::{
 };
 
 pub fn sys_pipe2(fds: UserPtr<[c_int; 2]>, flags: i32) -> LinuxResult<isize> {
-    if flags != 0 {
-        warn!("sys_pipe2: unsupported flags: {}", flags);
+    let flags = flags as u32;
+    if flags & !(O_CLOEXEC | O_NONBLOCK) != 0 {
+        return Err(LinuxError::EINVAL);
     }
 
     let fds = fds.get_as_mut()?;
 
     let (read_end, write_end) = Pipe::new();
-    let read_fd = read_end.add_to_fd_table()?;
+    if flags & O_NONBLOCK != 0 {
+        read_end.set_nonblocking(true)?;
+        write_end.set_nonblocking(true)?;
+    }
+    let read_fd = read_end.add_to_fd_table_with_flags(O_RDONLY | flags)?;
     let write_fd = write_end
-        .add_to_fd_table()
+        .add_to_fd_table_with_flags(O_WRONLY | flags)
         .inspect_err(|_| close_file_like(read_fd).unwrap())?;
 
     fds[0] = read_fd;
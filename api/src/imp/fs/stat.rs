@@ -1,37 +1,26 @@
 use core::ffi::{c_char, c_int};
 
-use axerrno::{AxError, LinuxError, LinuxResult};
-use axfs::fops::OpenOptions;
-use linux_raw_sys::general::{AT_EMPTY_PATH, stat, statx};
+use axerrno::{LinuxError, LinuxResult};
+use linux_raw_sys::general::{
+    AT_EMPTY_PATH, AT_FDCWD, AT_NO_AUTOMOUNT, AT_STATX_SYNC_TYPE, AT_SYMLINK_NOFOLLOW, stat, statx,
+};
 
 use crate::{
-    file::{Directory, File, FileLike, Kstat, get_file_like},
+    file::{Kstat, get_file_like},
     path::{resolve_path_with_flags, PathFlags},
     ptr::{UserConstPtr, UserPtr, nullable},
 };
 
 fn stat_at_path(path: &str) -> LinuxResult<Kstat> {
-    let opts = OpenOptions::new().set_read(true);
-    match axfs::fops::File::open(path, &opts) {
-        Ok(file) => File::new(file, path.into()).stat(),
-        Err(AxError::IsADirectory) => {
-            let dir = axfs::fops::Directory::open_dir(path, &opts)?;
-            Directory::new(dir, path.into()).stat()
-        }
-        Err(e) => Err(e.into()),
-    }
+    let (attr, inode) = axfs::api::stat(path)?;
+    Ok(Kstat::from_attr(&attr, &inode))
 }
 
 /// Get the file metadata by `path` and write into `statbuf`.
 ///
 /// Return 0 if success.
 pub fn sys_stat(path: UserConstPtr<c_char>, statbuf: UserPtr<stat>) -> LinuxResult<isize> {
-    let path = path.get_as_str()?;
-    debug!("sys_stat <= path: {}", path);
-
-    *statbuf.get_as_mut()? = stat_at_path(path)?.into();
-
-    Ok(0)
+    sys_fstatat(AT_FDCWD, path, statbuf, 0)
 }
 
 /// Get file metadata by `fd` and write into `statbuf`.
@@ -47,8 +36,7 @@ pub fn sys_fstat(fd: i32, statbuf: UserPtr<stat>) -> LinuxResult<isize> {
 ///
 /// Return 0 if success.
 pub fn sys_lstat(path: UserConstPtr<c_char>, statbuf: UserPtr<stat>) -> LinuxResult<isize> {
-    // TODO: symlink
-    sys_stat(path, statbuf)
+    sys_fstatat(AT_FDCWD, path, statbuf, AT_SYMLINK_NOFOLLOW)
 }
 
 pub fn sys_fstatat(
@@ -117,6 +105,12 @@ pub fn sys_statx(
         dirfd, path, flags
     );
 
+    if flags & !(AT_EMPTY_PATH | AT_NO_AUTOMOUNT | AT_SYMLINK_NOFOLLOW | AT_STATX_SYNC_TYPE) != 0
+        || flags & AT_STATX_SYNC_TYPE == AT_STATX_SYNC_TYPE
+    {
+        return Err(LinuxError::EINVAL);
+    }
+
     *statxbuf.get_as_mut()? = if path.is_none_or(|s| s.is_empty()) {
         if (flags & AT_EMPTY_PATH) == 0 {
             return Err(LinuxError::ENOENT);
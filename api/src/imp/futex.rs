@@ -1,4 +1,6 @@
+use alloc::boxed::Box;
 use axerrno::{LinuxError, LinuxResult};
+use axhal::time::{TimeValue, monotonic_time};
 use axtask::{TaskExtRef, current};
 use linux_raw_sys::general::{
     FUTEX_CMD_MASK, FUTEX_CMP_REQUEUE, FUTEX_REQUEUE, FUTEX_WAIT, FUTEX_WAKE, timespec,
@@ -6,9 +8,63 @@ use linux_raw_sys::general::{
 
 use crate::{
     ptr::{UserConstPtr, UserPtr, nullable},
+    signal::have_signals,
     time::TimeValueLike,
 };
 
+/// Wait on the futex at `uaddr` as long as it holds `value`, until it is
+/// woken up or the monotonic clock reaches `deadline`.
+///
+/// If a signal interrupts a wait with a deadline, it is continued by
+/// `restart_syscall` until the same deadline, unless the signal runs a
+/// handler.
+fn futex_wait(
+    uaddr: UserConstPtr<u32>,
+    value: u32,
+    deadline: Option<TimeValue>,
+) -> LinuxResult<isize> {
+    let curr = current();
+    let futex_table = &curr.task_ext().process_data().futex_table;
+    let mut wakes = futex_table.wakes();
+    if *uaddr.get_as_ref()? != value {
+        return Err(LinuxError::EAGAIN);
+    }
+    let wq = futex_table.get_or_insert(uaddr.address().as_usize());
+
+    loop {
+        if have_signals() {
+            if let Some(deadline) = deadline {
+                let addr = uaddr.address().as_usize();
+                *curr.task_ext().thread_data().restart_block.lock() = Some(Box::new(move || {
+                    futex_wait(addr.into(), value, Some(deadline))
+                }));
+            }
+            return Err(LinuxError::EINTR);
+        }
+        // Another futex of the process may be woken up instead, which is
+        // reported as a spurious wakeup.
+        let woken = || futex_table.wakes() != wakes;
+        if let Some(deadline) = deadline {
+            let now = monotonic_time();
+            if now >= deadline {
+                return Err(LinuxError::ETIMEDOUT);
+            }
+            wq.wait_timeout_until_interruptible(deadline - now, woken);
+        } else {
+            wq.wait_until_interruptible(woken);
+        }
+        if woken() {
+            return Ok(0);
+        }
+        // The wait timed out or was interrupted, which may be a late
+        // notification of an earlier signal, so look at the value again.
+        wakes = futex_table.wakes();
+        if *uaddr.get_as_ref()? != value {
+            return Ok(0);
+        }
+    }
+}
+
 pub fn sys_futex(
     uaddr: UserConstPtr<u32>,
     futex_op: u32,
@@ -26,23 +82,15 @@ pub fn sys_futex(
     let command = futex_op & (FUTEX_CMD_MASK as u32);
     match command {
         FUTEX_WAIT => {
-            if *uaddr.get_as_ref()? != value {
-                return Err(LinuxError::EAGAIN);
-            }
-            let wq = futex_table.get_or_insert(addr);
-
-            if let Some(timeout) = nullable!(timeout.get_as_ref())? {
-                wq.wait_timeout(timeout.to_time_value());
-            } else {
-                wq.wait();
-            }
-
-            Ok(0)
+            let deadline = nullable!(timeout.get_as_ref())?
+                .map(|timeout| monotonic_time() + timeout.to_time_value());
+            futex_wait(uaddr, value, deadline)
         }
         FUTEX_WAKE => {
             let wq = futex_table.get(addr);
             let mut count = 0;
             if let Some(wq) = wq {
+                futex_table.add_wake();
                 for _ in 0..value {
                     if !wq.notify_one(false) {
                         break;
@@ -64,6 +112,7 @@ pub fn sys_futex(
 
             let mut count = 0;
             if let Some(wq) = wq {
+                futex_table.add_wake();
                 for _ in 0..value {
                     if !wq.notify_one(false) {
                         break;
@@ -76,7 +76,8 @@ pub fn sys_mmap(
 ) -> LinuxResult<isize> {
     let curr = current();
     let process_data = curr.task_ext().process_data();
-    let mut aspace = process_data.aspace.lock();
+    let aspace = process_data.aspace();
+    let mut aspace = aspace.lock();
     let permission_flags = MmapProt::from_bits_truncate(prot);
     // TODO: check illegal flags for mmap
     // An example is the flags contained none of MAP_PRIVATE, MAP_SHARED, or MAP_SHARED_VALIDATE.
@@ -152,7 +153,8 @@ pub fn sys_mmap(
 pub fn sys_munmap(addr: usize, length: usize) -> LinuxResult<isize> {
     let curr = current();
     let process_data = curr.task_ext().process_data();
-    let mut aspace = process_data.aspace.lock();
+    let aspace = process_data.aspace();
+    let mut aspace = aspace.lock();
     let length = memory_addr::align_up_4k(length);
     let start_addr = VirtAddr::from(addr);
     aspace.unmap(start_addr, length)?;
@@ -171,7 +173,8 @@ pub fn sys_mprotect(addr: usize, length: usize, prot: u32) -> LinuxResult<isize>
 
     let curr = current();
     let process_data = curr.task_ext().process_data();
-    let mut aspace = process_data.aspace.lock();
+    let aspace = process_data.aspace();
+    let mut aspace = aspace.lock();
     let length = memory_addr::align_up_4k(length);
     let start_addr = VirtAddr::from(addr);
     aspace.protect(start_addr, length, permission_flags.into(), axhal::paging::PageSize::Size4K)?;
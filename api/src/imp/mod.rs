@@ -1,6 +1,8 @@
 mod fs;
 mod futex;
 mod mm;
+mod ptrace;
+mod seccomp;
 mod signal;
 mod sys;
 mod task;
@@ -11,4 +13,4 @@ mod rusage;
 mod random;
 mod blank;
 
-pub use self::{fs::*, futex::*, mm::*, signal::*, sys::*, task::*, time::*, select::*, shm::*, rusage::*, random::*, blank::*};
+pub use self::{fs::*, futex::*, mm::*, ptrace::*, seccomp::*, signal::*, sys::*, task::*, time::*, select::*, shm::*, rusage::*, random::*, blank::*};
@@ -0,0 +1,401 @@
+use alloc::{sync::Arc, vec::Vec};
+use axerrno::{AxResult, LinuxError, LinuxResult};
+use axhal::{
+    arch::TrapFrame,
+    paging::{MappingFlags, PageSize},
+};
+use axmm::AddrSpace;
+use axprocess::{Pid, Process, Thread};
+use axsignal::{SignalInfo, Signo};
+use axtask::{TaskExtRef, current};
+use linux_raw_sys::{
+    elf_uapi::NT_PRSTATUS,
+    general::{SI_KERNEL, SI_USER, iovec, siginfo},
+    ptrace::*,
+};
+use memory_addr::{MemoryAddr, VirtAddr, VirtAddrRange};
+use starry_core::{
+    ptrace::{PtraceResume, PtraceStop},
+    task::{ProcessData, ThreadData, get_thread},
+};
+
+use crate::{
+    ptr::{UserConstPtr, UserPtr},
+    regs::{ELF_NGREG, GeneralRegs, general_regs, set_general_regs},
+    signal::send_signal_thread,
+};
+
+/// The index of `orig_rax` in [`GeneralRegs`].
+#[cfg(target_arch = "x86_64")]
+const ORIG_RAX: usize = 15;
+
+/// The index in [`GeneralRegs`] of the number of the system call, which the
+/// tracer may change when the tracee enters it.
+#[cfg(target_arch = "x86_64")]
+const SYSNO_REG: Option<usize> = Some(ORIG_RAX);
+/// The index in [`GeneralRegs`] of the number of the system call, which the
+/// tracer may change when the tracee enters it.
+#[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+const SYSNO_REG: Option<usize> = Some(17);
+/// The index in [`GeneralRegs`] of the number of the system call, which the
+/// tracer may change when the tracee enters it. Linux only allows changing it
+/// with the `NT_ARM_SYSTEM_CALL` register set, which is not supported.
+#[cfg(target_arch = "aarch64")]
+const SYSNO_REG: Option<usize> = None;
+/// The index in [`GeneralRegs`] of the number of the system call, which the
+/// tracer may change when the tracee enters it.
+#[cfg(target_arch = "loongarch64")]
+const SYSNO_REG: Option<usize> = Some(11);
+
+/// The size of the user area of `PTRACE_PEEKUSR`, which is `struct user`.
+/// Only the general purpose registers at its start are supported, the rest
+/// reads as zero.
+#[cfg(target_arch = "x86_64")]
+const USER_SIZE: usize = 912;
+/// The size of the user area of `PTRACE_PEEKUSR`, which only holds the
+/// general purpose registers.
+#[cfg(not(target_arch = "x86_64"))]
+const USER_SIZE: usize = size_of::<GeneralRegs>();
+
+fn parse_signal(data: usize) -> LinuxResult<Option<Signo>> {
+    if data == 0 {
+        return Ok(None);
+    }
+    u8::try_from(data)
+        .ok()
+        .and_then(Signo::from_repr)
+        .map(Some)
+        .ok_or(LinuxError::EIO)
+}
+
+fn parse_options(data: usize) -> LinuxResult<u32> {
+    u32::try_from(data)
+        .ok()
+        .filter(|options| options & !PTRACE_O_MASK == 0)
+        .ok_or(LinuxError::EINVAL)
+}
+
+/// Make `tracer` trace `thread`.
+fn attach(
+    thread: &Arc<Thread>,
+    tracer: &Arc<Process>,
+    options: u32,
+    seized: bool,
+) -> LinuxResult<()> {
+    let data = thread.data::<ThreadData>().ok_or(LinuxError::ESRCH)?;
+    let tracer_data = tracer.data::<ProcessData>().ok_or(LinuxError::ESRCH)?;
+    {
+        let mut state = data.ptrace.lock();
+        if state.tracer().is_some() {
+            return Err(LinuxError::EPERM);
+        }
+        state.tracer = Some(Arc::downgrade(tracer));
+        state.options = options;
+        state.seized = seized;
+        state.resume = PtraceResume::Cont;
+        state.exit_status = None;
+    }
+    tracer_data.tracees.lock().push(thread.clone());
+    Ok(())
+}
+
+/// Find the thread `tid` traced by the current process, which must be in a
+/// stop if `stopped` is set.
+fn get_tracee(tid: Pid, stopped: bool) -> LinuxResult<Arc<Thread>> {
+    let thread = get_thread(tid)?;
+    let data = thread.data::<ThreadData>().ok_or(LinuxError::ESRCH)?;
+    let traced = {
+        let state = data.ptrace.lock();
+        let curr = current();
+        state
+            .tracer()
+            .is_some_and(|it| Arc::ptr_eq(&it, curr.task_ext().thread.process()))
+            && (!stopped || state.stop.is_some())
+    };
+    if !traced {
+        return Err(LinuxError::ESRCH);
+    }
+    Ok(thread)
+}
+
+/// Run `f` on the user registers of the stopped tracee.
+fn with_trap_frame<R>(data: &ThreadData, f: impl FnOnce(&mut TrapFrame) -> R) -> R {
+    let mut state = data.ptrace.lock();
+    match &mut state.start_regs {
+        Some(tf) => f(tf),
+        // SAFETY: The tracee is stopped in the kernel, where its registers are
+        // saved and not used until it is resumed.
+        None => f(unsafe { &mut *data.user_trap_frame() }),
+    }
+}
+
+/// Get the general purpose registers of the stopped tracee.
+fn get_regs(data: &ThreadData) -> GeneralRegs {
+    #[allow(unused_mut)]
+    let mut regs = with_trap_frame(data, |tf| general_regs(tf));
+    #[cfg(target_arch = "x86_64")]
+    {
+        let state = data.ptrace.lock();
+        if matches!(
+            state.stop,
+            Some(PtraceStop::SyscallEntry | PtraceStop::SyscallExit)
+        ) {
+            regs[ORIG_RAX] = state.sysno as _;
+        }
+    }
+    regs
+}
+
+/// Set the general purpose registers of the stopped tracee.
+fn set_regs(data: &ThreadData, regs: &GeneralRegs) -> LinuxResult {
+    with_trap_frame(data, |tf| set_general_regs(tf, regs))?;
+    if let Some(index) = SYSNO_REG {
+        let mut state = data.ptrace.lock();
+        if matches!(state.stop, Some(PtraceStop::SyscallEntry)) {
+            state.sysno = regs[index] as _;
+        }
+    }
+    Ok(())
+}
+
+/// Enable or disable hardware single-stepping of the stopped tracee.
+#[cfg(target_arch = "x86_64")]
+fn set_single_step(data: &ThreadData, enabled: bool) -> LinuxResult<()> {
+    const TRAP_FLAG: u64 = 1 << 8;
+    with_trap_frame(data, |tf| {
+        if enabled {
+            tf.rflags |= TRAP_FLAG;
+        } else {
+            tf.rflags &= !TRAP_FLAG;
+        }
+    });
+    Ok(())
+}
+
+/// Enable or disable hardware single-stepping of the stopped tracee.
+///
+/// The step is armed in its saved `SPSR_EL1`, and enabled in `MDSCR_EL1` of
+/// the CPU it runs on when it is switched in.
+#[cfg(target_arch = "aarch64")]
+fn set_single_step(data: &ThreadData, enabled: bool) -> LinuxResult<()> {
+    const SPSR_SS: u64 = 1 << 21;
+    with_trap_frame(data, |tf| {
+        if enabled {
+            tf.spsr |= SPSR_SS;
+        } else {
+            tf.spsr &= !SPSR_SS;
+        }
+    });
+    data.single_step
+        .store(enabled, core::sync::atomic::Ordering::Relaxed);
+    Ok(())
+}
+
+/// Enable or disable hardware single-stepping of the stopped tracee.
+///
+/// It is not supported on riscv64 and loongarch64, which have no hardware
+/// single-stepping for user space, and would need breakpoints set by the
+/// kernel.
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+fn set_single_step(_data: &ThreadData, enabled: bool) -> LinuxResult<()> {
+    if enabled {
+        return Err(LinuxError::EIO);
+    }
+    Ok(())
+}
+
+/// Access the word at `addr` in the address space of `thread` with `f`,
+/// regardless of the protection of the memory.
+fn access_word(
+    thread: &Thread,
+    addr: usize,
+    f: impl FnOnce(&AddrSpace, VirtAddr) -> AxResult,
+) -> LinuxResult<()> {
+    let data = thread
+        .process()
+        .data::<ProcessData>()
+        .ok_or(LinuxError::ESRCH)?;
+    let end = addr
+        .checked_add(size_of::<usize>())
+        .ok_or(LinuxError::EIO)?;
+    let range = VirtAddrRange::new(addr.into(), end.into());
+
+    let aspace = data.aspace();
+
+    let mut aspace = aspace.lock();
+    if !aspace.check_region_access(range, MappingFlags::USER) {
+        return Err(LinuxError::EIO);
+    }
+    let start = range.start.align_down_4k();
+    aspace
+        .populate_area(start, range.end.align_up_4k() - start, PageSize::Size4K)
+        .map_err(|_| LinuxError::EIO)?;
+    f(&aspace, range.start).map_err(|_| LinuxError::EIO)
+}
+
+fn peek_user(data: &ThreadData, offset: usize) -> LinuxResult<usize> {
+    if !offset.is_multiple_of(size_of::<u64>()) || offset >= USER_SIZE {
+        return Err(LinuxError::EIO);
+    }
+    Ok(get_regs(data)
+        .get(offset / size_of::<u64>())
+        .map_or(0, |reg| *reg as _))
+}
+
+fn poke_user(data: &ThreadData, offset: usize, value: usize) -> LinuxResult<isize> {
+    let index = offset / size_of::<u64>();
+    if !offset.is_multiple_of(size_of::<u64>()) || index >= ELF_NGREG {
+        return Err(LinuxError::EIO);
+    }
+    let mut regs = get_regs(data);
+    regs[index] = value as _;
+    set_regs(data, &regs)?;
+    Ok(0)
+}
+
+fn get_regset(data: &ThreadData, kind: usize, iov: UserPtr<iovec>) -> LinuxResult<isize> {
+    if kind != NT_PRSTATUS as usize {
+        return Err(LinuxError::EINVAL);
+    }
+    let iov = iov.get_as_mut()?;
+    let bytes = get_regs(data)
+        .iter()
+        .flat_map(|reg| reg.to_ne_bytes())
+        .collect::<Vec<_>>();
+    let len = bytes.len().min(iov.iov_len as usize);
+    UserPtr::<u8>::from(iov.iov_base as usize)
+        .get_as_mut_slice(len)?
+        .copy_from_slice(&bytes[..len]);
+    iov.iov_len = len as _;
+    Ok(0)
+}
+
+fn set_regset(data: &ThreadData, kind: usize, iov: UserPtr<iovec>) -> LinuxResult<isize> {
+    if kind != NT_PRSTATUS as usize {
+        return Err(LinuxError::EINVAL);
+    }
+    let iov = iov.get_as_mut()?;
+    let len = size_of::<GeneralRegs>().min(iov.iov_len as usize);
+    let bytes = UserConstPtr::<u8>::from(iov.iov_base as usize).get_as_slice(len)?;
+    let mut regs = get_regs(data);
+    for (reg, bytes) in regs.iter_mut().zip(bytes.chunks_exact(size_of::<u64>())) {
+        *reg = u64::from_ne_bytes(bytes.try_into().unwrap());
+    }
+    set_regs(data, &regs)?;
+    iov.iov_len = len as _;
+    Ok(0)
+}
+
+pub fn sys_ptrace(request: u32, pid: Pid, addr: usize, data: usize) -> LinuxResult<isize> {
+    info!(
+        "sys_ptrace <= request: {}, pid: {}, addr: {:#x}, data: {:#x}",
+        request, pid, addr, data
+    );
+
+    let curr = current();
+    let process = curr.task_ext().thread.process();
+    match request {
+        PTRACE_TRACEME => {
+            let parent = process.parent().ok_or(LinuxError::EPERM)?;
+            attach(&curr.task_ext().thread, &parent, 0, false)?;
+            return Ok(0);
+        }
+        PTRACE_ATTACH | PTRACE_SEIZE => {
+            let thread = get_thread(pid)?;
+            if Arc::ptr_eq(thread.process(), process) {
+                return Err(LinuxError::EPERM);
+            }
+            if request == PTRACE_SEIZE {
+                attach(&thread, process, parse_options(data)?, true)?;
+            } else {
+                attach(&thread, process, 0, false)?;
+                send_signal_thread(&thread, SignalInfo::new(Signo::SIGSTOP, SI_USER as _))?;
+            }
+            return Ok(0);
+        }
+        PTRACE_KILL => {
+            let thread = get_tracee(pid, false)?;
+            send_signal_thread(&thread, SignalInfo::new(Signo::SIGKILL, SI_KERNEL as _))?;
+            return Ok(0);
+        }
+        _ => {}
+    }
+
+    let thread = get_tracee(pid, true)?;
+    let thr_data = thread.data::<ThreadData>().ok_or(LinuxError::ESRCH)?;
+    match request {
+        PTRACE_PEEKTEXT | PTRACE_PEEKDATA => {
+            let mut word = [0; size_of::<usize>()];
+            access_word(&thread, addr, |aspace, start| {
+                aspace.read(start, PageSize::Size4K, &mut word)
+            })?;
+            *UserPtr::<usize>::from(data).get_as_mut()? = usize::from_ne_bytes(word);
+            Ok(0)
+        }
+        PTRACE_POKETEXT | PTRACE_POKEDATA => {
+            access_word(&thread, addr, |aspace, start| {
+                aspace.write(start, PageSize::Size4K, &data.to_ne_bytes())
+            })?;
+            Ok(0)
+        }
+        PTRACE_PEEKUSR => {
+            *UserPtr::<usize>::from(data).get_as_mut()? = peek_user(thr_data, addr)?;
+            Ok(0)
+        }
+        PTRACE_POKEUSR => poke_user(thr_data, addr, data),
+        #[cfg(target_arch = "x86_64")]
+        PTRACE_GETREGS => {
+            *UserPtr::<GeneralRegs>::from(data).get_as_mut()? = get_regs(thr_data);
+            Ok(0)
+        }
+        #[cfg(target_arch = "x86_64")]
+        PTRACE_SETREGS => {
+            set_regs(
+                thr_data,
+                UserConstPtr::<GeneralRegs>::from(data).get_as_ref()?,
+            )?;
+            Ok(0)
+        }
+        PTRACE_GETREGSET => get_regset(thr_data, addr, data.into()),
+        PTRACE_SETREGSET => set_regset(thr_data, addr, data.into()),
+        PTRACE_GETSIGINFO => {
+            let Some(PtraceStop::Signal(sig)) = thr_data.ptrace.lock().stop.clone() else {
+                return Err(LinuxError::EINVAL);
+            };
+            *UserPtr::<siginfo>::from(data).get_as_mut()? = sig.0;
+            Ok(0)
+        }
+        PTRACE_SETOPTIONS => {
+            thr_data.ptrace.lock().options = parse_options(data)?;
+            Ok(0)
+        }
+        PTRACE_GETEVENTMSG => {
+            let msg = thr_data.ptrace.lock().event_msg;
+            *UserPtr::<usize>::from(data).get_as_mut()? = msg;
+            Ok(0)
+        }
+        PTRACE_CONT | PTRACE_SYSCALL | PTRACE_SINGLESTEP => {
+            let signal = parse_signal(data)?;
+            let resume = match request {
+                PTRACE_SYSCALL => PtraceResume::Syscall,
+                PTRACE_SINGLESTEP => PtraceResume::SingleStep,
+                _ => PtraceResume::Cont,
+            };
+            set_single_step(thr_data, resume == PtraceResume::SingleStep)?;
+            thr_data.ptrace.resume(resume, signal);
+            Ok(0)
+        }
+        PTRACE_DETACH => {
+            let signal = parse_signal(data)?;
+            set_single_step(thr_data, false)?;
+            thr_data.ptrace.detach(signal);
+            curr.task_ext()
+                .process_data()
+                .tracees
+                .lock()
+                .retain(|it| !Arc::ptr_eq(it, &thread));
+            Ok(0)
+        }
+        _ => Err(LinuxError::EIO),
+    }
+}
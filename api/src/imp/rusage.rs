@@ -1,5 +1,4 @@
 use core::sync::atomic::Ordering;
-use core::time::Duration;
 
 use axerrno::LinuxResult;
 use axtask::current;
@@ -7,6 +6,8 @@ use axtask::TaskExtRef;
 use linux_raw_sys::general::__kernel_old_timeval;
 use linux_raw_sys::general::rusage;
 
+use starry_core::task::time_stat_update;
+
 use crate::ptr::UserPtr;
 use crate::time::TimeValueLike;
 // use crate::rusage::Rusage;
@@ -15,6 +16,7 @@ use crate::time::TimeValueLike;
 // use crate::rusage::RUSAGE_SELF;
 // use crate::rusage::RUSAGE_THREAD;
 const RUSAGE_SELF: i32 = 0; // 当前进程的资源使用情况
+const RUSAGE_THREAD: i32 = 1; // 当前线程的资源使用情况
 
 pub fn sys_getrusage(
     who: isize,
@@ -22,6 +24,8 @@ pub fn sys_getrusage(
 ) -> LinuxResult<isize> {
     let curr = current();
     let task = curr.task_ext();
+    // 先记入当前线程到目前为止的 CPU 时间
+    time_stat_update();
 
     let result:rusage = match who as i32 {
         // TODO!
@@ -58,14 +62,18 @@ pub fn sys_getrusage(
         //     //     return Err(axerrno::LinuxError::EINVAL);
         //     // }
         // },
-        RUSAGE_SELF => {
-            // 获取当前进程的资源使用情况
-            let timestat = task.time.borrow().output();
+        RUSAGE_SELF | RUSAGE_THREAD => {
+            // 获取当前进程或线程的资源使用情况
+            let cpu_time = if who as i32 == RUSAGE_SELF {
+                &task.process_data().cpu_time
+            } else {
+                &task.thread_data().cpu_time
+            };
             let minflt = task.minflt.load(Ordering::Relaxed);
             let majflt = task.majflt.load(Ordering::Relaxed);
             let res = rusage {
-                ru_utime: __kernel_old_timeval::from_time_value(Duration::from_nanos(timestat.0 as _)),
-                ru_stime: __kernel_old_timeval::from_time_value(Duration::from_nanos(timestat.1 as _)),
+                ru_utime: __kernel_old_timeval::from_time_value(cpu_time.user()),
+                ru_stime: __kernel_old_timeval::from_time_value(cpu_time.system()),
                 ru_maxrss: 0, // TODO
                 ru_ixrss: 0, // TODO
                 ru_idrss: 0, // TODO
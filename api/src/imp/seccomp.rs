@@ -0,0 +1,133 @@
+use alloc::{sync::Arc, vec, vec::Vec};
+use core::sync::atomic::Ordering;
+
+use axerrno::{LinuxError, LinuxResult};
+use axtask::{TaskExtRef, current};
+use linux_raw_sys::ptrace::{
+    SECCOMP_FILTER_FLAG_LOG, SECCOMP_FILTER_FLAG_SPEC_ALLOW, SECCOMP_FILTER_FLAG_TSYNC,
+    SECCOMP_GET_ACTION_AVAIL, SECCOMP_MODE_FILTER, SECCOMP_MODE_STRICT, SECCOMP_RET_ALLOW,
+    SECCOMP_RET_ERRNO, SECCOMP_RET_KILL_PROCESS, SECCOMP_RET_KILL_THREAD, SECCOMP_RET_LOG,
+    SECCOMP_RET_TRACE, SECCOMP_RET_TRAP, SECCOMP_SET_MODE_FILTER, SECCOMP_SET_MODE_STRICT,
+    sock_filter, sock_fprog,
+};
+use starry_core::{
+    seccomp::{Seccomp, SeccompFilter},
+    task::ThreadData,
+};
+
+use crate::ptr::UserConstPtr;
+
+/// Put the current thread in strict mode.
+fn set_mode_strict() -> LinuxResult<isize> {
+    let curr = current();
+    let mut seccomp = curr.task_ext().thread_data().seccomp.write();
+    match seccomp.mode {
+        SECCOMP_MODE_STRICT => {}
+        0 => seccomp.mode = SECCOMP_MODE_STRICT,
+        _ => return Err(LinuxError::EINVAL),
+    }
+    Ok(0)
+}
+
+/// Install the filter `prog` in the current thread, or in all the threads
+/// of its process with `SECCOMP_FILTER_FLAG_TSYNC`.
+///
+/// Returns the ID of a thread which runs filters the current thread does not,
+/// or is in strict mode, as it cannot be synchronized.
+fn set_mode_filter(flags: u32, prog: UserConstPtr<sock_fprog>) -> LinuxResult<isize> {
+    let supported =
+        SECCOMP_FILTER_FLAG_TSYNC | SECCOMP_FILTER_FLAG_LOG | SECCOMP_FILTER_FLAG_SPEC_ALLOW;
+    if flags & !supported != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+
+    let curr = current();
+    let thr_data = curr.task_ext().thread_data();
+    // Linux requires `no_new_privs` or `CAP_SYS_ADMIN`, so that filters cannot
+    // fool set-user-ID programs. There are no credentials here, and every
+    // task runs with the user ID 0, so it is always allowed.
+    let prog = prog.get_as_ref()?;
+    let insns =
+        UserConstPtr::<sock_filter>::from(prog.filter as usize).get_as_slice(prog.len as _)?;
+
+    let mut threads = if flags & SECCOMP_FILTER_FLAG_TSYNC != 0 {
+        curr.task_ext().thread.process().threads()
+    } else {
+        vec![curr.task_ext().thread.clone()]
+    };
+    // The threads are locked in the order of their IDs, so that concurrent
+    // synchronizations cannot deadlock.
+    threads.sort_by_key(|it| it.tid());
+    let mut states = threads
+        .iter()
+        .filter_map(|thread| {
+            let data = thread.data::<ThreadData>()?;
+            Some((thread.tid(), data, data.seccomp.write()))
+        })
+        .collect::<Vec<_>>();
+    let (_, _, seccomp) = states
+        .iter()
+        .find(|(_, data, _)| core::ptr::eq(*data, thr_data))
+        .ok_or(LinuxError::ESRCH)?;
+    if seccomp.mode == SECCOMP_MODE_STRICT {
+        return Err(LinuxError::EINVAL);
+    }
+    // A thread whose filters have diverged cannot be synchronized, and its ID
+    // is returned instead.
+    if let Some((tid, ..)) = states
+        .iter()
+        .find(|(_, _, state)| !state.is_ancestor_of(seccomp))
+    {
+        return Ok(*tid as _);
+    }
+
+    let filter = SeccompFilter::new(
+        insns,
+        flags & SECCOMP_FILTER_FLAG_LOG != 0,
+        seccomp.filter.clone(),
+    )?;
+    let new = Seccomp {
+        mode: SECCOMP_MODE_FILTER,
+        filter: Some(Arc::new(filter)),
+    };
+    let no_new_privs = thr_data.no_new_privs.load(Ordering::Relaxed);
+    for (_, data, state) in &mut states {
+        **state = new.clone();
+        if no_new_privs {
+            data.no_new_privs.store(true, Ordering::Relaxed);
+        }
+    }
+    Ok(0)
+}
+
+pub fn sys_seccomp(op: u32, flags: u32, args: usize) -> LinuxResult<isize> {
+    debug!(
+        "sys_seccomp <= op: {}, flags: {:#x}, args: {:#x}",
+        op, flags, args
+    );
+    match op {
+        SECCOMP_SET_MODE_STRICT => {
+            if flags != 0 || args != 0 {
+                return Err(LinuxError::EINVAL);
+            }
+            set_mode_strict()
+        }
+        SECCOMP_SET_MODE_FILTER => set_mode_filter(flags, args.into()),
+        SECCOMP_GET_ACTION_AVAIL => {
+            if flags != 0 {
+                return Err(LinuxError::EINVAL);
+            }
+            match *UserConstPtr::<u32>::from(args).get_as_ref()? {
+                SECCOMP_RET_KILL_PROCESS
+                | SECCOMP_RET_KILL_THREAD
+                | SECCOMP_RET_TRAP
+                | SECCOMP_RET_ERRNO
+                | SECCOMP_RET_TRACE
+                | SECCOMP_RET_LOG
+                | SECCOMP_RET_ALLOW => Ok(0),
+                _ => Err(LinuxError::EOPNOTSUPP),
+            }
+        }
+        _ => Err(LinuxError::EINVAL),
+    }
+}
@@ -3,6 +3,7 @@ use core::time::Duration;
 use crate::file::get_file_like;
 use crate::ptr::UserConstPtr;
 use crate::ptr::UserPtr;
+use crate::signal::have_signals;
 use crate::time::TimeValueLike;
 use axerrno::LinuxError;
 use axerrno::LinuxResult;
@@ -148,6 +149,9 @@ pub fn sys_select(
             debug!("    timeout!");
             return Ok(0);
         }
+        if have_signals() {
+            return Err(LinuxError::EINTR);
+        }
         axtask::yield_now();
     }
 }
@@ -239,6 +243,10 @@ pub fn sys_poll(
             None => {}, // 无限期等待
             _ => {}    // 继续等待直到超时
         }
+
+        if have_signals() {
+            return Err(LinuxError::EINTR);
+        }
         
         // 让出CPU时间片
         axtask::yield_now();
@@ -174,7 +174,8 @@ pub fn sys_shmat(shmid: ShmId, shmaddr: usize, shmflg: i32) -> LinuxResult<isize
     );
 
     let current_task = current();
-    let mut aspace = current_task.task_ext().process_data().aspace.lock();
+    let aspace = current_task.task_ext().process_data().aspace();
+    let mut aspace = aspace.lock();
 
     // 获取共享内存段
     let manager = SHM_MANAGER.lock();
@@ -261,7 +262,7 @@ pub fn sys_shmat(shmid: ShmId, shmaddr: usize, shmflg: i32) -> LinuxResult<isize
     {
         let mut segment = segment_arc.write();
         segment.attach_count += 1;
-        segment.attach_time = axhal::time::wall_time().as_secs();
+        segment.attach_time = starry_core::realtime::realtime().as_secs();
     }
 
     // 记录连接信息到管理器
@@ -349,7 +350,7 @@ pub fn sys_shmctl(shmid: ShmId, cmd: i32, buf: UserPtr<ShmidDs>) -> LinuxResult<
             segment.owner_uid = new_shmid_ds.shm_perm.uid;
             segment.owner_gid = new_shmid_ds.shm_perm.gid;
             segment.perm = new_shmid_ds.shm_perm.mode;
-            segment.change_time = axhal::time::wall_time().as_secs();
+            segment.change_time = starry_core::realtime::realtime().as_secs();
 
             debug!("sys_shmctl IPC_SET: updated segment attributes");
             Ok(0)
@@ -366,7 +367,7 @@ pub fn sys_shmctl(shmid: ShmId, cmd: i32, buf: UserPtr<ShmidDs>) -> LinuxResult<
 
             // 标记为删除
             segment.marked_for_removal = true;
-            segment.change_time = axhal::time::wall_time().as_secs();
+            segment.change_time = starry_core::realtime::realtime().as_secs();
 
             let attach_count = segment.attach_count;
             drop(segment);
@@ -475,7 +476,8 @@ pub fn sys_shmdt(shmaddr: usize) -> LinuxResult<isize> {
     drop(manager); // 释放管理器锁
 
     // 从进程地址空间中取消映射
-    let mut aspace = current_task.task_ext().process_data().aspace.lock();
+    let aspace = current_task.task_ext().process_data().aspace();
+    let mut aspace = aspace.lock();
     let aligned_length = memory_addr::align_up_4k(segment_size);
 
     match aspace.unmap(addr, aligned_length) {
@@ -500,7 +502,7 @@ pub fn sys_shmdt(shmaddr: usize) -> LinuxResult<isize> {
         if segment.attach_count > 0 {
             segment.attach_count -= 1;
         }
-        segment.detach_time = axhal::time::wall_time().as_secs();
+        segment.detach_time = starry_core::realtime::realtime().as_secs();
 
         // 检查是否应该清理段
         segment.marked_for_removal && segment.attach_count == 0
@@ -555,7 +557,7 @@ pub struct ShmSegment {
 impl ShmSegment {
     /// Create a new shared memory segment
     pub fn new(key: Key, size: usize, perm: u32, uid: u32, gid: u32, pid: u32) -> Self {
-        let current_time = axhal::time::wall_time().as_secs();
+        let current_time = starry_core::realtime::realtime().as_secs();
         Self {
             key,
             size,
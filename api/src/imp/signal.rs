@@ -7,12 +7,13 @@ use axprocess::{Pid, Thread};
 use axsignal::{SignalInfo, SignalSet, SignalStack, Signo};
 use axtask::{TaskExtRef, current};
 use linux_raw_sys::general::{
-    MINSIGSTKSZ, SI_TKILL, SI_USER, SIG_BLOCK, SIG_SETMASK, SIG_UNBLOCK, kernel_sigaction, siginfo,
-    timespec,
+    MINSIGSTKSZ, O_CLOEXEC, O_NONBLOCK, O_RDWR, SI_TKILL, SI_USER, SIG_BLOCK, SIG_SETMASK,
+    SIG_UNBLOCK, kernel_sigaction, siginfo, timespec,
 };
 use starry_core::task::{get_process, get_process_group, get_thread, processes};
 
 use crate::{
+    file::{FileLike, PidFd, SignalFd},
     ptr::{UserConstPtr, UserPtr, nullable},
     signal::{check_signals, send_signal_process, send_signal_process_group, send_signal_thread},
     time::TimeValueLike,
@@ -206,12 +207,51 @@ pub fn sys_rt_tgsigqueueinfo(
     Ok(0)
 }
 
+pub fn sys_pidfd_send_signal(
+    pidfd: i32,
+    signo: u32,
+    sig: UserConstPtr<SignalInfo>,
+    flags: u32,
+) -> LinuxResult<isize> {
+    if flags != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let process = PidFd::from_fd(pidfd)?.process()?;
+    if process.is_zombie() {
+        return Err(LinuxError::ESRCH);
+    }
+    let Some(mut info) = make_siginfo(signo, SI_USER as _)? else {
+        // TODO: should also check permissions
+        return Ok(0);
+    };
+    if !sig.is_null() {
+        info = make_queue_signal_info(process.pid(), signo, sig)?;
+    }
+    send_signal_process(&process, info)?;
+    Ok(0)
+}
+
 pub fn sys_rt_sigreturn(tf: &mut TrapFrame) -> LinuxResult<isize> {
     let curr = current();
     curr.task_ext().thread_data().signal.restore(tf);
     Ok(tf.retval() as isize)
 }
 
+/// Continue the system call interrupted by a signal which did not run a
+/// handler, such as a `nanosleep` stopped by `SIGSTOP` and resumed later.
+pub fn sys_restart_syscall() -> LinuxResult<isize> {
+    let block = current()
+        .task_ext()
+        .thread_data()
+        .restart_block
+        .lock()
+        .take();
+    match block {
+        Some(block) => block(),
+        None => Err(LinuxError::EINTR),
+    }
+}
+
 pub fn sys_rt_sigtimedwait(
     set: UserConstPtr<SignalSet>,
     info: UserPtr<siginfo>,
@@ -266,7 +306,8 @@ pub fn sys_rt_sigsuspend(
         curr.task_ext().process_data().signal.wait_signal();
     }
 
-    Ok(0)
+    // Keep the first argument of the handler, or `EINTR` if there is none.
+    Ok(tf.retval() as isize)
 }
 
 pub fn sys_sigaltstack(
@@ -293,3 +334,35 @@ pub fn sys_sigaltstack(
             Ok(0)
         })
 }
+
+/// The flags of `signalfd4`, which are the same as `O_NONBLOCK` and
+/// `O_CLOEXEC`.
+const SFD_NONBLOCK: u32 = O_NONBLOCK;
+const SFD_CLOEXEC: u32 = O_CLOEXEC;
+
+pub fn sys_signalfd4(
+    fd: i32,
+    mask: UserConstPtr<SignalSet>,
+    sizemask: usize,
+    flags: u32,
+) -> LinuxResult<isize> {
+    check_sigset_size(sizemask)?;
+    if flags & !(SFD_NONBLOCK | SFD_CLOEXEC) != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let mask = *mask.get_as_ref()?;
+    debug!(
+        "sys_signalfd4 <= fd: {}, mask: {:?}, flags: {:#x}",
+        fd, mask, flags
+    );
+
+    if fd != -1 {
+        SignalFd::from_fd(fd)?.set_mask(mask);
+        return Ok(fd as _);
+    }
+    let signalfd = SignalFd::new(mask);
+    signalfd.set_nonblocking(flags & SFD_NONBLOCK != 0)?;
+    signalfd
+        .add_to_fd_table_with_flags(O_RDWR | flags)
+        .map(|fd| fd as _)
+}
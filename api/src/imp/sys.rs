@@ -1,9 +1,18 @@
 use core::ffi::c_char;
 
-use axerrno::LinuxResult;
-use linux_raw_sys::system::{sysinfo, new_utsname, __IncompleteArrayField};
+use axerrno::{LinuxError, LinuxResult};
+use axprocess::Pid;
+use axtask::{TaskExtRef, current};
+use linux_raw_sys::{
+    general::{RLIM_NLIMITS, rlimit64},
+    system::{__IncompleteArrayField, new_utsname, sysinfo},
+};
+use starry_core::{
+    resource::Rlimit,
+    task::{ProcessData, get_process},
+};
 
-use crate::ptr::UserPtr;
+use crate::ptr::{UserConstPtr, UserPtr, nullable};
 
 pub fn sys_getuid() -> LinuxResult<isize> {
     Ok(0)
@@ -89,3 +98,39 @@ pub fn sys_sysinfo(info: UserPtr<sysinfo>) -> LinuxResult<isize> {
     *info.get_as_mut()? = sysinfo_data;
     Ok(0)
 }
+
+/// Get and set the resource limits of a process
+pub fn sys_prlimit64(
+    pid: Pid,
+    resource: u32,
+    new_limit: UserConstPtr<rlimit64>,
+    old_limit: UserPtr<rlimit64>,
+) -> LinuxResult<isize> {
+    debug!("sys_prlimit64 <= pid: {}, resource: {}", pid, resource);
+    if resource >= RLIM_NLIMITS {
+        return Err(LinuxError::EINVAL);
+    }
+    let process = if pid == 0 {
+        current().task_ext().thread.process().clone()
+    } else {
+        get_process(pid)?
+    };
+    let proc_data: &ProcessData = process.data().ok_or(LinuxError::ESRCH)?;
+
+    let new_limit = nullable!(new_limit.get_as_ref())?;
+    if new_limit.is_some_and(|it| it.rlim_cur > it.rlim_max) {
+        return Err(LinuxError::EINVAL);
+    }
+    let mut rlimits = proc_data.rlimits.write();
+    if let Some(old_limit) = nullable!(old_limit.get_as_mut())? {
+        let limit = rlimits[resource];
+        *old_limit = rlimit64 {
+            rlim_cur: limit.current,
+            rlim_max: limit.max,
+        };
+    }
+    if let Some(new_limit) = new_limit {
+        rlimits[resource] = Rlimit::new(new_limit.rlim_cur, new_limit.rlim_max);
+    }
+    Ok(0)
+}
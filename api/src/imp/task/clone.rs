@@ -1,3 +1,5 @@
+use core::{ffi::c_int, sync::atomic::Ordering};
+
 use alloc::sync::Arc;
 use axerrno::{LinuxError, LinuxResult};
 use axfs::{CURRENT_DIR, CURRENT_DIR_PATH};
@@ -7,13 +9,20 @@ use axsignal::Signo;
 use axsync::Mutex;
 use axtask::{TaskExtRef, current};
 use bitflags::bitflags;
-use linux_raw_sys::general::*;
+use linux_raw_sys::{general::*, ptrace::PTRACE_EVENT_VFORK_DONE};
+use memory_addr::PAGE_SIZE_4K;
 use starry_core::{
     mm::copy_from_kernel,
-    task::{ProcessData, TaskExt, ThreadData, add_thread_to_table, new_user_task},
+    task::{ProcessData, TaskExt, ThreadData, add_thread_to_table, get_thread, new_user_task},
 };
 
-use crate::{file::FD_TABLE, ptr::UserPtr};
+use super::new_pidfd;
+use crate::{
+    file::{FD_TABLE, get_file_like},
+    ptr::{UserConstPtr, UserPtr},
+    ptrace::{clone_event, ptrace_attach_child, ptrace_event},
+    trace::add_proc_entries,
+};
 
 bitflags! {
     /// Options for use with [`sys_clone`].
@@ -31,6 +40,9 @@ bitflags! {
         /// The calling process and the child process share the same table
         /// of signal handlers.
         const SIGHAND = CLONE_SIGHAND;
+        /// Allocate a pidfd referring to the child and store it at
+        /// `parent_tid` in the parent's memory.
+        const PIDFD = CLONE_PIDFD;
         /// If the calling process is being traced, then trace the child
         /// also.
         const PTRACE = CLONE_PTRACE;
@@ -78,15 +90,37 @@ bitflags! {
     }
 }
 
+/// The arguments of [`sys_clone`] and [`sys_clone3`].
+struct CloneArgs {
+    flags: CloneFlags,
+    exit_signal: Option<Signo>,
+    /// The stack pointer of the child, or 0 to keep that of the parent
+    stack: usize,
+    parent_tid: usize,
+    child_tid: usize,
+    tls: usize,
+    /// Where the pidfd of the child is stored with `CLONE_PIDFD`
+    pidfd: usize,
+    /// The TID requested for the child with `set_tid`
+    set_tid: Option<Pid>,
+}
+
 pub fn sys_clone(
     tf: &TrapFrame,
     flags: u32,
     stack: usize,
     parent_tid: usize,
-    #[cfg(any(target_arch = "x86_64", target_arch = "loongarch64"))] child_tid: usize,
-    tls: usize,
-    #[cfg(not(any(target_arch = "x86_64", target_arch = "loongarch64")))] child_tid: usize,
+    arg3: usize,
+    arg4: usize,
 ) -> LinuxResult<isize> {
+    // x86_64 and LoongArch pass the child TID before the TLS, and the other
+    // architectures after it.
+    let (child_tid, tls) = if cfg!(any(target_arch = "x86_64", target_arch = "loongarch64")) {
+        (arg3, arg4)
+    } else {
+        (arg4, arg3)
+    };
+
     const FLAG_MASK: u32 = 0xff;
     let exit_signal = flags & FLAG_MASK;
     let flags = CloneFlags::from_bits_truncate(flags & !FLAG_MASK);
@@ -99,10 +133,138 @@ pub fn sys_clone(
     if exit_signal != 0 && flags.contains(CloneFlags::THREAD | CloneFlags::PARENT) {
         return Err(LinuxError::EINVAL);
     }
+    // The pidfd is returned through `parent_tid`.
+    if flags.contains(CloneFlags::PIDFD | CloneFlags::PARENT_SETTID) {
+        return Err(LinuxError::EINVAL);
+    }
+
+    do_clone(
+        tf,
+        CloneArgs {
+            flags,
+            exit_signal: Signo::from_repr(exit_signal as u8),
+            stack,
+            parent_tid,
+            child_tid,
+            tls,
+            pidfd: parent_tid,
+            set_tid: None,
+        },
+    )
+}
+
+pub fn sys_clone3(tf: &TrapFrame, args: UserConstPtr<u8>, size: usize) -> LinuxResult<isize> {
+    if size < CLONE_ARGS_SIZE_VER0 as usize {
+        return Err(LinuxError::EINVAL);
+    }
+    if size > PAGE_SIZE_4K {
+        return Err(LinuxError::E2BIG);
+    }
+    let bytes = args.get_as_slice(size)?;
+    // The fields of newer versions of the structure must be zero.
+    let known = size_of::<clone_args>();
+    if bytes.len() > known && bytes[known..].iter().any(|&it| it != 0) {
+        return Err(LinuxError::E2BIG);
+    }
+    let mut args: clone_args = unsafe { core::mem::zeroed() };
+    // SAFETY: `clone_args` is plain old data, and at most its size is copied.
+    unsafe {
+        core::ptr::copy_nonoverlapping(
+            bytes.as_ptr(),
+            &mut args as *mut clone_args as *mut u8,
+            size.min(known),
+        );
+    }
+
+    let flags = CloneFlags::from_bits(args.flags as u32)
+        .filter(|_| args.flags & !(u32::MAX as u64 | CLONE_CLEAR_SIGHAND | CLONE_INTO_CGROUP) == 0)
+        .ok_or(LinuxError::EINVAL)?;
+    info!(
+        "sys_clone3 <= flags: {:?}, exit_signal: {}, stack: {:#x}, stack_size: {:#x}, pidfd: {:#x}, ptid: {:#x}, ctid: {:#x}, tls: {:#x}",
+        flags,
+        args.exit_signal,
+        args.stack,
+        args.stack_size,
+        args.pidfd,
+        args.parent_tid,
+        args.child_tid,
+        args.tls
+    );
+
+    let exit_signal = match args.exit_signal {
+        0 => None,
+        signo => Some(
+            u8::try_from(signo)
+                .ok()
+                .and_then(Signo::from_repr)
+                .ok_or(LinuxError::EINVAL)?,
+        ),
+    };
+    if exit_signal.is_some() && flags.intersects(CloneFlags::THREAD | CloneFlags::PARENT) {
+        return Err(LinuxError::EINVAL);
+    }
+    if (args.stack == 0) != (args.stack_size == 0) {
+        return Err(LinuxError::EINVAL);
+    }
+    // Children not sharing the signal handlers start with the default ones,
+    // so `CLONE_CLEAR_SIGHAND` only has to be checked.
+    if args.flags & CLONE_CLEAR_SIGHAND != 0 && flags.contains(CloneFlags::SIGHAND) {
+        return Err(LinuxError::EINVAL);
+    }
+    // All the processes are in the root cgroup, so only the file descriptor
+    // is checked.
+    if args.flags & CLONE_INTO_CGROUP != 0 {
+        get_file_like(args.cgroup as _)?;
+    }
+
+    // There is a single PID namespace, so at most one TID can be requested.
+    let set_tid = match (args.set_tid, args.set_tid_size) {
+        (0, 0) => None,
+        (set_tid, 1) if set_tid != 0 => {
+            let tid = *UserConstPtr::<i32>::from(set_tid as usize).get_as_ref()?;
+            if tid <= 0 {
+                return Err(LinuxError::EINVAL);
+            }
+            Some(tid as Pid)
+        }
+        _ => return Err(LinuxError::EINVAL),
+    };
+
+    do_clone(
+        tf,
+        CloneArgs {
+            flags,
+            exit_signal,
+            // The stack grows down on all the supported architectures.
+            stack: args.stack.wrapping_add(args.stack_size) as usize,
+            parent_tid: args.parent_tid as usize,
+            child_tid: args.child_tid as usize,
+            tls: args.tls as usize,
+            pidfd: args.pidfd as usize,
+            set_tid,
+        },
+    )
+}
+
+fn do_clone(tf: &TrapFrame, args: CloneArgs) -> LinuxResult<isize> {
+    let CloneArgs {
+        flags,
+        exit_signal,
+        stack,
+        parent_tid,
+        child_tid,
+        tls,
+        pidfd,
+        set_tid,
+    } = args;
+
     if flags.contains(CloneFlags::THREAD) && !flags.contains(CloneFlags::VM | CloneFlags::SIGHAND) {
         return Err(LinuxError::EINVAL);
     }
-    let exit_signal = Signo::from_repr(exit_signal as u8);
+    // A pidfd only refers to a process.
+    if flags.contains(CloneFlags::PIDFD | CloneFlags::THREAD) {
+        return Err(LinuxError::EINVAL);
+    }
 
     let mut new_uctx = UspaceContext::from(tf);
     if stack != 0 {
@@ -120,9 +282,20 @@ pub fn sys_clone(
     };
 
     let curr = current();
+    let child_tf = *new_uctx;
     let mut new_task = new_user_task(curr.name(), new_uctx, set_child_tid);
 
     let tid = new_task.id().as_u64() as Pid;
+    if let Some(set_tid) = set_tid {
+        // TIDs are the IDs of the tasks, so another one cannot be chosen.
+        if set_tid != tid {
+            return Err(if get_thread(set_tid).is_ok() {
+                LinuxError::EEXIST
+            } else {
+                LinuxError::EPERM
+            });
+        }
+    }
     if flags.contains(CloneFlags::PARENT_SETTID) {
         *UserPtr::<Pid>::from(parent_tid).get_as_mut()? = tid;
     }
@@ -131,7 +304,7 @@ pub fn sys_clone(
         new_task.ctx_mut().set_page_table_root(
             curr.task_ext()
                 .process_data()
-                .aspace
+                .aspace()
                 .lock()
                 .page_table_root(),
         );
@@ -150,10 +323,10 @@ pub fn sys_clone(
         let builder = parent.fork(tid);
 
         let aspace = if flags.contains(CloneFlags::VM) {
-            curr.task_ext().process_data().aspace.clone()
+            curr.task_ext().process_data().aspace()
         } else {
-            let mut aspace = curr.task_ext().process_data().aspace.lock();
-            let mut aspace = aspace.clone_or_err()?;
+            let aspace = curr.task_ext().process_data().aspace();
+            let mut aspace = aspace.lock().clone_or_err()?;
             copy_from_kernel(&mut aspace)?;
             Arc::new(Mutex::new(aspace))
         };
@@ -174,6 +347,21 @@ pub fn sys_clone(
             signal_actions,
             exit_signal,
         );
+        if !flags.contains(CloneFlags::PARENT) {
+            process_data.set_creator_tid(curr.task_ext().thread.tid());
+        }
+        if flags.contains(CloneFlags::VFORK) {
+            process_data.set_vfork_pending();
+        }
+        process_data.set_user_stack(curr.task_ext().process_data().get_user_stack());
+        *process_data.rlimits.write() = curr.task_ext().process_data().rlimits.read().clone();
+        process_data.syscall_trace.store(
+            curr.task_ext()
+                .process_data()
+                .syscall_trace
+                .load(Ordering::Relaxed),
+            Ordering::Relaxed,
+        );
 
         if flags.contains(CloneFlags::FILES) {
             FD_TABLE
@@ -202,20 +390,72 @@ pub fn sys_clone(
         }
         &builder.data(process_data).build()
     };
+    if !flags.contains(CloneFlags::THREAD) {
+        add_proc_entries(process);
+    }
 
-    let thread_data = ThreadData::new(process.data().unwrap());
+    let thread_data = ThreadData::new(
+        process.data().unwrap(),
+        new_task.kernel_stack_top().unwrap(),
+    );
     if flags.contains(CloneFlags::CHILD_CLEARTID) {
         thread_data.set_clear_child_tid(child_tid);
     }
+    let curr_data = curr.task_ext().thread_data();
+    *thread_data.seccomp.write() = curr_data.seccomp.read().clone();
+    thread_data.no_new_privs.store(
+        curr_data.no_new_privs.load(Ordering::Relaxed),
+        Ordering::Relaxed,
+    );
 
     let thread = process.new_thread(tid).data(thread_data).build();
     add_thread_to_table(&thread);
-    new_task.init_task_ext(TaskExt::new(thread));
-    axtask::spawn_task(new_task);
 
+    if flags.contains(CloneFlags::PIDFD) {
+        *UserPtr::<c_int>::from(pidfd).get_as_mut()? = new_pidfd(process, 0)?;
+    }
+
+    let event = clone_event(flags.contains(CloneFlags::VFORK), exit_signal);
+    let traced = !flags.contains(CloneFlags::UNTRACED)
+        && ptrace_attach_child(
+            &thread,
+            &child_tf,
+            event,
+            flags.contains(CloneFlags::PTRACE),
+        );
+
+    new_task.init_task_ext(TaskExt::new(thread.clone()));
+    let task = axtask::spawn_task(new_task);
+    thread.data::<ThreadData>().unwrap().set_task(&task);
+
+    if traced {
+        ptrace_event(event, tid as _);
+    }
+    if flags.contains(CloneFlags::VFORK) && !flags.contains(CloneFlags::THREAD) {
+        let data = process.data::<ProcessData>().unwrap();
+        let signal = &curr.task_ext().thread_data().signal;
+        // Like Linux, the wait is only given up for `SIGKILL`, sent to the
+        // thread or its process, which both interrupt it and are both in the
+        // pending signals of the thread.
+        let done = loop {
+            if data.wait_vfork_done() {
+                break true;
+            }
+            if signal.pending().has(Signo::SIGKILL) {
+                break false;
+            }
+        };
+        if done {
+            ptrace_event(PTRACE_EVENT_VFORK_DONE, tid as _);
+        }
+    }
     Ok(tid as _)
 }
 
 pub fn sys_fork(tf: &TrapFrame) -> LinuxResult<isize> {
     sys_clone(tf, SIGCHLD, 0, 0, 0, 0)
 }
+
+pub fn sys_vfork(tf: &TrapFrame) -> LinuxResult<isize> {
+    sys_clone(tf, CLONE_VM | CLONE_VFORK | SIGCHLD, 0, 0, 0, 0)
+}
@@ -1,15 +1,21 @@
 use core::ffi::{c_char, CStr};
 use xmas_elf::ElfFile;
 
-use alloc::{string::{String, ToString}, vec::Vec};
+use alloc::{string::{String, ToString}, sync::Arc, vec::Vec};
 use axerrno::{LinuxError, LinuxResult};
 use axhal::arch::TrapFrame;
+use axsync::Mutex;
 use axtask::{TaskExtRef, current};
-use starry_core::mm::{load_user_app, load_elf, map_trampoline};
+use memory_addr::PhysAddr;
+use starry_core::mm::{
+    copy_from_kernel, load_elf, load_user_app, map_trampoline, new_user_aspace_empty,
+};
 
 use crate::{
+    file::FD_TABLE,
     path::{resolve_path_with_flags, PathFlags},
     ptr::UserConstPtr,
+    ptrace::ptrace_exec,
 };
 
 /// Supported interpreter paths that map to musl libc
@@ -192,6 +198,18 @@ fn load_executable(
     }
 }
 
+/// Load `root` as the page table root of user space on the current CPU.
+unsafe fn switch_page_table_root(root: PhysAddr) {
+    #[cfg(any(target_arch = "aarch64", target_arch = "loongarch64"))]
+    unsafe {
+        axhal::arch::write_page_table_root0(root)
+    };
+    #[cfg(not(any(target_arch = "aarch64", target_arch = "loongarch64")))]
+    unsafe {
+        axhal::arch::write_page_table_root(root)
+    };
+}
+
 pub fn sys_execve(
     tf: &mut TrapFrame,
     path: UserConstPtr<c_char>,
@@ -235,8 +253,23 @@ pub fn sys_execve(
         FileFormat::Invalid => return Err(LinuxError::ENOEXEC),
     }
 
-    // Clear address space and set up new memory layout
-    let mut aspace = curr_ext.process_data().aspace.lock();
+    // Clear address space and set up new memory layout. A process sharing its
+    // address space, e.g. the child of `vfork`, gets a new one instead, so
+    // that the memory of the other processes is left as it is.
+    let proc_data = curr_ext.process_data();
+    // Besides that of the process, `aspace()` returns a reference of its own.
+    if Arc::strong_count(&proc_data.aspace()) > 2 {
+        let mut aspace = new_user_aspace_empty()?;
+        copy_from_kernel(&mut aspace)?;
+        let root = aspace.page_table_root();
+        proc_data.set_aspace(Arc::new(Mutex::new(aspace)));
+        curr.with_ctx_mut(|ctx| {
+            ctx.set_page_table_root(root);
+            unsafe { switch_page_table_root(root) };
+        });
+    }
+    let aspace = proc_data.aspace();
+    let mut aspace = aspace.lock();
     aspace.unmap_user_areas()?;
     map_trampoline(&mut aspace)?;
     axhal::arch::flush_tlb(None);
@@ -255,12 +288,20 @@ pub fn sys_execve(
     let name = path.rsplit_once('/').map_or(path.as_str(), |(_, name)| name);
     curr.set_name(name);
     *curr_ext.process_data().exe_path.write() = path;
+    curr_ext.process_data().set_execed();
+    curr_ext.process_data().vfork_done();
+    curr_ext
+        .process_data()
+        .set_user_stack(user_stack_base.as_usize());
 
-    // TODO: Handle file descriptor close-on-exec flags
+    FD_TABLE.close_on_exec();
+    curr_ext.process_data().timers.clear();
 
     // Set up execution context
     tf.set_ip(entry_point.as_usize());
     tf.set_sp(user_stack_base.as_usize());
+
+    ptrace_exec();
     
     Ok(0)
 }
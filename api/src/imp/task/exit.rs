@@ -1,31 +1,45 @@
-use axprocess::Pid;
+use axprocess::{Pid, Process};
 use axsignal::{SignalInfo, Signo};
 use axtask::{TaskExtRef, current};
-use linux_raw_sys::general::SI_KERNEL;
-use starry_core::task::ProcessData;
+use linux_raw_sys::general::{CLD_DUMPED, CLD_EXITED, CLD_KILLED, SI_KERNEL};
+use starry_core::task::{ProcessData, time_stat_update};
 
 use crate::{
+    coredump::WCOREFLAG,
     file::FD_TABLE,
     ptr::UserPtr,
-    signal::{send_signal_process, send_signal_thread},
+    ptrace::{detach_tracees, ptrace_exit},
+    signal::{send_signal_process, send_signal_thread, sigchld_info},
 };
 
+/// Build the signal reporting the exit of `process` to its parent, from its
+/// wait status.
+fn exit_info(process: &Process) -> SignalInfo {
+    let status = process.exit_code();
+    let (code, status) = match status & 0x7f {
+        0 => (CLD_EXITED, (status >> 8) & 0xff),
+        signo if status & WCOREFLAG != 0 => (CLD_DUMPED, signo),
+        signo => (CLD_KILLED, signo),
+    };
+    sigchld_info(process, code, status)
+}
+
 pub fn do_exit(exit_code: i32, group_exit: bool) -> ! {
     let curr = current();
     let curr_ext = curr.task_ext();
 
     let thread = &curr_ext.thread;
     info!("{:?} exit with code: {}", thread, exit_code);
+    ptrace_exit(exit_code);
 
     let clear_child_tid = UserPtr::<Pid>::from(curr_ext.thread_data().clear_child_tid());
     if let Ok(clear_tid) = clear_child_tid.get_as_mut() {
         *clear_tid = 0;
 
-        let guard = curr_ext
-            .process_data()
-            .futex_table
-            .get(clear_tid as *const _ as usize);
+        let futex_table = &curr_ext.process_data().futex_table;
+        let guard = futex_table.get(clear_tid as *const _ as usize);
         if let Some(futex) = guard {
+            futex_table.add_wake();
             futex.notify_one(false);
         }
         axtask::yield_now();
@@ -34,12 +48,19 @@ pub fn do_exit(exit_code: i32, group_exit: bool) -> ! {
     let process = thread.process();
     if thread.exit(exit_code) {
         process.exit();
+        curr_ext.process_data().vfork_done();
+        curr_ext.process_data().itimers.cancel_real();
+        curr_ext.process_data().timers.stop();
+        detach_tracees(curr_ext.process_data());
         if let Some(parent) = process.parent() {
             if let Some(signo) = process.data::<ProcessData>().and_then(|it| it.exit_signal) {
-                let _ = send_signal_process(&parent, SignalInfo::new(signo, SI_KERNEL as _));
+                time_stat_update();
+                let mut sig = exit_info(process);
+                sig.set_signo(signo);
+                let _ = send_signal_process(&parent, sig);
             }
             if let Some(data) = parent.data::<ProcessData>() {
-                data.child_exit_wq.notify_all(false)
+                data.notify_child_event()
             }
         }
 
@@ -59,9 +80,9 @@ pub fn do_exit(exit_code: i32, group_exit: bool) -> ! {
 }
 
 pub fn sys_exit(exit_code: i32) -> ! {
-    do_exit(exit_code << 8, false)
+    do_exit((exit_code & 0xff) << 8, false)
 }
 
 pub fn sys_exit_group(exit_code: i32) -> ! {
-    do_exit(exit_code << 8, true)
+    do_exit((exit_code & 0xff) << 8, true)
 }
@@ -0,0 +1,64 @@
+use core::ffi::c_int;
+
+use alloc::sync::Arc;
+use axerrno::{LinuxError, LinuxResult};
+use axprocess::{Pid, Process};
+use linux_raw_sys::general::{O_CLOEXEC, O_NONBLOCK, O_RDWR};
+use starry_core::task::{ProcessData, get_process};
+
+use crate::file::{FD_TABLE, FileDescriptor, FileLike, PidFd, add_file_descriptor};
+
+/// The flag of `pidfd_open` making `waitid(P_PIDFD)` non-blocking, which is
+/// the same as `O_NONBLOCK`.
+const PIDFD_NONBLOCK: u32 = O_NONBLOCK;
+
+pub fn sys_pidfd_open(pid: Pid, flags: u32) -> LinuxResult<isize> {
+    debug!("sys_pidfd_open <= pid: {}, flags: {:#x}", pid, flags);
+    if flags & !PIDFD_NONBLOCK != 0 || pid == 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let process = get_process(pid)?;
+    if process.is_zombie() {
+        return Err(LinuxError::ESRCH);
+    }
+    new_pidfd(&process, flags).map(|fd| fd as _)
+}
+
+/// Create a pidfd for `process` in the file descriptor table of the current
+/// process. Like Linux, pidfds are always close-on-exec.
+pub fn new_pidfd(process: &Arc<Process>, flags: u32) -> LinuxResult<c_int> {
+    let pidfd = PidFd::new(process);
+    pidfd.set_nonblocking(flags & PIDFD_NONBLOCK != 0)?;
+    pidfd.add_to_fd_table_with_flags(O_RDWR | O_CLOEXEC | flags)
+}
+
+pub fn sys_pidfd_getfd(pidfd: c_int, targetfd: c_int, flags: u32) -> LinuxResult<isize> {
+    debug!(
+        "sys_pidfd_getfd <= pidfd: {}, targetfd: {}, flags: {:#x}",
+        pidfd, targetfd, flags
+    );
+    if flags != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let process = PidFd::from_fd(pidfd)?.process()?;
+    if process.is_zombie() {
+        return Err(LinuxError::ESRCH);
+    }
+    let data = process.data::<ProcessData>().ok_or(LinuxError::ESRCH)?;
+    let file = FD_TABLE
+        .deref_from(&data.ns)
+        .read()
+        .get(targetfd as usize)
+        .map(|fd| fd.file.clone())
+        .ok_or(LinuxError::EBADF)?;
+    // The new descriptor shares the open file description of the target, as
+    // with `dup`, and is close-on-exec.
+    let fd = add_file_descriptor(
+        FileDescriptor {
+            file,
+            cloexec: true,
+        },
+        0,
+    )?;
+    Ok(fd as _)
+}
@@ -1,8 +1,19 @@
+use alloc::boxed::Box;
 use axerrno::{LinuxError, LinuxResult};
-use linux_raw_sys::general::timespec;
+use axhal::time::TimeValue;
+use axtask::{TaskExtRef, WaitQueue, current};
+use linux_raw_sys::general::{
+    __kernel_clockid_t, CLOCK_MONOTONIC_COARSE, CLOCK_MONOTONIC_RAW, CLOCK_REALTIME_COARSE,
+    TIMER_ABSTIME, timespec,
+};
+use starry_core::{
+    clock::Clock,
+    realtime::{realtime_set_count, realtime_set_wait_queue},
+};
 
 use crate::{
     ptr::{UserConstPtr, UserPtr, nullable},
+    signal::have_signals,
     time::TimeValueLike,
 };
 
@@ -11,32 +22,90 @@ pub fn sys_sched_yield() -> LinuxResult<isize> {
     Ok(0)
 }
 
-/// Sleep some nanoseconds
-///
-/// TODO: should be woken by signals, and set errno
-pub fn sys_nanosleep(req: UserConstPtr<timespec>, rem: UserPtr<timespec>) -> LinuxResult<isize> {
-    let req = req.get_as_ref()?;
-
-    if req.tv_nsec < 0 || req.tv_nsec > 999_999_999 || req.tv_sec < 0 {
+fn parse_timespec(ts: UserConstPtr<timespec>) -> LinuxResult<TimeValue> {
+    let ts = ts.get_as_ref()?;
+    if ts.tv_nsec < 0 || ts.tv_nsec > 999_999_999 || ts.tv_sec < 0 {
         return Err(LinuxError::EINVAL);
     }
+    Ok(ts.to_time_value())
+}
 
-    let dur = req.to_time_value();
-    debug!("sys_nanosleep <= {:?}", dur);
-
-    let now = axhal::time::monotonic_time();
-
-    axtask::sleep(dur);
+/// Sleep until `clock` reaches `deadline`, or until a signal arrives.
+///
+/// On interruption, the remaining time is written to `rem`, and the sleep is
+/// continued by `restart_syscall` unless the signal runs a handler.
+fn sleep_until(clock: Clock, deadline: TimeValue, rem: UserPtr<timespec>) -> LinuxResult<isize> {
+    let wq = WaitQueue::new();
+    loop {
+        let sets = realtime_set_count();
+        let now = clock.now();
+        if now >= deadline {
+            return Ok(0);
+        }
+        if have_signals() {
+            if let Some(rem) = nullable!(rem.get_as_mut())? {
+                *rem = timespec::from_time_value(deadline - now);
+            }
+            let rem = rem.address().as_usize();
+            *current().task_ext().thread_data().restart_block.lock() =
+                Some(Box::new(move || sleep_until(clock, deadline, rem.into())));
+            return Err(LinuxError::EINTR);
+        }
+        // Setting the realtime clock moves the deadlines of the clocks
+        // derived from it, and CPU-time clocks do not advance faster than
+        // the time slept.
+        let wq = match clock {
+            Clock::Realtime { .. } | Clock::Tai => realtime_set_wait_queue(),
+            _ => &wq,
+        };
+        wq.wait_timeout_until_interruptible(deadline - now, || realtime_set_count() != sets);
+    }
+}
 
-    let after = axhal::time::monotonic_time();
-    let actual = after - now;
+/// Sleep some nanoseconds
+pub fn sys_nanosleep(req: UserConstPtr<timespec>, rem: UserPtr<timespec>) -> LinuxResult<isize> {
+    let dur = parse_timespec(req)?;
+    debug!("sys_nanosleep <= {:?}", dur);
+    let clock = Clock::Monotonic { coarse: false };
+    sleep_until(clock.clone(), clock.now() + dur, rem)
+}
 
-    if let Some(diff) = dur.checked_sub(actual) {
-        if let Some(rem) = nullable!(rem.get_as_mut())? {
-            *rem = timespec::from_time_value(diff);
-        }
-        Err(LinuxError::EINTR)
+/// Sleep some nanoseconds measured by the given clock, or until an absolute
+/// time with `TIMER_ABSTIME`
+pub fn sys_clock_nanosleep(
+    clock_id: __kernel_clockid_t,
+    flags: u32,
+    req: UserConstPtr<timespec>,
+    rem: UserPtr<timespec>,
+) -> LinuxResult<isize> {
+    // Like Linux, clocks not backed by a high-resolution timer cannot be
+    // slept on.
+    if matches!(
+        clock_id as u32,
+        CLOCK_MONOTONIC_RAW | CLOCK_REALTIME_COARSE | CLOCK_MONOTONIC_COARSE
+    ) {
+        return Err(LinuxError::EOPNOTSUPP);
+    }
+    let clock = Clock::from_id(clock_id).inspect_err(|_| {
+        warn!(
+            "Called sys_clock_nanosleep for unsupported clock {}",
+            clock_id
+        )
+    })?;
+    // The thread would never wake up, as it does not run while sleeping.
+    if clock.is_current_thread_cpu() {
+        return Err(LinuxError::EINVAL);
+    }
+    let time = parse_timespec(req)?;
+    debug!(
+        "sys_clock_nanosleep <= {} {:?} {:#x}",
+        clock_id, time, flags
+    );
+    if flags & TIMER_ABSTIME != 0 {
+        // The remaining time is not reported for an absolute deadline.
+        sleep_until(clock, time, UserPtr::from(0))
     } else {
-        Ok(0)
+        let now = clock.now();
+        sleep_until(clock, now + time, rem)
     }
 }
@@ -1,8 +1,27 @@
-use axerrno::LinuxResult;
+use alloc::{string::String, sync::Arc};
+use axerrno::{LinuxError, LinuxResult};
+use axprocess::Pid;
 use axtask::{TaskExtRef, current};
+use core::sync::atomic::Ordering;
+use linux_raw_sys::{
+    prctl::{
+        PR_GET_NAME, PR_GET_NO_NEW_PRIVS, PR_GET_SECCOMP, PR_SET_NAME, PR_SET_NO_NEW_PRIVS,
+        PR_SET_SECCOMP,
+    },
+    ptrace::{
+        SECCOMP_MODE_FILTER, SECCOMP_MODE_STRICT, SECCOMP_SET_MODE_FILTER, SECCOMP_SET_MODE_STRICT,
+    },
+};
 use num_enum::TryFromPrimitive;
-use axprocess::Pid;
-use starry_core::task::get_process;
+use starry_core::task::{ProcessData, add_process_group_to_table, get_process, get_process_group};
+
+use crate::{
+    ptr::{UserConstPtr, UserPtr},
+    sys_seccomp,
+};
+
+/// The size of the name of a thread, including the terminating null byte
+const TASK_COMM_LEN: usize = 16;
 
 pub fn sys_getpid() -> LinuxResult<isize> {
     Ok(axtask::current().task_ext().thread.process().pid() as _)
@@ -37,6 +56,142 @@ pub fn sys_getpgid(pid: i32) -> LinuxResult<isize> {
     }
 }
 
+#[cfg(target_arch = "x86_64")]
+pub fn sys_getpgrp() -> LinuxResult<isize> {
+    sys_getpgid(0)
+}
+
+pub fn sys_setpgid(pid: i32, pgid: i32) -> LinuxResult<isize> {
+    debug!("sys_setpgid <= pid: {}, pgid: {}", pid, pgid);
+    if pid < 0 || pgid < 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let curr = current();
+    let process = curr.task_ext().thread.process();
+    let session = process.group().session();
+
+    // Only the caller itself or one of its children can be moved.
+    let target = if pid == 0 {
+        process.clone()
+    } else {
+        get_process(pid as Pid)?
+    };
+    if !Arc::ptr_eq(&target, process) {
+        if !target
+            .parent()
+            .is_some_and(|parent| Arc::ptr_eq(&parent, process))
+        {
+            return Err(LinuxError::ESRCH);
+        }
+        if target
+            .data::<ProcessData>()
+            .is_some_and(ProcessData::has_execed)
+        {
+            return Err(LinuxError::EACCES);
+        }
+        if !Arc::ptr_eq(&target.group().session(), &session) {
+            return Err(LinuxError::EPERM);
+        }
+    }
+    if session.sid() == target.pid() {
+        return Err(LinuxError::EPERM);
+    }
+
+    let pgid = if pgid == 0 { target.pid() } else { pgid as Pid };
+    match get_process_group(pgid) {
+        Ok(group) => {
+            if !target.move_to_group(&group) {
+                return Err(LinuxError::EPERM);
+            }
+        }
+        Err(_) if pgid == target.pid() => {
+            if let Some(group) = target.create_group() {
+                add_process_group_to_table(&group);
+            }
+        }
+        Err(_) => return Err(LinuxError::EPERM),
+    }
+    Ok(0)
+}
+
+pub fn sys_getsid(pid: i32) -> LinuxResult<isize> {
+    let process = if pid == 0 {
+        current().task_ext().thread.process().clone()
+    } else {
+        get_process(pid as Pid)?
+    };
+    Ok(process.group().session().sid() as _)
+}
+
+pub fn sys_setsid() -> LinuxResult<isize> {
+    let curr = current();
+    let process = curr.task_ext().thread.process();
+    // The new process group must not exist yet, which also rules out process
+    // group leaders.
+    if get_process_group(process.pid()).is_ok() {
+        return Err(LinuxError::EPERM);
+    }
+    let (session, group) = process.create_session().ok_or(LinuxError::EPERM)?;
+    add_process_group_to_table(&group);
+    Ok(session.sid() as _)
+}
+
+pub fn sys_prctl(
+    option: u32,
+    arg2: usize,
+    arg3: usize,
+    arg4: usize,
+    arg5: usize,
+) -> LinuxResult<isize> {
+    debug!(
+        "sys_prctl <= option: {}, args: {:#x}, {:#x}, {:#x}, {:#x}",
+        option, arg2, arg3, arg4, arg5
+    );
+    let curr = current();
+    let thr_data = curr.task_ext().thread_data();
+    match option {
+        PR_GET_SECCOMP => Ok(thr_data.seccomp.read().mode as _),
+        PR_SET_SECCOMP => match arg2 as u32 {
+            SECCOMP_MODE_STRICT => sys_seccomp(SECCOMP_SET_MODE_STRICT, 0, 0),
+            SECCOMP_MODE_FILTER => sys_seccomp(SECCOMP_SET_MODE_FILTER, 0, arg3),
+            _ => Err(LinuxError::EINVAL),
+        },
+        PR_GET_NO_NEW_PRIVS => {
+            if arg2 != 0 || arg3 != 0 || arg4 != 0 || arg5 != 0 {
+                return Err(LinuxError::EINVAL);
+            }
+            Ok(thr_data.no_new_privs.load(Ordering::Relaxed) as _)
+        }
+        PR_SET_NO_NEW_PRIVS => {
+            // The flag can only be set, and is kept across `execve`.
+            if arg2 != 1 || arg3 != 0 || arg4 != 0 || arg5 != 0 {
+                return Err(LinuxError::EINVAL);
+            }
+            thr_data.no_new_privs.store(true, Ordering::Relaxed);
+            Ok(0)
+        }
+        PR_SET_NAME => {
+            // Like Linux, a longer name is truncated.
+            let name = UserConstPtr::<u8>::from(arg2).get_as_null_terminated()?;
+            let name = &name[..name.len().min(TASK_COMM_LEN - 1)];
+            curr.set_name(&String::from_utf8_lossy(name));
+            Ok(0)
+        }
+        PR_GET_NAME => {
+            let buf = UserPtr::<u8>::from(arg2).get_as_mut_slice(TASK_COMM_LEN)?;
+            let name = curr.name().as_bytes();
+            let len = name.len().min(TASK_COMM_LEN - 1);
+            buf.fill(0);
+            buf[..len].copy_from_slice(&name[..len]);
+            Ok(0)
+        }
+        _ => {
+            warn!("sys_prctl: unsupported option {}", option);
+            Err(LinuxError::ENOSYS)
+        }
+    }
+}
+
 /// ARCH_PRCTL codes
 ///
 /// It is only avaliable on x86_64, and is not convenient
@@ -1,14 +1,32 @@
+use core::time::Duration;
+
 use alloc::{sync::Arc, vec::Vec};
 use axerrno::{LinuxError, LinuxResult};
-use axprocess::{Pid, Process};
+use axhal::time::nanos_to_ticks;
+use axprocess::{Pid, Process, Thread};
+use axsignal::{SignalInfo, Signo};
 use axtask::{TaskExtRef, current};
 use bitflags::bitflags;
-use linux_raw_sys::general::{
-    __WALL, __WCLONE, __WNOTHREAD, WCONTINUED, WEXITED, WNOHANG, WNOWAIT, WUNTRACED,
+use linux_raw_sys::{
+    general::{
+        __WALL, __WCLONE, __WNOTHREAD, __kernel_old_timeval, __sifields__bindgen_ty_4,
+        CLD_CONTINUED, CLD_DUMPED, CLD_EXITED, CLD_KILLED, CLD_STOPPED, CLD_TRAPPED, P_ALL, P_PGID,
+        P_PID, P_PIDFD, WCONTINUED, WEXITED, WNOHANG, WNOWAIT, WUNTRACED, rusage,
+    },
+    ptrace::{PTRACE_EVENT_STOP, PTRACE_O_TRACESYSGOOD},
+};
+use starry_core::{
+    ptrace::PtraceStop,
+    task::{JobEvent, ProcessData, ThreadData},
 };
-use starry_core::task::ProcessData;
 
-use crate::ptr::{UserPtr, nullable};
+use crate::{
+    file::{FileLike, PidFd},
+    ptr::{UserPtr, nullable},
+    signal::have_signals,
+    time::TimeValueLike,
+    trace::remove_proc_entries,
+};
 
 bitflags! {
     #[derive(Debug)]
@@ -53,54 +71,314 @@ impl WaitPid {
             WaitPid::Pgid(pgid) => child.group().pgid() == *pgid,
         }
     }
+
+    fn apply_thread(&self, thread: &Thread) -> bool {
+        match self {
+            WaitPid::Pid(tid) => thread.tid() == *tid,
+            _ => self.apply(thread.process()),
+        }
+    }
 }
 
-pub fn sys_waitpid(pid: i32, exit_code_ptr: UserPtr<i32>, options: u32) -> LinuxResult<isize> {
-    let options = WaitOptions::from_bits_truncate(options);
-    info!("sys_waitpid <= pid: {:?}, options: {:?}", pid, options);
+impl WaitOptions {
+    /// Whether a process or thread is waited for, given whether it is a
+    /// "clone" child.
+    fn accepts(&self, is_clone: bool) -> bool {
+        self.contains(WaitOptions::WALL) || self.contains(WaitOptions::WCLONE) == is_clone
+    }
+}
 
+/// Take the unreported job control state change of `child` selected by
+/// `options`, encoded as a wait status.
+fn job_status(child: &Process, options: &WaitOptions) -> Option<i32> {
+    let data = child.data::<ProcessData>()?;
+    let event = data.take_job_event(
+        options.contains(WaitOptions::WNOWAIT),
+        |event| match event {
+            JobEvent::Stopped(_) => options.contains(WaitOptions::WUNTRACED),
+            JobEvent::Continued => options.contains(WaitOptions::WCONTINUED),
+        },
+    )?;
+    Some(match event {
+        JobEvent::Stopped(signo) => ((signo as i32) << 8) | 0x7f,
+        JobEvent::Continued => 0xffff,
+    })
+}
+
+/// Take the unreported stop or exit of the traced thread `tracee`, encoded
+/// as a wait status, along with whether it exited.
+fn tracee_status(tracee: &Thread, options: &WaitOptions) -> Option<(i32, bool)> {
+    let data = tracee.data::<ThreadData>()?;
+    let peek = options.contains(WaitOptions::WNOWAIT);
+    let mut state = data.ptrace.lock();
+    if let Some(status) = state.exit_status {
+        return Some((status, true));
+    }
+    if state.reported {
+        return None;
+    }
+    let sig = match state.stop.as_ref()? {
+        PtraceStop::Signal(sig) => sig.signo() as u32,
+        PtraceStop::SyscallEntry | PtraceStop::SyscallExit => {
+            if state.options & PTRACE_O_TRACESYSGOOD != 0 {
+                Signo::SIGTRAP as u32 | 0x80
+            } else {
+                Signo::SIGTRAP as u32
+            }
+        }
+        PtraceStop::Event(PTRACE_EVENT_STOP) => Signo::SIGSTOP as u32 | (PTRACE_EVENT_STOP << 8),
+        PtraceStop::Event(event) => Signo::SIGTRAP as u32 | (event << 8),
+    };
+    if !peek {
+        state.reported = true;
+    }
+    Some((((sig << 8) | 0x7f) as i32, false))
+}
+
+/// Decode a wait status into the `si_code` and `si_status` of the
+/// `siginfo_t` reported by `waitid`.
+fn decode_status(status: i32, traced: bool) -> (u32, i32) {
+    if status == 0xffff {
+        (CLD_CONTINUED, Signo::SIGCONT as _)
+    } else if status & 0x7f == 0x7f {
+        let code = if traced { CLD_TRAPPED } else { CLD_STOPPED };
+        (code, (status >> 8) & 0xff)
+    } else if status & 0x7f == 0 {
+        (CLD_EXITED, (status >> 8) & 0xff)
+    } else if status & 0x80 != 0 {
+        (CLD_DUMPED, status & 0x7f)
+    } else {
+        (CLD_KILLED, status & 0x7f)
+    }
+}
+
+/// A state change of a child or tracee reported by [`do_wait`].
+struct WaitResult {
+    pid: Pid,
+    status: i32,
+    traced: bool,
+    /// The user and system time of the process, including that of its
+    /// waited-for children
+    utime: Duration,
+    stime: Duration,
+}
+
+impl WaitResult {
+    fn new(process: &Process, pid: Pid, status: i32, traced: bool) -> Self {
+        let (utime, stime) = process
+            .data::<ProcessData>()
+            .map_or_else(Default::default, |it| {
+                (
+                    it.cpu_time.user() + it.children_cpu_time.user(),
+                    it.cpu_time.system() + it.children_cpu_time.system(),
+                )
+            });
+        Self {
+            pid,
+            status,
+            traced,
+            utime,
+            stime,
+        }
+    }
+
+    fn rusage(&self) -> rusage {
+        let mut usage: rusage = unsafe { core::mem::zeroed() };
+        usage.ru_utime = __kernel_old_timeval::from_time_value(self.utime);
+        usage.ru_stime = __kernel_old_timeval::from_time_value(self.stime);
+        usage
+    }
+
+    fn siginfo(&self) -> SignalInfo {
+        let (code, status) = decode_status(self.status, self.traced);
+        let mut sig = SignalInfo::new(Signo::SIGCHLD, code as _);
+        sig.0.__bindgen_anon_1.__bindgen_anon_1._sifields._sigchld = __sifields__bindgen_ty_4 {
+            _pid: self.pid as _,
+            _uid: 0,
+            _status: status,
+            _utime: nanos_to_ticks(self.utime.as_nanos() as _) as _,
+            _stime: nanos_to_ticks(self.stime.as_nanos() as _) as _,
+        };
+        sig
+    }
+}
+
+/// Wait for a state change of the children or tracees of the current
+/// process selected by `pid` and `options`.
+///
+/// Returns `None` if none is available and `WNOHANG` is set.
+fn do_wait(pid: WaitPid, options: &WaitOptions) -> LinuxResult<Option<WaitResult>> {
     let curr = current();
     let proc_data = curr.task_ext().process_data();
     let process = curr.task_ext().thread.process();
 
-    let pid = if pid == -1 {
-        WaitPid::Any
-    } else if pid == 0 {
-        WaitPid::Pgid(process.group().pgid())
-    } else if pid > 0 {
-        WaitPid::Pid(pid as _)
-    } else {
-        WaitPid::Pgid(-pid as _)
-    };
-
     let children = process
         .children()
         .into_iter()
         .filter(|child| pid.apply(child))
         .filter(|child| {
-            options.contains(WaitOptions::WALL)
-                || (options.contains(WaitOptions::WCLONE)
-                    == child.data::<ProcessData>().unwrap().is_clone_child())
+            let data = child.data::<ProcessData>().unwrap();
+            options.accepts(data.is_clone_child())
+                && (!options.contains(WaitOptions::WNOTHREAD)
+                    || data.creator_tid() == curr.task_ext().thread.tid())
+        })
+        .collect::<Vec<_>>();
+    let tracees = proc_data
+        .tracees
+        .lock()
+        .iter()
+        .filter(|tracee| pid.apply_thread(tracee))
+        .filter(|tracee| {
+            let process = tracee.process();
+            options.accepts(
+                tracee.tid() != process.pid()
+                    || process.data::<ProcessData>().unwrap().is_clone_child(),
+            )
         })
+        .cloned()
         .collect::<Vec<_>>();
-    if children.is_empty() {
+    if children.is_empty() && tracees.is_empty() {
         return Err(LinuxError::ECHILD);
     }
 
-    let exit_code = nullable!(exit_code_ptr.get_as_mut())?;
     loop {
-        if let Some(child) = children.iter().find(|child| child.is_zombie()) {
+        let events = proc_data.child_events();
+        if let Some(child) = children
+            .iter()
+            .find(|child| options.contains(WaitOptions::WEXITED) && child.is_zombie())
+        {
+            let result = WaitResult::new(child, child.pid(), child.exit_code(), false);
             if !options.contains(WaitOptions::WNOWAIT) {
                 child.free();
+                remove_proc_entries(child.pid());
+                let nanos = |time: Duration| time.as_nanos() as u64;
+                proc_data
+                    .children_cpu_time
+                    .add(nanos(result.utime), nanos(result.stime));
             }
-            if let Some(exit_code) = exit_code {
-                *exit_code = child.exit_code();
+            return Ok(Some(result));
+        } else if let Some((tracee, (status, exited))) = tracees
+            .iter()
+            .find_map(|tracee| Some((tracee, tracee_status(tracee, options)?)))
+        {
+            if exited && !options.contains(WaitOptions::WNOWAIT) {
+                if let Some(data) = tracee.data::<ThreadData>() {
+                    data.ptrace.detach(None);
+                }
+                proc_data
+                    .tracees
+                    .lock()
+                    .retain(|it| !Arc::ptr_eq(it, tracee));
             }
-            return Ok(child.pid() as _);
+            return Ok(Some(WaitResult::new(
+                tracee.process(),
+                tracee.tid(),
+                status,
+                !exited,
+            )));
+        } else if let Some((child, status)) = children
+            .iter()
+            .find_map(|child| Some((child, job_status(child, options)?)))
+        {
+            return Ok(Some(WaitResult::new(child, child.pid(), status, false)));
         } else if options.contains(WaitOptions::WNOHANG) {
-            return Ok(0);
+            return Ok(None);
+        } else if have_signals() {
+            return Err(LinuxError::EINTR);
         } else {
-            proc_data.child_exit_wq.wait();
+            proc_data.wait_child_event(events);
         }
     }
 }
+
+pub fn sys_waitpid(
+    pid: i32,
+    exit_code_ptr: UserPtr<i32>,
+    options: u32,
+    rusage_ptr: UserPtr<rusage>,
+) -> LinuxResult<isize> {
+    let options = WaitOptions::from_bits(options).ok_or(LinuxError::EINVAL)? | WaitOptions::WEXITED;
+    info!("sys_waitpid <= pid: {:?}, options: {:?}", pid, options);
+
+    let pid = if pid == -1 {
+        WaitPid::Any
+    } else if pid == 0 {
+        WaitPid::Pgid(current().task_ext().thread.process().group().pgid())
+    } else if pid > 0 {
+        WaitPid::Pid(pid as _)
+    } else {
+        WaitPid::Pgid(-pid as _)
+    };
+
+    let exit_code = nullable!(exit_code_ptr.get_as_mut())?;
+    let usage = nullable!(rusage_ptr.get_as_mut())?;
+    let Some(result) = do_wait(pid, &options)? else {
+        return Ok(0);
+    };
+    if let Some(exit_code) = exit_code {
+        *exit_code = result.status;
+    }
+    if let Some(usage) = usage {
+        *usage = result.rusage();
+    }
+    Ok(result.pid as _)
+}
+
+pub fn sys_waitid(
+    idtype: u32,
+    id: i32,
+    info_ptr: UserPtr<SignalInfo>,
+    options: u32,
+    rusage_ptr: UserPtr<rusage>,
+) -> LinuxResult<isize> {
+    let mut options = WaitOptions::from_bits(options).ok_or(LinuxError::EINVAL)?;
+    info!(
+        "sys_waitid <= idtype: {}, id: {}, options: {:?}",
+        idtype, id, options
+    );
+    if !options.intersects(WaitOptions::WEXITED | WaitOptions::WUNTRACED | WaitOptions::WCONTINUED)
+    {
+        return Err(LinuxError::EINVAL);
+    }
+
+    let mut nonblocking_pidfd = false;
+    let pid = match idtype {
+        P_ALL => WaitPid::Any,
+        P_PID if id > 0 => WaitPid::Pid(id as _),
+        P_PGID if id == 0 => WaitPid::Pgid(current().task_ext().thread.process().group().pgid()),
+        P_PGID if id > 0 => WaitPid::Pgid(id as _),
+        P_PIDFD => {
+            let pidfd = PidFd::from_fd(id)?;
+            // A reaped process can no longer be waited for.
+            pidfd.process().map_err(|_| LinuxError::ECHILD)?;
+            if pidfd.nonblocking() && !options.contains(WaitOptions::WNOHANG) {
+                options |= WaitOptions::WNOHANG;
+                nonblocking_pidfd = true;
+            }
+            WaitPid::Pid(pidfd.pid())
+        }
+        _ => return Err(LinuxError::EINVAL),
+    };
+
+    let info = nullable!(info_ptr.get_as_mut())?;
+    let usage = nullable!(rusage_ptr.get_as_mut())?;
+    let result = do_wait(pid, &options)?;
+    if result.is_none() && nonblocking_pidfd {
+        return Err(LinuxError::EAGAIN);
+    }
+    if let Some(info) = info {
+        // Like Linux, the `siginfo_t` is zeroed if no child is waitable and
+        // `WNOHANG` is given.
+        *info = match &result {
+            Some(result) => result.siginfo(),
+            None => SignalInfo(unsafe { core::mem::zeroed() }),
+        };
+    }
+    if let Some(usage) = usage {
+        *usage = match &result {
+            Some(result) => result.rusage(),
+            None => unsafe { core::mem::zeroed() },
+        };
+    }
+    Ok(0)
+}
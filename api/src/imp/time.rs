@@ -1,36 +1,283 @@
+use alloc::sync::Arc;
+use core::{ffi::c_int, time::Duration};
+
 use axerrno::{LinuxError, LinuxResult};
-use axhal::time::{monotonic_time, monotonic_time_nanos, nanos_to_ticks, wall_time};
+use axhal::time::{monotonic_time_nanos, nanos_to_ticks};
+use axsignal::Signo;
+use axtask::{TaskExtRef, current};
 use linux_raw_sys::general::{
-    __kernel_clockid_t, CLOCK_MONOTONIC, CLOCK_REALTIME, timespec, timeval,
+    __kernel_clockid_t, __kernel_timer_t, CLOCK_BOOTTIME, CLOCK_MONOTONIC,
+    CLOCK_PROCESS_CPUTIME_ID, CLOCK_REALTIME, CLOCK_TAI, CLOCK_THREAD_CPUTIME_ID, ITIMER_REAL,
+    O_RDWR, SIGEV_NONE, SIGEV_SIGNAL, SIGEV_THREAD, SIGEV_THREAD_ID, TFD_CLOEXEC, TFD_NONBLOCK,
+    TFD_TIMER_ABSTIME, TFD_TIMER_CANCEL_ON_SET, TIMER_ABSTIME, itimerspec, itimerval, sigevent,
+    timespec, timeval,
+};
+use starry_core::{
+    clock::Clock,
+    itimer::ITimer,
+    posix_timer::{PosixTimer, TimerNotify},
+    realtime::{
+        ClockAdjustment, MAX_FREQ, NTP_PHASE_LIMIT, STA_UNSYNC, adjust_clock, clock_adjustment,
+        realtime, set_realtime, shift_realtime,
+    },
+    task::time_stat_update,
 };
-use starry_core::task::time_stat_output;
 
-use crate::{ptr::UserPtr, time::TimeValueLike};
+use crate::{
+    file::{FileLike, TimerFd},
+    ptr::{UserConstPtr, UserPtr, nullable},
+    time::TimeValueLike,
+};
 
 pub fn sys_clock_gettime(
     clock_id: __kernel_clockid_t,
     ts: UserPtr<timespec>,
 ) -> LinuxResult<isize> {
-    let now = match clock_id as u32 {
-        CLOCK_REALTIME => wall_time(),
-        CLOCK_MONOTONIC => monotonic_time(),
-        _ => {
-            warn!(
-                "Called sys_clock_gettime for unsupported clock {}",
-                clock_id
-            );
-            return Err(LinuxError::EINVAL);
-        }
-    };
-    *ts.get_as_mut()? = timespec::from_time_value(now);
+    let clock = Clock::from_id(clock_id).inspect_err(|_| {
+        warn!(
+            "Called sys_clock_gettime for unsupported clock {}",
+            clock_id
+        )
+    })?;
+    *ts.get_as_mut()? = timespec::from_time_value(clock.now());
+    Ok(0)
+}
+
+pub fn sys_clock_getres(
+    clock_id: __kernel_clockid_t,
+    res: UserPtr<timespec>,
+) -> LinuxResult<isize> {
+    let clock = Clock::from_id(clock_id)
+        .inspect_err(|_| warn!("Called sys_clock_getres for unsupported clock {}", clock_id))?;
+    if let Some(res) = nullable!(res.get_as_mut())? {
+        *res = timespec::from_time_value(clock.resolution());
+    }
     Ok(0)
 }
 
 pub fn sys_gettimeofday(ts: UserPtr<timeval>) -> LinuxResult<isize> {
-    *ts.get_as_mut()? = timeval::from_time_value(wall_time());
+    *ts.get_as_mut()? = timeval::from_time_value(realtime());
     Ok(0)
 }
 
+pub fn sys_settimeofday(tv: UserConstPtr<timeval>, _tz: UserConstPtr<u8>) -> LinuxResult<isize> {
+    // The time zone is obsolete and ignored.
+    let Some(tv) = nullable!(tv.get_as_ref())? else {
+        return Ok(0);
+    };
+    let time = parse_timeval(tv)?;
+    debug!("sys_settimeofday <= {:?}", time);
+    set_realtime(time);
+    Ok(0)
+}
+
+pub fn sys_clock_settime(
+    clock_id: __kernel_clockid_t,
+    ts: UserConstPtr<timespec>,
+) -> LinuxResult<isize> {
+    // Only the realtime clock can be set.
+    if clock_id as u32 != CLOCK_REALTIME {
+        Clock::from_id(clock_id)?;
+        return Err(LinuxError::EINVAL);
+    }
+    let time = parse_timespec(ts.get_as_ref()?)?;
+    debug!("sys_clock_settime <= {:?}", time);
+    set_realtime(time);
+    Ok(0)
+}
+
+/// The parameters of the kernel clock, `struct timex`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Timex {
+    modes: u32,
+    offset: i64,
+    freq: i64,
+    maxerror: i64,
+    esterror: i64,
+    status: i32,
+    constant: i64,
+    precision: i64,
+    tolerance: i64,
+    time: timeval,
+    tick: i64,
+    ppsfreq: i64,
+    jitter: i64,
+    shift: i32,
+    stabil: i64,
+    jitcnt: i64,
+    calcnt: i64,
+    errcnt: i64,
+    stbcnt: i64,
+    tai: i32,
+    _reserved: [i32; 11],
+}
+
+const ADJ_OFFSET: u32 = 0x0001;
+const ADJ_FREQUENCY: u32 = 0x0002;
+const ADJ_MAXERROR: u32 = 0x0004;
+const ADJ_ESTERROR: u32 = 0x0008;
+const ADJ_STATUS: u32 = 0x0010;
+const ADJ_TIMECONST: u32 = 0x0020;
+const ADJ_TAI: u32 = 0x0080;
+const ADJ_SETOFFSET: u32 = 0x0100;
+const ADJ_MICRO: u32 = 0x1000;
+const ADJ_NANO: u32 = 0x2000;
+const ADJ_TICK: u32 = 0x4000;
+/// Slew the offset like `adjtime(3)`
+const ADJ_OFFSET_SINGLESHOT: u32 = 0x8001;
+/// Only read the offset left to slew by `adjtime(3)`
+const ADJ_OFFSET_SS_READ: u32 = 0xa001;
+
+/// The status flags which cannot be changed
+const STA_RONLY: i32 = 0xff00;
+const STA_CLOCKERR: i32 = 0x1000;
+const STA_NANO: i32 = 0x2000;
+
+const TIME_OK: isize = 0;
+const TIME_ERROR: isize = 5;
+
+/// The maximum offset which can be slewed with `ADJ_OFFSET`, in nanoseconds
+const MAXPHASE: i64 = 500_000_000;
+/// The maximum time constant
+const MAXTC: i64 = 10;
+
+/// Read and change the adjustment of the realtime clock, as requested by
+/// `tx`.
+fn do_adjtimex(tx: &mut Timex) -> LinuxResult<isize> {
+    let modes = tx.modes;
+    if modes & ADJ_OFFSET_SINGLESHOT == ADJ_OFFSET_SINGLESHOT {
+        // `adjtime(3)` cannot be mixed with the other modes.
+        if modes != ADJ_OFFSET_SINGLESHOT && modes != ADJ_OFFSET_SS_READ {
+            return Err(LinuxError::EINVAL);
+        }
+    } else if modes & ADJ_TICK != 0 {
+        let tick = ClockAdjustment::default_tick();
+        if !(tick * 9 / 10..=tick * 11 / 10).contains(&tx.tick) {
+            return Err(LinuxError::EINVAL);
+        }
+    }
+    let nano_time = modes & ADJ_NANO != 0;
+    let setoffset = if modes & ADJ_SETOFFSET != 0 {
+        let frac_limit = if nano_time { 1_000_000_000 } else { 1_000_000 };
+        if !(0..frac_limit).contains(&tx.time.tv_usec) {
+            return Err(LinuxError::EINVAL);
+        }
+        let frac_ns = if nano_time {
+            tx.time.tv_usec
+        } else {
+            tx.time.tv_usec * 1000
+        };
+        Some(tx.time.tv_sec * 1_000_000_000 + frac_ns)
+    } else {
+        None
+    };
+    if let Some(delta_ns) = setoffset {
+        shift_realtime(delta_ns);
+    }
+    let old = adjust_clock(|adj| {
+        if modes & ADJ_OFFSET_SINGLESHOT == ADJ_OFFSET_SINGLESHOT {
+            if modes == ADJ_OFFSET_SINGLESHOT {
+                adj.offset_ns = tx.offset * 1000;
+            }
+            return;
+        }
+        if modes & ADJ_STATUS != 0 {
+            adj.status = (adj.status & STA_RONLY) | (tx.status & !STA_RONLY);
+        }
+        if modes & ADJ_NANO != 0 {
+            adj.status |= STA_NANO;
+        }
+        if modes & ADJ_MICRO != 0 {
+            adj.status &= !STA_NANO;
+        }
+        if modes & ADJ_FREQUENCY != 0 {
+            adj.freq = tx.freq;
+        }
+        if modes & ADJ_MAXERROR != 0 {
+            adj.maxerror = tx.maxerror.clamp(0, NTP_PHASE_LIMIT);
+        }
+        if modes & ADJ_ESTERROR != 0 {
+            adj.esterror = tx.esterror.clamp(0, NTP_PHASE_LIMIT);
+        }
+        if modes & ADJ_TIMECONST != 0 {
+            adj.constant = tx.constant.clamp(0, MAXTC);
+        }
+        // Like Linux, the offset of TAI is passed in `constant`.
+        if modes & ADJ_TAI != 0 && tx.constant >= 0 {
+            adj.tai = tx.constant as _;
+        }
+        if modes & ADJ_OFFSET != 0 {
+            let offset_ns = if adj.status & STA_NANO != 0 {
+                tx.offset
+            } else {
+                tx.offset * 1000
+            };
+            adj.offset_ns = offset_ns.clamp(-MAXPHASE, MAXPHASE);
+        }
+        if modes & ADJ_TICK != 0 {
+            adj.tick = tx.tick;
+        }
+    });
+    debug!("sys_adjtimex <= modes: {:#x}, old: {:?}", modes, old);
+
+    let adj = clock_adjustment();
+    let nano = adj.status & STA_NANO != 0;
+    tx.offset = if modes & ADJ_OFFSET_SINGLESHOT == ADJ_OFFSET_SINGLESHOT {
+        // `adjtime(3)` gets the offset which was left before the call.
+        old.offset_ns / 1000
+    } else if nano {
+        adj.offset_ns
+    } else {
+        adj.offset_ns / 1000
+    };
+    tx.freq = adj.freq;
+    tx.maxerror = adj.maxerror;
+    tx.esterror = adj.esterror;
+    tx.status = adj.status;
+    tx.constant = adj.constant;
+    tx.precision = 1;
+    tx.tolerance = MAX_FREQ;
+    let now = realtime();
+    tx.time = timeval {
+        tv_sec: now.as_secs() as _,
+        tv_usec: if nano {
+            now.subsec_nanos()
+        } else {
+            now.subsec_micros()
+        } as _,
+    };
+    tx.tick = adj.tick;
+    tx.ppsfreq = 0;
+    tx.jitter = 0;
+    tx.shift = 0;
+    tx.stabil = 0;
+    tx.jitcnt = 0;
+    tx.calcnt = 0;
+    tx.errcnt = 0;
+    tx.stbcnt = 0;
+    tx.tai = adj.tai;
+
+    Ok(if adj.status & (STA_UNSYNC | STA_CLOCKERR) != 0 {
+        TIME_ERROR
+    } else {
+        TIME_OK
+    })
+}
+
+pub fn sys_adjtimex(tx: UserPtr<Timex>) -> LinuxResult<isize> {
+    do_adjtimex(tx.get_as_mut()?)
+}
+
+pub fn sys_clock_adjtime(clock_id: __kernel_clockid_t, tx: UserPtr<Timex>) -> LinuxResult<isize> {
+    // Only the realtime clock can be adjusted.
+    if clock_id as u32 != CLOCK_REALTIME {
+        Clock::from_id(clock_id)?;
+        return Err(LinuxError::EOPNOTSUPP);
+    }
+    do_adjtimex(tx.get_as_mut()?)
+}
+
 #[repr(C)]
 pub struct Tms {
     /// user time
@@ -44,12 +291,304 @@ pub struct Tms {
 }
 
 pub fn sys_times(tms: UserPtr<Tms>) -> LinuxResult<isize> {
-    let (_, utime_us, _, stime_us) = time_stat_output();
+    time_stat_update();
+    let proc_data = current().task_ext().process_data();
+    let ticks = |time: Duration| nanos_to_ticks(time.as_nanos() as _) as usize;
     *tms.get_as_mut()? = Tms {
-        tms_utime: utime_us,
-        tms_stime: stime_us,
-        tms_cutime: utime_us,
-        tms_cstime: stime_us,
+        tms_utime: ticks(proc_data.cpu_time.user()),
+        tms_stime: ticks(proc_data.cpu_time.system()),
+        tms_cutime: ticks(proc_data.children_cpu_time.user()),
+        tms_cstime: ticks(proc_data.children_cpu_time.system()),
     };
     Ok(nanos_to_ticks(monotonic_time_nanos()) as _)
 }
+
+fn itimerval_from(timer: ITimer) -> itimerval {
+    itimerval {
+        it_interval: timeval::from_time_value(timer.interval),
+        it_value: timeval::from_time_value(timer.value),
+    }
+}
+
+fn parse_timeval(tv: &timeval) -> LinuxResult<Duration> {
+    if tv.tv_sec < 0 || !(0..1_000_000).contains(&tv.tv_usec) {
+        return Err(LinuxError::EINVAL);
+    }
+    Ok(tv.to_time_value())
+}
+
+/// Set the interval timer `which` of the current process to `timer`,
+/// returning the previous setting.
+fn set_itimer(which: u32, timer: ITimer) -> LinuxResult<ITimer> {
+    let curr = current();
+    let process = Arc::downgrade(curr.task_ext().thread.process());
+    curr.task_ext()
+        .process_data()
+        .itimers
+        .set(process, which, timer)
+        .ok_or(LinuxError::EINVAL)
+}
+
+pub fn sys_getitimer(which: u32, curr_value: UserPtr<itimerval>) -> LinuxResult<isize> {
+    let timer = current()
+        .task_ext()
+        .process_data()
+        .itimers
+        .get(which)
+        .ok_or(LinuxError::EINVAL)?;
+    *curr_value.get_as_mut()? = itimerval_from(timer);
+    Ok(0)
+}
+
+pub fn sys_setitimer(
+    which: u32,
+    new_value: UserConstPtr<itimerval>,
+    old_value: UserPtr<itimerval>,
+) -> LinuxResult<isize> {
+    // A null new value disarms the timer, as Linux still accepts it.
+    let timer = match nullable!(new_value.get_as_ref())? {
+        Some(new_value) => ITimer {
+            interval: parse_timeval(&new_value.it_interval)?,
+            value: parse_timeval(&new_value.it_value)?,
+        },
+        None => ITimer::default(),
+    };
+    debug!("sys_setitimer <= which: {}, {:?}", which, timer);
+    let old = set_itimer(which, timer)?;
+    if let Some(old_value) = nullable!(old_value.get_as_mut())? {
+        *old_value = itimerval_from(old);
+    }
+    Ok(0)
+}
+
+/// Deliver `SIGALRM` in `seconds`, or cancel the alarm if `seconds` is zero.
+///
+/// Returns the seconds left before the previous alarm, rounded to the nearest
+/// second but at least 1.
+pub fn sys_alarm(seconds: u32) -> LinuxResult<isize> {
+    let old = set_itimer(
+        ITIMER_REAL,
+        ITimer {
+            interval: Duration::ZERO,
+            value: Duration::from_secs(seconds as _),
+        },
+    )?;
+    let mut left = old.value.as_secs();
+    if old.value.subsec_micros() >= 500_000 || (left == 0 && !old.value.is_zero()) {
+        left += 1;
+    }
+    Ok(left as _)
+}
+
+fn parse_timespec(ts: &timespec) -> LinuxResult<Duration> {
+    if ts.tv_sec < 0 || !(0..1_000_000_000).contains(&ts.tv_nsec) {
+        return Err(LinuxError::EINVAL);
+    }
+    Ok(ts.to_time_value())
+}
+
+fn itimerspec_from(timer: ITimer) -> itimerspec {
+    itimerspec {
+        it_interval: timespec::from_time_value(timer.interval),
+        it_value: timespec::from_time_value(timer.value),
+    }
+}
+
+/// Get how the expirations of a new timer are notified, as requested by
+/// `sev`.
+fn parse_sigevent(sev: Option<&sigevent>) -> LinuxResult<TimerNotify> {
+    // Without a `sigevent`, `SIGALRM` is sent with the ID as its value.
+    let Some(sev) = sev else {
+        return Ok(TimerNotify::Signal {
+            signo: Signo::SIGALRM,
+            value: None,
+            tid: None,
+        });
+    };
+    let notify = sev.sigev_notify as u32;
+    if notify == SIGEV_NONE {
+        return Ok(TimerNotify::None);
+    }
+    let tid = match notify {
+        // The threads of `SIGEV_THREAD` are created by libc, which lets the
+        // kernel notify them with a signal.
+        SIGEV_SIGNAL | SIGEV_THREAD => None,
+        // SAFETY: `_tid` is the member used with `SIGEV_THREAD_ID`.
+        _ if notify == SIGEV_SIGNAL | SIGEV_THREAD_ID => Some(unsafe { sev._sigev_un._tid }),
+        _ => return Err(LinuxError::EINVAL),
+    };
+    let signo = Signo::from_repr(sev.sigev_signo as _).ok_or(LinuxError::EINVAL)?;
+    if let Some(tid) = tid {
+        let curr = current();
+        let in_process = curr
+            .task_ext()
+            .thread
+            .process()
+            .threads()
+            .iter()
+            .any(|thread| thread.tid() as i32 == tid);
+        if !in_process {
+            return Err(LinuxError::EINVAL);
+        }
+    }
+    Ok(TimerNotify::Signal {
+        signo,
+        // SAFETY: all the members of `sigval` are plain data.
+        value: Some(unsafe { sev.sigev_value.sival_ptr } as _),
+        tid: tid.map(|it| it as _),
+    })
+}
+
+fn get_timer(timerid: __kernel_timer_t) -> LinuxResult<Arc<PosixTimer>> {
+    current()
+        .task_ext()
+        .process_data()
+        .timers
+        .get(timerid)
+        .ok_or(LinuxError::EINVAL)
+}
+
+pub fn sys_timer_create(
+    clock_id: __kernel_clockid_t,
+    sevp: UserConstPtr<sigevent>,
+    timerid: UserPtr<__kernel_timer_t>,
+) -> LinuxResult<isize> {
+    // Timers cannot measure the coarse and raw clocks.
+    let supported = clock_id < 0
+        || matches!(
+            clock_id as u32,
+            CLOCK_REALTIME
+                | CLOCK_TAI
+                | CLOCK_MONOTONIC
+                | CLOCK_BOOTTIME
+                | CLOCK_PROCESS_CPUTIME_ID
+                | CLOCK_THREAD_CPUTIME_ID
+        );
+    let clock = if supported {
+        Clock::from_id(clock_id)
+    } else {
+        Err(LinuxError::EINVAL)
+    }
+    .inspect_err(|_| warn!("Called sys_timer_create for unsupported clock {}", clock_id))?;
+    let notify = parse_sigevent(nullable!(sevp.get_as_ref())?)?;
+    let timerid = timerid.get_as_mut()?;
+    let curr = current();
+    let process = Arc::downgrade(curr.task_ext().thread.process());
+    let id = curr
+        .task_ext()
+        .process_data()
+        .timers
+        .create(process, clock, notify)?;
+    debug!("sys_timer_create <= clock: {}, id: {}", clock_id, id);
+    *timerid = id;
+    Ok(0)
+}
+
+pub fn sys_timer_settime(
+    timerid: __kernel_timer_t,
+    flags: u32,
+    new_value: UserConstPtr<itimerspec>,
+    old_value: UserPtr<itimerspec>,
+) -> LinuxResult<isize> {
+    let new_value = new_value.get_as_ref()?;
+    let new = ITimer {
+        interval: parse_timespec(&new_value.it_interval)?,
+        value: parse_timespec(&new_value.it_value)?,
+    };
+    debug!(
+        "sys_timer_settime <= id: {}, flags: {:#x}, {:?}",
+        timerid, flags, new
+    );
+    let old = current()
+        .task_ext()
+        .process_data()
+        .timers
+        .set(timerid, new, flags & TIMER_ABSTIME != 0)
+        .ok_or(LinuxError::EINVAL)?;
+    if let Some(old_value) = nullable!(old_value.get_as_mut())? {
+        *old_value = itimerspec_from(old);
+    }
+    Ok(0)
+}
+
+pub fn sys_timer_gettime(
+    timerid: __kernel_timer_t,
+    curr_value: UserPtr<itimerspec>,
+) -> LinuxResult<isize> {
+    let timer = get_timer(timerid)?;
+    *curr_value.get_as_mut()? = itimerspec_from(timer.get());
+    Ok(0)
+}
+
+pub fn sys_timer_getoverrun(timerid: __kernel_timer_t) -> LinuxResult<isize> {
+    Ok(get_timer(timerid)?.overrun() as _)
+}
+
+pub fn sys_timer_delete(timerid: __kernel_timer_t) -> LinuxResult<isize> {
+    debug!("sys_timer_delete <= id: {}", timerid);
+    if !current().task_ext().process_data().timers.delete(timerid) {
+        return Err(LinuxError::EINVAL);
+    }
+    Ok(0)
+}
+
+pub fn sys_timerfd_create(clock_id: __kernel_clockid_t, flags: u32) -> LinuxResult<isize> {
+    debug!(
+        "sys_timerfd_create <= clock: {}, flags: {:#x}",
+        clock_id, flags
+    );
+    if flags & !(TFD_NONBLOCK | TFD_CLOEXEC) != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let realtime_clock = match clock_id as u32 {
+        CLOCK_REALTIME => true,
+        CLOCK_MONOTONIC | CLOCK_BOOTTIME => false,
+        _ => {
+            warn!(
+                "Called sys_timerfd_create for unsupported clock {}",
+                clock_id
+            );
+            return Err(LinuxError::EINVAL);
+        }
+    };
+    let timerfd = TimerFd::new(realtime_clock);
+    timerfd.set_nonblocking(flags & TFD_NONBLOCK != 0)?;
+    timerfd
+        .add_to_fd_table_with_flags(O_RDWR | flags)
+        .map(|fd| fd as _)
+}
+
+pub fn sys_timerfd_settime(
+    fd: c_int,
+    flags: u32,
+    new_value: UserConstPtr<itimerspec>,
+    old_value: UserPtr<itimerspec>,
+) -> LinuxResult<isize> {
+    if flags & !(TFD_TIMER_ABSTIME | TFD_TIMER_CANCEL_ON_SET) != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let timerfd = TimerFd::from_fd(fd)?;
+    let new_value = new_value.get_as_ref()?;
+    let timer = ITimer {
+        interval: parse_timespec(&new_value.it_interval)?,
+        value: parse_timespec(&new_value.it_value)?,
+    };
+    debug!(
+        "sys_timerfd_settime <= fd: {}, flags: {:#x}, {:?}",
+        fd, flags, timer
+    );
+    let old = timerfd.set(
+        timer,
+        flags & TFD_TIMER_ABSTIME != 0,
+        flags & TFD_TIMER_CANCEL_ON_SET != 0,
+    );
+    if let Some(old_value) = nullable!(old_value.get_as_mut())? {
+        *old_value = itimerspec_from(old);
+    }
+    Ok(0)
+}
+
+pub fn sys_timerfd_gettime(fd: c_int, curr_value: UserPtr<itimerspec>) -> LinuxResult<isize> {
+    *curr_value.get_as_mut()? = itimerspec_from(TimerFd::from_fd(fd)?.get());
+    Ok(0)
+}
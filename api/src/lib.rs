@@ -5,12 +5,18 @@
 extern crate axlog;
 extern crate alloc;
 
+mod coredump;
 pub mod file;
 pub mod path;
+pub mod ptrace;
 pub mod ptr;
+pub mod regs;
+pub mod seccomp;
 pub mod signal;
 pub mod sockaddr;
+pub mod terminal;
 pub mod time;
+pub mod trace;
 
 mod imp;
 pub use imp::*;
@@ -1,15 +1,18 @@
 use core::{ffi::c_int, fmt, ops::Deref};
 
 use alloc::{
-    collections::btree_map::BTreeMap,
     string::{String, ToString},
+    vec::Vec,
 };
 use axerrno::{AxError, AxResult, LinuxError, LinuxResult};
 use axfs::api::canonicalize;
-use linux_raw_sys::general::AT_FDCWD;
-use spin::RwLock;
+use linux_raw_sys::general::{
+    AT_EMPTY_PATH, AT_FDCWD, AT_SYMLINK_FOLLOW, AT_SYMLINK_NOFOLLOW, O_CREAT, O_DIRECTORY,
+    O_NOFOLLOW, PATH_MAX, RESOLVE_BENEATH, RESOLVE_IN_ROOT, RESOLVE_NO_MAGICLINKS,
+    RESOLVE_NO_SYMLINKS, RESOLVE_NO_XDEV,
+};
 
-use crate::file::{Directory, File, FileLike};
+use crate::file::{Directory, File, FileLike, get_file_like};
 
 /// 一个规范化的文件路径表示
 #[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Debug)]
@@ -33,7 +36,7 @@ impl FilePath {
             "canonical path should start with /"
         );
 
-        Ok(Self(HARDLINK_MANAGER.real_path(&new_path)))
+        Ok(Self(new_path))
     }
 
     /// 返回底层路径的字符串切片
@@ -149,152 +152,22 @@ impl Deref for FilePath {
     }
 }
 
-/// 错误类型
-#[derive(Debug)]
-pub enum LinkError {
-    LinkExists,  // 链接已存在
-    InvalidPath, // 无效路径
-    NotFound,    // 文件不存在
-    NotFile,     // 不是文件
-}
-
-impl From<LinkError> for AxError {
-    fn from(err: LinkError) -> AxError {
-        match err {
-            LinkError::LinkExists => AxError::AlreadyExists,
-            LinkError::InvalidPath => AxError::InvalidInput,
-            LinkError::NotFound => AxError::NotFound,
-            LinkError::NotFile => AxError::InvalidInput,
-        }
-    }
-}
-
-impl From<LinkError> for LinuxError {
-    fn from(err: LinkError) -> LinuxError {
-        AxError::from(err).into()
-    }
-}
-
-/// A global hardlink manager
-pub static HARDLINK_MANAGER: HardlinkManager = HardlinkManager::new();
-
-/// A manager for hardlinks
-pub struct HardlinkManager {
-    inner: RwLock<LinkManagerInner>,
-}
-struct LinkManagerInner {
-    links: BTreeMap<String, String>,
-    ref_counts: BTreeMap<String, usize>,
-}
-
-// 关于innner的操作都在atomic_开头的函数中
-impl HardlinkManager {
-    const fn new() -> Self {
-        Self {
-            inner: RwLock::new(LinkManagerInner {
-                links: BTreeMap::new(),
-                ref_counts: BTreeMap::new(),
-            }),
-        }
-    }
-
-    /// 创建链接
-    /// 如果目标路径不存在，则返回 `LinkError::NotFound`
-    /// 如果目标路径不是文件，则返回 `LinkError::NotFile`
-    pub fn create_link(&self, src: &FilePath, dst: &FilePath) -> Result<(), LinkError> {
-        if !dst.exists() {
-            return Err(LinkError::NotFound);
-        }
-        if !dst.is_dir() {
-            return Err(LinkError::NotFile);
-        }
-
-        let mut inner = self.inner.write();
-        self.atomic_link_update(&mut inner, src, dst);
-        Ok(())
-    }
-
-    /// 移除链接
-    /// 链接数量为零 或 没有链接时， 删除文件
-    /// 如果路径对应的链接不存在 或 路径对应的文件不存在，则返回 `None`
-    /// 否则返回链接的目标路径
-    pub fn remove_link(&self, src: &FilePath) -> Option<String> {
-        let mut inner = self.inner.write();
-        self.atomic_link_remove(&mut inner, src).or_else(|| {
-            axfs::api::remove_file(src.as_str())
-                .ok()
-                .map(|_| src.to_string())
-        })
-    }
-
-    pub fn real_path(&self, path: &str) -> String {
-        self.inner
-            .read()
-            .links
-            .get(path)
-            .cloned()
-            .unwrap_or_else(|| path.to_string())
-    }
-
-    pub fn link_count(&self, path: &FilePath) -> usize {
-        let inner = self.inner.read();
-        inner
-            .ref_counts
-            .get(path.as_str())
-            .copied()
-            .unwrap_or_else(|| if path.exists() { 1 } else { 0 })
-    }
-
-    // 原子操作helpers
-
-    /// 创建或更新链接
-    /// 如果链接已存在，则更新目标路径
-    /// 如果目标路径不存在，则返回 `LinkError::NotFound`
-    fn atomic_link_update(&self, inner: &mut LinkManagerInner, src: &FilePath, dst: &FilePath) {
-        if let Some(old_dst) = inner.links.get(src.as_str()) {
-            if old_dst == dst.as_str() {
-                return;
-            }
-            self.decrease_ref_count(inner, &old_dst.to_string());
-        }
-        inner.links.insert(src.to_string(), dst.to_string());
-        *inner.ref_counts.entry(dst.to_string()).or_insert(0) += 1;
-    }
-
-    /// 移除链接
-    /// 如果链接不存在，则返回 `None`，否则返回链接的目标路径
-    fn atomic_link_remove(&self, inner: &mut LinkManagerInner, src: &FilePath) -> Option<String> {
-        inner.links.remove(src.as_str()).inspect(|dst| {
-            self.decrease_ref_count(inner, dst);
-        })
-    }
-
-    /// 减少引用计数
-    /// 如果引用计数为零，则删除链接，并删除文件，如果删除文件失败，则返回 `None`
-    /// 如果链接不存在，则返回 `None`
-    fn decrease_ref_count(&self, inner: &mut LinkManagerInner, path: &str) -> Option<()> {
-        match inner.ref_counts.get_mut(path) {
-            Some(count) => {
-                *count -= 1;
-                if *count == 0 {
-                    inner.ref_counts.remove(path);
-                    axfs::api::remove_file(path).ok()?
-                }
-                Some(())
-            }
-            None => {
-                axlog::error!("link exists but ref count is zero");
-                None
-            }
-        }
-    }
-}
+/// 一次路径解析中最多跟随的符号链接数，与 Linux 保持一致
+const MAX_SYMLINK_FOLLOWS: usize = 40;
 
 /// 路径处理标志
 #[derive(Debug, Clone, Copy)]
 pub struct PathFlags {
-    /// 是否跟随符号链接
+    /// 是否跟随最后一个路径分量的符号链接
     pub follow_symlinks: bool,
+    /// 是否允许空路径，此时解析为 dirfd 本身（AT_EMPTY_PATH）
+    pub empty_path: bool,
+    /// 解析结果是否必须为目录（O_DIRECTORY）
+    pub directory: bool,
+    /// 最后一个分量不存在时是否创建它（O_CREAT）
+    pub create: bool,
+    /// openat2 的 RESOLVE_* 标志
+    pub resolve: u32,
 }
 
 impl PathFlags {
@@ -302,6 +175,10 @@ impl PathFlags {
     pub const fn new() -> Self {
         Self {
             follow_symlinks: true,
+            empty_path: false,
+            directory: false,
+            create: false,
+            resolve: 0,
         }
     }
 
@@ -309,15 +186,48 @@ impl PathFlags {
     pub const fn no_follow() -> Self {
         Self {
             follow_symlinks: false,
+            ..Self::new()
         }
     }
 
-    /// 从AT_SYMLINK_NOFOLLOW标志创建
+    /// 从 AT_SYMLINK_NOFOLLOW 和 AT_EMPTY_PATH 标志创建
     pub const fn from_at_flags(flags: u32) -> Self {
         Self {
-            follow_symlinks: (flags & linux_raw_sys::general::AT_SYMLINK_NOFOLLOW) == 0,
+            follow_symlinks: (flags & AT_SYMLINK_NOFOLLOW) == 0,
+            empty_path: (flags & AT_EMPTY_PATH) != 0,
+            ..Self::new()
+        }
+    }
+
+    /// 从 linkat 的标志创建，默认不跟随符号链接，除非指定 AT_SYMLINK_FOLLOW
+    pub const fn from_link_flags(flags: u32) -> Self {
+        Self {
+            follow_symlinks: (flags & AT_SYMLINK_FOLLOW) != 0,
+            empty_path: (flags & AT_EMPTY_PATH) != 0,
+            ..Self::new()
         }
     }
+
+    /// 从 open 的标志（O_NOFOLLOW、O_DIRECTORY、O_CREAT）创建
+    pub const fn from_open_flags(flags: u32) -> Self {
+        Self {
+            follow_symlinks: (flags & O_NOFOLLOW) == 0,
+            directory: (flags & O_DIRECTORY) != 0,
+            create: (flags & O_CREAT) != 0,
+            ..Self::new()
+        }
+    }
+
+    /// 设置 openat2 的 RESOLVE_* 标志
+    pub const fn with_resolve(mut self, resolve: u32) -> Self {
+        self.resolve = resolve;
+        self
+    }
+
+    /// 是否将解析限制在起始目录之内（RESOLVE_BENEATH 或 RESOLVE_IN_ROOT）
+    const fn scoped(&self) -> bool {
+        self.resolve & (RESOLVE_BENEATH | RESOLVE_IN_ROOT) != 0
+    }
 }
 
 impl Default for PathFlags {
@@ -342,49 +252,217 @@ pub fn resolve_path(dirfd: c_int, path: &str) -> LinuxResult<FilePath> {
     }
 }
 
+/// 返回 dirfd 所指目录的绝对路径
+fn dirfd_path(dirfd: c_int) -> LinuxResult<String> {
+    if dirfd == AT_FDCWD {
+        Ok(axfs::api::current_dir()?)
+    } else {
+        Ok(Directory::from_fd(dirfd)?.path().to_string())
+    }
+}
+
+/// 返回 fd 本身对应的绝对路径，用于 AT_EMPTY_PATH
+fn fd_path(fd: c_int) -> LinuxResult<String> {
+    if fd == AT_FDCWD {
+        return Ok(axfs::api::current_dir()?);
+    }
+    let f = get_file_like(fd)?.into_any();
+    if let Some(dir) = f.downcast_ref::<Directory>() {
+        Ok(dir.path().to_string())
+    } else if let Some(file) = f.downcast_ref::<File>() {
+        Ok(file.path().to_string())
+    } else {
+        Err(LinuxError::ENOENT)
+    }
+}
+
+/// 路径分量对应节点的类型
+enum NodeKind {
+    Missing,
+    Symlink,
+    Dir,
+    Other,
+}
+
+fn node_kind(path: &str) -> LinuxResult<NodeKind> {
+    match axfs::api::is_symlink(path) {
+        Ok(true) => Ok(NodeKind::Symlink),
+        Ok(false) => {
+            if axfs::api::metadata(path)?.is_dir() {
+                Ok(NodeKind::Dir)
+            } else {
+                Ok(NodeKind::Other)
+            }
+        }
+        Err(AxError::NotFound) => Ok(NodeKind::Missing),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn join_components(components: &[String]) -> String {
+    let mut path = String::from("/");
+    path.push_str(&components.join("/"));
+    path
+}
+
+/// 是否为 procfs 中的魔法链接：`/proc/<pid>/{exe,cwd,root}`、`/proc/<pid>/fd/<n>`，
+/// 其中 `<pid>` 也可以是 `self` 或 `thread-self`
+fn is_magic_link(components: &[String]) -> bool {
+    let [proc, pid, rest @ ..] = components else {
+        return false;
+    };
+    if proc != "proc"
+        || !(pid == "self" || pid == "thread-self" || pid.bytes().all(|b| b.is_ascii_digit()))
+    {
+        return false;
+    }
+    match rest {
+        [name] => matches!(name.as_str(), "exe" | "cwd" | "root"),
+        [fd, _] => fd == "fd",
+        _ => false,
+    }
+}
+
+/// 在 RESOLVE_NO_XDEV 下，解析过程不得离开起始目录所在的文件系统
+fn check_xdev(flags: &PathFlags, start: &str, components: &[String]) -> LinuxResult<()> {
+    if flags.resolve & RESOLVE_NO_XDEV != 0
+        && !axfs::api::same_filesystem(start, &join_components(components))?
+    {
+        return Err(LinuxError::EXDEV);
+    }
+    Ok(())
+}
+
+/// 将路径按分量逆序压入待处理栈
+fn push_components(pending: &mut Vec<String>, path: &str) {
+    pending.extend(
+        path.split('/')
+            .filter(|c| !c.is_empty() && *c != ".")
+            .rev()
+            .map(String::from),
+    );
+}
+
 /// 将绝对/相对路径解析成绝对路径，根据标志处理符号链接
-pub fn resolve_path_with_flags(dirfd: c_int, path: &str, flags: PathFlags) -> LinuxResult<FilePath> {
-    let mut absolute_path = resolve_path(dirfd, path)?;
-
-    if flags.follow_symlinks && axfs::api::is_symlink(absolute_path.as_str())? {
-        // 处理符号链接
-        let buf: &mut [u8] = &mut [0; 4096];
-        let mut depth = 0;
-        const MAX_SYMLINK_DEPTH: usize = 8; // 限制符号链接解析深度
-        
-        loop {
-            if depth >= MAX_SYMLINK_DEPTH {
-                return Err(LinuxError::ELOOP.into());
+///
+/// 与 namei 类似，逐个分量地解析路径：中间分量的符号链接总是被跟随，
+/// 链接目标相对于链接所在的目录解析；最后一个分量仅在 `follow_symlinks`
+/// 为真或路径以 '/' 结尾时被跟随。最后一个分量可以不存在，以便调用者创建它。
+pub fn resolve_path_with_flags(
+    dirfd: c_int,
+    path: &str,
+    flags: PathFlags,
+) -> LinuxResult<FilePath> {
+    if path.is_empty() {
+        if !flags.empty_path {
+            return Err(LinuxError::ENOENT);
+        }
+        return Ok(FilePath::new(fd_path(dirfd)?)?);
+    }
+
+    let base = dirfd_path(dirfd)?;
+    let mut root = Vec::new();
+    if flags.scoped() {
+        push_components(&mut root, &base);
+        root.reverse();
+    }
+    let mut current = if path.starts_with('/') {
+        if flags.resolve & RESOLVE_BENEATH != 0 {
+            return Err(LinuxError::EXDEV);
+        }
+        root.clone()
+    } else {
+        let mut base_components = Vec::new();
+        push_components(&mut base_components, &base);
+        base_components.reverse();
+        base_components
+    };
+    check_xdev(&flags, &base, &current)?;
+
+    let trailing_slash = path.ends_with('/');
+    let mut pending = Vec::new();
+    push_components(&mut pending, path);
+    let mut links = 0;
+
+    while let Some(component) = pending.pop() {
+        if component == ".." {
+            if current.len() <= root.len() {
+                // 不允许通过 ".." 逃出起始目录
+                if flags.resolve & RESOLVE_BENEATH != 0 {
+                    return Err(LinuxError::EXDEV);
+                }
+            } else {
+                current.pop();
+                check_xdev(&flags, &base, &current)?;
             }
-            
-            match axfs::api::read_link(absolute_path.as_str(), buf) {
-                Ok(len) if len > 0 => {
-                    let link_target = core::str::from_utf8(&buf[..len])
-                        .map_err(|_| AxError::InvalidInput)?;
-
-                    absolute_path = resolve_path(dirfd, link_target)?;
-                    depth += 1;
-
-                    // 检查新路径是否还是符号链接
-                    if !axfs::api::is_symlink(absolute_path.as_str())? {
-                        break;
+            continue;
+        }
+
+        current.push(component);
+        let is_last = pending.is_empty();
+        check_xdev(&flags, &base, &current)?;
+        if flags.resolve & (RESOLVE_NO_MAGICLINKS | RESOLVE_NO_SYMLINKS) != 0
+            && (!is_last || flags.follow_symlinks || trailing_slash)
+            && is_magic_link(&current)
+        {
+            return Err(LinuxError::ELOOP);
+        }
+        let current_path = join_components(&current);
+        match node_kind(&current_path)? {
+            // 以 "/" 结尾的路径只能是目录，open 不会将其创建为普通文件
+            NodeKind::Missing if is_last && flags.create && trailing_slash => {
+                return Err(LinuxError::EISDIR);
+            }
+            NodeKind::Missing if is_last => break,
+            NodeKind::Missing => return Err(LinuxError::ENOENT),
+            NodeKind::Symlink if !is_last || flags.follow_symlinks || trailing_slash => {
+                if flags.resolve & RESOLVE_NO_SYMLINKS != 0 {
+                    return Err(LinuxError::ELOOP);
+                }
+                links += 1;
+                if links > MAX_SYMLINK_FOLLOWS {
+                    return Err(LinuxError::ELOOP);
+                }
+
+                let mut buf = [0u8; PATH_MAX as usize];
+                let len = axfs::api::read_link(&current_path, &mut buf)?;
+                let target = core::str::from_utf8(&buf[..len]).map_err(|_| LinuxError::EINVAL)?;
+                if target.is_empty() {
+                    return Err(LinuxError::ENOENT);
+                }
+
+                // 链接目标相对于链接所在的目录
+                current.pop();
+                if target.starts_with('/') {
+                    if flags.resolve & RESOLVE_BENEATH != 0 {
+                        return Err(LinuxError::EXDEV);
                     }
+                    current = root.clone();
+                    check_xdev(&flags, &base, &current)?;
                 }
-                _ => {
-                    // 不是符号链接或读取出错，返回当前路径
-                    break;
+                push_components(&mut pending, target);
+            }
+            NodeKind::Symlink | NodeKind::Dir => {}
+            NodeKind::Other if is_last => {
+                if flags.directory || trailing_slash {
+                    return Err(LinuxError::ENOTDIR);
                 }
             }
+            NodeKind::Other => return Err(LinuxError::ENOTDIR),
         }
     }
 
-    Ok(absolute_path)
+    let mut resolved = join_components(&current);
+    if trailing_slash && !resolved.ends_with('/') {
+        resolved.push('/');
+    }
+    Ok(FilePath::new(resolved)?)
 }
 
 // 向后兼容的便利函数
-/// 将绝对/相对路径解析成绝对路径（不跟随符号链接）
+/// 将绝对/相对路径解析成绝对路径（不跟随最后一个分量的符号链接）
 pub fn handle_file_path(dirfd: c_int, path: &str) -> LinuxResult<FilePath> {
-    resolve_path(dirfd, path)
+    resolve_path_with_flags(dirfd, path, PathFlags::no_follow())
 }
 
 /// 将绝对/相对路径解析成绝对路径，并跟随符号链接
@@ -0,0 +1,241 @@
+//! Stops of threads traced by a debugger, see `ptrace(2)`.
+
+use alloc::sync::Arc;
+
+use axhal::arch::TrapFrame;
+use axprocess::{Process, Thread};
+use axsignal::{SignalInfo, Signo};
+use axtask::{TaskExtRef, current};
+use linux_raw_sys::{
+    general::{CLD_TRAPPED, SI_KERNEL, SI_USER},
+    ptrace::{
+        PTRACE_EVENT_CLONE, PTRACE_EVENT_EXEC, PTRACE_EVENT_EXIT, PTRACE_EVENT_FORK,
+        PTRACE_EVENT_STOP, PTRACE_EVENT_VFORK, PTRACE_O_EXITKILL,
+    },
+};
+use starry_core::{
+    ptrace::{PtraceResume, PtraceStop},
+    task::{ProcessData, ThreadData},
+};
+
+use crate::signal::{send_signal_process, send_signal_thread};
+
+/// Whether the current thread is traced.
+pub fn is_traced() -> bool {
+    current().task_ext().thread_data().ptrace.tracer().is_some()
+}
+
+/// Tell `tracer` that one of its tracees stopped or exited.
+pub fn notify_tracer(tracer: &Process) {
+    let _ = send_signal_process(tracer, SignalInfo::new(Signo::SIGCHLD, CLD_TRAPPED as _));
+    if let Some(data) = tracer.data::<ProcessData>() {
+        data.notify_child_event();
+    }
+}
+
+/// Put the current thread in `stop` and wait until its tracer resumes it.
+///
+/// Returns the signal the tracer asks to deliver, or `None` if the thread is
+/// not traced.
+pub fn ptrace_stop(stop: PtraceStop) -> Option<Signo> {
+    let curr = current();
+    let thr_data = curr.task_ext().thread_data();
+    let tracer = {
+        let mut state = thr_data.ptrace.lock();
+        let tracer = state.tracer()?;
+        state.stop = Some(stop);
+        state.reported = false;
+        tracer
+    };
+    debug!(
+        "Thread {} stops for tracer {}",
+        curr.id().as_u64(),
+        tracer.pid()
+    );
+    notify_tracer(&tracer);
+    drop(tracer);
+
+    let signal = &thr_data.signal;
+    thr_data
+        .ptrace
+        .wait_resumed(|| signal.pending().has(Signo::SIGKILL))
+}
+
+/// Deliver the signal the tracer asked for after a stop other than a
+/// signal-delivery-stop.
+fn send_injected(signo: Option<Signo>) {
+    if let Some(signo) = signo {
+        let curr = current();
+        let _ = send_signal_thread(
+            &curr.task_ext().thread,
+            SignalInfo::new(signo, SI_USER as _),
+        );
+    }
+}
+
+/// Whether the tracer of the current thread asked to stop at system calls.
+fn traces_syscalls() -> bool {
+    let curr = current();
+    let state = curr.task_ext().thread_data().ptrace.lock();
+    state.tracer.is_some() && state.resume == PtraceResume::Syscall
+}
+
+/// Stop when entering the system call `sysno`, if the tracer asked for it
+/// with `PTRACE_SYSCALL`.
+///
+/// Returns the system call to execute, which the tracer may have changed.
+pub fn ptrace_syscall_entry(sysno: usize) -> usize {
+    if !traces_syscalls() {
+        return sysno;
+    }
+    let curr = current();
+    let ptrace = &curr.task_ext().thread_data().ptrace;
+    ptrace.lock().sysno = sysno;
+    send_injected(ptrace_stop(PtraceStop::SyscallEntry));
+    ptrace.lock().sysno
+}
+
+/// Stop when leaving a system call returning `ret`, if the tracer asked for
+/// it with `PTRACE_SYSCALL`.
+///
+/// Returns the return value of the system call, which the tracer may have
+/// changed.
+pub fn ptrace_syscall_exit(tf: &mut TrapFrame, ret: isize) -> isize {
+    if !traces_syscalls() {
+        return ret;
+    }
+    tf.set_retval(ret as _);
+    send_injected(ptrace_stop(PtraceStop::SyscallExit));
+    tf.retval() as _
+}
+
+/// Stop for the `PTRACE_EVENT_*` event `event`, if the tracer asked for it
+/// with the corresponding option. `msg` is returned by `PTRACE_GETEVENTMSG`.
+///
+/// Returns whether the thread stopped.
+pub fn ptrace_event(event: u32, msg: usize) -> bool {
+    let curr = current();
+    {
+        let mut state = curr.task_ext().thread_data().ptrace.lock();
+        if state.tracer.is_none() || state.options & (1 << event) == 0 {
+            return false;
+        }
+        state.event_msg = msg;
+    }
+    send_injected(ptrace_stop(PtraceStop::Event(event)));
+    true
+}
+
+/// Report a successful `execve` of the current thread to its tracer, with an
+/// event stop or else with `SIGTRAP`.
+pub fn ptrace_exec() {
+    let curr = current();
+    if ptrace_event(PTRACE_EVENT_EXEC, curr.id().as_u64() as _) {
+        return;
+    }
+    let seized = {
+        let state = curr.task_ext().thread_data().ptrace.lock();
+        if state.tracer.is_none() {
+            return;
+        }
+        state.seized
+    };
+    if !seized {
+        send_injected(Some(Signo::SIGTRAP));
+    }
+}
+
+/// The event reported to the tracer for a `clone` with `exit_signal`.
+pub fn clone_event(vfork: bool, exit_signal: Option<Signo>) -> u32 {
+    if vfork {
+        PTRACE_EVENT_VFORK
+    } else if exit_signal == Some(Signo::SIGCHLD) {
+        PTRACE_EVENT_FORK
+    } else {
+        PTRACE_EVENT_CLONE
+    }
+}
+
+/// Attach `child`, created by the current thread, to the tracer of the
+/// current thread if the tracer asked for `event`, or if `force` is set for
+/// `CLONE_PTRACE`.
+///
+/// The child starts in a stop, before entering user space with `tf`.
+/// Returns whether it is traced.
+pub fn ptrace_attach_child(child: &Arc<Thread>, tf: &TrapFrame, event: u32, force: bool) -> bool {
+    let curr = current();
+    let (tracer, options, seized) = {
+        let state = curr.task_ext().thread_data().ptrace.lock();
+        let Some(tracer) = state.tracer() else {
+            return false;
+        };
+        if !force && state.options & (1 << event) == 0 {
+            return false;
+        }
+        (tracer, state.options, state.seized)
+    };
+    let Some(child_data) = child.data::<ThreadData>() else {
+        return false;
+    };
+
+    {
+        let mut state = child_data.ptrace.lock();
+        state.tracer = Some(Arc::downgrade(&tracer));
+        state.options = options;
+        state.seized = seized;
+        state.stop = Some(if seized {
+            PtraceStop::Event(PTRACE_EVENT_STOP)
+        } else {
+            PtraceStop::Signal(SignalInfo::new(Signo::SIGSTOP, SI_USER as _))
+        });
+        state.reported = false;
+        state.start_regs = Some(*tf);
+    }
+    if let Some(data) = tracer.data::<ProcessData>() {
+        data.tracees.lock().push(child.clone());
+    }
+    notify_tracer(&tracer);
+    true
+}
+
+/// Report the exit of the current thread with `exit_code` to its tracer,
+/// stopping first if the tracer asked for `PTRACE_EVENT_EXIT`.
+pub fn ptrace_exit(exit_code: i32) {
+    ptrace_event(PTRACE_EVENT_EXIT, exit_code as _);
+
+    let curr = current();
+    let thread = &curr.task_ext().thread;
+    let mut state = curr.task_ext().thread_data().ptrace.lock();
+    let Some(tracer) = state.tracer() else {
+        return;
+    };
+    // The exit of a child process is reported to its parent as usual.
+    let process = thread.process();
+    if thread.tid() == process.pid() && process.parent().is_some_and(|it| Arc::ptr_eq(&it, &tracer))
+    {
+        state.tracer = None;
+        drop(state);
+        if let Some(data) = tracer.data::<ProcessData>() {
+            data.tracees.lock().retain(|it| it.tid() != thread.tid());
+        }
+    } else {
+        state.exit_status = Some(exit_code);
+        drop(state);
+    }
+    notify_tracer(&tracer);
+}
+
+/// Stop tracing the threads traced by the exiting process `proc_data`,
+/// killing those which asked for it with `PTRACE_O_EXITKILL`.
+pub fn detach_tracees(proc_data: &ProcessData) {
+    let tracees = core::mem::take(&mut *proc_data.tracees.lock());
+    for tracee in tracees {
+        let Some(data) = tracee.data::<ThreadData>() else {
+            continue;
+        };
+        if data.ptrace.lock().options & PTRACE_O_EXITKILL != 0 {
+            let _ = send_signal_thread(&tracee, SignalInfo::new(Signo::SIGKILL, SI_KERNEL as _));
+        }
+        data.ptrace.detach(None);
+    }
+}
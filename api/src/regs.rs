@@ -0,0 +1,404 @@
+//! The registers of user threads in the layouts of Linux, as found in the
+//! notes of core dumps and used by `ptrace`.
+
+use axerrno::LinuxResult;
+use axhal::arch::TrapFrame;
+
+/// Number of general purpose registers in [`GeneralRegs`].
+#[cfg(target_arch = "x86_64")]
+pub const ELF_NGREG: usize = 27;
+/// Number of general purpose registers in [`GeneralRegs`].
+#[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+pub const ELF_NGREG: usize = 32;
+/// Number of general purpose registers in [`GeneralRegs`].
+#[cfg(target_arch = "aarch64")]
+pub const ELF_NGREG: usize = 34;
+/// Number of general purpose registers in [`GeneralRegs`].
+#[cfg(target_arch = "loongarch64")]
+pub const ELF_NGREG: usize = 45;
+
+/// The general purpose registers, as `elf_gregset_t`.
+pub type GeneralRegs = [u64; ELF_NGREG];
+
+/// Get the general purpose registers saved in `tf`.
+#[cfg(target_arch = "x86_64")]
+pub fn general_regs(tf: &TrapFrame) -> GeneralRegs {
+    // See `struct user_regs_struct`, `orig_rax` is unknown after a trap.
+    [
+        tf.r15,
+        tf.r14,
+        tf.r13,
+        tf.r12,
+        tf.rbp,
+        tf.rbx,
+        tf.r11,
+        tf.r10,
+        tf.r9,
+        tf.r8,
+        tf.rax,
+        tf.rcx,
+        tf.rdx,
+        tf.rsi,
+        tf.rdi,
+        u64::MAX,
+        tf.rip,
+        tf.cs,
+        tf.rflags,
+        tf.rsp,
+        tf.ss,
+        tf.fs_base,
+        0,
+        0,
+        0,
+        0,
+        0,
+    ]
+}
+
+/// Set the general purpose registers saved in `tf` to `regs`.
+///
+/// The segment selectors and the privileged bits of `rflags` are kept.
+/// Fails with `EIO` if `rip` or `fs_base` is not a user address, as the
+/// return to user space with `sysretq` or the load of `fs_base` would fault
+/// in the kernel on a non-canonical address.
+#[cfg(target_arch = "x86_64")]
+pub fn set_general_regs(tf: &mut TrapFrame, regs: &GeneralRegs) -> LinuxResult {
+    // CF, PF, AF, ZF, SF, TF, DF, OF, NT, RF and AC, see `FLAG_MASK` of Linux
+    const FLAG_MASK: u64 = 0x54dd5;
+    // The end of the lower canonical half, with 4-level paging
+    const USER_END: u64 = 1 << 47;
+    if regs[16] >= USER_END || regs[21] >= USER_END {
+        return Err(axerrno::LinuxError::EIO);
+    }
+    tf.r15 = regs[0];
+    tf.r14 = regs[1];
+    tf.r13 = regs[2];
+    tf.r12 = regs[3];
+    tf.rbp = regs[4];
+    tf.rbx = regs[5];
+    tf.r11 = regs[6];
+    tf.r10 = regs[7];
+    tf.r9 = regs[8];
+    tf.r8 = regs[9];
+    tf.rax = regs[10];
+    tf.rcx = regs[11];
+    tf.rdx = regs[12];
+    tf.rsi = regs[13];
+    tf.rdi = regs[14];
+    tf.rip = regs[16];
+    tf.rflags = (tf.rflags & !FLAG_MASK) | (regs[18] & FLAG_MASK);
+    tf.rsp = regs[19];
+    tf.fs_base = regs[21];
+    Ok(())
+}
+
+/// Get the general purpose registers saved in `tf`.
+#[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+pub fn general_regs(tf: &TrapFrame) -> GeneralRegs {
+    // See `struct user_regs_struct`, which has `pc` in place of `zero`.
+    let mut regs = [0; ELF_NGREG];
+    regs[0] = tf.sepc as _;
+    // SAFETY: `GeneralRegisters` is 31 `usize`s from `ra` to `t6`.
+    let gprs: &[usize; 31] = unsafe { core::mem::transmute(&tf.regs) };
+    for (reg, gpr) in regs[1..].iter_mut().zip(gprs) {
+        *reg = *gpr as _;
+    }
+    regs
+}
+
+/// Set the general purpose registers saved in `tf` to `regs`.
+#[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+pub fn set_general_regs(tf: &mut TrapFrame, regs: &GeneralRegs) -> LinuxResult {
+    tf.sepc = regs[0] as _;
+    // SAFETY: `GeneralRegisters` is 31 `usize`s from `ra` to `t6`.
+    let gprs: &mut [usize; 31] = unsafe { core::mem::transmute(&mut tf.regs) };
+    for (gpr, reg) in gprs.iter_mut().zip(&regs[1..]) {
+        *gpr = *reg as _;
+    }
+    Ok(())
+}
+
+/// Get the general purpose registers saved in `tf`.
+#[cfg(target_arch = "aarch64")]
+pub fn general_regs(tf: &TrapFrame) -> GeneralRegs {
+    // See `struct user_pt_regs`.
+    let mut regs = [0; ELF_NGREG];
+    regs[..31].copy_from_slice(&tf.r);
+    regs[31] = tf.usp;
+    regs[32] = tf.elr;
+    regs[33] = tf.spsr;
+    regs
+}
+
+/// Set the general purpose registers saved in `tf` to `regs`.
+///
+/// Only the condition flags of `pstate` can be changed.
+#[cfg(target_arch = "aarch64")]
+pub fn set_general_regs(tf: &mut TrapFrame, regs: &GeneralRegs) -> LinuxResult {
+    const NZCV: u64 = 0xf000_0000;
+    tf.r.copy_from_slice(&regs[..31]);
+    tf.usp = regs[31];
+    tf.elr = regs[32];
+    tf.spsr = (tf.spsr & !NZCV) | (regs[33] & NZCV);
+    Ok(())
+}
+
+/// Get the general purpose registers saved in `tf`.
+#[cfg(target_arch = "loongarch64")]
+pub fn general_regs(tf: &TrapFrame) -> GeneralRegs {
+    // See `struct user_pt_regs`, followed by `orig_a0`, `csr_era`,
+    // `csr_badv` and reserved words.
+    let mut regs = [0; ELF_NGREG];
+    // SAFETY: `GeneralRegisters` is 32 `usize`s from `zero` to `s8`.
+    let gprs: &[usize; 32] = unsafe { core::mem::transmute(&tf.regs) };
+    for (reg, gpr) in regs[..32].iter_mut().zip(gprs) {
+        *reg = *gpr as _;
+    }
+    regs[32] = tf.regs.a0 as _;
+    regs[33] = tf.era as _;
+    regs
+}
+
+/// Set the general purpose registers saved in `tf` to `regs`.
+#[cfg(target_arch = "loongarch64")]
+pub fn set_general_regs(tf: &mut TrapFrame, regs: &GeneralRegs) -> LinuxResult {
+    // SAFETY: `GeneralRegisters` is 32 `usize`s from `zero` to `s8`.
+    let gprs: &mut [usize; 32] = unsafe { core::mem::transmute(&mut tf.regs) };
+    for (gpr, reg) in gprs[1..].iter_mut().zip(&regs[1..32]) {
+        *gpr = *reg as _;
+    }
+    tf.era = regs[33] as _;
+    Ok(())
+}
+
+/// The floating-point registers, as `user_fxsr_struct`.
+#[cfg(target_arch = "x86_64")]
+#[repr(C, align(16))]
+pub struct FpRegs(pub [u8; 512]);
+
+/// Get the floating-point registers of the current thread, which are still
+/// those of user space in the kernel.
+#[cfg(target_arch = "x86_64")]
+pub fn current_fp_regs(_tf: &TrapFrame) -> Option<FpRegs> {
+    let mut regs = FpRegs([0; 512]);
+    unsafe { core::arch::asm!("fxsave64 [{}]", in(reg) regs.0.as_mut_ptr()) };
+    Some(regs)
+}
+
+/// The floating-point registers, as `struct __riscv_d_ext_state`.
+#[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+#[repr(C)]
+pub struct FpRegs {
+    pub f: [u64; 32],
+    pub fcsr: u32,
+    _pad: u32,
+}
+
+/// Get the floating-point registers of the current thread, which are still
+/// those of user space in the kernel.
+///
+/// Returns `None` if the thread has not enabled them.
+#[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+pub fn current_fp_regs(tf: &TrapFrame) -> Option<FpRegs> {
+    // `sstatus.FS` is unchanged by traps, reading the registers while it is
+    // off would fault.
+    if tf.sstatus & (0b11 << 13) == 0 {
+        return None;
+    }
+    let mut regs = FpRegs {
+        f: [0; 32],
+        fcsr: 0,
+        _pad: 0,
+    };
+    let fcsr: usize;
+    unsafe {
+        core::arch::asm!(
+            "fsd f0, 0 * 8({0})",
+            "fsd f1, 1 * 8({0})",
+            "fsd f2, 2 * 8({0})",
+            "fsd f3, 3 * 8({0})",
+            "fsd f4, 4 * 8({0})",
+            "fsd f5, 5 * 8({0})",
+            "fsd f6, 6 * 8({0})",
+            "fsd f7, 7 * 8({0})",
+            "fsd f8, 8 * 8({0})",
+            "fsd f9, 9 * 8({0})",
+            "fsd f10, 10 * 8({0})",
+            "fsd f11, 11 * 8({0})",
+            "fsd f12, 12 * 8({0})",
+            "fsd f13, 13 * 8({0})",
+            "fsd f14, 14 * 8({0})",
+            "fsd f15, 15 * 8({0})",
+            "fsd f16, 16 * 8({0})",
+            "fsd f17, 17 * 8({0})",
+            "fsd f18, 18 * 8({0})",
+            "fsd f19, 19 * 8({0})",
+            "fsd f20, 20 * 8({0})",
+            "fsd f21, 21 * 8({0})",
+            "fsd f22, 22 * 8({0})",
+            "fsd f23, 23 * 8({0})",
+            "fsd f24, 24 * 8({0})",
+            "fsd f25, 25 * 8({0})",
+            "fsd f26, 26 * 8({0})",
+            "fsd f27, 27 * 8({0})",
+            "fsd f28, 28 * 8({0})",
+            "fsd f29, 29 * 8({0})",
+            "fsd f30, 30 * 8({0})",
+            "fsd f31, 31 * 8({0})",
+            "frcsr {1}",
+            in(reg) regs.f.as_mut_ptr(),
+            out(reg) fcsr,
+        )
+    };
+    regs.fcsr = fcsr as _;
+    Some(regs)
+}
+
+/// The floating-point registers, as `struct user_fpsimd_state`.
+#[cfg(target_arch = "aarch64")]
+#[repr(C, align(16))]
+pub struct FpRegs {
+    pub vregs: [u128; 32],
+    pub fpsr: u32,
+    pub fpcr: u32,
+    _reserved: [u32; 2],
+}
+
+/// Get the floating-point registers of the current thread, which are still
+/// those of user space in the kernel.
+///
+/// Returns `None` if the kernel is built without FP/SIMD support.
+#[cfg(target_arch = "aarch64")]
+pub fn current_fp_regs(_tf: &TrapFrame) -> Option<FpRegs> {
+    #[cfg(target_feature = "neon")]
+    {
+        let mut regs = FpRegs {
+            vregs: [0; 32],
+            fpsr: 0,
+            fpcr: 0,
+            _reserved: [0; 2],
+        };
+        let (fpsr, fpcr): (u64, u64);
+        unsafe {
+            core::arch::asm!(
+                "stp q0, q1, [{0}, 0 * 16]",
+                "stp q2, q3, [{0}, 2 * 16]",
+                "stp q4, q5, [{0}, 4 * 16]",
+                "stp q6, q7, [{0}, 6 * 16]",
+                "stp q8, q9, [{0}, 8 * 16]",
+                "stp q10, q11, [{0}, 10 * 16]",
+                "stp q12, q13, [{0}, 12 * 16]",
+                "stp q14, q15, [{0}, 14 * 16]",
+                "stp q16, q17, [{0}, 16 * 16]",
+                "stp q18, q19, [{0}, 18 * 16]",
+                "stp q20, q21, [{0}, 20 * 16]",
+                "stp q22, q23, [{0}, 22 * 16]",
+                "stp q24, q25, [{0}, 24 * 16]",
+                "stp q26, q27, [{0}, 26 * 16]",
+                "stp q28, q29, [{0}, 28 * 16]",
+                "stp q30, q31, [{0}, 30 * 16]",
+                "mrs {1}, fpsr",
+                "mrs {2}, fpcr",
+                in(reg) regs.vregs.as_mut_ptr(),
+                out(reg) fpsr,
+                out(reg) fpcr,
+            )
+        };
+        regs.fpsr = fpsr as _;
+        regs.fpcr = fpcr as _;
+        Some(regs)
+    }
+    #[cfg(not(target_feature = "neon"))]
+    None
+}
+
+/// The floating-point registers, as `struct user_fp_state`.
+#[cfg(target_arch = "loongarch64")]
+#[repr(C)]
+pub struct FpRegs {
+    pub fpr: [u64; 32],
+    pub fcc: u64,
+    pub fcsr: u32,
+    _pad: u32,
+}
+
+/// Get the floating-point registers of the current thread, which are still
+/// those of user space in the kernel.
+///
+/// Returns `None` if they are not enabled.
+#[cfg(target_arch = "loongarch64")]
+pub fn current_fp_regs(_tf: &TrapFrame) -> Option<FpRegs> {
+    // `EUEN.FPE`, reading the registers while it is clear would fault.
+    let euen: usize;
+    unsafe { core::arch::asm!("csrrd {}, 0x2", out(reg) euen) };
+    if euen & 1 == 0 {
+        return None;
+    }
+    let mut regs = FpRegs {
+        fpr: [0; 32],
+        fcc: 0,
+        fcsr: 0,
+        _pad: 0,
+    };
+    let (fcc, fcsr): (usize, usize);
+    unsafe {
+        core::arch::asm!(
+            "fst.d $f0, {0}, 0 * 8",
+            "fst.d $f1, {0}, 1 * 8",
+            "fst.d $f2, {0}, 2 * 8",
+            "fst.d $f3, {0}, 3 * 8",
+            "fst.d $f4, {0}, 4 * 8",
+            "fst.d $f5, {0}, 5 * 8",
+            "fst.d $f6, {0}, 6 * 8",
+            "fst.d $f7, {0}, 7 * 8",
+            "fst.d $f8, {0}, 8 * 8",
+            "fst.d $f9, {0}, 9 * 8",
+            "fst.d $f10, {0}, 10 * 8",
+            "fst.d $f11, {0}, 11 * 8",
+            "fst.d $f12, {0}, 12 * 8",
+            "fst.d $f13, {0}, 13 * 8",
+            "fst.d $f14, {0}, 14 * 8",
+            "fst.d $f15, {0}, 15 * 8",
+            "fst.d $f16, {0}, 16 * 8",
+            "fst.d $f17, {0}, 17 * 8",
+            "fst.d $f18, {0}, 18 * 8",
+            "fst.d $f19, {0}, 19 * 8",
+            "fst.d $f20, {0}, 20 * 8",
+            "fst.d $f21, {0}, 21 * 8",
+            "fst.d $f22, {0}, 22 * 8",
+            "fst.d $f23, {0}, 23 * 8",
+            "fst.d $f24, {0}, 24 * 8",
+            "fst.d $f25, {0}, 25 * 8",
+            "fst.d $f26, {0}, 26 * 8",
+            "fst.d $f27, {0}, 27 * 8",
+            "fst.d $f28, {0}, 28 * 8",
+            "fst.d $f29, {0}, 29 * 8",
+            "fst.d $f30, {0}, 30 * 8",
+            "fst.d $f31, {0}, 31 * 8",
+            // One byte for each condition flag, from `fcc0` to `fcc7`.
+            "movcf2gr {1}, $fcc0",
+            "movcf2gr {3}, $fcc1",
+            "bstrins.d {1}, {3}, 15, 8",
+            "movcf2gr {3}, $fcc2",
+            "bstrins.d {1}, {3}, 23, 16",
+            "movcf2gr {3}, $fcc3",
+            "bstrins.d {1}, {3}, 31, 24",
+            "movcf2gr {3}, $fcc4",
+            "bstrins.d {1}, {3}, 39, 32",
+            "movcf2gr {3}, $fcc5",
+            "bstrins.d {1}, {3}, 47, 40",
+            "movcf2gr {3}, $fcc6",
+            "bstrins.d {1}, {3}, 55, 48",
+            "movcf2gr {3}, $fcc7",
+            "bstrins.d {1}, {3}, 63, 56",
+            "movfcsr2gr {2}, $fcsr0",
+            in(reg) regs.fpr.as_mut_ptr(),
+            out(reg) fcc,
+            out(reg) fcsr,
+            out(reg) _,
+        )
+    };
+    regs.fcc = fcc as _;
+    regs.fcsr = fcsr as _;
+    Some(regs)
+}
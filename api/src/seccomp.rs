@@ -0,0 +1,136 @@
+//! Filtering of the system calls of the current thread, see `seccomp(2)`.
+
+use axerrno::LinuxError;
+use axhal::arch::TrapFrame;
+use axsignal::{SignalInfo, Signo};
+use axtask::{TaskExtRef, current};
+use linux_raw_sys::{
+    general::{
+        __NR_exit, __NR_read, __NR_rt_sigreturn, __NR_write, __sifields__bindgen_ty_7, SYS_SECCOMP,
+    },
+    ptrace::{
+        PTRACE_EVENT_SECCOMP, SECCOMP_MODE_FILTER, SECCOMP_MODE_STRICT, SECCOMP_RET_ACTION_FULL,
+        SECCOMP_RET_ALLOW, SECCOMP_RET_DATA, SECCOMP_RET_ERRNO, SECCOMP_RET_KILL_THREAD,
+        SECCOMP_RET_LOG, SECCOMP_RET_TRACE, SECCOMP_RET_TRAP, SECCOMP_RET_USER_NOTIF, seccomp_data,
+    },
+};
+use starry_core::seccomp::SECCOMP_ARCH;
+
+use crate::{
+    coredump::{self, WCOREFLAG},
+    do_exit,
+    ptrace::ptrace_event,
+    signal::force_signal,
+};
+
+/// The highest error number a filter can return with `SECCOMP_RET_ERRNO`.
+const MAX_ERRNO: u32 = 4095;
+
+/// The `SIGSYS` signal sent for the system call described by `data`, with
+/// the data `errno` of the action.
+fn sigsys(data: &seccomp_data, errno: u32) -> SignalInfo {
+    let mut sig = SignalInfo::new(Signo::SIGSYS, SYS_SECCOMP as _);
+    sig.0.__bindgen_anon_1.__bindgen_anon_1.si_errno = errno as _;
+    sig.0.__bindgen_anon_1.__bindgen_anon_1._sifields._sigsys = __sifields__bindgen_ty_7 {
+        _call_addr: data.instruction_pointer as _,
+        _syscall: data.nr,
+        _arch: data.arch,
+    };
+    sig
+}
+
+/// Kill the current thread, or its whole process if `group_exit` is set or
+/// if it is the last thread, for the system call described by `data`.
+fn kill(tf: &TrapFrame, data: &seccomp_data, group_exit: bool) -> ! {
+    let curr = current();
+    let process = curr.task_ext().thread.process();
+    if group_exit || process.threads().len() == 1 {
+        let mut status = Signo::SIGSYS as i32;
+        if coredump::dump_core(tf, &sigsys(data, 0)) {
+            status |= WCOREFLAG;
+        }
+        do_exit(status, true);
+    }
+    do_exit(Signo::SIGSYS as i32, false)
+}
+
+/// Check whether the current thread may execute the system call `sysno`,
+/// with the arguments in `tf`, before dispatching it.
+///
+/// Returns the system call to execute, which a tracer may have changed, or
+/// the value to return instead of executing it.
+pub fn seccomp_syscall_entry(tf: &mut TrapFrame, sysno: usize) -> Result<usize, isize> {
+    seccomp_check(tf, sysno, false)
+}
+
+fn seccomp_check(tf: &mut TrapFrame, sysno: usize, recheck: bool) -> Result<usize, isize> {
+    let curr = current();
+    let thr_data = curr.task_ext().thread_data();
+    let seccomp = thr_data.seccomp.read().clone();
+    let data = seccomp_data {
+        nr: sysno as _,
+        arch: SECCOMP_ARCH,
+        instruction_pointer: tf.ip() as _,
+        args: [
+            tf.arg0() as _,
+            tf.arg1() as _,
+            tf.arg2() as _,
+            tf.arg3() as _,
+            tf.arg4() as _,
+            tf.arg5() as _,
+        ],
+    };
+
+    match seccomp.mode {
+        SECCOMP_MODE_STRICT => {
+            let allowed = [__NR_read, __NR_write, __NR_exit, __NR_rt_sigreturn];
+            if !allowed.contains(&(sysno as u32)) {
+                warn!("seccomp: system call {sysno} denied in strict mode");
+                do_exit(Signo::SIGKILL as i32, false);
+            }
+            return Ok(sysno);
+        }
+        SECCOMP_MODE_FILTER => {}
+        _ => return Ok(sysno),
+    }
+
+    let Some((ret, log)) = seccomp.run(&data) else {
+        return Ok(sysno);
+    };
+    let action = ret & SECCOMP_RET_ACTION_FULL;
+    let errno = ret & SECCOMP_RET_DATA;
+    if action == SECCOMP_RET_LOG || (log && action != SECCOMP_RET_ALLOW) {
+        warn!("seccomp: system call {sysno} filtered with action {action:#x}");
+    }
+
+    match action {
+        SECCOMP_RET_ALLOW | SECCOMP_RET_LOG => Ok(sysno),
+        SECCOMP_RET_ERRNO => Err(-(errno.min(MAX_ERRNO) as isize)),
+        SECCOMP_RET_TRAP => {
+            force_signal(sigsys(&data, errno));
+            Err(-(LinuxError::ENOSYS.code() as isize))
+        }
+        SECCOMP_RET_TRACE => {
+            // Once the tracer has seen the system call, it is allowed if it
+            // passes the filters again.
+            if recheck {
+                return Ok(sysno);
+            }
+            thr_data.ptrace.lock().sysno = sysno;
+            if !ptrace_event(PTRACE_EVENT_SECCOMP, errno as _) {
+                return Err(-(LinuxError::ENOSYS.code() as isize));
+            }
+            // The tracer skips the system call by changing its number to -1.
+            let sysno = thr_data.ptrace.lock().sysno;
+            if sysno as isize == -1 {
+                return Err(-(LinuxError::ENOSYS.code() as isize));
+            }
+            seccomp_check(tf, sysno, true)
+        }
+        // There is no supervisor to notify.
+        SECCOMP_RET_USER_NOTIF => Err(-(LinuxError::ENOSYS.code() as isize)),
+        SECCOMP_RET_KILL_THREAD => kill(tf, &data, false),
+        // `SECCOMP_RET_KILL_PROCESS`, and unknown actions handled as such
+        _ => kill(tf, &data, true),
+    }
+}
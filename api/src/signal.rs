@@ -1,40 +1,71 @@
+use core::time::Duration;
+
 use axerrno::{LinuxError, LinuxResult};
 use axhal::{
     arch::TrapFrame,
-    trap::{POST_TRAP, register_trap_handler},
+    mem::VirtAddr,
+    time::nanos_to_ticks,
+    trap::{ExceptionKind, POST_TRAP, USER_EXCEPTION, register_trap_handler},
 };
 use axprocess::{Process, ProcessGroup, Thread};
-use axsignal::{SignalInfo, SignalOSAction, SignalSet};
-use axtask::{TaskExtRef, current};
-use starry_core::task::{ProcessData, ThreadData};
+use axsignal::{
+    DefaultSignalAction, SignalActionFlags, SignalDisposition, SignalInfo, SignalOSAction,
+    SignalSet, Signo,
+};
+use axtask::{TaskExtRef, WaitQueue, current};
+use linux_raw_sys::general::{
+    __sifields__bindgen_ty_4, BUS_ADRALN, BUS_ADRERR, CLD_CONTINUED, CLD_STOPPED, FPE_INTDIV,
+    ILL_ILLOPC, SI_KERNEL, SI_USER, TRAP_BRKPT, TRAP_TRACE,
+};
+use starry_core::{
+    ptrace::PtraceStop,
+    task::{
+        InterruptedSyscall, ProcessData, SyscallRestart, ThreadData, interrupt_threads,
+        send_expired_itimer_signals, time_stat_from_kernel_to_user,
+    },
+};
 
-use crate::do_exit;
+use crate::{
+    coredump::{self, WCOREFLAG},
+    do_exit,
+    ptrace::ptrace_stop,
+};
 
 pub fn check_signals(tf: &mut TrapFrame, restore_blocked: Option<SignalSet>) -> bool {
-    let Some((sig, os_action)) = current()
-        .task_ext()
-        .thread_data()
-        .signal
-        .check_signals(tf, restore_blocked)
-    else {
+    let curr = current();
+    let thr_data = curr.task_ext().thread_data();
+    let interrupted = thr_data.interrupted.lock().take();
+    if let Some(interrupted) = interrupted {
+        restart_syscall(tf, interrupted);
+    }
+    ptrace_signal_stops(thr_data);
+
+    let Some((sig, os_action)) = thr_data.signal.check_signals(tf, restore_blocked) else {
         return false;
     };
 
     let signo = sig.signo();
     match os_action {
         SignalOSAction::Terminate => {
-            do_exit(128 + signo as i32, true);
+            do_exit(signo as i32, true);
         }
         SignalOSAction::CoreDump => {
-            // TODO: implement core dump
-            do_exit(128 + signo as i32, true);
+            let mut status = signo as i32;
+            if coredump::dump_core(tf, &sig) {
+                status |= WCOREFLAG;
+            }
+            do_exit(status, true);
         }
         SignalOSAction::Stop => {
-            // TODO: implement stop
-            do_exit(1, true);
+            let curr = current();
+            let process = curr.task_ext().thread.process();
+            if curr.task_ext().process_data().stop(signo) {
+                notify_parent(process, CLD_STOPPED, signo);
+            }
+            wait_while_stopped();
         }
         SignalOSAction::Continue => {
-            // TODO: implement continue
+            // The process has been resumed when `SIGCONT` was sent.
         }
         SignalOSAction::Handler => {
             // do nothing
@@ -43,30 +74,321 @@ pub fn check_signals(tf: &mut TrapFrame, restore_blocked: Option<SignalSet>) ->
     true
 }
 
+/// Take the pending signal `signo`, which is not blocked, from the current
+/// thread.
+fn take_signal(thr_data: &ThreadData, signo: Signo) -> Option<SignalInfo> {
+    let signal = &thr_data.signal;
+    let mut set = SignalSet::default();
+    set.add(signo);
+    // Only blocked signals can be waited for.
+    let blocked = signal.blocked();
+    signal.with_blocked_mut(|it| it.add(signo));
+    let sig = signal.wait_timeout(set, Some(Duration::ZERO));
+    signal.with_blocked_mut(|it| *it = blocked);
+    sig
+}
+
+/// Stop the current thread, if it is traced, before delivering each of its
+/// signals but `SIGKILL`, so that the tracer can suppress or replace it.
+fn ptrace_signal_stops(thr_data: &ThreadData) {
+    // The signals the tracer let through, which are not stopped for again
+    let mut passed = SignalSet::default();
+    while thr_data.ptrace.tracer().is_some() {
+        let mut mask = !thr_data.signal.blocked() & !passed;
+        mask.remove(Signo::SIGKILL);
+        let Some(signo) = thr_data.signal.pending().dequeue(&mask) else {
+            break;
+        };
+        let Some(sig) = take_signal(thr_data, signo) else {
+            break;
+        };
+        if let Some(injected) = ptrace_stop(PtraceStop::Signal(sig.clone())) {
+            let sig = if injected == signo {
+                sig
+            } else {
+                SignalInfo::new(injected, SI_USER as _)
+            };
+            passed.add(injected);
+            thr_data.signal.send_signal(sig);
+        }
+    }
+}
+
+/// Record that the current system call, whose number is `sysno` and whose
+/// arguments are still in `tf`, failed with `EINTR`.
+///
+/// Whether it is restarted is decided by [`check_signals`] before it delivers
+/// the signal.
+pub fn set_interrupted(tf: &TrapFrame, sysno: usize, restart: SyscallRestart) {
+    *current().task_ext().thread_data().interrupted.lock() = Some(InterruptedSyscall {
+        sysno,
+        arg0: tf.arg0(),
+        restart,
+    });
+}
+
+/// Whether the handler the next signal to deliver runs has `SA_RESTART`, or
+/// `None` if it does not run a handler.
+fn next_handler_restarts(thr_data: &ThreadData) -> Option<bool> {
+    let mut pending = thr_data.signal.pending();
+    let mask = !thr_data.signal.blocked();
+    let curr = current();
+    let actions = curr.task_ext().process_data().signal.actions.lock();
+    while let Some(signo) = pending.dequeue(&mask) {
+        let action = &actions[signo];
+        match &action.disposition {
+            SignalDisposition::Handler(_) => {
+                return Some(action.flags.contains(SignalActionFlags::RESTART));
+            }
+            SignalDisposition::Ignore => {}
+            SignalDisposition::Default => {
+                if !matches!(signo.default_action(), DefaultSignalAction::Ignore) {
+                    return None;
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Make the interrupted system call execute again when returning to user
+/// space, if the signal about to be delivered allows it. Otherwise it fails
+/// with `EINTR`, which is already in `tf`.
+fn restart_syscall(tf: &mut TrapFrame, interrupted: InterruptedSyscall) {
+    let curr = current();
+    let thr_data = curr.task_ext().thread_data();
+    let handler_restarts = next_handler_restarts(thr_data);
+    let mut restart_block = thr_data.restart_block.lock();
+    let restart = match interrupted.restart {
+        SyscallRestart::Sys => handler_restarts.unwrap_or(true),
+        SyscallRestart::NoHandler => handler_restarts.is_none(),
+        SyscallRestart::Block => handler_restarts.is_none() && restart_block.is_some(),
+    };
+    if !restart {
+        *restart_block = None;
+        return;
+    }
+    debug!("Restart syscall {}", interrupted.sysno);
+
+    #[cfg(target_arch = "x86_64")]
+    {
+        tf.rip -= 2;
+        tf.rax = interrupted.sysno as _;
+    }
+    #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+    {
+        tf.sepc -= 4;
+        tf.regs.a7 = interrupted.sysno;
+    }
+    #[cfg(target_arch = "aarch64")]
+    {
+        tf.elr -= 4;
+        tf.r[8] = interrupted.sysno as _;
+    }
+    #[cfg(target_arch = "loongarch64")]
+    {
+        tf.era -= 4;
+        tf.regs.a7 = interrupted.sysno;
+    }
+    tf.set_arg0(interrupted.arg0);
+}
+
+/// Send a signal caused by an instruction of the current thread, such as
+/// `SIGSEGV` for an invalid memory access, with the faulting address `addr`.
+pub fn send_fault_signal(signo: Signo, code: u32, addr: usize) {
+    let mut sig = SignalInfo::new(signo, code as _);
+    sig.0
+        .__bindgen_anon_1
+        .__bindgen_anon_1
+        ._sifields
+        ._sigfault
+        ._addr = addr as _;
+    force_signal(sig);
+}
+
+/// Send `sig` to the current thread, which cannot block or ignore it.
+///
+/// The signal is unblocked and its action reset to the default in that case,
+/// as done by Linux, since the instruction or system call causing it would
+/// be executed again if the signal returned.
+pub fn force_signal(sig: SignalInfo) {
+    let signo = sig.signo();
+    let curr = current();
+    let signal = &curr.task_ext().thread_data().signal;
+    let blocked = signal.blocked().has(signo);
+    {
+        let mut actions = curr.task_ext().process_data().signal.actions.lock();
+        let action = &mut actions[signo];
+        if blocked || matches!(action.disposition, SignalDisposition::Ignore) {
+            action.disposition = SignalDisposition::Default;
+        }
+    }
+    if blocked {
+        signal.with_blocked_mut(|blocked| blocked.remove(signo));
+    }
+    let _ = send_signal_thread(&curr.task_ext().thread, sig);
+}
+
+#[register_trap_handler(USER_EXCEPTION)]
+fn user_exception_callback(kind: ExceptionKind, vaddr: VirtAddr) -> bool {
+    let (signo, code) = match kind {
+        ExceptionKind::Breakpoint => (Signo::SIGTRAP, TRAP_BRKPT),
+        ExceptionKind::IllegalInstruction => (Signo::SIGILL, ILL_ILLOPC),
+        ExceptionKind::Misaligned => (Signo::SIGBUS, BUS_ADRALN),
+        ExceptionKind::BusError => (Signo::SIGBUS, BUS_ADRERR),
+        ExceptionKind::DivideByZero => (Signo::SIGFPE, FPE_INTDIV),
+        ExceptionKind::ProtectionFault => (Signo::SIGSEGV, SI_KERNEL),
+        ExceptionKind::SingleStep => (Signo::SIGTRAP, TRAP_TRACE),
+    };
+    debug!("{:?} at {:#x}, sending {:?}", kind, vaddr.as_usize(), signo);
+    send_fault_signal(signo, code, vaddr.as_usize());
+    true
+}
+
 #[register_trap_handler(POST_TRAP)]
 fn post_trap_callback(tf: &mut TrapFrame, from_user: bool) {
     if !from_user {
         return;
     }
 
+    send_expired_itimer_signals();
+    wait_while_stopped();
     check_signals(tf, None);
+    time_stat_from_kernel_to_user();
+}
+
+/// Suspend the current thread while its process is stopped by a job control
+/// signal, until `SIGCONT` or `SIGKILL` arrives.
+pub fn wait_while_stopped() {
+    let curr = current();
+    let proc_data = curr.task_ext().process_data();
+    if !proc_data.is_stopped() {
+        return;
+    }
+    let signal = &curr.task_ext().thread_data().signal;
+    proc_data.wait_while_stopped(|| signal.pending().has(Signo::SIGKILL));
+}
+
+/// Build the `SIGCHLD` signal reporting a state change of `proc`, where
+/// `code` is one of the `CLD_*` values and `status` is the exit code or the
+/// signal which caused the change.
+pub fn sigchld_info(proc: &Process, code: u32, status: i32) -> SignalInfo {
+    let mut sig = SignalInfo::new(Signo::SIGCHLD, code as _);
+    let (utime, stime) = proc.data::<ProcessData>().map_or((0, 0), |data| {
+        (
+            nanos_to_ticks(data.cpu_time.user().as_nanos() as _),
+            nanos_to_ticks(data.cpu_time.system().as_nanos() as _),
+        )
+    });
+    sig.0.__bindgen_anon_1.__bindgen_anon_1._sifields._sigchld = __sifields__bindgen_ty_4 {
+        _pid: proc.pid() as _,
+        _uid: 0,
+        _status: status,
+        _utime: utime as _,
+        _stime: stime as _,
+    };
+    sig
+}
+
+/// Notify the parent of `proc` that it has stopped or continued because of
+/// `signo`.
+///
+/// No `SIGCHLD` is sent if the parent set `SA_NOCLDSTOP` for it, but its
+/// waiters are still woken up.
+fn notify_parent(proc: &Process, code: u32, signo: Signo) {
+    let Some(parent) = proc.parent() else {
+        return;
+    };
+    let Some(data) = parent.data::<ProcessData>() else {
+        return;
+    };
+    let nocldstop = data.signal.actions.lock()[Signo::SIGCHLD]
+        .flags
+        .contains(SignalActionFlags::NOCLDSTOP);
+    if !nocldstop {
+        let _ = send_signal_process(&parent, sigchld_info(proc, code, signo as _));
+    }
+    data.notify_child_event();
+}
+
+/// The signals which stop a process.
+fn stop_signals() -> SignalSet {
+    let mut set = SignalSet::default();
+    for signo in [
+        Signo::SIGSTOP,
+        Signo::SIGTSTP,
+        Signo::SIGTTIN,
+        Signo::SIGTTOU,
+    ] {
+        set.add(signo);
+    }
+    set
+}
+
+/// Discard the signals in `set` pending for `proc` or for any of its threads.
+fn discard_pending(proc: &Process, set: &SignalSet) {
+    if let Some(data) = proc.data::<ProcessData>() {
+        data.signal.discard_signals(set);
+    }
+    for thr in proc.threads() {
+        if let Some(data) = thr.data::<ThreadData>() {
+            data.signal.discard_signals(set);
+        }
+    }
+}
+
+/// Apply the side effects `signo` has on the process as soon as it is sent,
+/// regardless of whether it is blocked or handled.
+///
+/// As in Linux, `SIGCONT` discards the pending stop signals and the stop
+/// signals discard the pending `SIGCONT`.
+fn handle_job_control(proc: &Process, signo: Signo) {
+    let Some(data) = proc.data::<ProcessData>() else {
+        return;
+    };
+    match signo {
+        Signo::SIGCONT => {
+            discard_pending(proc, &stop_signals());
+            if data.resume() {
+                notify_parent(proc, CLD_CONTINUED, signo);
+            }
+        }
+        Signo::SIGSTOP | Signo::SIGTSTP | Signo::SIGTTIN | Signo::SIGTTOU => {
+            let mut set = SignalSet::default();
+            set.add(Signo::SIGCONT);
+            discard_pending(proc, &set);
+        }
+        Signo::SIGKILL => {
+            data.wake_stopped();
+            for thr in proc.threads() {
+                if let Some(data) = thr.data::<ThreadData>() {
+                    data.ptrace.wake();
+                }
+            }
+        }
+        _ => {}
+    }
 }
 
 pub fn send_signal_thread(thr: &Thread, sig: SignalInfo) -> LinuxResult<()> {
     info!("Send signal {:?} to thread {}", sig.signo(), thr.tid());
-    let Some(thr) = thr.data::<ThreadData>() else {
+    let Some(data) = thr.data::<ThreadData>() else {
         return Err(LinuxError::EPERM);
     };
-    thr.signal.send_signal(sig);
+    handle_job_control(thr.process(), sig.signo());
+    data.signal.send_signal(sig);
+    data.interrupt();
     Ok(())
 }
 
 pub fn send_signal_process(proc: &Process, sig: SignalInfo) -> LinuxResult<()> {
     info!("Send signal {:?} to process {}", sig.signo(), proc.pid());
-    let Some(proc) = proc.data::<ProcessData>() else {
+    let Some(data) = proc.data::<ProcessData>() else {
         return Err(LinuxError::EPERM);
     };
-    proc.signal.send_signal(sig);
+    handle_job_control(proc, sig.signo());
+    data.signal.send_signal(sig);
+    interrupt_threads(proc);
     Ok(())
 }
 
@@ -82,3 +404,19 @@ pub fn send_signal_process_group(pg: &ProcessGroup, sig: SignalInfo) -> usize {
     }
     count
 }
+
+/// Whether the current thread has a pending signal that is not blocked.
+///
+/// Blocking system calls use this to return `EINTR` early.
+pub fn have_signals() -> bool {
+    let curr = current();
+    let signal = &curr.task_ext().thread_data().signal;
+    signal.pending().dequeue(&!signal.blocked()).is_some()
+}
+
+/// Block the current thread until a signal is sent to it or its process.
+///
+/// It may return early, so the callers check again what they wait for.
+pub fn wait_for_signal() {
+    WaitQueue::new().wait_until_interruptible(|| false);
+}
@@ -0,0 +1,50 @@
+//! The terminal of the system console.
+
+use core::time::Duration;
+
+use alloc::{boxed::Box, string::ToString, sync::Arc};
+use spin::Lazy;
+
+use super::{Terminal, TerminalDriver, register_terminal};
+
+/// The device ID of `/dev/tty`, i.e. `makedev(5, 0)`.
+pub(super) const TTY_RDEV: u64 = 5 << 8;
+/// The device ID of `/dev/console`, i.e. `makedev(5, 1)`.
+const CONSOLE_RDEV: u64 = (5 << 8) | 1;
+
+/// How often the console is polled for input.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+struct ConsoleDriver;
+
+impl TerminalDriver for ConsoleDriver {
+    fn write(&self, buf: &[u8]) {
+        axhal::console::write_bytes(buf);
+    }
+}
+
+/// The terminal of the system console.
+pub static CONSOLE: Lazy<Arc<Terminal>> =
+    Lazy::new(|| register_terminal(Terminal::new(CONSOLE_RDEV, Box::new(ConsoleDriver))));
+
+/// The console has no input interrupt, so a kernel task polls it and feeds
+/// the line discipline. This way the job control characters work even when
+/// nobody is reading.
+#[ctor_bare::register_ctor]
+fn init_console() {
+    axtask::spawn_raw(
+        || {
+            let mut buf = [0; 64];
+            loop {
+                let len = axhal::console::read_bytes(&mut buf);
+                if len > 0 {
+                    CONSOLE.receive(&buf[..len]);
+                } else {
+                    axtask::sleep(POLL_INTERVAL);
+                }
+            }
+        },
+        "console".to_string(),
+        axconfig::TASK_STACK_SIZE,
+    );
+}
@@ -0,0 +1,360 @@
+//! The line discipline, which processes the input and output of a terminal
+//! according to its termios settings.
+
+use alloc::{collections::vec_deque::VecDeque, vec::Vec};
+use axsignal::Signo;
+use linux_raw_sys::general::{
+    B38400, CREAD, CS8, ECHO, ECHOCTL, ECHOE, ECHOK, ECHOKE, ECHONL, HUPCL, ICANON, ICRNL, IEXTEN,
+    IGNCR, INLCR, ISIG, ISTRIP, IUTF8, IXON, NOFLSH, OCRNL, ONLCR, OPOST, VEOF, VEOL, VEOL2,
+    VERASE, VINTR, VKILL, VLNEXT, VMIN, VQUIT, VREPRINT, VSTART, VSTOP, VSUSP, VWERASE, termios,
+};
+
+/// Maximum number of bytes buffered as input.
+const INPUT_BUFFER_SIZE: usize = 4096;
+
+/// The termios settings of a newly created terminal, as set by Linux.
+fn default_termios() -> termios {
+    let mut c_cc = [0; 19];
+    c_cc[VINTR as usize] = 0x03; // ^C
+    c_cc[VQUIT as usize] = 0x1c; // ^\
+    c_cc[VERASE as usize] = 0x7f; // DEL
+    c_cc[VKILL as usize] = 0x15; // ^U
+    c_cc[VEOF as usize] = 0x04; // ^D
+    c_cc[VMIN as usize] = 1;
+    c_cc[VSTART as usize] = 0x11; // ^Q
+    c_cc[VSTOP as usize] = 0x13; // ^S
+    c_cc[VSUSP as usize] = 0x1a; // ^Z
+    c_cc[VREPRINT as usize] = 0x12; // ^R
+    c_cc[VWERASE as usize] = 0x17; // ^W
+    c_cc[VLNEXT as usize] = 0x16; // ^V
+    termios {
+        c_iflag: ICRNL | IXON | IUTF8,
+        c_oflag: OPOST | ONLCR,
+        c_cflag: B38400 | CS8 | CREAD | HUPCL,
+        c_lflag: ISIG | ICANON | ECHO | ECHOE | ECHOK | ECHOCTL | ECHOKE | IEXTEN,
+        c_line: 0,
+        c_cc,
+    }
+}
+
+/// Whether `c` is echoed as `^X` with `ECHOCTL`.
+fn is_control(c: u8) -> bool {
+    (c < 0x20 && c != b'\n' && c != b'\t') || c == 0x7f
+}
+
+/// What the terminal has to do after receiving some input.
+#[derive(Default)]
+pub struct InputEffects {
+    /// Bytes to echo back to the device, before output processing
+    pub echo: Vec<u8>,
+    /// Signals to send to the foreground process group
+    pub signals: Vec<Signo>,
+}
+
+pub struct LineDiscipline {
+    pub termios: termios,
+    /// Input ready to be read
+    input: VecDeque<u8>,
+    /// In canonical mode, the lengths of the complete lines at the head of
+    /// `input`. An empty line is an end-of-file.
+    lines: VecDeque<usize>,
+    /// The line being edited in canonical mode
+    line: Vec<u8>,
+    /// Whether the next byte is taken literally, after `VLNEXT`
+    literal_next: bool,
+}
+
+impl LineDiscipline {
+    pub fn new() -> Self {
+        Self {
+            termios: default_termios(),
+            input: VecDeque::new(),
+            lines: VecDeque::new(),
+            line: Vec::new(),
+            literal_next: false,
+        }
+    }
+
+    pub fn is_canonical(&self) -> bool {
+        self.termios.c_lflag & ICANON != 0
+    }
+
+    /// Whether `c` is the special character at `index` of `c_cc`.
+    ///
+    /// A zero entry disables the special character.
+    fn is_special(&self, c: u8, index: u32) -> bool {
+        let special = self.termios.c_cc[index as usize];
+        special != 0 && c == special
+    }
+
+    /// Change the termios settings, keeping the buffered input readable.
+    pub fn set_termios(&mut self, termios: termios) {
+        let was_canonical = self.is_canonical();
+        self.termios = termios;
+        match (was_canonical, self.is_canonical()) {
+            (true, false) => {
+                self.input.extend(self.line.drain(..));
+                self.lines.clear();
+            }
+            (false, true) => {
+                let pending = self.input.len() - self.lines.iter().sum::<usize>();
+                if pending > 0 {
+                    self.lines.push_back(pending);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Discard all the buffered input.
+    pub fn flush_input(&mut self) {
+        self.input.clear();
+        self.lines.clear();
+        self.line.clear();
+    }
+
+    /// Whether a read would return immediately.
+    pub fn readable(&self) -> bool {
+        if self.is_canonical() {
+            !self.lines.is_empty()
+        } else {
+            !self.input.is_empty()
+        }
+    }
+
+    /// Number of bytes which can be read, as reported by `FIONREAD`.
+    pub fn available(&self) -> usize {
+        if self.is_canonical() {
+            self.lines.iter().sum()
+        } else {
+            self.input.len()
+        }
+    }
+
+    /// Number of bytes which can still be buffered as input.
+    pub fn input_room(&self) -> usize {
+        INPUT_BUFFER_SIZE.saturating_sub(self.input.len() + self.line.len())
+    }
+
+    /// Read at most one line in canonical mode, or `None` if no complete line
+    /// has been entered yet.
+    pub fn read_line(&mut self, buf: &mut [u8]) -> Option<usize> {
+        let line = self.lines.front_mut()?;
+        let len = (*line).min(buf.len());
+        for (dst, src) in buf.iter_mut().zip(self.input.drain(..len)) {
+            *dst = src;
+        }
+        *line -= len;
+        if *line == 0 {
+            self.lines.pop_front();
+        }
+        Some(len)
+    }
+
+    /// Read the available input in non-canonical mode.
+    pub fn read_raw(&mut self, buf: &mut [u8]) -> usize {
+        let len = self.input.len().min(buf.len());
+        for (dst, src) in buf.iter_mut().zip(self.input.drain(..len)) {
+            *dst = src;
+        }
+        len
+    }
+
+    fn echo(&self, c: u8, effects: &mut InputEffects) {
+        let lflag = self.termios.c_lflag;
+        if lflag & ECHO == 0 {
+            if c == b'\n' && lflag & ECHONL != 0 {
+                effects.echo.push(c);
+            }
+        } else if lflag & ECHOCTL != 0 && is_control(c) {
+            effects.echo.extend_from_slice(&[b'^', c ^ 0x40]);
+        } else {
+            effects.echo.push(c);
+        }
+    }
+
+    /// Remove the last character of the line being edited, erasing it from
+    /// the screen if `visual` is set.
+    fn erase_char(&mut self, visual: bool, effects: &mut InputEffects) -> Option<u8> {
+        let mut c = self.line.pop()?;
+        if self.termios.c_iflag & IUTF8 != 0 {
+            // Remove the whole UTF-8 sequence.
+            while c & 0xc0 == 0x80 {
+                match self.line.pop() {
+                    Some(lead) => c = lead,
+                    None => break,
+                }
+            }
+        }
+        if visual && self.termios.c_lflag & ECHO != 0 {
+            let width = if self.termios.c_lflag & ECHOCTL != 0 && is_control(c) {
+                2
+            } else {
+                1
+            };
+            for _ in 0..width {
+                effects.echo.extend_from_slice(b"\x08 \x08");
+            }
+        }
+        Some(c)
+    }
+
+    /// Move the line being edited to the input, making it readable.
+    fn commit_line(&mut self) {
+        self.lines.push_back(self.line.len());
+        self.input.extend(self.line.drain(..));
+    }
+
+    /// Process a byte received from the device.
+    pub fn receive(&mut self, byte: u8, effects: &mut InputEffects) {
+        let iflag = self.termios.c_iflag;
+        let lflag = self.termios.c_lflag;
+        let canonical = self.is_canonical();
+
+        let mut c = byte;
+        if self.literal_next {
+            self.literal_next = false;
+            if lflag & ECHO != 0 && lflag & ECHOCTL != 0 {
+                // Remove the `^` echoed for `VLNEXT`.
+                effects.echo.extend_from_slice(b"\x08");
+            }
+            self.echo(c, effects);
+            self.push(c);
+            return;
+        }
+
+        if iflag & ISTRIP != 0 {
+            c &= 0x7f;
+        }
+        if c == b'\r' {
+            if iflag & IGNCR != 0 {
+                return;
+            }
+            if iflag & ICRNL != 0 {
+                c = b'\n';
+            }
+        } else if c == b'\n' && iflag & INLCR != 0 {
+            c = b'\r';
+        }
+
+        // Output is never suspended, so the flow control characters are
+        // just swallowed.
+        if iflag & IXON != 0 && (self.is_special(c, VSTOP) || self.is_special(c, VSTART)) {
+            return;
+        }
+
+        if lflag & ISIG != 0 {
+            let signo = if self.is_special(c, VINTR) {
+                Some(Signo::SIGINT)
+            } else if self.is_special(c, VQUIT) {
+                Some(Signo::SIGQUIT)
+            } else if self.is_special(c, VSUSP) {
+                Some(Signo::SIGTSTP)
+            } else {
+                None
+            };
+            if let Some(signo) = signo {
+                if lflag & NOFLSH == 0 {
+                    self.flush_input();
+                }
+                self.echo(c, effects);
+                effects.signals.push(signo);
+                return;
+            }
+        }
+
+        if canonical {
+            if self.is_special(c, VERASE) {
+                if lflag & ECHOE != 0 {
+                    self.erase_char(true, effects);
+                } else if self.erase_char(false, effects).is_some() {
+                    self.echo(c, effects);
+                }
+                return;
+            }
+            if self.is_special(c, VKILL) {
+                if lflag & ECHOKE != 0 && lflag & ECHOE != 0 {
+                    while self.erase_char(true, effects).is_some() {}
+                } else {
+                    self.line.clear();
+                    self.echo(c, effects);
+                    if lflag & ECHOK != 0 {
+                        effects.echo.push(b'\n');
+                    }
+                }
+                return;
+            }
+            if lflag & IEXTEN != 0 {
+                if self.is_special(c, VWERASE) {
+                    while self.line.last().is_some_and(u8::is_ascii_whitespace) {
+                        self.erase_char(true, effects);
+                    }
+                    while self.line.last().is_some_and(|it| !it.is_ascii_whitespace()) {
+                        self.erase_char(true, effects);
+                    }
+                    return;
+                }
+                if self.is_special(c, VLNEXT) {
+                    self.literal_next = true;
+                    if lflag & ECHO != 0 && lflag & ECHOCTL != 0 {
+                        effects.echo.push(b'^');
+                    }
+                    return;
+                }
+                if self.is_special(c, VREPRINT) {
+                    self.echo(c, effects);
+                    effects.echo.push(b'\n');
+                    for i in 0..self.line.len() {
+                        self.echo(self.line[i], effects);
+                    }
+                    return;
+                }
+            }
+            if self.is_special(c, VEOF) {
+                self.commit_line();
+                return;
+            }
+            if c == b'\n'
+                || self.is_special(c, VEOL)
+                || (lflag & IEXTEN != 0 && self.is_special(c, VEOL2))
+            {
+                self.echo(c, effects);
+                self.line.push(c);
+                self.commit_line();
+                return;
+            }
+        }
+
+        self.echo(c, effects);
+        self.push(c);
+    }
+
+    /// Buffer an ordinary input byte, dropping it if the buffer is full.
+    fn push(&mut self, c: u8) {
+        if self.input.len() + self.line.len() >= INPUT_BUFFER_SIZE {
+            return;
+        }
+        if self.is_canonical() {
+            self.line.push(c);
+        } else {
+            self.input.push_back(c);
+        }
+    }
+
+    /// Apply output processing to `buf`.
+    pub fn process_output(&self, buf: &[u8]) -> Vec<u8> {
+        let oflag = self.termios.c_oflag;
+        if oflag & OPOST == 0 {
+            return buf.to_vec();
+        }
+        let mut out = Vec::with_capacity(buf.len());
+        for &c in buf {
+            match c {
+                b'\n' if oflag & ONLCR != 0 => out.extend_from_slice(b"\r\n"),
+                b'\r' if oflag & OCRNL != 0 => out.push(b'\n'),
+                _ => out.push(c),
+            }
+        }
+        out
+    }
+}
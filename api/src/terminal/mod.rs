@@ -0,0 +1,605 @@
+//! Terminals and their relationship with sessions and process groups.
+//!
+//! A terminal can be the controlling terminal of at most one session, and a
+//! session has at most one controlling terminal. The session leader acquires
+//! it by opening a terminal without `O_NOCTTY` while the session has none, or
+//! with `TIOCSCTTY`, and gives it up with `TIOCNOTTY`.
+//!
+//! The input of a terminal goes through its [line discipline](ldisc), which
+//! implements line editing, echo and the job control characters.
+//!
+//! Besides the console, terminals are created in pairs with a
+//! [pseudo-terminal](pty) master by opening `/dev/ptmx`.
+
+mod console;
+mod ldisc;
+mod pty;
+
+use core::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use alloc::{
+    boxed::Box,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use axerrno::{LinuxError, LinuxResult};
+use axhal::time::monotonic_time;
+use axprocess::{Pid, ProcessGroup, Session};
+use axsignal::{SignalDisposition, SignalInfo, Signo};
+use axtask::{TaskExtRef, WaitQueue, current};
+use linux_raw_sys::general::{
+    O_NOCTTY, O_NONBLOCK, SI_KERNEL, TCIFLUSH, TCIOFLUSH, TCOFLUSH, TOSTOP, VMIN, VTIME, termios,
+    winsize,
+};
+use spin::Mutex;
+use starry_core::task::{get_process, get_process_group};
+
+use self::ldisc::{InputEffects, LineDiscipline};
+use crate::{
+    file::{FileLike, PtyMaster, Tty},
+    ptr::{UserConstPtr, UserPtr},
+    signal::{have_signals, send_signal_process, send_signal_process_group},
+};
+
+pub use self::{
+    console::CONSOLE,
+    pty::{PTMX_RDEV, Pty},
+};
+
+const TCGETS: u32 = 0x5401;
+const TCSETS: u32 = 0x5402;
+const TCSETSW: u32 = 0x5403;
+const TCSETSF: u32 = 0x5404;
+const TCSBRK: u32 = 0x5409;
+const TCXONC: u32 = 0x540A;
+const TCFLSH: u32 = 0x540B;
+const TIOCSCTTY: u32 = 0x540E;
+const TIOCGPGRP: u32 = 0x540F;
+const TIOCSPGRP: u32 = 0x5410;
+const TIOCOUTQ: u32 = 0x5411;
+const TIOCGWINSZ: u32 = 0x5413;
+const TIOCSWINSZ: u32 = 0x5414;
+const FIONREAD: u32 = 0x541B;
+const TIOCNOTTY: u32 = 0x5422;
+const TIOCGSID: u32 = 0x5429;
+
+/// How often a blocked reader or writer checks for progress, as the drivers
+/// do not all notify it. Signals wake it up at once.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The device under a terminal, which receives its processed output.
+///
+/// The input of the device is fed to the terminal with [`Terminal::receive`].
+pub trait TerminalDriver: Send + Sync {
+    /// Send output to the device.
+    fn write(&self, buf: &[u8]);
+
+    /// How many bytes of output the device can take without blocking.
+    fn write_room(&self) -> usize {
+        usize::MAX
+    }
+
+    /// Number of output bytes not yet consumed by the device.
+    fn output_pending(&self) -> usize {
+        0
+    }
+
+    /// Called when the terminal is opened, which fails if the device refuses
+    /// it.
+    fn open(&self) -> LinuxResult {
+        Ok(())
+    }
+
+    /// Called when a file opened on the terminal is closed.
+    fn release(&self) {}
+
+    /// Called when the input or the output is flushed with `TCFLSH`.
+    fn flush(&self, _input: bool, _output: bool) {}
+
+    /// Called when the termios settings are changed.
+    fn set_termios(&self, _termios: &termios) {}
+}
+
+/// A terminal device.
+pub struct Terminal {
+    /// The device ID
+    rdev: u64,
+    driver: Box<dyn TerminalDriver>,
+    ldisc: Mutex<LineDiscipline>,
+    winsize: Mutex<winsize>,
+    /// The readers waiting for input
+    read_wq: WaitQueue,
+    /// The writers waiting for the device to take more output
+    write_wq: WaitQueue,
+    /// Whether the device has gone away
+    hung_up: AtomicBool,
+
+    /// The session this terminal is the controlling terminal of
+    session: Mutex<Weak<Session>>,
+    /// The foreground process group
+    foreground: Mutex<Weak<ProcessGroup>>,
+}
+
+impl Terminal {
+    /// Create a terminal over `driver` with the default settings.
+    pub fn new(rdev: u64, driver: Box<dyn TerminalDriver>) -> Self {
+        Self {
+            rdev,
+            driver,
+            ldisc: Mutex::new(LineDiscipline::new()),
+            winsize: Mutex::new(winsize {
+                ws_row: 24,
+                ws_col: 80,
+                ws_xpixel: 0,
+                ws_ypixel: 0,
+            }),
+            read_wq: WaitQueue::new(),
+            write_wq: WaitQueue::new(),
+            hung_up: AtomicBool::new(false),
+            session: Mutex::new(Weak::new()),
+            foreground: Mutex::new(Weak::new()),
+        }
+    }
+
+    /// The device ID of the terminal.
+    pub fn rdev(&self) -> u64 {
+        self.rdev
+    }
+
+    /// Open the terminal, as done for each file opened on it.
+    pub fn open(&self) -> LinuxResult {
+        if self.is_hung_up() {
+            return Err(LinuxError::EIO);
+        }
+        self.driver.open()
+    }
+
+    /// Close a file opened with [`Terminal::open`].
+    pub fn release(&self) {
+        self.driver.release();
+    }
+
+    /// Whether the device has gone away.
+    pub fn is_hung_up(&self) -> bool {
+        self.hung_up.load(Ordering::Acquire)
+    }
+
+    /// Hang up the terminal when the device goes away.
+    ///
+    /// The session leader and the foreground process group get `SIGHUP` and
+    /// `SIGCONT`, and the terminal is no longer the controlling terminal of
+    /// the session. Afterwards reads return end-of-file and writes fail with
+    /// `EIO`.
+    pub fn hangup(&self) {
+        self.hung_up.store(true, Ordering::Release);
+        if let Some(leader) = self.session().and_then(|it| get_process(it.sid()).ok()) {
+            for signo in [Signo::SIGHUP, Signo::SIGCONT] {
+                let _ = send_signal_process(&leader, SignalInfo::new(signo, SI_KERNEL as _));
+            }
+        }
+        self.signal_foreground(Signo::SIGHUP);
+        self.signal_foreground(Signo::SIGCONT);
+        self.detach();
+        self.read_wq.notify_all(false);
+        self.write_wq.notify_all(false);
+    }
+
+    /// Wake up the writers after the device has consumed some output.
+    pub fn wake_writers(&self) {
+        self.write_wq.notify_all(false);
+    }
+
+    /// The session this terminal is the controlling terminal of.
+    pub fn session(&self) -> Option<Arc<Session>> {
+        self.session.lock().upgrade()
+    }
+
+    /// The foreground process group.
+    pub fn foreground(&self) -> Option<Arc<ProcessGroup>> {
+        self.foreground.lock().upgrade()
+    }
+
+    /// Whether this is the controlling terminal of `session`.
+    pub fn controls(&self, session: &Arc<Session>) -> bool {
+        self.session().is_some_and(|it| Arc::ptr_eq(&it, session))
+    }
+
+    /// Make this the controlling terminal of `session`, with `foreground` as
+    /// the foreground process group.
+    pub fn attach(&self, session: &Arc<Session>, foreground: &Arc<ProcessGroup>) {
+        *self.session.lock() = Arc::downgrade(session);
+        *self.foreground.lock() = Arc::downgrade(foreground);
+    }
+
+    /// Disassociate this terminal from its session.
+    pub fn detach(&self) {
+        *self.session.lock() = Weak::new();
+        *self.foreground.lock() = Weak::new();
+    }
+
+    /// Change the foreground process group, which must belong to the session
+    /// controlled by this terminal.
+    pub fn set_foreground(&self, group: &Arc<ProcessGroup>) -> LinuxResult {
+        if !self.controls(&group.session()) {
+            return Err(LinuxError::EPERM);
+        }
+        *self.foreground.lock() = Arc::downgrade(group);
+        Ok(())
+    }
+
+    fn signal_foreground(&self, signo: Signo) {
+        if let Some(foreground) = self.foreground() {
+            send_signal_process_group(&foreground, SignalInfo::new(signo, SI_KERNEL as _));
+        }
+    }
+
+    /// Apply job control to the current process accessing this terminal.
+    ///
+    /// A process in a background process group of the session gets `SIGTTIN`
+    /// when reading, and `SIGTTOU` when writing or changing the settings. The
+    /// access is refused with `EIO` if the group is orphaned, or if it ignores
+    /// or blocks `SIGTTIN`; a process which ignores or blocks `SIGTTOU` is
+    /// allowed to write.
+    fn check_background(&self, signo: Signo) -> LinuxResult {
+        let curr = current();
+        let process = curr.task_ext().thread.process();
+        let group = process.group();
+        if !self.controls(&group.session())
+            || self
+                .foreground()
+                .is_none_or(|foreground| Arc::ptr_eq(&foreground, &group))
+        {
+            return Ok(());
+        }
+
+        let ignored = curr.task_ext().thread_data().signal.blocked().has(signo)
+            || matches!(
+                curr.task_ext().process_data().signal.actions.lock()[signo].disposition,
+                SignalDisposition::Ignore
+            );
+        if ignored {
+            return if signo == Signo::SIGTTOU {
+                Ok(())
+            } else {
+                Err(LinuxError::EIO)
+            };
+        }
+        if is_orphaned(&group) {
+            return Err(LinuxError::EIO);
+        }
+        send_signal_process_group(&group, SignalInfo::new(signo, SI_KERNEL as _));
+        Err(LinuxError::EINTR)
+    }
+
+    /// Feed input received from the device to the line discipline.
+    pub fn receive(&self, bytes: &[u8]) {
+        let mut effects = InputEffects::default();
+        let mut ldisc = self.ldisc.lock();
+        for &byte in bytes {
+            ldisc.receive(byte, &mut effects);
+        }
+        let echo = ldisc.process_output(&effects.echo);
+        drop(ldisc);
+
+        if !echo.is_empty() {
+            self.driver.write(&echo);
+        }
+        for signo in effects.signals {
+            self.signal_foreground(signo);
+        }
+        self.read_wq.notify_all(false);
+    }
+
+    /// Whether a read would return immediately.
+    pub fn readable(&self) -> bool {
+        self.ldisc.lock().readable() || self.is_hung_up()
+    }
+
+    /// Number of input bytes which can still be received.
+    pub fn input_room(&self) -> usize {
+        self.ldisc.lock().input_room()
+    }
+
+    /// Read the input, following the canonical mode or `VMIN` and `VTIME`.
+    pub fn read(&self, buf: &mut [u8], nonblocking: bool) -> LinuxResult<usize> {
+        self.check_background(Signo::SIGTTIN)?;
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let mut last_available = 0;
+        let mut last_change = monotonic_time();
+        loop {
+            let mut ldisc = self.ldisc.lock();
+            if ldisc.is_canonical() {
+                if let Some(len) = ldisc.read_line(buf) {
+                    return Ok(len);
+                }
+            } else {
+                let vmin = ldisc.termios.c_cc[VMIN as usize] as usize;
+                let vtime = Duration::from_millis(ldisc.termios.c_cc[VTIME as usize] as u64 * 100);
+                let available = ldisc.available();
+                let now = monotonic_time();
+                if available != last_available {
+                    last_available = available;
+                    last_change = now;
+                }
+                // With `VMIN > 0`, `VTIME` is an inter-byte timer which only
+                // starts after the first byte.
+                let timed_out =
+                    !vtime.is_zero() && now - last_change >= vtime && (vmin == 0 || available > 0);
+                if available >= vmin.min(buf.len()).max(1)
+                    || (vmin == 0 && vtime.is_zero())
+                    || timed_out
+                {
+                    return Ok(ldisc.read_raw(buf));
+                }
+            }
+            drop(ldisc);
+
+            if self.is_hung_up() {
+                return Ok(0);
+            }
+            if nonblocking {
+                return Err(LinuxError::EAGAIN);
+            }
+            if have_signals() {
+                return Err(LinuxError::EINTR);
+            }
+            self.read_wq.wait_timeout_interruptible(POLL_INTERVAL);
+        }
+    }
+
+    /// Write to the terminal, applying output processing.
+    ///
+    /// Blocks while the device cannot take more output.
+    pub fn write(&self, buf: &[u8], nonblocking: bool) -> LinuxResult<usize> {
+        if self.ldisc.lock().termios.c_lflag & TOSTOP != 0 {
+            self.check_background(Signo::SIGTTOU)?;
+        }
+
+        let mut written = 0;
+        while written < buf.len() {
+            if self.is_hung_up() {
+                return Err(LinuxError::EIO);
+            }
+            let room = self.driver.write_room();
+            if room == 0 {
+                if nonblocking || have_signals() {
+                    break;
+                }
+                self.write_wq.wait_timeout_interruptible(POLL_INTERVAL);
+                continue;
+            }
+            let chunk = &buf[written..][..room.min(buf.len() - written)];
+            let out = self.ldisc.lock().process_output(chunk);
+            self.driver.write(&out);
+            written += chunk.len();
+        }
+
+        if written == 0 && !buf.is_empty() {
+            Err(if nonblocking {
+                LinuxError::EAGAIN
+            } else {
+                LinuxError::EINTR
+            })
+        } else {
+            Ok(written)
+        }
+    }
+
+    /// Handle `ioctl` on the terminal.
+    pub fn ioctl(&self, op: u32, arg: usize) -> LinuxResult<isize> {
+        match op {
+            TCGETS => *UserPtr::<termios>::from(arg).get_as_mut()? = self.ldisc.lock().termios,
+            TCSETS | TCSETSW | TCSETSF => {
+                let termios = *UserConstPtr::<termios>::from(arg).get_as_ref()?;
+                self.check_background(Signo::SIGTTOU)?;
+                // Output is written synchronously, so there is nothing to
+                // drain for `TCSETSW`.
+                let mut ldisc = self.ldisc.lock();
+                if op == TCSETSF {
+                    ldisc.flush_input();
+                }
+                ldisc.set_termios(termios);
+                drop(ldisc);
+                self.driver.set_termios(&termios);
+            }
+            TCFLSH => {
+                self.check_background(Signo::SIGTTOU)?;
+                let (input, output) = match arg as u32 {
+                    TCIFLUSH => (true, false),
+                    TCOFLUSH => (false, true),
+                    TCIOFLUSH => (true, true),
+                    _ => return Err(LinuxError::EINVAL),
+                };
+                if input {
+                    self.ldisc.lock().flush_input();
+                }
+                self.driver.flush(input, output);
+                self.wake_writers();
+            }
+            TCSBRK | TCXONC => {}
+            TIOCOUTQ => {
+                *UserPtr::<i32>::from(arg).get_as_mut()? = self.driver.output_pending() as _
+            }
+            FIONREAD => {
+                *UserPtr::<i32>::from(arg).get_as_mut()? = self.ldisc.lock().available() as _
+            }
+            TIOCGWINSZ => *UserPtr::<winsize>::from(arg).get_as_mut()? = *self.winsize.lock(),
+            TIOCSWINSZ => {
+                let new = *UserConstPtr::<winsize>::from(arg).get_as_ref()?;
+                let mut winsize = self.winsize.lock();
+                let changed = (
+                    winsize.ws_row,
+                    winsize.ws_col,
+                    winsize.ws_xpixel,
+                    winsize.ws_ypixel,
+                ) != (new.ws_row, new.ws_col, new.ws_xpixel, new.ws_ypixel);
+                *winsize = new;
+                drop(winsize);
+                if changed {
+                    self.signal_foreground(Signo::SIGWINCH);
+                }
+            }
+            TIOCSCTTY | TIOCNOTTY | TIOCGPGRP | TIOCSPGRP | TIOCGSID => {
+                return self.job_control_ioctl(op, arg);
+            }
+            _ => return Err(LinuxError::ENOTTY),
+        }
+        Ok(0)
+    }
+
+    /// Handle the job control requests of `ioctl`.
+    fn job_control_ioctl(&self, op: u32, arg: usize) -> LinuxResult<isize> {
+        let curr = current();
+        let process = curr.task_ext().thread.process();
+        let group = process.group();
+        let session = group.session();
+        let is_leader = session.sid() == process.pid();
+
+        if op == TIOCSCTTY {
+            if self.controls(&session) {
+                return Ok(0);
+            }
+            if !is_leader || controlling_terminal(&session).is_some() {
+                return Err(LinuxError::EPERM);
+            }
+            // Stealing a terminal from another session requires `arg == 1` and
+            // privileges, which every process has here.
+            if self.session().is_some() && arg != 1 {
+                return Err(LinuxError::EPERM);
+            }
+            self.attach(&session, &group);
+            return Ok(0);
+        }
+        if !self.controls(&session) {
+            return Err(LinuxError::ENOTTY);
+        }
+
+        match op {
+            TIOCNOTTY => {
+                if is_leader {
+                    self.signal_foreground(Signo::SIGHUP);
+                    self.signal_foreground(Signo::SIGCONT);
+                    self.detach();
+                }
+            }
+            TIOCGPGRP => {
+                *UserPtr::<Pid>::from(arg).get_as_mut()? =
+                    self.foreground().map_or(0, |it| it.pgid());
+            }
+            TIOCSPGRP => {
+                let pgid = *UserPtr::<i32>::from(arg).get_as_mut()?;
+                if pgid < 0 {
+                    return Err(LinuxError::EINVAL);
+                }
+                self.check_background(Signo::SIGTTOU)?;
+                self.set_foreground(&get_process_group(pgid as Pid)?)?;
+            }
+            TIOCGSID => *UserPtr::<Pid>::from(arg).get_as_mut()? = session.sid(),
+            _ => return Err(LinuxError::ENOTTY),
+        }
+        Ok(0)
+    }
+}
+
+/// Whether `group` is an orphaned process group, i.e. no member has a parent
+/// in another process group of the same session.
+fn is_orphaned(group: &Arc<ProcessGroup>) -> bool {
+    let session = group.session();
+    !group.processes().iter().any(|process| {
+        process.parent().is_some_and(|parent| {
+            let parent_group = parent.group();
+            !Arc::ptr_eq(&parent_group, group) && Arc::ptr_eq(&parent_group.session(), &session)
+        })
+    })
+}
+
+static TERMINALS: Mutex<Vec<Weak<Terminal>>> = Mutex::new(Vec::new());
+
+/// Register a terminal so that it can be found as the controlling terminal of
+/// a session.
+pub fn register_terminal(terminal: Terminal) -> Arc<Terminal> {
+    let terminal = Arc::new(terminal);
+    let mut terminals = TERMINALS.lock();
+    terminals.retain(|it| it.strong_count() > 0);
+    terminals.push(Arc::downgrade(&terminal));
+    terminal
+}
+
+/// Find the controlling terminal of `session`.
+pub fn controlling_terminal(session: &Arc<Session>) -> Option<Arc<Terminal>> {
+    TERMINALS
+        .lock()
+        .iter()
+        .filter_map(Weak::upgrade)
+        .find(|it| it.controls(session))
+}
+
+/// Find the terminal a character device refers to.
+///
+/// `/dev/tty` refers to the controlling terminal of the caller, and fails
+/// with `ENXIO` if there is none. Returns `None` if the device is not a
+/// terminal.
+pub fn device_terminal(rdev: u64) -> LinuxResult<Option<Arc<Terminal>>> {
+    if rdev == console::TTY_RDEV {
+        let curr = current();
+        let session = curr.task_ext().thread.process().group().session();
+        return controlling_terminal(&session)
+            .map(Some)
+            .ok_or(LinuxError::ENXIO);
+    }
+    // A hung up terminal may still be around while its device number is
+    // reused, e.g. by a new pseudo-terminal.
+    Ok(TERMINALS
+        .lock()
+        .iter()
+        .filter_map(Weak::upgrade)
+        .find(|it| it.rdev == rdev && !it.is_hung_up()))
+}
+
+/// Open the terminal a character device refers to, or return `None` if the
+/// device is not a terminal.
+///
+/// Opening `/dev/ptmx` creates a new pseudo-terminal and returns its master.
+/// Any other terminal becomes the controlling terminal of the caller unless
+/// `O_NOCTTY` is given.
+pub fn open_terminal(rdev: u64, flags: u32) -> LinuxResult<Option<Arc<dyn FileLike>>> {
+    let file: Arc<dyn FileLike> = if rdev == PTMX_RDEV {
+        Arc::new(PtyMaster::new(Pty::new()?))
+    } else {
+        let Some(terminal) = device_terminal(rdev)? else {
+            return Ok(None);
+        };
+        let tty = Tty::new(terminal.clone())?;
+        if flags & O_NOCTTY == 0 {
+            acquire_controlling_terminal(&terminal);
+        }
+        Arc::new(tty)
+    };
+    file.set_nonblocking(flags & O_NONBLOCK != 0)?;
+    Ok(Some(file))
+}
+
+/// Make `terminal` the controlling terminal of the current session when it is
+/// opened without `O_NOCTTY`.
+///
+/// This only happens if the current process is a session leader, its session
+/// has no controlling terminal and `terminal` controls no other session.
+pub fn acquire_controlling_terminal(terminal: &Arc<Terminal>) {
+    let curr = current();
+    let process = curr.task_ext().thread.process();
+    let group = process.group();
+    let session = group.session();
+    if session.sid() != process.pid()
+        || terminal.session().is_some()
+        || controlling_terminal(&session).is_some()
+    {
+        return;
+    }
+    terminal.attach(&session, &group);
+}
@@ -0,0 +1,340 @@
+//! Pseudo-terminals.
+//!
+//! Opening `/dev/ptmx` creates a master, which is the opened file, and a slave
+//! terminal which appears as `/dev/pts/<n>`. The bytes written to the master
+//! are the input of the slave, and the output of the slave is read from the
+//! master. The slave can only be opened after it is unlocked with
+//! `TIOCSPTLCK`, as done by `unlockpt`.
+
+use core::sync::atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering};
+
+use alloc::{
+    boxed::Box,
+    collections::{btree_set::BTreeSet, vec_deque::VecDeque},
+    sync::Arc,
+};
+use axerrno::{LinuxError, LinuxResult};
+use axtask::WaitQueue;
+use linux_raw_sys::general::{IXON, VSTART, VSTOP, termios};
+use spin::Mutex;
+
+use super::{POLL_INTERVAL, Terminal, TerminalDriver, register_terminal};
+use crate::{
+    ptr::{UserConstPtr, UserPtr},
+    signal::have_signals,
+};
+
+/// The device ID of `/dev/ptmx`, i.e. `makedev(5, 2)`.
+pub const PTMX_RDEV: u64 = (5 << 8) | 2;
+/// The major number of the slave devices.
+const PTS_MAJOR: u64 = 136;
+/// Maximum number of pseudo-terminals, as the default of Linux.
+const MAX_PTYS: u32 = 4096;
+/// Maximum number of output bytes buffered for the master.
+const OUTPUT_BUFFER_SIZE: usize = 4096;
+
+const TIOCPKT: u32 = 0x5420;
+const TIOCGPTN: u32 = 0x8004_5430;
+const TIOCSPTLCK: u32 = 0x4004_5431;
+const TIOCGPKT: u32 = 0x8004_5438;
+const TIOCGPTLCK: u32 = 0x8004_5439;
+
+// The status bits reported by a master in packet mode
+const TIOCPKT_DATA: u8 = 0;
+const TIOCPKT_FLUSHREAD: u8 = 1;
+const TIOCPKT_FLUSHWRITE: u8 = 2;
+const TIOCPKT_NOSTOP: u8 = 16;
+const TIOCPKT_DOSTOP: u8 = 32;
+
+/// The indices in use.
+static PTY_INDICES: Mutex<BTreeSet<u32>> = Mutex::new(BTreeSet::new());
+
+/// The device ID of the slave `index`.
+fn pts_rdev(index: u32) -> u64 {
+    let index = index as u64;
+    (PTS_MAJOR << 8) | ((index & 0xffff_ff00) << 12) | (index & 0xff)
+}
+
+/// Whether `termios` makes the slave stop its output on `^S` and restart it
+/// on `^Q`, which is reported in packet mode.
+fn does_stop(termios: &termios) -> bool {
+    termios.c_iflag & IXON != 0
+        && termios.c_cc[VSTOP as usize] == 0x13
+        && termios.c_cc[VSTART as usize] == 0x11
+}
+
+/// The state shared by the master and the driver of the slave.
+struct Link {
+    /// The output of the slave, waiting to be read from the master
+    output: Mutex<VecDeque<u8>>,
+    /// The readers of the master waiting for output
+    read_wq: WaitQueue,
+    /// The writers of the master waiting for room in the input of the slave
+    write_wq: WaitQueue,
+    /// Whether the slave is locked against opening
+    locked: AtomicBool,
+    /// Whether the master is in packet mode
+    packet: AtomicBool,
+    /// Status bits to report in packet mode
+    status: AtomicU8,
+    /// Whether the slave stops on `^S`, see [`does_stop`]
+    stops: AtomicBool,
+    /// Number of files opened on the slave
+    slave_opens: AtomicUsize,
+    /// Whether all the files opened on the slave have been closed
+    slave_closed: AtomicBool,
+}
+
+impl Link {
+    fn report(&self, status: u8) {
+        if self.packet.load(Ordering::Acquire) {
+            self.status.fetch_or(status, Ordering::AcqRel);
+            self.read_wq.notify_all(false);
+        }
+    }
+}
+
+struct SlaveDriver(Arc<Link>);
+
+impl TerminalDriver for SlaveDriver {
+    fn write(&self, buf: &[u8]) {
+        self.0.output.lock().extend(buf);
+        self.0.read_wq.notify_all(false);
+    }
+
+    fn write_room(&self) -> usize {
+        OUTPUT_BUFFER_SIZE.saturating_sub(self.0.output.lock().len())
+    }
+
+    fn output_pending(&self) -> usize {
+        self.0.output.lock().len()
+    }
+
+    fn open(&self) -> LinuxResult {
+        if self.0.locked.load(Ordering::Acquire) {
+            return Err(LinuxError::EIO);
+        }
+        self.0.slave_opens.fetch_add(1, Ordering::AcqRel);
+        self.0.slave_closed.store(false, Ordering::Release);
+        Ok(())
+    }
+
+    fn release(&self) {
+        if self.0.slave_opens.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.0.slave_closed.store(true, Ordering::Release);
+            self.0.read_wq.notify_all(false);
+        }
+    }
+
+    fn flush(&self, input: bool, output: bool) {
+        if output {
+            self.0.output.lock().clear();
+        }
+        let mut status = 0;
+        if input {
+            status |= TIOCPKT_FLUSHREAD;
+        }
+        if output {
+            status |= TIOCPKT_FLUSHWRITE;
+        }
+        self.0.report(status);
+    }
+
+    fn set_termios(&self, termios: &termios) {
+        let stops = does_stop(termios);
+        if self.0.stops.swap(stops, Ordering::AcqRel) != stops {
+            self.0.report(if stops {
+                TIOCPKT_DOSTOP
+            } else {
+                TIOCPKT_NOSTOP
+            });
+        }
+    }
+}
+
+/// A pseudo-terminal, owned by its master.
+///
+/// It is destroyed when the master is closed, which hangs up the slave.
+pub struct Pty {
+    index: u32,
+    link: Arc<Link>,
+    slave: Arc<Terminal>,
+}
+
+impl Pty {
+    /// Create a new pseudo-terminal, with its slave locked.
+    pub fn new() -> LinuxResult<Self> {
+        let index = {
+            let mut indices = PTY_INDICES.lock();
+            let index = (0..MAX_PTYS)
+                .find(|it| !indices.contains(it))
+                .ok_or(LinuxError::ENOSPC)?;
+            indices.insert(index);
+            index
+        };
+        let link = Arc::new(Link {
+            output: Mutex::new(VecDeque::new()),
+            read_wq: WaitQueue::new(),
+            write_wq: WaitQueue::new(),
+            locked: AtomicBool::new(true),
+            packet: AtomicBool::new(false),
+            status: AtomicU8::new(0),
+            stops: AtomicBool::new(true),
+            slave_opens: AtomicUsize::new(0),
+            slave_closed: AtomicBool::new(false),
+        });
+        let slave = register_terminal(Terminal::new(
+            pts_rdev(index),
+            Box::new(SlaveDriver(link.clone())),
+        ));
+        axfs::api::add_pts(index);
+        Ok(Self { index, link, slave })
+    }
+
+    /// Take the output of the slave, prefixed by the status byte in packet
+    /// mode. Returns `None` if there is nothing to read.
+    fn take_output(&self, buf: &mut [u8]) -> Option<usize> {
+        let mut output = self.link.output.lock();
+        let len = if self.link.packet.load(Ordering::Acquire) {
+            let status = self.link.status.swap(0, Ordering::AcqRel);
+            if status != 0 {
+                buf[0] = status;
+                return Some(1);
+            }
+            if output.is_empty() {
+                return None;
+            }
+            buf[0] = TIOCPKT_DATA;
+            let len = output.len().min(buf.len() - 1);
+            for (dst, src) in buf[1..].iter_mut().zip(output.drain(..len)) {
+                *dst = src;
+            }
+            len + 1
+        } else {
+            if output.is_empty() {
+                return None;
+            }
+            let len = output.len().min(buf.len());
+            for (dst, src) in buf.iter_mut().zip(output.drain(..len)) {
+                *dst = src;
+            }
+            len
+        };
+        drop(output);
+        self.slave.wake_writers();
+        Some(len)
+    }
+}
+
+impl Drop for Pty {
+    fn drop(&mut self) {
+        self.slave.hangup();
+        axfs::api::remove_pts(self.index);
+        PTY_INDICES.lock().remove(&self.index);
+    }
+}
+
+impl Pty {
+    /// The slave terminal.
+    pub fn slave(&self) -> &Arc<Terminal> {
+        &self.slave
+    }
+
+    /// Read the output of the slave.
+    ///
+    /// Fails with `EIO` once all the files opened on the slave are closed.
+    pub fn read(&self, buf: &mut [u8], nonblocking: bool) -> LinuxResult<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            if let Some(len) = self.take_output(buf) {
+                return Ok(len);
+            }
+            if self.link.slave_closed.load(Ordering::Acquire) {
+                return Err(LinuxError::EIO);
+            }
+            if nonblocking {
+                return Err(LinuxError::EAGAIN);
+            }
+            if have_signals() {
+                return Err(LinuxError::EINTR);
+            }
+            self.link.read_wq.wait_timeout_interruptible(POLL_INTERVAL);
+        }
+    }
+
+    /// Send input to the slave.
+    ///
+    /// Blocks while the input of the slave is full. In nonblocking mode the
+    /// write is short, or fails with `EAGAIN` if nothing fits.
+    pub fn write(&self, buf: &[u8], nonblocking: bool) -> LinuxResult<usize> {
+        let mut written = 0;
+        while written < buf.len() {
+            let room = self.slave.input_room();
+            if room == 0 {
+                if nonblocking || have_signals() {
+                    break;
+                }
+                self.link.write_wq.wait_timeout_interruptible(POLL_INTERVAL);
+                continue;
+            }
+            let chunk = &buf[written..][..room.min(buf.len() - written)];
+            self.slave.receive(chunk);
+            written += chunk.len();
+        }
+
+        if written == 0 && !buf.is_empty() {
+            Err(if nonblocking {
+                LinuxError::EAGAIN
+            } else {
+                LinuxError::EINTR
+            })
+        } else {
+            Ok(written)
+        }
+    }
+
+    /// Whether a write would not block.
+    pub fn writable(&self) -> bool {
+        self.slave.input_room() > 0
+    }
+
+    /// Whether a read would return immediately.
+    pub fn readable(&self) -> bool {
+        !self.link.output.lock().is_empty()
+            || self.link.status.load(Ordering::Acquire) != 0
+            || self.link.slave_closed.load(Ordering::Acquire)
+    }
+
+    /// Handle `ioctl` on the master.
+    ///
+    /// The requests specific to the master are handled here, the others apply
+    /// to the slave. `TIOCGPTPEER` is left to the caller, which opens the
+    /// slave as a new file.
+    pub fn ioctl(&self, op: u32, arg: usize) -> LinuxResult<isize> {
+        match op {
+            TIOCGPTN => *UserPtr::<u32>::from(arg).get_as_mut()? = self.index,
+            TIOCSPTLCK => {
+                let lock = *UserConstPtr::<i32>::from(arg).get_as_ref()? != 0;
+                self.link.locked.store(lock, Ordering::Release);
+            }
+            TIOCGPTLCK => {
+                *UserPtr::<i32>::from(arg).get_as_mut()? =
+                    self.link.locked.load(Ordering::Acquire) as _
+            }
+            TIOCPKT => {
+                let packet = *UserConstPtr::<i32>::from(arg).get_as_ref()? != 0;
+                if self.link.packet.swap(packet, Ordering::AcqRel) != packet {
+                    self.link.status.store(0, Ordering::Release);
+                }
+            }
+            TIOCGPKT => {
+                *UserPtr::<i32>::from(arg).get_as_mut()? =
+                    self.link.packet.load(Ordering::Acquire) as _
+            }
+            _ => return self.slave.ioctl(op, arg),
+        }
+        Ok(0)
+    }
+}
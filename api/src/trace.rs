@@ -0,0 +1,187 @@
+//! Tracing of the system calls of processes, controlled and read through
+//! `/proc`:
+//!
+//! - `/proc/<pid>/syscall_trace` enables the tracing of the process when `1`
+//!   is written to it, and disables it with `0`;
+//! - `/proc/<pid>/syscall_stats` shows the statistics of the system calls of
+//!   the process;
+//! - `/proc/syscall_trace` shows the traced system calls of all processes,
+//!   and is cleared when written to.
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    sync::{Arc, Weak},
+};
+use core::{ffi::c_char, sync::atomic::Ordering};
+
+use axerrno::{AxError, AxResult};
+use axhal::{arch::TrapFrame, time::monotonic_time_nanos};
+use axprocess::{Pid, Process};
+use axtask::{TaskExtRef, current};
+use starry_core::{
+    task::ProcessData,
+    trace::{SyscallEvent, clear_trace_buffer, push_trace_event, trace_buffer_output},
+};
+
+use crate::ptr::UserConstPtr;
+
+/// The maximum length of the strings shown in traced arguments.
+const MAX_STRING_LEN: usize = 256;
+
+/// A system call being executed by the current thread.
+pub struct SyscallTrace {
+    sysno: usize,
+    name: &'static str,
+    start_ns: u64,
+    /// The arguments, if the process is traced
+    args: Option<[(usize, Option<String>); 6]>,
+}
+
+/// Start recording the system call `sysno` named `name`, with the arguments
+/// in `tf`. The arguments at the indices in `strings` are strings, which are
+/// shown if the process is traced.
+pub fn trace_syscall_entry(
+    tf: &TrapFrame,
+    sysno: usize,
+    name: &'static str,
+    strings: &[usize],
+) -> SyscallTrace {
+    let curr = current();
+    let traced = curr
+        .task_ext()
+        .process_data()
+        .syscall_trace
+        .load(Ordering::Relaxed);
+    let args = traced.then(|| {
+        let mut args = [
+            tf.arg0(),
+            tf.arg1(),
+            tf.arg2(),
+            tf.arg3(),
+            tf.arg4(),
+            tf.arg5(),
+        ]
+        .map(|value| (value, None));
+        for &index in strings {
+            let (value, string) = &mut args[index];
+            *string = user_string(*value);
+        }
+        args
+    });
+    SyscallTrace {
+        sysno,
+        name,
+        start_ns: monotonic_time_nanos(),
+        args,
+    }
+}
+
+/// Read the string at `addr` in user space, truncated for display.
+fn user_string(addr: usize) -> Option<String> {
+    let string = UserConstPtr::<c_char>::from(addr).get_as_str().ok()?;
+    Some(match string.char_indices().nth(MAX_STRING_LEN) {
+        Some((end, _)) => format!("{}...", &string[..end]),
+        None => string.to_string(),
+    })
+}
+
+/// Finish recording the system call `trace`, which returned `ret`.
+///
+/// `unimplemented` is set for the system calls which are not implemented.
+pub fn trace_syscall_exit(trace: SyscallTrace, ret: isize, unimplemented: bool) {
+    let duration_ns = monotonic_time_nanos().saturating_sub(trace.start_ns);
+    let curr = current();
+    curr.task_ext().process_data().syscall_stats.record(
+        trace.sysno,
+        trace.name,
+        ret,
+        duration_ns,
+        unimplemented,
+    );
+    if let Some(args) = trace.args {
+        push_trace_event(SyscallEvent {
+            timestamp_ns: trace.start_ns,
+            pid: curr.task_ext().thread.process().pid(),
+            tid: curr.id().as_u64() as _,
+            name: trace.name,
+            args,
+            ret,
+            duration_ns,
+        });
+    }
+}
+
+/// Create the entries of `process` in `/proc/<pid>`.
+pub fn add_proc_entries(process: &Arc<Process>) {
+    let dir = format!("/proc/{}", process.pid());
+    if let Err(err) = axfs::api::create_dir(&dir) {
+        warn!("failed to create {}: {:?}", dir, err);
+        return;
+    }
+
+    let weak = Arc::downgrade(process);
+    let result = axfs::api::create_generated_file(&format!("{dir}/syscall_stats"), move || {
+        with_proc_data(&weak, |data| data.syscall_stats.output()).unwrap_or_default()
+    });
+    if let Err(err) = result {
+        warn!("failed to create {}/syscall_stats: {:?}", dir, err);
+    }
+
+    let weak_read = Arc::downgrade(process);
+    let weak_write = weak_read.clone();
+    let result = axfs::api::create_control_file(
+        &format!("{dir}/syscall_trace"),
+        move || {
+            with_proc_data(&weak_read, |data| {
+                format!("{}\n", data.syscall_trace.load(Ordering::Relaxed) as u8).into_bytes()
+            })
+            .unwrap_or_default()
+        },
+        move |buf| {
+            let enabled = match buf.trim_ascii() {
+                b"0" => false,
+                b"1" => true,
+                _ => return Err(AxError::InvalidInput),
+            };
+            with_proc_data(&weak_write, |data| {
+                data.syscall_trace.store(enabled, Ordering::Relaxed)
+            })
+            .ok_or(AxError::NotFound)
+        },
+    );
+    if let Err(err) = result {
+        warn!("failed to create {}/syscall_trace: {:?}", dir, err);
+    }
+}
+
+fn with_proc_data<R>(process: &Weak<Process>, f: impl FnOnce(&ProcessData) -> R) -> Option<R> {
+    let process = process.upgrade()?;
+    process.data::<ProcessData>().map(f)
+}
+
+/// Remove `/proc/<pid>` once the process `pid` is freed.
+pub fn remove_proc_entries(pid: Pid) {
+    let dir = format!("/proc/{pid}");
+    let _ = axfs::api::remove_file(&format!("{dir}/syscall_stats"));
+    let _ = axfs::api::remove_file(&format!("{dir}/syscall_trace"));
+    if let Err(err) = axfs::api::remove_dir(&dir) {
+        warn!("failed to remove {}: {:?}", dir, err);
+    }
+}
+
+fn write_trace_buffer(_buf: &[u8]) -> AxResult<()> {
+    clear_trace_buffer();
+    Ok(())
+}
+
+#[ctor_bare::register_ctor]
+fn init_proc_syscall_trace() {
+    if let Err(err) = axfs::api::create_control_file(
+        "/proc/syscall_trace",
+        trace_buffer_output,
+        write_trace_buffer,
+    ) {
+        warn!("failed to create /proc/syscall_trace: {:?}", err);
+    }
+}
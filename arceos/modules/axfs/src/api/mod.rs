@@ -8,6 +8,9 @@ pub use self::file::{File, FileType, Metadata, OpenOptions, Permissions};
 
 use alloc::{string::String, vec::Vec};
 use axio::{self as io, prelude::*};
+use core::time::Duration;
+
+use crate::fops::{FileAttr, InodeAttr};
 
 /// Returns an iterator over the entries within a directory.
 pub fn read_dir(path: &str) -> io::Result<ReadDir> {
@@ -93,6 +96,73 @@ pub fn absolute_path_exists(path: &str) -> bool {
     crate::root::lookup(None, path).is_ok()
 }
 
+/// Creates a new hard link `new` to the existing file `old`.
+///
+/// Both paths must be in the same mounted fs.
+pub fn hard_link(old: &str, new: &str) -> io::Result<()> {
+    crate::root::link(old, new)
+}
+
+/// Returns the attributes of the file or directory at `path`, together with
+/// its inode attributes.
+///
+/// A symbolic link at `path` is not followed.
+pub fn stat(path: &str) -> io::Result<(FileAttr, InodeAttr)> {
+    crate::root::stat(path)
+}
+
+/// Sets the access and modification times of the file or directory at
+/// `path`. `None` leaves the corresponding time unchanged.
+///
+/// A symbolic link at `path` is not followed.
+pub fn set_times(path: &str, atime: Option<Duration>, mtime: Option<Duration>) -> io::Result<()> {
+    crate::root::set_times(path, atime, mtime)
+}
+
+/// Creates a read-only file at `path` whose content is produced by
+/// `generate` each time it is read.
+///
+/// The parent directory must be in a RAM filesystem, such as `/proc`.
+#[cfg(any(feature = "ramfs", feature = "procfs", feature = "sysfs"))]
+pub fn create_generated_file<F>(path: &str, generate: F) -> io::Result<()>
+where
+    F: Fn() -> Vec<u8> + Send + Sync + 'static,
+{
+    crate::root::create_generated_file(path, alloc::boxed::Box::new(generate), None)
+}
+
+/// Creates a file at `path` whose content is produced by `generate` each
+/// time it is read, and whose written data is passed to `write`, like the
+/// tunables in `/proc/sys`.
+///
+/// The parent directory must be in a RAM filesystem, such as `/proc`.
+#[cfg(any(feature = "ramfs", feature = "procfs", feature = "sysfs"))]
+pub fn create_control_file<F, W>(path: &str, generate: F, write: W) -> io::Result<()>
+where
+    F: Fn() -> Vec<u8> + Send + Sync + 'static,
+    W: Fn(&[u8]) -> io::Result<()> + Send + Sync + 'static,
+{
+    use alloc::boxed::Box;
+    crate::root::create_generated_file(path, Box::new(generate), Some(Box::new(write)))
+}
+
+/// Makes the slave of the pseudo-terminal `index` appear as `/dev/pts/<index>`.
+#[cfg(feature = "devfs")]
+pub fn add_pts(index: u32) {
+    crate::fs::devpts::add_pts(index)
+}
+
+/// Removes `/dev/pts/<index>` once the pseudo-terminal `index` is destroyed.
+#[cfg(feature = "devfs")]
+pub fn remove_pts(index: u32) {
+    crate::fs::devpts::remove_pts(index)
+}
+
+/// Checks whether the two paths are in the same mounted fs.
+pub fn same_filesystem(path1: &str, path2: &str) -> io::Result<bool> {
+    crate::root::same_filesystem(path1, path2)
+}
+
 pub fn create_symlink(old: &str, new: &str) -> io::Result<()> {
     crate::root::create_symlink(old, new)
 }
@@ -1,10 +1,12 @@
 //! Low-level filesystem operations.
 
+use alloc::sync::Arc;
 use axerrno::{AxError, AxResult, ax_err, ax_err_type};
 use axfs_vfs::{VfsError, VfsNodeRef};
 use axio::SeekFrom;
 use cap_access::{Cap, WithCap};
 use core::fmt;
+use core::time::Duration;
 
 #[cfg(feature = "myfs")]
 pub use crate::dev::Disk;
@@ -20,6 +22,56 @@ pub type FileAttr = axfs_vfs::VfsNodeAttr;
 /// Alias of [`axfs_vfs::VfsNodePerm`].
 pub type FilePerm = axfs_vfs::VfsNodePerm;
 
+/// Inode attributes that are not part of [`FileAttr`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct InodeAttr {
+    /// Inode number.
+    pub ino: u64,
+    /// Number of hard links.
+    pub nlink: u32,
+    /// ID of the device containing the node.
+    pub dev: u64,
+    /// Device ID of the node itself, if it is a device file.
+    pub rdev: u64,
+    /// Time of last access.
+    pub atime: Duration,
+    /// Time of last modification of the content.
+    pub mtime: Duration,
+    /// Time of last status change.
+    pub ctime: Duration,
+    /// Time of creation, if recorded by the filesystem.
+    pub btime: Option<Duration>,
+}
+
+/// Get the inode attributes of a node.
+///
+/// For filesystems without a notion of inodes, the address of the node is
+/// used as the inode number, and all the times are zero.
+pub(crate) fn inode_attr_of(node: &VfsNodeRef) -> AxResult<InodeAttr> {
+    match crate::fs::inode_ops(node) {
+        Some(ops) => ops.inode_attr(),
+        None => Ok(InodeAttr {
+            ino: Arc::as_ptr(node) as *const () as u64,
+            nlink: if node.get_attr()?.is_dir() { 2 } else { 1 },
+            #[cfg(feature = "devfs")]
+            rdev: crate::fs::devfs::rdev_of(node),
+            ..Default::default()
+        }),
+    }
+}
+
+/// Set the access and modification times of a node.
+pub(crate) fn set_times_of(
+    node: &VfsNodeRef,
+    atime: Option<Duration>,
+    mtime: Option<Duration>,
+) -> AxResult {
+    match crate::fs::inode_ops(node) {
+        Some(ops) => ops.set_times(atime, mtime),
+        None => ax_err!(Unsupported),
+    }
+}
+
 /// An opened file object, with open permissions and a cursor.
 pub struct File {
     node: WithCap<VfsNodeRef>,
@@ -245,6 +297,11 @@ impl File {
         Ok(write_len)
     }
 
+    /// Sets whether writes always go to the end of the file.
+    pub fn set_append(&mut self, append: bool) {
+        self.is_append = append;
+    }
+
     /// Writes the file at the given position. Returns the number of bytes
     /// written.
     ///
@@ -279,6 +336,17 @@ impl File {
     pub fn get_attr(&self) -> AxResult<FileAttr> {
         self.access_node(Cap::empty())?.get_attr()
     }
+
+    /// Gets the inode attributes of the file.
+    pub fn get_inode_attr(&self) -> AxResult<InodeAttr> {
+        inode_attr_of(self.access_node(Cap::empty())?)
+    }
+
+    /// Sets the access and modification times of the file. `None` leaves
+    /// the corresponding time unchanged.
+    pub fn set_times(&self, atime: Option<Duration>, mtime: Option<Duration>) -> AxResult {
+        set_times_of(self.access_node(Cap::empty())?, atime, mtime)
+    }
 }
 
 impl Directory {
@@ -375,6 +443,22 @@ impl Directory {
         Ok(n)
     }
 
+    /// Gets the directory attributes.
+    pub fn get_attr(&self) -> AxResult<FileAttr> {
+        self.access_node(Cap::empty())?.get_attr()
+    }
+
+    /// Gets the inode attributes of the directory.
+    pub fn get_inode_attr(&self) -> AxResult<InodeAttr> {
+        inode_attr_of(self.access_node(Cap::empty())?)
+    }
+
+    /// Sets the access and modification times of the directory. `None`
+    /// leaves the corresponding time unchanged.
+    pub fn set_times(&self, atime: Option<Duration>, mtime: Option<Duration>) -> AxResult {
+        set_times_of(self.access_node(Cap::empty())?, atime, mtime)
+    }
+
     /// Rename a file or directory to a new name.
     /// Delete the original file if `old` already exists.
     ///
@@ -2,7 +2,33 @@
 pub use axfs_devfs::*;
 use axhal::console::{read_bytes, write_bytes};
 
-use axfs_vfs::{VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeType, VfsResult};
+use axfs_vfs::{VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeRef, VfsNodeType, VfsResult};
+
+use super::{
+    devpts::{PtmxDev, PtsDev, pts_rdev},
+    makedev,
+};
+
+/// Get the device ID of a device node, with the same numbers as on Linux.
+/// Returns 0 if the node is not a known device.
+pub(crate) fn rdev_of(node: &VfsNodeRef) -> u64 {
+    let any = node.as_any();
+    if any.is::<NullDev>() {
+        makedev(1, 3)
+    } else if any.is::<ZeroDev>() {
+        makedev(1, 5)
+    } else if any.is::<UrandomDev>() {
+        makedev(1, 9)
+    } else if any.is::<TtyDev>() {
+        makedev(5, 0)
+    } else if any.is::<PtmxDev>() {
+        makedev(5, 2)
+    } else if let Some(pts) = any.downcast_ref::<PtsDev>() {
+        pts_rdev(pts.index)
+    } else {
+        0
+    }
+}
 
 /// A tty device behaves like `/dev/tty`.
 ///
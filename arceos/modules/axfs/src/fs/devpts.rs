@@ -0,0 +1,180 @@
+//! The devpts filesystem, which has a node for the slave side of each
+//! pseudo-terminal.
+//!
+//! The pseudo-terminals themselves are managed by the kernel, which calls
+//! [`add_pts`] and [`remove_pts`] as they are created and destroyed. The nodes
+//! only carry the device number, opening them is up to the caller.
+
+use alloc::{
+    collections::BTreeSet,
+    string::ToString,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use axfs_vfs::{
+    VfsDirEntry, VfsError, VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeRef, VfsNodeType, VfsResult,
+};
+use spin::RwLock;
+
+use super::makedev;
+
+/// The major number of the slave devices.
+const PTS_MAJOR: u32 = 136;
+
+/// The indices of the existing pseudo-terminals.
+static PTYS: RwLock<BTreeSet<u32>> = RwLock::new(BTreeSet::new());
+
+/// Make the slave of the pseudo-terminal `index` appear as `pts/<index>`.
+pub(crate) fn add_pts(index: u32) {
+    PTYS.write().insert(index);
+}
+
+/// Remove the node of the pseudo-terminal `index`.
+pub(crate) fn remove_pts(index: u32) {
+    PTYS.write().remove(&index);
+}
+
+/// The device ID of the slave of the pseudo-terminal `index`.
+pub(crate) fn pts_rdev(index: u32) -> u64 {
+    makedev(PTS_MAJOR, index)
+}
+
+/// The multiplexer `ptmx`, which creates a new pseudo-terminal each time it
+/// is opened.
+pub struct PtmxDev;
+
+impl VfsNodeOps for PtmxDev {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        Ok(VfsNodeAttr::new(
+            VfsNodePerm::from_bits_truncate(0o666),
+            VfsNodeType::CharDevice,
+            0,
+            0,
+        ))
+    }
+
+    fn read_at(&self, _offset: u64, _buf: &mut [u8]) -> VfsResult<usize> {
+        Err(VfsError::Unsupported)
+    }
+
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> VfsResult<usize> {
+        Err(VfsError::Unsupported)
+    }
+
+    fn truncate(&self, _size: u64) -> VfsResult {
+        Ok(())
+    }
+
+    axfs_vfs::impl_vfs_non_dir_default! {}
+}
+
+/// The slave side of a pseudo-terminal.
+pub struct PtsDev {
+    pub(crate) index: u32,
+}
+
+impl VfsNodeOps for PtsDev {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        Ok(VfsNodeAttr::new(
+            VfsNodePerm::from_bits_truncate(0o620),
+            VfsNodeType::CharDevice,
+            0,
+            0,
+        ))
+    }
+
+    fn read_at(&self, _offset: u64, _buf: &mut [u8]) -> VfsResult<usize> {
+        Err(VfsError::Unsupported)
+    }
+
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> VfsResult<usize> {
+        Err(VfsError::Unsupported)
+    }
+
+    fn truncate(&self, _size: u64) -> VfsResult {
+        Ok(())
+    }
+
+    axfs_vfs::impl_vfs_non_dir_default! {}
+}
+
+/// The root directory of devpts, listing `ptmx` and the existing slaves.
+pub struct PtsDir {
+    parent: Weak<dyn VfsNodeOps>,
+}
+
+impl PtsDir {
+    /// Create the directory, to be added under `parent`.
+    pub fn new(parent: &VfsNodeRef) -> Arc<Self> {
+        Arc::new(Self {
+            parent: Arc::downgrade(parent),
+        })
+    }
+}
+
+impl VfsNodeOps for PtsDir {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        Ok(VfsNodeAttr::new_dir(4096, 0))
+    }
+
+    fn parent(&self) -> Option<VfsNodeRef> {
+        self.parent.upgrade()
+    }
+
+    fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
+        let path = path.trim_start_matches('/');
+        let (name, rest) = match path.split_once('/') {
+            Some((name, rest)) => (name, Some(rest)),
+            None => (path, None),
+        };
+        let node: VfsNodeRef = match name {
+            "" | "." => self.clone(),
+            ".." => self.parent().ok_or(VfsError::NotFound)?,
+            "ptmx" => Arc::new(PtmxDev),
+            _ => {
+                let index = name.parse::<u32>().map_err(|_| VfsError::NotFound)?;
+                // Reject names such as "01" which parse to an existing index.
+                if !PTYS.read().contains(&index) || index.to_string() != name {
+                    return Err(VfsError::NotFound);
+                }
+                Arc::new(PtsDev { index })
+            }
+        };
+        match rest {
+            Some(rest) if !rest.is_empty() => node.lookup(rest),
+            _ => Ok(node),
+        }
+    }
+
+    fn read_dir(&self, start_idx: usize, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
+        let ptys = PTYS.read().iter().map(u32::to_string).collect::<Vec<_>>();
+        let mut entries = [
+            (".", VfsNodeType::Dir),
+            ("..", VfsNodeType::Dir),
+            ("ptmx", VfsNodeType::CharDevice),
+        ]
+        .into_iter()
+        .chain(
+            ptys.iter()
+                .map(|name| (name.as_str(), VfsNodeType::CharDevice)),
+        )
+        .skip(start_idx);
+        for (i, ent) in dirents.iter_mut().enumerate() {
+            match entries.next() {
+                Some((name, ty)) => *ent = VfsDirEntry::new(name, ty),
+                None => return Ok(i),
+            }
+        }
+        Ok(dirents.len())
+    }
+
+    fn create(&self, _path: &str, _ty: VfsNodeType) -> VfsResult {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn remove(&self, _path: &str) -> VfsResult {
+        Err(VfsError::PermissionDenied)
+    }
+
+    axfs_vfs::impl_vfs_dir_default! {}
+}
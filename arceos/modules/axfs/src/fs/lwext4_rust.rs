@@ -1,17 +1,198 @@
 use crate::alloc::string::String;
+use alloc::collections::BTreeMap;
+use alloc::ffi::CString;
+use alloc::format;
 use alloc::sync::Arc;
 use axerrno::AxError;
 use axfs_vfs::{VfsDirEntry, VfsError, VfsNodePerm, VfsResult};
 use axfs_vfs::{VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType, VfsOps};
 use axsync::Mutex;
+use core::mem::offset_of;
+use core::sync::atomic::{AtomicU32, Ordering};
+use core::time::Duration;
 use lwext4_rust::bindings::{
-    O_CREAT, O_RDONLY, O_RDWR, O_TRUNC, O_WRONLY, SEEK_CUR, SEEK_END, SEEK_SET,
+    EOK, O_CREAT, O_RDONLY, O_RDWR, O_TRUNC, O_WRONLY, SEEK_CUR, SEEK_END, SEEK_SET, ext4_flink,
+    ext4_fs, ext4_fs_get_inode_ref, ext4_fs_put_inode_ref, ext4_get_sblock, ext4_inode,
+    ext4_inode_ref, ext4_raw_inode_fill, ext4_sblock,
 };
 use lwext4_rust::{Ext4BlockWrapper, Ext4File, InodeTypes, KernelDevOp};
 
+use super::{InodeOps, makedev};
 use crate::dev::Disk;
+use crate::fops::InodeAttr;
 pub const BLOCK_SIZE: usize = 512;
 
+/// Device ID of the disk, as the first virtio block device on Linux.
+const DISK_DEV: u64 = makedev(254, 0);
+
+/// Minimum interval between two access time updates, like `relatime`.
+const ATIME_UPDATE_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Directory where files unlinked while still opened are kept until their
+/// last close.
+const ORPHAN_DIR: &str = "/lost+found";
+
+/// An opened regular file.
+#[derive(Default)]
+struct OpenedInode {
+    count: usize,
+    /// The link of the file in [`ORPHAN_DIR`], made when one of its links
+    /// was removed or renamed while it was opened.
+    orphan: Option<String>,
+}
+
+/// Opened regular files, by inode number.
+///
+/// lwext4 accesses files by path, so a file unlinked or renamed while still
+/// opened gets a link in [`ORPHAN_DIR`], which is removed when it is closed
+/// for the last time.
+static OPENED_INODES: spin::Mutex<BTreeMap<u32, OpenedInode>> = spin::Mutex::new(BTreeMap::new());
+
+/// Read the on-disk inode of `path`. Returns the inode number and the inode.
+fn raw_inode(path: &str) -> VfsResult<(u32, ext4_inode)> {
+    let c_path = CString::new(path).map_err(|_| VfsError::InvalidInput)?;
+    let mut ino = 0;
+    // SAFETY: `ext4_inode` is a plain C struct
+    let mut inode: ext4_inode = unsafe { core::mem::zeroed() };
+    let r = unsafe { ext4_raw_inode_fill(c_path.as_ptr(), &mut ino, &mut inode) };
+    if r != EOK as i32 {
+        return Err(r.try_into().unwrap());
+    }
+    Ok((ino, inode))
+}
+
+/// Decode an inode timestamp. The low 2 bits of `extra` extend the seconds
+/// to 34 bits, and the other 30 bits are the nanoseconds.
+fn inode_time(sec: u32, extra: u32) -> Duration {
+    let sec = sec as i32 as i64 + (((extra & 0b11) as i64) << 32);
+    Duration::new(sec.max(0) as u64, extra >> 2)
+}
+
+/// Encode an inode timestamp, the inverse of [`inode_time`].
+fn encode_inode_time(time: Duration) -> (u32, u32) {
+    let sec = time.as_secs();
+    let extra = ((sec >> 32) as u32 & 0b11) | (time.subsec_nanos() << 2);
+    (sec as u32, extra)
+}
+
+/// Truncate `time` to what the inode can store, i.e. to seconds if it has no
+/// room for the nanoseconds.
+fn inode_precision(inode: &ext4_inode, time: Duration) -> Duration {
+    if inode.extra_isize >= 16 {
+        time
+    } else {
+        Duration::from_secs(time.as_secs())
+    }
+}
+
+/// Get the inode attributes from the on-disk inode.
+fn inode_attr(ino: u32, inode: &ext4_inode) -> InodeAttr {
+    // the `*_extra` fields are only present if the extra inode size covers
+    // them (the sizes below are their end offsets)
+    let extra = |value: u32, end: u16| {
+        if inode.extra_isize >= end { value } else { 0 }
+    };
+    let mode = inode.mode as u32 & 0o170000;
+    let rdev = if mode == 0o020000 || mode == 0o060000 {
+        // old encoding in `blocks[0]`, or new encoding in `blocks[1]`
+        let (old, new) = (inode.blocks[0], inode.blocks[1]);
+        if old != 0 {
+            makedev((old >> 8) & 0xff, old & 0xff)
+        } else {
+            makedev((new & 0xfff00) >> 8, (new & 0xff) | ((new >> 12) & 0xfff00))
+        }
+    } else {
+        0
+    };
+    let crtime = extra(inode.crtime, 20);
+    InodeAttr {
+        ino: ino as u64,
+        nlink: inode.links_count as u32,
+        dev: DISK_DEV,
+        rdev,
+        atime: inode_time(inode.access_time, extra(inode.atime_extra, 16)),
+        mtime: inode_time(inode.modification_time, extra(inode.mtime_extra, 12)),
+        ctime: inode_time(inode.change_inode_time, extra(inode.ctime_extra, 8)),
+        btime: (crtime != 0).then(|| inode_time(crtime, extra(inode.crtime_extra, 24))),
+    }
+}
+
+/// Set the timestamps of `path`, with their nanoseconds if the inode has
+/// room for them, in a single update of the inode. `None` leaves the
+/// corresponding time unchanged.
+///
+/// lwext4 never updates the timestamps by itself, and its setters only store
+/// the seconds, so the inode is updated directly.
+fn set_inode_times(
+    path: &str,
+    atime: Option<Duration>,
+    mtime: Option<Duration>,
+    ctime: Option<Duration>,
+) -> VfsResult {
+    let (ino, _) = raw_inode(path)?;
+    let mut sb: *mut ext4_sblock = core::ptr::null_mut();
+    let r = unsafe { ext4_get_sblock(c"/".as_ptr(), &mut sb) };
+    if r != EOK as i32 {
+        return Err(r.try_into().unwrap());
+    }
+    // SAFETY: the superblock is embedded in the `ext4_fs` of the mount point
+    let fs = unsafe { sb.byte_sub(offset_of!(ext4_fs, sb)) as *mut ext4_fs };
+    // SAFETY: `ext4_inode_ref` is a plain C struct
+    let mut inode_ref: ext4_inode_ref = unsafe { core::mem::zeroed() };
+    let r = unsafe { ext4_fs_get_inode_ref(fs, ino, &mut inode_ref) };
+    if r != EOK as i32 {
+        error!("set inode time error: rc = {}", r);
+        return Err(r.try_into().unwrap());
+    }
+
+    let inode = unsafe { &mut *inode_ref.inode };
+    // the `*_extra` fields are only present if the extra inode size covers
+    // them (the sizes below are their end offsets)
+    let extra_isize = inode.extra_isize;
+    let has_extra = |end: u16| extra_isize >= end;
+    if let Some((sec, extra)) = atime.map(encode_inode_time) {
+        inode.access_time = sec;
+        if has_extra(16) {
+            inode.atime_extra = extra;
+        }
+    }
+    if let Some((sec, extra)) = mtime.map(encode_inode_time) {
+        inode.modification_time = sec;
+        if has_extra(12) {
+            inode.mtime_extra = extra;
+        }
+    }
+    if let Some((sec, extra)) = ctime.map(encode_inode_time) {
+        inode.change_inode_time = sec;
+        if has_extra(8) {
+            inode.ctime_extra = extra;
+        }
+    }
+    inode_ref.dirty = true;
+
+    let r = unsafe { ext4_fs_put_inode_ref(&mut inode_ref) };
+    if r != EOK as i32 {
+        error!("set inode time error: rc = {}", r);
+        return Err(r.try_into().unwrap());
+    }
+    Ok(())
+}
+
+/// Update the modification and change times of `path` to the current time,
+/// once per write.
+fn touch_modified(path: &str) {
+    let now = crate::now();
+    let _ = set_inode_times(path, None, Some(now), Some(now));
+}
+
+/// Get the path of the directory containing `path`.
+fn parent_path(path: &str) -> &str {
+    match path.trim_end_matches('/').rsplit_once('/') {
+        Some(("", _)) | None => "/",
+        Some((parent, _)) => parent,
+    }
+}
+
 #[allow(dead_code)]
 pub struct Ext4FileSystem {
     inner: Ext4BlockWrapper<Disk>,
@@ -52,7 +233,10 @@ impl VfsOps for Ext4FileSystem {
     }
 }
 
-pub struct FileWrapper(Mutex<Ext4File>);
+/// A node of the ext4 filesystem.
+///
+/// The second field is the inode number of the file while it is opened, or 0.
+pub struct FileWrapper(Mutex<Ext4File>, AtomicU32);
 
 unsafe impl Send for FileWrapper {}
 unsafe impl Sync for FileWrapper {}
@@ -62,7 +246,75 @@ impl FileWrapper {
         info!("FileWrapper new {:?} {}", types, path);
         //file.file_read_test("/test/test.txt", &mut buf);
 
-        Self(Mutex::new(Ext4File::new(path, types)))
+        Self(Mutex::new(Ext4File::new(path, types)), AtomicU32::new(0))
+    }
+
+    /// Get the current path of the node, which differs from the path it was
+    /// looked up with if it has been unlinked while opened.
+    fn node_path(&self, file: &Ext4File) -> String {
+        let ino = self.1.load(Ordering::Acquire);
+        if ino != 0 {
+            if let Some(orphan) = OPENED_INODES
+                .lock()
+                .get(&ino)
+                .and_then(|opened| opened.orphan.clone())
+            {
+                return orphan;
+            }
+        }
+        String::from(file.get_path().to_str().unwrap())
+    }
+
+    /// Update the access time of `path` after it is read, if it is older than
+    /// the modification or change time, or than [`ATIME_UPDATE_INTERVAL`].
+    ///
+    /// The inode is left as it is if the access time would not change, as
+    /// when it is only stored in seconds.
+    fn touch_accessed(path: &str) {
+        let Ok((ino, inode)) = raw_inode(path) else {
+            return;
+        };
+        let attr = inode_attr(ino, &inode);
+        let now = inode_precision(&inode, crate::now());
+        if attr.atime != now
+            && (attr.atime <= attr.mtime
+                || attr.atime <= attr.ctime
+                || now.saturating_sub(attr.atime) >= ATIME_UPDATE_INTERVAL)
+        {
+            let _ = set_inode_times(path, Some(now), None, None);
+        }
+    }
+
+    /// If `fpath` is a link of a file that is still opened, give the file a
+    /// link in [`ORPHAN_DIR`] before `fpath` is removed or renamed, as
+    /// lwext4 accesses files by path. The opened file is then accessed
+    /// through that link, which is removed when it is closed for the last
+    /// time.
+    fn keep_if_opened(file: &mut Ext4File, fpath: &str) -> VfsResult {
+        let Ok((ino, _)) = raw_inode(fpath) else {
+            return Ok(());
+        };
+        let mut opened_inodes = OPENED_INODES.lock();
+        let Some(opened) = opened_inodes.get_mut(&ino) else {
+            return Ok(());
+        };
+        if opened.orphan.is_some() {
+            return Ok(());
+        }
+
+        if !file.check_inode_exist(ORPHAN_DIR, InodeTypes::EXT4_DE_DIR) {
+            file.dir_mk(ORPHAN_DIR)
+                .map_err(|e| <i32 as TryInto<AxError>>::try_into(e).unwrap())?;
+        }
+        let orphan = format!("{}/#{}", ORPHAN_DIR, ino);
+        let c_path = CString::new(fpath).map_err(|_| VfsError::InvalidInput)?;
+        let c_orphan = CString::new(orphan.as_str()).map_err(|_| VfsError::InvalidInput)?;
+        let r = unsafe { ext4_flink(c_path.as_ptr(), c_orphan.as_ptr()) };
+        if r != EOK as i32 {
+            return Err(r.try_into().unwrap());
+        }
+        opened.orphan = Some(orphan);
+        Ok(())
     }
 
     fn path_deal_with(&self, path: &str) -> String {
@@ -117,8 +369,8 @@ impl VfsNodeOps for FileWrapper {
         };
 
         let size = if vtype == VfsNodeType::File {
-            let path = file.get_path();
-            let path = path.to_str().unwrap();
+            let path = self.node_path(&file);
+            let path = path.as_str();
             file.file_open(path, O_RDONLY)
                 .map_err(|e| <i32 as TryInto<AxError>>::try_into(e).unwrap())?;
             let fsize = file.file_size();
@@ -165,14 +417,18 @@ impl VfsNodeOps for FileWrapper {
             if types == InodeTypes::EXT4_DE_DIR {
                 file.dir_mk(fpath)
                     .map(|_v| ())
-                    .map_err(|e| e.try_into().unwrap())
+                    .map_err(|e| <i32 as TryInto<AxError>>::try_into(e).unwrap())?;
             } else {
                 file.file_open(fpath, O_WRONLY | O_CREAT | O_TRUNC)
                     .expect("create file failed");
                 file.file_close()
                     .map(|_v| ())
-                    .map_err(|e| e.try_into().unwrap())
+                    .map_err(|e| <i32 as TryInto<AxError>>::try_into(e).unwrap())?;
             }
+            let now = crate::now();
+            let _ = set_inode_times(fpath, Some(now), Some(now), Some(now));
+            touch_modified(parent_path(fpath));
+            Ok(())
         }
     }
 
@@ -188,12 +444,52 @@ impl VfsNodeOps for FileWrapper {
             // Recursive directory remove
             file.dir_rm(fpath)
                 .map(|_v| ())
-                .map_err(|e| e.try_into().unwrap())
+                .map_err(|e| <i32 as TryInto<AxError>>::try_into(e).unwrap())?;
         } else {
+            Self::keep_if_opened(&mut file, fpath)?;
             file.file_remove(fpath)
                 .map(|_v| ())
-                .map_err(|e| e.try_into().unwrap())
+                .map_err(|e| <i32 as TryInto<AxError>>::try_into(e).unwrap())?;
+        }
+        touch_modified(parent_path(fpath));
+        Ok(())
+    }
+
+    fn open(&self) -> VfsResult {
+        let file = self.0.lock();
+        if file.get_type() != InodeTypes::EXT4_DE_REG_FILE {
+            return Ok(());
+        }
+        let (ino, _) = raw_inode(&self.node_path(&file))?;
+        if self.1.swap(ino, Ordering::AcqRel) == 0 {
+            OPENED_INODES.lock().entry(ino).or_default().count += 1;
+        }
+        Ok(())
+    }
+
+    fn release(&self) -> VfsResult {
+        let ino = self.1.swap(0, Ordering::AcqRel);
+        if ino == 0 {
+            return Ok(());
         }
+        let orphan = {
+            let mut opened_inodes = OPENED_INODES.lock();
+            let Some(opened) = opened_inodes.get_mut(&ino) else {
+                return Ok(());
+            };
+            opened.count -= 1;
+            if opened.count > 0 {
+                return Ok(());
+            }
+            opened_inodes.remove(&ino).and_then(|opened| opened.orphan)
+        };
+        if let Some(orphan) = orphan {
+            self.0
+                .lock()
+                .file_remove(&orphan)
+                .map_err(|e| <i32 as TryInto<AxError>>::try_into(e).unwrap())?;
+        }
+        Ok(())
     }
 
     /// Get the parent directory of this directory.
@@ -226,15 +522,18 @@ impl VfsNodeOps for FileWrapper {
 
             match itypes {
                 Some(t) => {
-                    let ty = if *t == InodeTypes::EXT4_DE_DIR {
-                        VfsNodeType::Dir
-                    } else if *t == InodeTypes::EXT4_DE_REG_FILE {
-                        VfsNodeType::File
-                    } else if *t == InodeTypes::EXT4_DE_SYMLINK {
-                        VfsNodeType::SymLink
-                    } else {
-                        error!("unknown file type: {:?}", itypes);
-                        unreachable!()
+                    let ty = match *t {
+                        InodeTypes::EXT4_DE_DIR => VfsNodeType::Dir,
+                        InodeTypes::EXT4_DE_REG_FILE => VfsNodeType::File,
+                        InodeTypes::EXT4_DE_SYMLINK => VfsNodeType::SymLink,
+                        InodeTypes::EXT4_DE_CHRDEV => VfsNodeType::CharDevice,
+                        InodeTypes::EXT4_DE_BLKDEV => VfsNodeType::BlockDevice,
+                        InodeTypes::EXT4_DE_FIFO => VfsNodeType::Fifo,
+                        InodeTypes::EXT4_DE_SOCK => VfsNodeType::Socket,
+                        _ => {
+                            error!("unknown file type: {:?}", itypes);
+                            unreachable!()
+                        }
                     };
 
                     *out_entry =
@@ -270,14 +569,24 @@ impl VfsNodeOps for FileWrapper {
             trace!("lookup new SYMLINK FileWrapper");
             Ok(Arc::new(Self::new(fpath, InodeTypes::EXT4_DE_SYMLINK)))
         } else {
-            Err(VfsError::NotFound)
+            // special files
+            [
+                InodeTypes::EXT4_DE_CHRDEV,
+                InodeTypes::EXT4_DE_BLKDEV,
+                InodeTypes::EXT4_DE_FIFO,
+                InodeTypes::EXT4_DE_SOCK,
+            ]
+            .into_iter()
+            .find(|ty| file.check_inode_exist(fpath, ty.clone()))
+            .map(|ty| Arc::new(Self::new(fpath, ty)) as VfsNodeRef)
+            .ok_or(VfsError::NotFound)
         }
     }
 
     fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
         let mut file = self.0.lock();
-        let path = file.get_path();
-        let path = path.to_str().unwrap();
+        let path = self.node_path(&file);
+        let path = path.as_str();
         file.file_open(path, O_RDONLY)
             .map_err(|e| <i32 as TryInto<AxError>>::try_into(e).unwrap())?;
 
@@ -286,13 +595,16 @@ impl VfsNodeOps for FileWrapper {
         let r = file.file_read(buf);
 
         let _ = file.file_close();
+        if r.is_ok() {
+            Self::touch_accessed(path);
+        }
         r.map_err(|e| e.try_into().unwrap())
     }
 
     fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
         let mut file = self.0.lock();
-        let path = file.get_path();
-        let path = path.to_str().unwrap();
+        let path = self.node_path(&file);
+        let path = path.as_str();
         file.file_open(path, O_RDWR)
             .map_err(|e| <i32 as TryInto<AxError>>::try_into(e).unwrap())?;
 
@@ -301,27 +613,40 @@ impl VfsNodeOps for FileWrapper {
         let r = file.file_write(buf);
 
         let _ = file.file_close();
+        if r.is_ok() {
+            touch_modified(path);
+        }
         r.map_err(|e| e.try_into().unwrap())
     }
 
     fn truncate(&self, size: u64) -> VfsResult {
         let mut file = self.0.lock();
-        let path = file.get_path();
-        let path = path.to_str().unwrap();
+        let path = self.node_path(&file);
+        let path = path.as_str();
         file.file_open(path, O_RDWR | O_CREAT | O_TRUNC)
             .map_err(|e| <i32 as TryInto<AxError>>::try_into(e).unwrap())?;
 
         let t = file.file_truncate(size);
 
         let _ = file.file_close();
+        if t.is_ok() {
+            touch_modified(path);
+        }
         t.map(|_v| ()).map_err(|e| e.try_into().unwrap())
     }
 
     fn rename(&self, src_path: &str, dst_path: &str) -> VfsResult {
         let mut file = self.0.lock();
+        Self::keep_if_opened(&mut file, src_path)?;
+        Self::keep_if_opened(&mut file, dst_path)?;
         file.file_rename(src_path, dst_path)
             .map(|_v| ())
-            .map_err(|e| e.try_into().unwrap())
+            .map_err(|e| <i32 as TryInto<AxError>>::try_into(e).unwrap())?;
+        let now = crate::now();
+        let _ = set_inode_times(dst_path, None, None, Some(now));
+        touch_modified(parent_path(src_path));
+        touch_modified(parent_path(dst_path));
+        Ok(())
     }
 
     fn as_any(&self) -> &dyn core::any::Any {
@@ -332,7 +657,9 @@ impl VfsNodeOps for FileWrapper {
         let mut file = self.0.lock();
         file.create_symlink(target, path)
             .map(|_v| ())
-            .map_err(|e| e.try_into().unwrap())
+            .map_err(|e| <i32 as TryInto<AxError>>::try_into(e).unwrap())?;
+        touch_modified(parent_path(path));
+        Ok(())
     }
 
     fn readlink(&self, path: &str, buf: &mut [u8]) -> VfsResult<usize> {
@@ -348,6 +675,38 @@ impl VfsNodeOps for FileWrapper {
     }
 }
 
+impl InodeOps for FileWrapper {
+    fn inode_attr(&self) -> VfsResult<InodeAttr> {
+        let file = self.0.lock();
+        let (ino, inode) = raw_inode(&self.node_path(&file))?;
+        Ok(inode_attr(ino, &inode))
+    }
+
+    fn link(&self, old_path: &str, new_path: &str) -> VfsResult {
+        let old_path = self.path_deal_with(old_path);
+        let new_path = self.path_deal_with(new_path);
+        let c_old = CString::new(old_path).map_err(|_| VfsError::InvalidInput)?;
+        let c_new = CString::new(new_path).map_err(|_| VfsError::InvalidInput)?;
+
+        let _file = self.0.lock();
+        let r = unsafe { ext4_flink(c_old.as_ptr(), c_new.as_ptr()) };
+        if r != EOK as i32 {
+            error!("ext4_flink error: rc = {}", r);
+            return Err(r.try_into().unwrap());
+        }
+        let new_path = c_new.to_str().unwrap();
+        let _ = set_inode_times(new_path, None, None, Some(crate::now()));
+        touch_modified(parent_path(new_path));
+        Ok(())
+    }
+
+    fn set_times(&self, atime: Option<Duration>, mtime: Option<Duration>) -> VfsResult {
+        let file = self.0.lock();
+        let now = crate::now();
+        set_inode_times(&self.node_path(&file), atime, mtime, Some(now))
+    }
+}
+
 impl Drop for FileWrapper {
     fn drop(&mut self) {
         let mut file = self.0.lock();
@@ -1,3 +1,9 @@
+use axfs_vfs::{VfsNodeRef, VfsResult};
+use core::sync::atomic::{AtomicU32, Ordering};
+use core::time::Duration;
+
+use crate::fops::InodeAttr;
+
 cfg_if::cfg_if! {
     if #[cfg(feature = "myfs")] {
         pub mod myfs;
@@ -12,5 +18,69 @@ cfg_if::cfg_if! {
 #[cfg(feature = "devfs")]
 pub mod devfs;
 
-#[cfg(feature = "ramfs")]
-pub use axfs_ramfs as ramfs;
+#[cfg(feature = "devfs")]
+pub mod devpts;
+
+#[cfg(any(feature = "ramfs", feature = "procfs", feature = "sysfs"))]
+pub mod ramfs;
+
+/// Inode operations that are not covered by [`axfs_vfs::VfsNodeOps`].
+///
+/// They are implemented by the filesystems that have a notion of inodes.
+pub(crate) trait InodeOps {
+    /// Get the inode number and the link count of the node.
+    fn inode_attr(&self) -> VfsResult<InodeAttr>;
+
+    /// Create a hard link `new_path` to the existing `old_path`, both
+    /// relative to this directory.
+    fn link(&self, _old_path: &str, _new_path: &str) -> VfsResult {
+        Err(axfs_vfs::VfsError::Unsupported)
+    }
+
+    /// Set the access and modification times of the node. `None` leaves the
+    /// corresponding time unchanged. The change time is set to the current
+    /// time.
+    fn set_times(&self, _atime: Option<Duration>, _mtime: Option<Duration>) -> VfsResult {
+        Err(axfs_vfs::VfsError::Unsupported)
+    }
+}
+
+/// Encode a device ID from its major and minor numbers, in the same format as
+/// Linux's `dev_t`.
+pub(crate) const fn makedev(major: u32, minor: u32) -> u64 {
+    let (major, minor) = (major as u64, minor as u64);
+    ((major & 0xffff_f000) << 32)
+        | ((major & 0xfff) << 8)
+        | ((minor & 0xffff_ff00) << 12)
+        | (minor & 0xff)
+}
+
+/// Allocate a device ID for a filesystem that is not backed by a block
+/// device. Like Linux, such filesystems use the major number 0.
+pub(crate) fn alloc_anon_dev() -> u64 {
+    static NEXT_MINOR: AtomicU32 = AtomicU32::new(1);
+    makedev(0, NEXT_MINOR.fetch_add(1, Ordering::Relaxed))
+}
+
+/// Get the [`InodeOps`] of a node, if its filesystem supports them.
+pub(crate) fn inode_ops(node: &VfsNodeRef) -> Option<&dyn InodeOps> {
+    let any = node.as_any();
+    #[cfg(any(feature = "ramfs", feature = "procfs", feature = "sysfs"))]
+    {
+        if let Some(dir) = any.downcast_ref::<ramfs::DirNode>() {
+            return Some(dir);
+        }
+        if let Some(file) = any.downcast_ref::<ramfs::FileNode>() {
+            return Some(file);
+        }
+        if let Some(file) = any.downcast_ref::<ramfs::GeneratedFileNode>() {
+            return Some(file);
+        }
+    }
+    #[cfg(all(not(feature = "myfs"), feature = "lwext4_rs"))]
+    if let Some(file) = any.downcast_ref::<lwext4_rust::FileWrapper>() {
+        return Some(file);
+    }
+    let _ = any;
+    None
+}
@@ -0,0 +1,530 @@
+//! RAM filesystem with an inode model.
+//!
+//! Every node carries an inode number and a link count, so that a file can be
+//! reached from several directory entries (hard links). The content of a file
+//! is freed when its last link is removed and the last reference to the node
+//! (e.g. an opened file) is dropped. Nodes also keep their access,
+//! modification, change and creation times, in nanoseconds.
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
+use alloc::{string::String, vec::Vec};
+use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use core::time::Duration;
+
+use axfs_vfs::{
+    VfsDirEntry, VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeRef, VfsNodeType, VfsOps,
+};
+use axfs_vfs::{VfsError, VfsResult};
+use spin::{RwLock, once::Once};
+
+use super::{InodeOps, alloc_anon_dev};
+use crate::fops::InodeAttr;
+
+/// Inode numbers are shared by all RAM filesystems.
+static NEXT_INO: AtomicU64 = AtomicU64::new(1);
+
+/// Timestamps of a node.
+#[derive(Clone, Copy)]
+struct Times {
+    atime: Duration,
+    mtime: Duration,
+    ctime: Duration,
+    btime: Duration,
+}
+
+/// Common inode metadata of the RAM filesystem nodes.
+struct Inode {
+    ino: u64,
+    dev: u64,
+    nlink: AtomicU32,
+    times: RwLock<Times>,
+}
+
+impl Inode {
+    fn new(dev: u64) -> Self {
+        let now = crate::now();
+        Self {
+            ino: NEXT_INO.fetch_add(1, Ordering::Relaxed),
+            dev,
+            nlink: AtomicU32::new(1),
+            times: RwLock::new(Times {
+                atime: now,
+                mtime: now,
+                ctime: now,
+                btime: now,
+            }),
+        }
+    }
+
+    fn attr(&self, nlink: u32) -> InodeAttr {
+        let times = *self.times.read();
+        InodeAttr {
+            ino: self.ino,
+            nlink,
+            dev: self.dev,
+            rdev: 0,
+            atime: times.atime,
+            mtime: times.mtime,
+            ctime: times.ctime,
+            btime: Some(times.btime),
+        }
+    }
+
+    /// Update the access time, after the content is read.
+    fn accessed(&self) {
+        self.times.write().atime = crate::now();
+    }
+
+    /// Update the modification and change times, after the content is
+    /// modified.
+    fn modified(&self) {
+        let now = crate::now();
+        let mut times = self.times.write();
+        times.mtime = now;
+        times.ctime = now;
+    }
+
+    /// Update the change time, after the metadata is changed.
+    fn changed(&self) {
+        self.times.write().ctime = crate::now();
+    }
+
+    fn set_times(&self, atime: Option<Duration>, mtime: Option<Duration>) {
+        let mut times = self.times.write();
+        if let Some(atime) = atime {
+            times.atime = atime;
+        }
+        if let Some(mtime) = mtime {
+            times.mtime = mtime;
+        }
+        times.ctime = crate::now();
+    }
+}
+
+/// A RAM filesystem that implements [`axfs_vfs::VfsOps`].
+pub struct RamFileSystem {
+    parent: Once<VfsNodeRef>,
+    root: Arc<DirNode>,
+}
+
+impl RamFileSystem {
+    /// Create a new instance.
+    pub fn new() -> Self {
+        Self {
+            parent: Once::new(),
+            root: DirNode::new(None, alloc_anon_dev()),
+        }
+    }
+}
+
+impl VfsOps for RamFileSystem {
+    fn mount(&self, _path: &str, mount_point: VfsNodeRef) -> VfsResult {
+        if let Some(parent) = mount_point.parent() {
+            self.root.set_parent(Some(self.parent.call_once(|| parent)));
+        } else {
+            self.root.set_parent(None);
+        }
+        Ok(())
+    }
+
+    fn root_dir(&self) -> VfsNodeRef {
+        self.root.clone()
+    }
+}
+
+impl Default for RamFileSystem {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns the inode metadata of a node in the RAM filesystem.
+fn inode_of(node: &VfsNodeRef) -> Option<&Inode> {
+    let any = node.as_any();
+    if let Some(file) = any.downcast_ref::<FileNode>() {
+        Some(&file.inode)
+    } else {
+        any.downcast_ref::<DirNode>().map(|dir| &dir.inode)
+    }
+}
+
+/// The directory node in the RAM filesystem.
+pub struct DirNode {
+    this: Weak<DirNode>,
+    parent: RwLock<Weak<dyn VfsNodeOps>>,
+    children: RwLock<BTreeMap<String, VfsNodeRef>>,
+    inode: Inode,
+}
+
+impl DirNode {
+    fn new(parent: Option<Weak<dyn VfsNodeOps>>, dev: u64) -> Arc<Self> {
+        Arc::new_cyclic(|this| Self {
+            this: this.clone(),
+            parent: RwLock::new(parent.unwrap_or_else(|| Weak::<Self>::new())),
+            children: RwLock::new(BTreeMap::new()),
+            inode: Inode::new(dev),
+        })
+    }
+
+    fn set_parent(&self, parent: Option<&VfsNodeRef>) {
+        *self.parent.write() = parent.map_or(Weak::<Self>::new() as _, Arc::downgrade);
+    }
+
+    /// Checks whether a node with the given name exists in this directory.
+    pub fn exist(&self, name: &str) -> bool {
+        self.children.read().contains_key(name)
+    }
+
+    /// Creates a new node with the given name and type in this directory.
+    pub fn create_node(&self, name: &str, ty: VfsNodeType) -> VfsResult {
+        if self.exist(name) {
+            error!("AlreadyExists {name}");
+            return Err(VfsError::AlreadyExists);
+        }
+        let dev = self.inode.dev;
+        let node: VfsNodeRef = match ty {
+            VfsNodeType::File => Arc::new(FileNode::new(dev)),
+            VfsNodeType::Dir => Self::new(Some(self.this.clone()), dev),
+            _ => return Err(VfsError::Unsupported),
+        };
+        self.children.write().insert(name.into(), node);
+        self.inode.modified();
+        Ok(())
+    }
+
+    /// Creates a [`GeneratedFileNode`] with the given name in this directory.
+    ///
+    /// The file is writable if `write` is given.
+    pub fn create_generated(
+        &self,
+        name: &str,
+        generate: Box<ContentGenerator>,
+        write: Option<Box<ContentWriter>>,
+    ) -> VfsResult {
+        let mut children = self.children.write();
+        if children.contains_key(name) {
+            return Err(VfsError::AlreadyExists);
+        }
+        let node = GeneratedFileNode {
+            generate,
+            write,
+            inode: Inode::new(self.inode.dev),
+        };
+        children.insert(name.into(), Arc::new(node));
+        self.inode.modified();
+        Ok(())
+    }
+
+    /// Adds a new entry `name` in this directory referring to `node`.
+    pub fn link_node(&self, name: &str, node: VfsNodeRef) -> VfsResult {
+        let inode = match node.as_any().downcast_ref::<FileNode>() {
+            Some(file) => &file.inode,
+            // hard links to directories are not allowed
+            None if node.as_any().is::<DirNode>() => return Err(VfsError::PermissionDenied),
+            None => return Err(VfsError::Unsupported),
+        };
+        let mut children = self.children.write();
+        if children.contains_key(name) {
+            return Err(VfsError::AlreadyExists);
+        }
+        inode.nlink.fetch_add(1, Ordering::AcqRel);
+        inode.changed();
+        children.insert(name.into(), node);
+        self.inode.modified();
+        Ok(())
+    }
+
+    /// Removes a node by the given name in this directory.
+    pub fn remove_node(&self, name: &str) -> VfsResult {
+        let mut children = self.children.write();
+        let node = children.get(name).ok_or(VfsError::NotFound)?;
+        if let Some(dir) = node.as_any().downcast_ref::<DirNode>() {
+            if !dir.children.read().is_empty() {
+                return Err(VfsError::DirectoryNotEmpty);
+            }
+        }
+        if let Some(inode) = inode_of(node) {
+            inode.nlink.fetch_sub(1, Ordering::AcqRel);
+            inode.changed();
+        }
+        children.remove(name);
+        self.inode.modified();
+        Ok(())
+    }
+
+    /// Returns the directory containing the last component of `path`, and
+    /// the name of that component.
+    fn parent_of<'a>(self: &Arc<Self>, path: &'a str) -> VfsResult<(Arc<DirNode>, &'a str)> {
+        let path = path.trim_matches('/');
+        let (dir, name) = match path.rsplit_once('/') {
+            Some((dir, name)) => (self.clone().lookup(dir)?, name),
+            None => (self.clone() as VfsNodeRef, path),
+        };
+        if name.is_empty() || name == "." || name == ".." {
+            return Err(VfsError::InvalidInput);
+        }
+        let dir = dir
+            .as_any()
+            .downcast_ref::<DirNode>()
+            .and_then(|dir| dir.this.upgrade())
+            .ok_or(VfsError::NotADirectory)?;
+        Ok((dir, name))
+    }
+}
+
+impl VfsNodeOps for DirNode {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        Ok(VfsNodeAttr::new_dir(4096, 0))
+    }
+
+    fn parent(&self) -> Option<VfsNodeRef> {
+        self.parent.read().upgrade()
+    }
+
+    fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
+        let (name, rest) = split_path(path);
+        let node = match name {
+            "" | "." => Ok(self.clone() as VfsNodeRef),
+            ".." => self.parent().ok_or(VfsError::NotFound),
+            _ => self
+                .children
+                .read()
+                .get(name)
+                .cloned()
+                .ok_or(VfsError::NotFound),
+        }?;
+
+        if let Some(rest) = rest {
+            node.lookup(rest)
+        } else {
+            Ok(node)
+        }
+    }
+
+    fn read_dir(&self, start_idx: usize, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
+        let children = self.children.read();
+        let mut children = children.iter().skip(start_idx.max(2) - 2);
+        for (i, ent) in dirents.iter_mut().enumerate() {
+            match i + start_idx {
+                0 => *ent = VfsDirEntry::new(".", VfsNodeType::Dir),
+                1 => *ent = VfsDirEntry::new("..", VfsNodeType::Dir),
+                _ => {
+                    if let Some((name, node)) = children.next() {
+                        *ent = VfsDirEntry::new(name, node.get_attr().unwrap().file_type());
+                    } else {
+                        return Ok(i);
+                    }
+                }
+            }
+        }
+        Ok(dirents.len())
+    }
+
+    fn create(&self, path: &str, ty: VfsNodeType) -> VfsResult {
+        debug!("create {ty:?} at ramfs: {path}");
+        let (name, rest) = split_path(path);
+        if let Some(rest) = rest {
+            match name {
+                "" | "." => self.create(rest, ty),
+                ".." => self.parent().ok_or(VfsError::NotFound)?.create(rest, ty),
+                _ => {
+                    let subdir = self
+                        .children
+                        .read()
+                        .get(name)
+                        .ok_or(VfsError::NotFound)?
+                        .clone();
+                    subdir.create(rest, ty)
+                }
+            }
+        } else if name.is_empty() || name == "." || name == ".." {
+            Ok(()) // already exists
+        } else {
+            self.create_node(name, ty)
+        }
+    }
+
+    fn remove(&self, path: &str) -> VfsResult {
+        debug!("remove at ramfs: {path}");
+        let (name, rest) = split_path(path);
+        if let Some(rest) = rest {
+            match name {
+                "" | "." => self.remove(rest),
+                ".." => self.parent().ok_or(VfsError::NotFound)?.remove(rest),
+                _ => {
+                    let subdir = self
+                        .children
+                        .read()
+                        .get(name)
+                        .ok_or(VfsError::NotFound)?
+                        .clone();
+                    subdir.remove(rest)
+                }
+            }
+        } else if name.is_empty() || name == "." || name == ".." {
+            Err(VfsError::InvalidInput) // remove '.' or '..
+        } else {
+            self.remove_node(name)
+        }
+    }
+
+    axfs_vfs::impl_vfs_dir_default! {}
+}
+
+impl InodeOps for DirNode {
+    fn inode_attr(&self) -> VfsResult<InodeAttr> {
+        let subdirs = self
+            .children
+            .read()
+            .values()
+            .filter(|node| node.as_any().is::<DirNode>())
+            .count();
+        Ok(self.inode.attr(2 + subdirs as u32))
+    }
+
+    fn link(&self, old_path: &str, new_path: &str) -> VfsResult {
+        let this = self.this.upgrade().ok_or(VfsError::NotFound)?;
+        let node = this.clone().lookup(old_path)?;
+        let (dir, name) = this.parent_of(new_path)?;
+        dir.link_node(name, node)
+    }
+
+    fn set_times(&self, atime: Option<Duration>, mtime: Option<Duration>) -> VfsResult {
+        self.inode.set_times(atime, mtime);
+        Ok(())
+    }
+}
+
+/// The file node in the RAM filesystem.
+pub struct FileNode {
+    content: RwLock<Vec<u8>>,
+    inode: Inode,
+}
+
+impl FileNode {
+    fn new(dev: u64) -> Self {
+        Self {
+            content: RwLock::new(Vec::new()),
+            inode: Inode::new(dev),
+        }
+    }
+}
+
+impl VfsNodeOps for FileNode {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        Ok(VfsNodeAttr::new_file(self.content.read().len() as _, 0))
+    }
+
+    fn truncate(&self, size: u64) -> VfsResult {
+        let mut content = self.content.write();
+        if size < content.len() as u64 {
+            content.truncate(size as _);
+        } else {
+            content.resize(size as _, 0);
+        }
+        self.inode.modified();
+        Ok(())
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let content = self.content.read();
+        let start = content.len().min(offset as usize);
+        let end = content.len().min(offset as usize + buf.len());
+        let src = &content[start..end];
+        buf[..src.len()].copy_from_slice(src);
+        self.inode.accessed();
+        Ok(src.len())
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        let offset = offset as usize;
+        let mut content = self.content.write();
+        if offset + buf.len() > content.len() {
+            content.resize(offset + buf.len(), 0);
+        }
+        let dst = &mut content[offset..offset + buf.len()];
+        dst.copy_from_slice(&buf[..dst.len()]);
+        self.inode.modified();
+        Ok(buf.len())
+    }
+
+    axfs_vfs::impl_vfs_non_dir_default! {}
+}
+
+impl InodeOps for FileNode {
+    fn inode_attr(&self) -> VfsResult<InodeAttr> {
+        Ok(self.inode.attr(self.inode.nlink.load(Ordering::Acquire)))
+    }
+
+    fn set_times(&self, atime: Option<Duration>, mtime: Option<Duration>) -> VfsResult {
+        self.inode.set_times(atime, mtime);
+        Ok(())
+    }
+}
+
+/// Generates the content of a [`GeneratedFileNode`].
+pub type ContentGenerator = dyn Fn() -> Vec<u8> + Send + Sync;
+
+/// Handles the data written to a [`GeneratedFileNode`].
+pub type ContentWriter = dyn Fn(&[u8]) -> VfsResult + Send + Sync;
+
+/// A file whose content is generated each time it is read, like the files of
+/// Linux's procfs. It is read-only, unless it has a handler for writes.
+pub struct GeneratedFileNode {
+    generate: Box<ContentGenerator>,
+    write: Option<Box<ContentWriter>>,
+    inode: Inode,
+}
+
+impl VfsNodeOps for GeneratedFileNode {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        let mode = if self.write.is_some() { 0o644 } else { 0o444 };
+        // the size is unknown until the content is generated
+        Ok(VfsNodeAttr::new(
+            VfsNodePerm::from_bits_truncate(mode),
+            VfsNodeType::File,
+            0,
+            0,
+        ))
+    }
+
+    fn truncate(&self, _size: u64) -> VfsResult {
+        // there is no content to truncate when opening for writing
+        match self.write {
+            Some(_) => Ok(()),
+            None => Err(VfsError::PermissionDenied),
+        }
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let content = (self.generate)();
+        let start = content.len().min(offset as usize);
+        let end = content.len().min(offset as usize + buf.len());
+        let src = &content[start..end];
+        buf[..src.len()].copy_from_slice(src);
+        Ok(src.len())
+    }
+
+    fn write_at(&self, _offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        let write = self.write.as_ref().ok_or(VfsError::PermissionDenied)?;
+        write(buf)?;
+        Ok(buf.len())
+    }
+
+    axfs_vfs::impl_vfs_non_dir_default! {}
+}
+
+impl InodeOps for GeneratedFileNode {
+    fn inode_attr(&self) -> VfsResult<InodeAttr> {
+        Ok(self.inode.attr(1))
+    }
+}
+
+fn split_path(path: &str) -> (&str, Option<&str>) {
+    let trimmed_path = path.trim_start_matches('/');
+    trimmed_path.find('/').map_or((trimmed_path, None), |n| {
+        (&trimmed_path[..n], Some(&trimmed_path[n + 1..]))
+    })
+}
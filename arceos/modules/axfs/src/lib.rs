@@ -8,7 +8,7 @@
 //!    is **enabled** by default.
 //! - `devfs`: Mount [`axfs_devfs::DeviceFileSystem`] on `/dev`. This feature is
 //!    **enabled** by default.
-//! - `ramfs`: Mount [`axfs_ramfs::RamFileSystem`] on `/tmp`. This feature is
+//! - `ramfs`: Mount a RAM filesystem with hard link support on `/tmp`. This feature is
 //!    **enabled** by default.
 //! - `myfs`: Allow users to define their custom filesystems to override the
 //!    default. In this case, [`MyFileSystemIf`] is required to be implemented
@@ -35,8 +35,28 @@ pub mod api;
 pub mod fops;
 pub use root::{CURRENT_DIR, CURRENT_DIR_PATH};
 
+use core::time::Duration;
+
 use axdriver::{AxDeviceContainer, prelude::*};
 
+/// The clock of the timestamps of files, see [`set_clock`].
+static CLOCK: spin::Once<fn() -> Duration> = spin::Once::new();
+
+/// Set the clock of the timestamps of files, as the time since the epoch.
+///
+/// It defaults to the wall-clock time of the platform, and can be set once,
+/// e.g. to a realtime clock which can be set by the users.
+pub fn set_clock(clock: fn() -> Duration) {
+    CLOCK.call_once(|| clock);
+}
+
+/// The current time of the clock of the timestamps of files.
+pub(crate) fn now() -> Duration {
+    CLOCK
+        .get()
+        .map_or_else(axhal::time::wall_time, |clock| clock())
+}
+
 /// Initializes filesystems by block devices.
 pub fn init_filesystems(mut blk_devs: AxDeviceContainer<AxBlockDevice>) {
     info!("Initialize filesystems...");
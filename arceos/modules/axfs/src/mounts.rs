@@ -9,14 +9,15 @@ pub(crate) fn devfs() -> Arc<fs::devfs::DeviceFileSystem> {
     let zero = fs::devfs::ZeroDev;
     let tty = fs::devfs::TtyDev;
     let urandom = fs::devfs::UrandomDev::default();
-    let bar = fs::devfs::ZeroDev;
     let devfs = fs::devfs::DeviceFileSystem::new();
-    let foo_dir = devfs.mkdir("foo");
     devfs.add("null", Arc::new(null));
     devfs.add("zero", Arc::new(zero));
     devfs.add("tty", Arc::new(tty));
     devfs.add("urandom", Arc::new(urandom));
-    foo_dir.add("bar", Arc::new(bar));
+    devfs.add("ptmx", Arc::new(fs::devpts::PtmxDev));
+    // devpts lives in a directory of devfs rather than being mounted on
+    // `/dev/pts`, as nested mount points are not well supported.
+    devfs.add("pts", fs::devpts::PtsDir::new(&devfs.root_dir()));
     Arc::new(devfs)
 }
 
@@ -44,6 +45,12 @@ pub(crate) fn procfs() -> VfsResult<Arc<fs::ramfs::RamFileSystem>> {
     let file_over = proc_root.clone().lookup("./sys/vm/overcommit_memory")?;
     file_over.write_at(0, b"0\n")?;
 
+    // Create /proc/sys/kernel/core_pattern
+    proc_root.create("sys/kernel", VfsNodeType::Dir)?;
+    proc_root.create("sys/kernel/core_pattern", VfsNodeType::File)?;
+    let file_core_pattern = proc_root.clone().lookup("./sys/kernel/core_pattern")?;
+    file_core_pattern.write_at(0, b"core\n")?;
+
     // Create /proc/self/stat
     proc_root.create("self", VfsNodeType::Dir)?;
     proc_root.create("self/stat", VfsNodeType::File)?;
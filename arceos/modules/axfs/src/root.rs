@@ -7,11 +7,13 @@ use axerrno::{AxError, AxResult, ax_err};
 use axfs_vfs::{VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType, VfsOps, VfsResult};
 use axns::{ResArc, def_resource};
 use axsync::Mutex;
+use core::time::Duration;
 use lazyinit::LazyInit;
 use spin::RwLock;
 
 use crate::{
     api::FileType,
+    fops::{InodeAttr, inode_attr_of, set_times_of},
     fs::{self},
     mounts,
 };
@@ -124,6 +126,30 @@ impl RootDirectory {
     }
 }
 
+impl RootDirectory {
+    fn same_fs(&self, path1: &str, path2: &str) -> bool {
+        self.lookup_mounted_fs(path1, |fs1, _| {
+            self.lookup_mounted_fs(path2, |fs2, _| Ok(Arc::ptr_eq(&fs1, &fs2)))
+        })
+        .unwrap_or(false)
+    }
+
+    fn link(&self, old_path: &str, new_path: &str) -> VfsResult {
+        if !self.same_fs(old_path, new_path) {
+            return ax_err!(Unsupported);
+        }
+        self.lookup_mounted_fs(old_path, |fs, old_rest| {
+            self.lookup_mounted_fs(new_path, |_, new_rest| {
+                let root = fs.root_dir();
+                match fs::inode_ops(&root) {
+                    Some(ops) => ops.link(old_rest, new_rest),
+                    None => ax_err!(Unsupported),
+                }
+            })
+        })
+    }
+}
+
 impl VfsNodeOps for RootDirectory {
     axfs_vfs::impl_vfs_dir_default! {}
 
@@ -387,6 +413,51 @@ pub(crate) fn set_perm(path: &str, mode: u16) -> AxResult {
     Ok(())
 }
 
+pub(crate) fn same_filesystem(path1: &str, path2: &str) -> AxResult<bool> {
+    Ok(ROOT_DIR.same_fs(&absolute_path(path1)?, &absolute_path(path2)?))
+}
+
+pub(crate) fn link(old: &str, new: &str) -> AxResult {
+    if old.is_empty() || new.is_empty() {
+        return ax_err!(NotFound);
+    }
+    if lookup(None, old)?.get_attr()?.is_dir() {
+        return ax_err!(PermissionDenied);
+    }
+    if lookup(None, new).is_ok() {
+        return ax_err!(AlreadyExists);
+    }
+    ROOT_DIR.link(&absolute_path(old)?, &absolute_path(new)?)
+}
+
+pub(crate) fn stat(path: &str) -> AxResult<(VfsNodeAttr, InodeAttr)> {
+    let node = lookup(None, path)?;
+    Ok((node.get_attr()?, inode_attr_of(&node)?))
+}
+
+pub(crate) fn set_times(path: &str, atime: Option<Duration>, mtime: Option<Duration>) -> AxResult {
+    let node = lookup(None, path)?;
+    set_times_of(&node, atime, mtime)
+}
+
+#[cfg(any(feature = "ramfs", feature = "procfs", feature = "sysfs"))]
+pub(crate) fn create_generated_file(
+    path: &str,
+    generate: alloc::boxed::Box<fs::ramfs::ContentGenerator>,
+    write: Option<alloc::boxed::Box<fs::ramfs::ContentWriter>>,
+) -> AxResult {
+    let path = absolute_path(path)?;
+    let (parent, name) = path.rsplit_once('/').ok_or(AxError::InvalidInput)?;
+    if name.is_empty() {
+        return ax_err!(InvalidInput);
+    }
+    let parent = lookup(None, if parent.is_empty() { "/" } else { parent })?;
+    match parent.as_any().downcast_ref::<fs::ramfs::DirNode>() {
+        Some(dir) => dir.create_generated(name, generate, write),
+        None => ax_err!(Unsupported),
+    }
+}
+
 pub(crate) fn is_symlink(path: &str) -> AxResult<bool> {
     debug!("Checking if path is a symlink: {}", path);
     if path.is_empty() {
@@ -140,6 +140,33 @@ pub unsafe fn write_thread_pointer(tpidr_el0: usize) {
     TPIDR_EL0.set(tpidr_el0 as _)
 }
 
+/// Enables or disables the software step of user space on the current CPU
+/// (sets `MDSCR_EL1.SS`).
+///
+/// When enabled, an exception return to user space with `SPSR_EL1.SS` set
+/// runs one instruction, then takes a software step exception.
+#[inline]
+pub fn set_single_step(enabled: bool) {
+    const MDSCR_SS: u64 = 1 << 0;
+    let mdscr: u64;
+    unsafe { asm!("mrs {}, mdscr_el1", out(reg) mdscr) };
+    let new = if enabled {
+        mdscr | MDSCR_SS
+    } else {
+        mdscr & !MDSCR_SS
+    };
+    if new == mdscr {
+        return;
+    }
+    unsafe {
+        if enabled {
+            // The OS lock blocks debug exceptions, including software steps.
+            asm!("msr oslar_el1, xzr");
+        }
+        asm!("msr mdscr_el1, {}; isb", in(reg) new);
+    }
+}
+
 /// Initializes CPU states on the current CPU.
 ///
 /// On AArch64, it sets the exception vector base address (`VBAR_EL1`) and `TTBR0_EL1`.
@@ -5,6 +5,8 @@ use page_table_entry::MappingFlags;
 use tock_registers::interfaces::Readable;
 
 use super::TrapFrame;
+#[cfg(feature = "uspace")]
+use crate::trap::{ExceptionKind, handle_user_exception};
 
 global_asm!(
     include_str!("trap.S"),
@@ -58,6 +60,10 @@ fn handle_instruction_abort(tf: &TrapFrame, iss: u64, is_user: bool) {
     }
     let vaddr = va!(FAR_EL1.get() as usize);
 
+    #[cfg(feature = "uspace")]
+    if is_user && !matches!(iss & 0b111100, 0b0100 | 0b1100) {
+        return handle_user_exception(tf, ExceptionKind::BusError, vaddr);
+    }
     // Only handle Translation fault and Permission fault
     if !matches!(iss & 0b111100, 0b0100 | 0b1100) // IFSC or DFSC bits
         || !handle_trap!(PAGE_FAULT, vaddr, access_flags, is_user)
@@ -87,6 +93,16 @@ fn handle_data_abort(tf: &TrapFrame, iss: u64, is_user: bool) {
     }
     let vaddr = va!(FAR_EL1.get() as usize);
 
+    #[cfg(feature = "uspace")]
+    if is_user && !matches!(iss & 0b111100, 0b0100 | 0b1100) {
+        // DFSC 0b100001 is an alignment fault
+        let kind = if iss & 0b111111 == 0b100001 {
+            ExceptionKind::Misaligned
+        } else {
+            ExceptionKind::BusError
+        };
+        return handle_user_exception(tf, kind, vaddr);
+    }
     // Only handle Translation fault and Permission fault
     if !matches!(iss & 0b111100, 0b0100 | 0b1100) // IFSC or DFSC bits
         || !handle_trap!(PAGE_FAULT, vaddr, access_flags, is_user)
@@ -119,6 +135,29 @@ fn handle_sync_exception(tf: &mut TrapFrame, source: TrapSource) {
         Some(ESR_EL1::EC::Value::InstrAbortCurrentEL) => handle_instruction_abort(tf, iss, false),
         Some(ESR_EL1::EC::Value::DataAbortLowerEL) => handle_data_abort(tf, iss, true),
         Some(ESR_EL1::EC::Value::DataAbortCurrentEL) => handle_data_abort(tf, iss, false),
+        #[cfg(feature = "uspace")]
+        Some(ESR_EL1::EC::Value::Brk64) if source.is_from_user() => {
+            handle_user_exception(tf, ExceptionKind::Breakpoint, va!(tf.elr as usize))
+        }
+        #[cfg(feature = "uspace")]
+        Some(ESR_EL1::EC::Value::SoftwareStepLowerEL) => {
+            handle_user_exception(tf, ExceptionKind::SingleStep, va!(tf.elr as usize))
+        }
+        #[cfg(feature = "uspace")]
+        Some(ESR_EL1::EC::Value::Unknown | ESR_EL1::EC::Value::IllegalExecutionState)
+            if source.is_from_user() =>
+        {
+            handle_user_exception(tf, ExceptionKind::IllegalInstruction, va!(tf.elr as usize))
+        }
+        #[cfg(feature = "uspace")]
+        Some(ESR_EL1::EC::Value::PCAlignmentFault) if source.is_from_user() => {
+            let vaddr = va!(FAR_EL1.get() as usize);
+            handle_user_exception(tf, ExceptionKind::Misaligned, vaddr)
+        }
+        #[cfg(feature = "uspace")]
+        Some(ESR_EL1::EC::Value::SPAlignmentFault) if source.is_from_user() => {
+            handle_user_exception(tf, ExceptionKind::Misaligned, va!(tf.usp as usize))
+        }
         Some(ESR_EL1::EC::Value::Brk64) => {
             debug!("BRK #{:#x} @ {:#x} ", iss, tf.elr);
             tf.elr += 4;
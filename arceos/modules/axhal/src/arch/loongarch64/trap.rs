@@ -1,4 +1,6 @@
 use super::context::TrapFrame;
+#[cfg(feature = "uspace")]
+use crate::trap::{ExceptionKind, handle_user_exception};
 use loongArch64::register::{
     badv,
     estat::{self, Exception, Trap},
@@ -60,6 +62,24 @@ fn loongarch64_trap_handler(tf: &mut TrapFrame, from_user: bool) {
         | Trap::Exception(Exception::PageNonExecutableFault) => {
             handle_page_fault(tf, MappingFlags::EXECUTE, from_user);
         }
+        #[cfg(feature = "uspace")]
+        Trap::Exception(Exception::Breakpoint) if from_user => {
+            handle_user_exception(tf, ExceptionKind::Breakpoint, va!(tf.era))
+        }
+        #[cfg(feature = "uspace")]
+        Trap::Exception(
+            Exception::InstructionNotExist | Exception::InstructionPrivilegeIllegal,
+        ) if from_user => handle_user_exception(tf, ExceptionKind::IllegalInstruction, va!(tf.era)),
+        #[cfg(feature = "uspace")]
+        Trap::Exception(Exception::AddressNotAligned) if from_user => {
+            handle_user_exception(tf, ExceptionKind::Misaligned, va!(badv::read().raw()))
+        }
+        #[cfg(feature = "uspace")]
+        Trap::Exception(
+            Exception::FetchInstructionAddressError | Exception::MemoryAccessAddressError,
+        ) if from_user => {
+            handle_user_exception(tf, ExceptionKind::ProtectionFault, va!(badv::read().raw()))
+        }
         Trap::Exception(Exception::Breakpoint) => handle_breakpoint(&mut tf.era),
         Trap::Interrupt(_) => {
             let irq_num: usize = estat.is().trailing_zeros() as usize;
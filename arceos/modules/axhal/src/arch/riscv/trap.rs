@@ -5,6 +5,8 @@ use riscv::interrupt::supervisor::{Exception as E, Interrupt as I};
 use riscv::register::{scause, stval};
 
 use super::TrapFrame;
+#[cfg(feature = "uspace")]
+use crate::trap::{ExceptionKind, handle_user_exception};
 
 core::arch::global_asm!(
     include_asm_macros!(),
@@ -63,6 +65,24 @@ fn riscv_trap_handler(tf: &mut TrapFrame, from_user: bool) {
             Trap::Exception(E::InstructionPageFault) => {
                 handle_page_fault(tf, vaddr, MappingFlags::EXECUTE, from_user)
             }
+            #[cfg(feature = "uspace")]
+            Trap::Exception(E::Breakpoint) if from_user => {
+                handle_user_exception(tf, ExceptionKind::Breakpoint, va!(tf.sepc))
+            }
+            #[cfg(feature = "uspace")]
+            Trap::Exception(E::IllegalInstruction) if from_user => {
+                handle_user_exception(tf, ExceptionKind::IllegalInstruction, va!(tf.sepc))
+            }
+            #[cfg(feature = "uspace")]
+            Trap::Exception(E::InstructionMisaligned | E::LoadMisaligned | E::StoreMisaligned)
+                if from_user =>
+            {
+                handle_user_exception(tf, ExceptionKind::Misaligned, vaddr)
+            }
+            #[cfg(feature = "uspace")]
+            Trap::Exception(E::InstructionFault | E::LoadFault | E::StoreFault) if from_user => {
+                handle_user_exception(tf, ExceptionKind::BusError, vaddr)
+            }
             Trap::Exception(E::Breakpoint) => handle_breakpoint(&mut tf.sepc),
             Trap::Interrupt(_) => {
                 handle_trap!(IRQ, scause.bits());
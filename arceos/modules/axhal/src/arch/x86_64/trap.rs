@@ -3,6 +3,8 @@ use x86::{controlregs::cr2, irq::*};
 use x86_64::structures::idt::PageFaultErrorCode;
 
 use super::context::TrapFrame;
+#[cfg(feature = "uspace")]
+use crate::trap::{ExceptionKind, handle_user_exception};
 
 core::arch::global_asm!(include_str!("trap.S"));
 
@@ -38,6 +40,31 @@ fn x86_trap_handler(tf: &mut TrapFrame) {
     }
     match tf.vector as u8 {
         PAGE_FAULT_VECTOR => handle_page_fault(tf),
+        #[cfg(feature = "uspace")]
+        DIVIDE_ERROR_VECTOR if tf.is_user() => {
+            handle_user_exception(tf, ExceptionKind::DivideByZero, va!(tf.rip as usize))
+        }
+        #[cfg(feature = "uspace")]
+        DEBUG_VECTOR if tf.is_user() => {
+            handle_user_exception(tf, ExceptionKind::SingleStep, va!(tf.rip as usize))
+        }
+        #[cfg(feature = "uspace")]
+        BREAKPOINT_VECTOR if tf.is_user() => {
+            handle_user_exception(tf, ExceptionKind::Breakpoint, va!(tf.rip as usize))
+        }
+        #[cfg(feature = "uspace")]
+        INVALID_OPCODE_VECTOR if tf.is_user() => {
+            handle_user_exception(tf, ExceptionKind::IllegalInstruction, va!(tf.rip as usize))
+        }
+        #[cfg(feature = "uspace")]
+        GENERAL_PROTECTION_FAULT_VECTOR if tf.is_user() => {
+            // The faulting address is not reported, as done by Linux.
+            handle_user_exception(tf, ExceptionKind::ProtectionFault, va!(0))
+        }
+        #[cfg(feature = "uspace")]
+        ALIGNMENT_CHECK_VECTOR if tf.is_user() => {
+            handle_user_exception(tf, ExceptionKind::Misaligned, va!(tf.rip as usize))
+        }
         BREAKPOINT_VECTOR => debug!("#BP @ {:#x} ", tf.rip),
         GENERAL_PROTECTION_FAULT_VECTOR => {
             panic!(
@@ -77,7 +104,8 @@ fn vec_to_str(vec: u64) -> &'static str {
 
 fn err_code_to_flags(err_code: u64) -> Result<MappingFlags, u64> {
     let code = PageFaultErrorCode::from_bits_truncate(err_code);
-    let reserved_bits = (PageFaultErrorCode::CAUSED_BY_WRITE
+    let reserved_bits = (PageFaultErrorCode::PROTECTION_VIOLATION
+        | PageFaultErrorCode::CAUSED_BY_WRITE
         | PageFaultErrorCode::USER_MODE
         | PageFaultErrorCode::INSTRUCTION_FETCH)
         .complement();
@@ -21,6 +21,35 @@ pub static PAGE_FAULT: [fn(VirtAddr, MappingFlags, bool) -> bool];
 #[def_trap_handler]
 pub static SYSCALL: [fn(&mut TrapFrame, usize) -> isize];
 
+/// A slice of handler functions of the exceptions raised in user space which
+/// the kernel cannot resolve, such as illegal instructions.
+///
+/// The handler is given the kind of the exception and the faulting address.
+#[cfg(feature = "uspace")]
+#[def_trap_handler]
+pub static USER_EXCEPTION: [fn(ExceptionKind, VirtAddr) -> bool];
+
+/// An exception raised by user space, other than page faults and syscalls.
+#[cfg(feature = "uspace")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExceptionKind {
+    /// A breakpoint instruction was executed.
+    Breakpoint,
+    /// An illegal or undefined instruction was executed.
+    IllegalInstruction,
+    /// A memory access or instruction fetch was misaligned.
+    Misaligned,
+    /// A memory access was rejected by the hardware.
+    BusError,
+    /// An integer was divided by zero.
+    DivideByZero,
+    /// An address or instruction user space is not allowed to use, such as a
+    /// general protection fault on x86_64.
+    ProtectionFault,
+    /// An instruction was executed with hardware single-stepping enabled.
+    SingleStep,
+}
+
 /// A slice of callbacks to be invoked after a trap.
 #[linkme::distributed_slice]
 pub static POST_TRAP: [fn(&mut TrapFrame, bool)];
@@ -48,6 +77,17 @@ pub(crate) fn post_trap_callback(tf: &mut TrapFrame, from_user: bool) {
     }
 }
 
+/// Call the external handler of an exception raised in user space.
+#[cfg(feature = "uspace")]
+pub(crate) fn handle_user_exception(tf: &TrapFrame, kind: ExceptionKind, vaddr: VirtAddr) {
+    if !handle_trap!(USER_EXCEPTION, kind, vaddr) {
+        panic!(
+            "Unhandled user exception {:?}, vaddr={:#x}:\n{:#x?}",
+            kind, vaddr, tf
+        );
+    }
+}
+
 /// Call the external syscall handler.
 #[cfg(feature = "uspace")]
 pub(crate) fn handle_syscall(tf: &mut TrapFrame, syscall_num: usize) -> isize {
@@ -445,6 +445,14 @@ impl AddrSpace {
         self.areas.clear(&mut self.pt).unwrap();
     }
 
+    /// Returns an iterator over the memory areas, as their address ranges and
+    /// mapping flags in ascending order of addresses.
+    pub fn areas(&self) -> impl Iterator<Item = (VirtAddrRange, MappingFlags)> + '_ {
+        self.areas
+            .iter()
+            .map(|area| (area.va_range(), area.flags()))
+    }
+
     /// Checks whether an access to the specified memory region is valid.
     ///
     /// Returns `true` if the memory region given by `range` is all mapped and
@@ -12,7 +12,7 @@ pub(crate) use crate::run_queue::{current_run_queue, select_run_queue};
 #[doc(cfg(feature = "multitask"))]
 pub use crate::task::{CurrentTask, TaskId, TaskInner};
 #[doc(cfg(feature = "multitask"))]
-pub use crate::task_ext::{TaskExtMut, TaskExtRef};
+pub use crate::task_ext::{TaskExtHooks, TaskExtMut, TaskExtRef};
 #[doc(cfg(feature = "multitask"))]
 pub use crate::wait_queue::WaitQueue;
 
@@ -101,6 +101,9 @@ pub fn init_scheduler_secondary() {
 #[doc(cfg(feature = "irq"))]
 pub fn on_timer_tick() {
     use kernel_guard::NoOp;
+    if let Some(curr) = current_may_uninit() {
+        curr.task_ext_hooks().on_tick();
+    }
     crate::timers::check_events();
     // Since irq and preemption are both disabled here,
     // we can get current run queue with the default `kernel_guard::NoOp`.
@@ -207,6 +210,19 @@ pub fn sleep_until(deadline: axhal::time::TimeValue) {
     axhal::time::busy_wait_until(deadline);
 }
 
+/// Interrupts `task` if it is in an interruptible wait, such as
+/// [`WaitQueue::wait_until_interruptible`], or its next interruptible wait
+/// otherwise.
+pub fn interrupt(task: &AxTaskRef) {
+    task.set_interrupted();
+    // Pairs with the fence of `blocked_resched()`, so that the task is either
+    // unblocked here, or sees the flag before it blocks.
+    core::sync::atomic::fence(core::sync::atomic::Ordering::SeqCst);
+    if task.is_interruptible() {
+        select_run_queue::<NoPreemptIrqSave>(task).unblock_task(task.clone(), true);
+    }
+}
+
 /// Exits the current task.
 pub fn exit(exit_code: i32) -> ! {
     current_run_queue::<NoPreemptIrqSave>().exit_current(exit_code)
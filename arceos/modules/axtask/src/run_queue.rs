@@ -1,6 +1,7 @@
 use alloc::collections::VecDeque;
 use alloc::sync::Arc;
 use core::mem::MaybeUninit;
+use core::sync::atomic::{Ordering, fence};
 
 #[cfg(feature = "smp")]
 use alloc::sync::Weak;
@@ -415,6 +416,18 @@ impl<G: BaseGuard> CurrentRunQueueRef<'_, G> {
         // Note that the state may have been set as `Ready` in `unblock_task()`,
         // see `unblock_task()` for details.
 
+        // An interruptible wait may have been interrupted after it checked for
+        // it, when the task was not blocked yet, see `interrupt()`. The task
+        // stays in the wait queue, which the waiter leaves afterwards.
+        if curr.is_interruptible() {
+            fence(Ordering::SeqCst);
+            if curr.is_interrupted()
+                && curr.transition_state(TaskState::Blocked, TaskState::Running)
+            {
+                return;
+            }
+        }
+
         debug!("task block: {}", curr.id_name());
         self.inner.resched();
     }
@@ -560,6 +573,7 @@ impl AxRunQueue {
             assert!(Arc::strong_count(prev_task.as_task_ref()) > 1);
             assert!(Arc::strong_count(&next_task) >= 1);
 
+            prev_task.task_ext_hooks().on_leave();
             CurrentTask::set_current(prev_task, next_task);
 
             (*prev_ctx_ptr).switch_to(&*next_ctx_ptr);
@@ -568,6 +582,9 @@ impl AxRunQueue {
             // to indicate that it has finished its scheduling process and no longer running on this CPU.
             #[cfg(feature = "smp")]
             clear_prev_task_on_cpu();
+
+            // The task which called `switch_to` is switched back in.
+            crate::current().task_ext_hooks().on_enter();
         }
     }
 }
@@ -50,6 +50,12 @@ pub struct TaskInner {
 
     /// Mark whether the task is in the wait queue.
     in_wait_queue: AtomicBool,
+    /// Mark whether the task is in an interruptible wait, which
+    /// [`interrupt`](crate::interrupt) ends.
+    interruptible: AtomicBool,
+    /// Set by [`interrupt`](crate::interrupt), cleared when it ends an
+    /// interruptible wait.
+    interrupted: AtomicBool,
 
     /// Used to indicate whether the task is running on a CPU.
     #[cfg(feature = "smp")]
@@ -173,6 +179,11 @@ impl TaskInner {
         self.task_ext.as_ptr()
     }
 
+    /// The task extended data, to call its hooks.
+    pub(crate) fn task_ext_hooks(&self) -> &AxTaskExt {
+        &self.task_ext
+    }
+
     /// Initialize the user-defined task extended data.
     ///
     /// Returns a reference to the task extended data if it has not been
@@ -245,6 +256,8 @@ impl TaskInner {
             // By default, the task is allowed to run on all CPUs.
             cpumask: SpinNoIrq::new(AxCpuMask::full()),
             in_wait_queue: AtomicBool::new(false),
+            interruptible: AtomicBool::new(false),
+            interrupted: AtomicBool::new(false),
             #[cfg(feature = "irq")]
             timer_ticket_id: AtomicU64::new(0),
             #[cfg(feature = "smp")]
@@ -343,6 +356,36 @@ impl TaskInner {
         self.in_wait_queue.store(in_wait_queue, Ordering::Release);
     }
 
+    // The flags of interruptible waits are sequentially consistent, so that
+    // the waiter and `interrupt()` cannot both miss each other, see
+    // `blocked_resched()`.
+
+    #[inline]
+    pub(crate) fn is_interruptible(&self) -> bool {
+        self.interruptible.load(Ordering::SeqCst)
+    }
+
+    #[inline]
+    pub(crate) fn set_interruptible(&self, interruptible: bool) {
+        self.interruptible.store(interruptible, Ordering::SeqCst);
+    }
+
+    #[inline]
+    pub(crate) fn is_interrupted(&self) -> bool {
+        self.interrupted.load(Ordering::SeqCst)
+    }
+
+    #[inline]
+    pub(crate) fn set_interrupted(&self) {
+        self.interrupted.store(true, Ordering::SeqCst);
+    }
+
+    /// Clears the interrupted flag, returning whether it was set.
+    #[inline]
+    pub(crate) fn take_interrupted(&self) -> bool {
+        self.interrupted.swap(false, Ordering::SeqCst)
+    }
+
     /// Returns task's current timer ticket ID.
     #[inline]
     #[cfg(feature = "irq")]
@@ -512,6 +555,17 @@ impl CurrentTask {
         self.0.deref().clone()
     }
 
+    /// Runs `f` with a mutable reference to the context of the current task,
+    /// with IRQs and preemption disabled.
+    ///
+    /// The context is only loaded when the task is switched in, so `f` may
+    /// change what takes effect then, such as the page table root, but must
+    /// apply the change to the CPU itself.
+    pub fn with_ctx_mut<R>(&self, f: impl FnOnce(&mut TaskContext) -> R) -> R {
+        let _guard = kernel_guard::NoPreemptIrqSave::new();
+        f(unsafe { &mut *self.ctx_mut_ptr() })
+    }
+
     pub(crate) fn ptr_eq(&self, other: &AxTaskRef) -> bool {
         Arc::ptr_eq(&self.0, other)
     }
@@ -551,10 +605,11 @@ extern "C" fn task_entry() -> ! {
         // Clear the prev task on CPU before running the task entry function.
         crate::run_queue::clear_prev_task_on_cpu();
     }
+    let task = crate::current();
+    task.task_ext_hooks().on_enter();
     // Enable irq (if feature "irq" is enabled) before running the task entry function.
     #[cfg(feature = "irq")]
     axhal::arch::enable_irqs();
-    let task = crate::current();
     if let Some(entry) = task.entry {
         unsafe { Box::from_raw(entry)() };
     }
@@ -15,6 +15,18 @@ static __AX_TASK_EXT_ALIGN: usize = 0;
 #[linkage = "weak"]
 fn __ax_task_ext_drop(_data: *mut u8) {}
 
+#[unsafe(no_mangle)]
+#[linkage = "weak"]
+fn __ax_task_ext_on_leave(_data: *mut u8) {}
+
+#[unsafe(no_mangle)]
+#[linkage = "weak"]
+fn __ax_task_ext_on_enter(_data: *mut u8) {}
+
+#[unsafe(no_mangle)]
+#[linkage = "weak"]
+fn __ax_task_ext_on_tick(_data: *mut u8) {}
+
 /// A wrapper of pointer to the task extended data.
 pub(crate) struct AxTaskExt {
     ptr: *mut u8,
@@ -69,6 +81,36 @@ impl AxTaskExt {
         self.ptr
     }
 
+    /// Call [`TaskExtHooks::on_leave`] if the data is initialized.
+    pub fn on_leave(&self) {
+        if !self.ptr.is_null() {
+            unsafe extern "C" {
+                fn __ax_task_ext_on_leave(data: *mut u8);
+            }
+            unsafe { __ax_task_ext_on_leave(self.ptr) };
+        }
+    }
+
+    /// Call [`TaskExtHooks::on_enter`] if the data is initialized.
+    pub fn on_enter(&self) {
+        if !self.ptr.is_null() {
+            unsafe extern "C" {
+                fn __ax_task_ext_on_enter(data: *mut u8);
+            }
+            unsafe { __ax_task_ext_on_enter(self.ptr) };
+        }
+    }
+
+    /// Call [`TaskExtHooks::on_tick`] if the data is initialized.
+    pub fn on_tick(&self) {
+        if !self.ptr.is_null() {
+            unsafe extern "C" {
+                fn __ax_task_ext_on_tick(data: *mut u8);
+            }
+            unsafe { __ax_task_ext_on_tick(self.ptr) };
+        }
+    }
+
     /// Write the given object to the task extended data.
     ///
     /// Returns [`None`] if the data size is zero, otherwise returns a mutable
@@ -135,6 +177,22 @@ pub trait TaskExtMut<T: Sized> {
     fn task_ext_mut(&mut self) -> &mut T;
 }
 
+/// Hooks of the task extended data, called by the scheduler with IRQs
+/// disabled on the CPU running the task.
+///
+/// They are enabled with [`def_task_ext_hooks!`].
+pub trait TaskExtHooks {
+    /// Called right before the task is switched out.
+    fn on_leave(&self) {}
+
+    /// Called right after the task is switched in, including when it first
+    /// runs.
+    fn on_enter(&self) {}
+
+    /// Called on each timer tick while the task is running.
+    fn on_tick(&self) {}
+}
+
 /// Define the task extended data.
 ///
 /// It automatically implements [`TaskExtRef`] and [`TaskExtMut`] for
@@ -199,3 +257,28 @@ macro_rules! def_task_ext {
         }
     };
 }
+
+/// Enable the [`TaskExtHooks`] of the task extended data defined with
+/// [`def_task_ext!`].
+#[macro_export]
+macro_rules! def_task_ext_hooks {
+    ($task_ext_struct:ty) => {
+        #[unsafe(no_mangle)]
+        fn __ax_task_ext_on_leave(data: *mut u8) {
+            let ext = unsafe { &*(data as *const $task_ext_struct) };
+            <$task_ext_struct as $crate::TaskExtHooks>::on_leave(ext);
+        }
+
+        #[unsafe(no_mangle)]
+        fn __ax_task_ext_on_enter(data: *mut u8) {
+            let ext = unsafe { &*(data as *const $task_ext_struct) };
+            <$task_ext_struct as $crate::TaskExtHooks>::on_enter(ext);
+        }
+
+        #[unsafe(no_mangle)]
+        fn __ax_task_ext_on_tick(data: *mut u8) {
+            let ext = unsafe { &*(data as *const $task_ext_struct) };
+            <$task_ext_struct as $crate::TaskExtHooks>::on_tick(ext);
+        }
+    };
+}
@@ -165,6 +165,109 @@ impl WaitQueue {
         timeout
     }
 
+    /// Blocks the current task and put it into the wait queue, until the given
+    /// `condition` becomes true, or the task is interrupted with
+    /// [`interrupt`](crate::interrupt).
+    ///
+    /// Returns `true` if it was interrupted before the condition became true.
+    pub fn wait_until_interruptible<F>(&self, condition: F) -> bool
+    where
+        F: Fn() -> bool,
+    {
+        let curr = crate::current();
+        curr.set_interruptible(true);
+        let mut interrupted = false;
+        loop {
+            let mut rq = current_run_queue::<NoPreemptIrqSave>();
+            let wq = self.queue.lock();
+            if condition() {
+                break;
+            }
+            if curr.take_interrupted() {
+                interrupted = true;
+                break;
+            }
+            rq.blocked_resched(wq);
+            // Preemption may occur here.
+        }
+        curr.set_interruptible(false);
+        self.cancel_events(curr, false);
+        interrupted
+    }
+
+    /// Blocks the current task and put it into the wait queue, until the given
+    /// `condition` becomes true, the given duration has elapsed, or the task
+    /// is interrupted with [`interrupt`](crate::interrupt).
+    ///
+    /// Returns `true` if the condition did not become true.
+    #[cfg(feature = "irq")]
+    pub fn wait_timeout_until_interruptible<F>(
+        &self,
+        dur: core::time::Duration,
+        condition: F,
+    ) -> bool
+    where
+        F: Fn() -> bool,
+    {
+        let curr = crate::current();
+        let deadline = axhal::time::wall_time() + dur;
+        crate::timers::set_alarm_wakeup(deadline, curr.clone());
+        curr.set_interruptible(true);
+
+        let mut failed = true;
+        loop {
+            let mut rq = current_run_queue::<NoPreemptIrqSave>();
+            if axhal::time::wall_time() >= deadline {
+                break;
+            }
+            let wq = self.queue.lock();
+            if condition() {
+                failed = false;
+                break;
+            }
+            if curr.take_interrupted() {
+                break;
+            }
+            rq.blocked_resched(wq);
+            // Preemption may occur here.
+        }
+        curr.set_interruptible(false);
+        // Always try to remove the task from the timer list.
+        self.cancel_events(curr, true);
+        failed
+    }
+
+    /// Blocks the current task and put it into the wait queue, until other tasks
+    /// notify it, the given duration has elapsed, or the task is interrupted
+    /// with [`interrupt`](crate::interrupt).
+    ///
+    /// Returns `true` if it was not notified.
+    #[cfg(feature = "irq")]
+    pub fn wait_timeout_interruptible(&self, dur: core::time::Duration) -> bool {
+        let mut rq = current_run_queue::<NoPreemptIrqSave>();
+        let curr = crate::current();
+        let deadline = axhal::time::wall_time() + dur;
+        crate::timers::set_alarm_wakeup(deadline, curr.clone());
+        curr.set_interruptible(true);
+
+        let wq = self.queue.lock();
+        let mut interrupted = curr.take_interrupted();
+        if !interrupted {
+            rq.blocked_resched(wq);
+            interrupted = curr.take_interrupted();
+        } else {
+            drop(wq);
+        }
+        curr.set_interruptible(false);
+
+        // Still in the wait queue if it was not notified.
+        let timeout = interrupted || curr.in_wait_queue();
+
+        // Always try to remove the task from the timer list.
+        self.cancel_events(curr, true);
+        timeout
+    }
+
     /// Wakes up one task in the wait queue, usually the first one.
     ///
     /// If `resched` is true, the current task will be preempted when the
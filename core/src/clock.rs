@@ -0,0 +1,206 @@
+//! The clocks of `clock_gettime(2)`, including the CPU-time clocks of
+//! processes and threads.
+
+use alloc::sync::Arc;
+use core::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use axerrno::{LinuxError, LinuxResult};
+use axhal::time::{NANOS_PER_SEC, TimeValue, monotonic_time};
+use axprocess::{Pid, Process, Thread};
+use axtask::{TaskExtRef, current};
+use linux_raw_sys::general::{
+    CLOCK_BOOTTIME, CLOCK_MONOTONIC, CLOCK_MONOTONIC_COARSE, CLOCK_MONOTONIC_RAW,
+    CLOCK_PROCESS_CPUTIME_ID, CLOCK_REALTIME, CLOCK_REALTIME_COARSE, CLOCK_TAI,
+    CLOCK_THREAD_CPUTIME_ID,
+};
+
+use crate::{
+    realtime::{realtime, tai_time},
+    task::{ProcessData, ThreadData, get_process, get_thread, time_stat_update},
+};
+
+/// The CPU time spent by a thread, or by all the threads of a process, as
+/// accounted by their [`TimeStat`]s.
+#[derive(Default)]
+pub struct CpuTime {
+    user_ns: AtomicU64,
+    system_ns: AtomicU64,
+}
+
+impl CpuTime {
+    /// Add `user_ns` and `system_ns` nanoseconds of user and system time.
+    pub fn add(&self, user_ns: u64, system_ns: u64) {
+        self.user_ns.fetch_add(user_ns, Ordering::Relaxed);
+        self.system_ns.fetch_add(system_ns, Ordering::Relaxed);
+    }
+
+    /// The user time.
+    pub fn user(&self) -> Duration {
+        Duration::from_nanos(self.user_ns.load(Ordering::Relaxed))
+    }
+
+    /// The system time.
+    pub fn system(&self) -> Duration {
+        Duration::from_nanos(self.system_ns.load(Ordering::Relaxed))
+    }
+}
+
+/// The time a CPU-time clock measures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CpuClockKind {
+    /// The user and system time, `CPUCLOCK_PROF`
+    Prof,
+    /// The user time, `CPUCLOCK_VIRT`
+    Virt,
+    /// The time spent running, `CPUCLOCK_SCHED`
+    Sched,
+}
+
+/// Marks the clock IDs of CPU-time clocks of threads, as encoded by
+/// `clock_getcpuclockid(3)` and `pthread_getcpuclockid(3)`.
+const CPUCLOCK_PERTHREAD_MASK: i32 = 4;
+/// The bits of the kind of a CPU-time clock in its ID.
+const CPUCLOCK_CLOCK_MASK: i32 = 3;
+
+/// A clock, as identified by a clock ID of `clock_gettime(2)`.
+#[derive(Clone)]
+pub enum Clock {
+    /// The wall-clock time, `CLOCK_REALTIME`
+    Realtime {
+        /// Whether the clock only advances by scheduler ticks
+        coarse: bool,
+    },
+    /// The International Atomic Time, `CLOCK_TAI`
+    Tai,
+    /// The time since boot, for `CLOCK_MONOTONIC`, `CLOCK_MONOTONIC_RAW` and
+    /// `CLOCK_BOOTTIME`, as the system is never suspended
+    Monotonic {
+        /// Whether the clock only advances by scheduler ticks
+        coarse: bool,
+    },
+    /// The CPU time of all the threads of a process
+    ProcessCpu(Arc<Process>, CpuClockKind),
+    /// The CPU time of a thread
+    ThreadCpu(Arc<Thread>, CpuClockKind),
+}
+
+impl Clock {
+    /// The clock of the ID `clock_id`, in which the negative IDs refer to the
+    /// CPU-time clocks of processes and threads.
+    pub fn from_id(clock_id: i32) -> LinuxResult<Self> {
+        if clock_id < 0 {
+            return Self::from_cpu_clock_id(clock_id);
+        }
+        let curr = current();
+        Ok(match clock_id as u32 {
+            CLOCK_REALTIME => Self::Realtime { coarse: false },
+            CLOCK_TAI => Self::Tai,
+            CLOCK_REALTIME_COARSE => Self::Realtime { coarse: true },
+            CLOCK_MONOTONIC | CLOCK_MONOTONIC_RAW | CLOCK_BOOTTIME => {
+                Self::Monotonic { coarse: false }
+            }
+            CLOCK_MONOTONIC_COARSE => Self::Monotonic { coarse: true },
+            CLOCK_PROCESS_CPUTIME_ID => Self::ProcessCpu(
+                curr.task_ext().thread.process().clone(),
+                CpuClockKind::Sched,
+            ),
+            CLOCK_THREAD_CPUTIME_ID => {
+                Self::ThreadCpu(curr.task_ext().thread.clone(), CpuClockKind::Sched)
+            }
+            _ => return Err(LinuxError::EINVAL),
+        })
+    }
+
+    fn from_cpu_clock_id(clock_id: i32) -> LinuxResult<Self> {
+        let kind = match clock_id & CPUCLOCK_CLOCK_MASK {
+            0 => CpuClockKind::Prof,
+            1 => CpuClockKind::Virt,
+            2 => CpuClockKind::Sched,
+            _ => return Err(LinuxError::EINVAL),
+        };
+        let pid = !(clock_id >> 3) as Pid;
+        let curr = current();
+        let curr_thread = &curr.task_ext().thread;
+        if clock_id & CPUCLOCK_PERTHREAD_MASK != 0 {
+            let thread = if pid == 0 {
+                curr_thread.clone()
+            } else {
+                get_thread(pid).map_err(|_| LinuxError::EINVAL)?
+            };
+            // Like Linux, only the clocks of the threads of the same process
+            // can be read.
+            if !Arc::ptr_eq(thread.process(), curr_thread.process()) {
+                return Err(LinuxError::EINVAL);
+            }
+            Ok(Self::ThreadCpu(thread, kind))
+        } else {
+            let process = if pid == 0 {
+                curr_thread.process().clone()
+            } else {
+                get_process(pid).map_err(|_| LinuxError::EINVAL)?
+            };
+            Ok(Self::ProcessCpu(process, kind))
+        }
+    }
+
+    /// The current time of the clock.
+    pub fn now(&self) -> TimeValue {
+        match self {
+            Self::Realtime { coarse } => truncate_to_tick(realtime(), *coarse),
+            Self::Tai => tai_time(),
+            Self::Monotonic { coarse } => truncate_to_tick(monotonic_time(), *coarse),
+            Self::ProcessCpu(process, kind) => {
+                if Arc::ptr_eq(process, current().task_ext().thread.process()) {
+                    time_stat_update();
+                }
+                process
+                    .data::<ProcessData>()
+                    .map_or(Duration::ZERO, |data| cpu_clock_time(&data.cpu_time, *kind))
+            }
+            Self::ThreadCpu(thread, kind) => {
+                if Arc::ptr_eq(thread, &current().task_ext().thread) {
+                    time_stat_update();
+                }
+                thread
+                    .data::<ThreadData>()
+                    .map_or(Duration::ZERO, |data| cpu_clock_time(&data.cpu_time, *kind))
+            }
+        }
+    }
+
+    /// The resolution of the clock.
+    pub fn resolution(&self) -> Duration {
+        match self {
+            Self::Realtime { coarse: true } | Self::Monotonic { coarse: true } => tick(),
+            _ => Duration::from_nanos(1),
+        }
+    }
+
+    /// Whether the clock is the CPU-time clock of the current thread.
+    pub fn is_current_thread_cpu(&self) -> bool {
+        matches!(self, Self::ThreadCpu(thread, _) if Arc::ptr_eq(thread, &current().task_ext().thread))
+    }
+}
+
+pub(crate) fn cpu_clock_time(cpu_time: &CpuTime, kind: CpuClockKind) -> Duration {
+    match kind {
+        CpuClockKind::Virt => cpu_time.user(),
+        CpuClockKind::Prof | CpuClockKind::Sched => cpu_time.user() + cpu_time.system(),
+    }
+}
+
+/// The period of the scheduler ticks.
+fn tick() -> Duration {
+    Duration::from_nanos(NANOS_PER_SEC / axconfig::TICKS_PER_SEC as u64)
+}
+
+fn truncate_to_tick(time: TimeValue, coarse: bool) -> TimeValue {
+    if !coarse {
+        return time;
+    }
+    let tick = tick().as_nanos();
+    Duration::from_nanos((time.as_nanos() - time.as_nanos() % tick) as u64)
+}
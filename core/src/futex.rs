@@ -1,22 +1,47 @@
 //! Futex implementation.
 
-use core::ops::Deref;
+use core::{
+    ops::Deref,
+    sync::atomic::{AtomicU64, Ordering},
+};
 
 use alloc::{collections::btree_map::BTreeMap, sync::Arc};
 use axsync::Mutex;
 use axtask::{TaskExtRef, WaitQueue, current};
 
 /// A table mapping memory addresses to futex wait queues.
-pub struct FutexTable(Mutex<BTreeMap<usize, Arc<WaitQueue>>>);
+pub struct FutexTable {
+    queues: Mutex<BTreeMap<usize, Arc<WaitQueue>>>,
+    /// The number of times futexes of the table were woken up
+    wakes: AtomicU64,
+}
 impl FutexTable {
     /// Creates a new `FutexTable`.
     pub fn new() -> Self {
-        Self(Mutex::new(BTreeMap::new()))
+        Self {
+            queues: Mutex::new(BTreeMap::new()),
+            wakes: AtomicU64::new(0),
+        }
+    }
+
+    /// The number of times futexes of the table were woken up.
+    ///
+    /// A waiter reads it before checking the value of the futex, and stops
+    /// waiting once it changes, so that a wakeup happening before it is put
+    /// in the wait queue is not missed.
+    pub fn wakes(&self) -> u64 {
+        self.wakes.load(Ordering::Acquire)
+    }
+
+    /// Record a wakeup of futexes of the table, before notifying their wait
+    /// queues.
+    pub fn add_wake(&self) {
+        self.wakes.fetch_add(1, Ordering::AcqRel);
     }
 
     /// Gets the wait queue associated with the given address.
     pub fn get(&self, addr: usize) -> Option<WaitQueueGuard> {
-        let wq = self.0.lock().get(&addr).cloned()?;
+        let wq = self.queues.lock().get(&addr).cloned()?;
         Some(WaitQueueGuard {
             key: addr,
             inner: wq,
@@ -26,7 +51,7 @@ impl FutexTable {
     /// Gets the wait queue associated with the given address, or inserts a a
     /// new one if it doesn't exist.
     pub fn get_or_insert(&self, addr: usize) -> WaitQueueGuard {
-        let mut table = self.0.lock();
+        let mut table = self.queues.lock();
         let wq = table
             .entry(addr)
             .or_insert_with(|| Arc::new(WaitQueue::new()));
@@ -52,7 +77,7 @@ impl Deref for WaitQueueGuard {
 impl Drop for WaitQueueGuard {
     fn drop(&mut self) {
         let curr = current();
-        let mut table = curr.task_ext().process_data().futex_table.0.lock();
+        let mut table = curr.task_ext().process_data().futex_table.queues.lock();
         if Arc::strong_count(&self.inner) == 1 && self.inner.is_empty() {
             table.remove(&self.key);
         }
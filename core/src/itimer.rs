@@ -0,0 +1,243 @@
+//! Interval timers of processes, see `setitimer(2)`.
+//!
+//! `ITIMER_REAL` counts down in real time, with a kernel task of the process
+//! waiting for its expiration. `ITIMER_VIRTUAL` and `ITIMER_PROF` count down
+//! in the user time and in the user and system time of the process, as
+//! accounted at timer ticks, context switches and kernel entries and exits of
+//! its threads.
+
+use alloc::{string::ToString, sync::Weak};
+use core::{
+    sync::atomic::{AtomicU8, Ordering},
+    time::Duration,
+};
+
+use axhal::time::{TimeValue, monotonic_time};
+use axprocess::Process;
+use axsignal::{SignalInfo, Signo};
+use axsync::spin::SpinNoIrq;
+use axtask::WaitQueue;
+use linux_raw_sys::general::{ITIMER_PROF, ITIMER_REAL, ITIMER_VIRTUAL, SI_KERNEL};
+use spin::Mutex;
+
+use crate::task::{ProcessData, interrupt_threads};
+
+/// The setting of an interval timer.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ITimer {
+    /// The period of the timer after it first expires, or zero for a
+    /// one-shot timer
+    pub interval: Duration,
+    /// The time until the timer expires, or zero if it is disarmed
+    pub value: Duration,
+}
+
+impl ITimer {
+    /// Count down `delta`, reloading the interval if the timer expires.
+    ///
+    /// Returns whether the timer expired.
+    fn count_down(&mut self, delta: Duration) -> bool {
+        if self.value.is_zero() {
+            return false;
+        }
+        if self.value > delta {
+            self.value -= delta;
+            return false;
+        }
+        self.value = if self.interval.is_zero() {
+            Duration::ZERO
+        } else {
+            let overrun = (delta - self.value).as_nanos() % self.interval.as_nanos();
+            self.interval - Duration::from_nanos(overrun as u64)
+        };
+        true
+    }
+}
+
+struct RealTimer {
+    interval: Duration,
+    deadline: Option<TimeValue>,
+    /// Incremented each time the timer is set, so that the task waiting for
+    /// the previous deadline wakes up
+    generation: u64,
+    /// Whether the task waiting for the deadlines was spawned
+    task_spawned: bool,
+    /// Whether the process exited, so that the task stops
+    exited: bool,
+}
+
+const VIRT_EXPIRED: u8 = 1 << 0;
+const PROF_EXPIRED: u8 = 1 << 1;
+
+/// The interval timers of a process.
+pub struct ITimers {
+    real: Mutex<RealTimer>,
+    real_wq: WaitQueue,
+    virt: SpinNoIrq<ITimer>,
+    prof: SpinNoIrq<ITimer>,
+    /// The CPU time timers which expired and whose signals are not sent yet
+    expired: AtomicU8,
+}
+
+impl Default for ITimers {
+    fn default() -> Self {
+        Self {
+            real: Mutex::new(RealTimer {
+                interval: Duration::ZERO,
+                deadline: None,
+                generation: 0,
+                task_spawned: false,
+                exited: false,
+            }),
+            real_wq: WaitQueue::new(),
+            virt: SpinNoIrq::new(ITimer::default()),
+            prof: SpinNoIrq::new(ITimer::default()),
+            expired: AtomicU8::new(0),
+        }
+    }
+}
+
+impl ITimers {
+    /// Get the timer `which`, one of the `ITIMER_*` values.
+    ///
+    /// Returns `None` if `which` is not valid.
+    pub fn get(&self, which: u32) -> Option<ITimer> {
+        match which {
+            ITIMER_REAL => {
+                let real = self.real.lock();
+                Some(ITimer {
+                    interval: real.interval,
+                    value: real.deadline.map_or(Duration::ZERO, |deadline| {
+                        // An expired timer not yet reloaded is shown as
+                        // almost expired rather than disarmed.
+                        deadline
+                            .saturating_sub(monotonic_time())
+                            .max(Duration::from_nanos(1))
+                    }),
+                })
+            }
+            ITIMER_VIRTUAL => Some(*self.virt.lock()),
+            ITIMER_PROF => Some(*self.prof.lock()),
+            _ => None,
+        }
+    }
+
+    /// Set the timer `which` of `process`, whose timers these are.
+    ///
+    /// Returns the previous setting, or `None` if `which` is not valid.
+    pub fn set(&self, process: Weak<Process>, which: u32, timer: ITimer) -> Option<ITimer> {
+        let old = self.get(which)?;
+        match which {
+            ITIMER_REAL => {
+                let mut real = self.real.lock();
+                real.generation += 1;
+                real.interval = timer.interval;
+                real.deadline = (!timer.value.is_zero()).then(|| monotonic_time() + timer.value);
+                let spawn = real.deadline.is_some() && !real.task_spawned && !real.exited;
+                real.task_spawned |= spawn;
+                drop(real);
+
+                self.real_wq.notify_all(false);
+                if spawn {
+                    axtask::spawn_raw(
+                        move || run_real_timer(process),
+                        "itimer".to_string(),
+                        axconfig::TASK_STACK_SIZE,
+                    );
+                }
+            }
+            ITIMER_VIRTUAL => *self.virt.lock() = timer,
+            ITIMER_PROF => *self.prof.lock() = timer,
+            _ => unreachable!(),
+        }
+        Some(old)
+    }
+
+    /// Disarm `ITIMER_REAL` and stop its task, when the process exits.
+    pub fn cancel_real(&self) {
+        let mut real = self.real.lock();
+        real.generation += 1;
+        real.deadline = None;
+        real.exited = true;
+        drop(real);
+        self.real_wq.notify_all(false);
+    }
+
+    /// Count down the CPU time timers by the `user` and `system` time spent
+    /// by a thread of the process.
+    ///
+    /// This may run in IRQ context, so the timers which expired are only
+    /// recorded, see [`ITimers::take_expired`].
+    pub fn account(&self, user: Duration, system: Duration) {
+        let mut expired = 0;
+        if self.virt.lock().count_down(user) {
+            expired |= VIRT_EXPIRED;
+        }
+        if self.prof.lock().count_down(user + system) {
+            expired |= PROF_EXPIRED;
+        }
+        if expired != 0 {
+            self.expired.fetch_or(expired, Ordering::AcqRel);
+        }
+    }
+
+    /// Take the signals to send for the CPU time timers which expired since
+    /// the last call.
+    pub fn take_expired(&self) -> impl Iterator<Item = Signo> {
+        let expired = self.expired.swap(0, Ordering::AcqRel);
+        [
+            (VIRT_EXPIRED, Signo::SIGVTALRM),
+            (PROF_EXPIRED, Signo::SIGPROF),
+        ]
+        .into_iter()
+        .filter_map(move |(bit, signo)| (expired & bit != 0).then_some(signo))
+    }
+}
+
+/// Wait for the deadlines of `ITIMER_REAL` of `process` and send `SIGALRM`,
+/// until the process exits.
+fn run_real_timer(process: Weak<Process>) {
+    let Some(process) = process.upgrade() else {
+        return;
+    };
+    let Some(data) = process.data::<ProcessData>() else {
+        return;
+    };
+    let timers = &data.itimers;
+    loop {
+        let (deadline, generation) = {
+            let real = timers.real.lock();
+            if real.exited {
+                return;
+            }
+            (real.deadline, real.generation)
+        };
+        let changed = || timers.real.lock().generation != generation;
+
+        let Some(deadline) = deadline else {
+            timers.real_wq.wait_until(changed);
+            continue;
+        };
+        let now = monotonic_time();
+        if now < deadline {
+            timers.real_wq.wait_timeout_until(deadline - now, changed);
+            continue;
+        }
+
+        let mut real = timers.real.lock();
+        if real.generation != generation {
+            continue;
+        }
+        real.deadline = if real.interval.is_zero() {
+            None
+        } else {
+            let missed = (now - deadline).as_nanos() / real.interval.as_nanos();
+            Some(deadline + real.interval * (missed as u32 + 1))
+        };
+        drop(real);
+
+        data.signal
+            .send_signal(SignalInfo::new(Signo::SIGALRM, SI_KERNEL as _));
+        interrupt_threads(&process);
+    }
+}
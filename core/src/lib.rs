@@ -1,14 +1,22 @@
 //! The core functionality of a monolithic kernel, including loading user
 //! programs and managing processes.
 
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 #![warn(missing_docs)]
 
 #[macro_use]
 extern crate axlog;
 extern crate alloc;
 
+pub mod clock;
 pub mod futex;
+pub mod itimer;
 pub mod mm;
+pub mod posix_timer;
+pub mod ptrace;
+pub mod realtime;
+pub mod resource;
+pub mod seccomp;
 pub mod task;
+pub mod trace;
 mod time;
@@ -0,0 +1,502 @@
+//! POSIX per-process timers, see `timer_create(2)`.
+//!
+//! The timers of a process share a kernel task, which waits for the earliest
+//! expiration on the timer list of `axtask` and then queues the signals of the
+//! timers which expired. The task is spawned with the first timer, lives as
+//! long as the process, and is woken up whenever a timer is set or deleted.
+//!
+//! The timers of the CPU-time clocks cannot be waited for in real time, so
+//! the task waits for the least real time in which they may expire, and
+//! checks them again.
+
+use alloc::{collections::BTreeMap, string::ToString, sync::Arc, sync::Weak, vec::Vec};
+use core::time::Duration;
+
+use axerrno::{LinuxError, LinuxResult};
+use axhal::time::{NANOS_PER_SEC, TimeValue, monotonic_time};
+use axprocess::{Pid, Process, Thread};
+use axsignal::{SignalInfo, Signo};
+use axtask::WaitQueue;
+use linux_raw_sys::general::{__sifields__bindgen_ty_2, SI_TIMER, sigval};
+use spin::Mutex;
+
+use crate::{
+    clock::{Clock, CpuClockKind, cpu_clock_time},
+    itimer::ITimer,
+    realtime::{realtime, tai_time},
+    task::{ProcessData, ThreadData, interrupt_threads, processes},
+};
+
+/// The maximum overrun count of a timer, `DELAYTIMER_MAX`.
+pub const DELAYTIMER_MAX: u32 = i32::MAX as u32;
+
+/// The maximum number of timers of a process.
+pub const TIMER_MAX: usize = 4096;
+
+/// The clock a timer measures.
+enum TimerClock {
+    /// `CLOCK_REALTIME`
+    Realtime,
+    /// `CLOCK_TAI`
+    Tai,
+    /// `CLOCK_MONOTONIC` and `CLOCK_BOOTTIME`
+    Monotonic,
+    /// The CPU time of all the threads of a process
+    ProcessCpu(Weak<Process>, CpuClockKind),
+    /// The CPU time of a thread
+    ThreadCpu(Weak<Thread>, CpuClockKind),
+}
+
+impl TimerClock {
+    fn new(clock: Clock) -> Option<Self> {
+        Some(match clock {
+            Clock::Realtime { coarse: false } => Self::Realtime,
+            Clock::Tai => Self::Tai,
+            Clock::Monotonic { coarse: false } => Self::Monotonic,
+            Clock::ProcessCpu(process, kind) => Self::ProcessCpu(Arc::downgrade(&process), kind),
+            Clock::ThreadCpu(thread, kind) => Self::ThreadCpu(Arc::downgrade(&thread), kind),
+            _ => return None,
+        })
+    }
+
+    /// The time the deadlines of the timers of the clock are measured in:
+    /// the monotonic time for the wall clocks, and the CPU time for the
+    /// CPU-time clocks.
+    ///
+    /// The CPU time of an exited process or thread no longer advances.
+    fn base_now(&self) -> TimeValue {
+        match self {
+            Self::Realtime | Self::Tai | Self::Monotonic => monotonic_time(),
+            Self::ProcessCpu(process, kind) => process.upgrade().map_or(Duration::ZERO, |it| {
+                it.data::<ProcessData>()
+                    .map_or(Duration::ZERO, |data| cpu_clock_time(&data.cpu_time, *kind))
+            }),
+            Self::ThreadCpu(thread, kind) => thread.upgrade().map_or(Duration::ZERO, |it| {
+                it.data::<ThreadData>()
+                    .map_or(Duration::ZERO, |data| cpu_clock_time(&data.cpu_time, *kind))
+            }),
+        }
+    }
+
+    /// The current time of the clock, which absolute times are given in.
+    fn now(&self) -> TimeValue {
+        match self {
+            Self::Realtime => realtime(),
+            Self::Tai => tai_time(),
+            _ => self.base_now(),
+        }
+    }
+
+    /// The real time to wait before the clock may have advanced by `delta`.
+    fn real_time_for(&self, delta: Duration) -> Duration {
+        // The CPU time is accounted at timer ticks, so there is no need to
+        // check it more often.
+        let tick = Duration::from_nanos(NANOS_PER_SEC / axconfig::TICKS_PER_SEC as u64);
+        match self {
+            // All the threads of the process may run at once.
+            Self::ProcessCpu(..) => (delta / axconfig::SMP as u32).max(tick),
+            Self::ThreadCpu(..) => delta.max(tick),
+            _ => delta,
+        }
+    }
+}
+
+/// How the expiration of a timer is notified.
+#[derive(Debug, Clone, Copy)]
+pub enum TimerNotify {
+    /// Not notified, the timer can only be polled with `timer_gettime`
+    None,
+    /// Notified by sending a signal
+    Signal {
+        /// The signal to send
+        signo: Signo,
+        /// The `si_value` of the signal, or the ID of the timer if `None`
+        value: Option<usize>,
+        /// The thread to send the signal to, or `None` for the process
+        tid: Option<Pid>,
+    },
+}
+
+struct TimerState {
+    interval: Duration,
+    /// The time at which the timer expires, if it is armed, see
+    /// [`TimerClock::base_now`]
+    deadline: Option<TimeValue>,
+    /// Whether the timer was armed with an absolute time of its clock
+    absolute: bool,
+    /// The number of expirations not notified since the last signal
+    overrun: u32,
+}
+
+/// A POSIX timer.
+pub struct PosixTimer {
+    id: i32,
+    clock: TimerClock,
+    notify: TimerNotify,
+    state: Mutex<TimerState>,
+}
+
+impl PosixTimer {
+    /// The current setting of the timer, with the time until it expires.
+    pub fn get(&self) -> ITimer {
+        let state = self.state.lock();
+        ITimer {
+            interval: state.interval,
+            value: state.deadline.map_or(Duration::ZERO, |deadline| {
+                // An expired timer not yet reloaded is shown as almost
+                // expired rather than disarmed.
+                deadline
+                    .saturating_sub(self.clock.base_now())
+                    .max(Duration::from_nanos(1))
+            }),
+        }
+    }
+
+    /// Arm the timer with `timer`, or disarm it if its value is zero.
+    ///
+    /// If `absolute` is set, the value is the time of the clock of the timer
+    /// at which it expires, rather than the time until then.
+    ///
+    /// Returns the previous setting.
+    fn set(&self, timer: ITimer, absolute: bool) -> ITimer {
+        let old = self.get();
+        let now = self.clock.base_now();
+        let value = if absolute {
+            // Expire at once if the time has already passed.
+            timer
+                .value
+                .saturating_sub(self.clock.now())
+                .max(Duration::from_nanos(1))
+        } else {
+            timer.value
+        };
+
+        let mut state = self.state.lock();
+        state.interval = timer.interval;
+        state.deadline = (!timer.value.is_zero()).then(|| now + value);
+        state.absolute = absolute;
+        state.overrun = 0;
+        old
+    }
+
+    /// The number of expirations which were not notified, as the signal of
+    /// the timer was still queued, since it was last queued.
+    pub fn overrun(&self) -> u32 {
+        self.state.lock().overrun
+    }
+
+    /// Reload the timer if it expired.
+    ///
+    /// Returns the number of expirations since it was last checked, and the
+    /// real time to wait before checking it again, if it is armed.
+    fn check(&self) -> (u32, Option<Duration>) {
+        let now = self.clock.base_now();
+        let mut state = self.state.lock();
+        let Some(deadline) = state.deadline else {
+            return (0, None);
+        };
+        if now < deadline {
+            return (0, Some(self.clock.real_time_for(deadline - now)));
+        }
+        // The expirations missed while the task was not running count too.
+        let mut count = 1;
+        state.deadline = if state.interval.is_zero() {
+            None
+        } else {
+            let missed = (now - deadline).as_nanos() / state.interval.as_nanos();
+            count += missed.min(DELAYTIMER_MAX as u128 - 1) as u32;
+            Some(deadline + state.interval * count)
+        };
+        let wait = state
+            .deadline
+            .map(|deadline| self.clock.real_time_for(deadline - now));
+        (count, wait)
+    }
+
+    /// Notify the expiration of the timer, which expired `count` times since
+    /// it was last notified.
+    fn notify(&self, process: &Process, data: &ProcessData, count: u32) {
+        let TimerNotify::Signal { signo, value, tid } = self.notify else {
+            self.add_overrun(count - 1);
+            return;
+        };
+        let thread = tid.and_then(|tid| {
+            process
+                .threads()
+                .into_iter()
+                .find(|thread| thread.tid() == tid)
+        });
+        let thr_data = thread.as_ref().and_then(|it| it.data::<ThreadData>());
+
+        // Like Linux, only one signal per timer is queued at a time, and
+        // further expirations are counted as overruns of the queued signal.
+        let mut update_queued = |sig: &mut SignalInfo| {
+            if sig.code() != SI_TIMER {
+                return false;
+            }
+            // SAFETY: `_timer` is the member used with `SI_TIMER`.
+            let timer = unsafe { &mut sig.0.__bindgen_anon_1.__bindgen_anon_1._sifields._timer };
+            if timer._tid != self.id {
+                return false;
+            }
+            timer._overrun = self.add_overrun(count) as _;
+            true
+        };
+        let queued = match thr_data {
+            Some(thr_data) => thr_data
+                .signal
+                .update_pending_signal(signo, &mut update_queued),
+            None => data.signal.update_pending_signal(signo, &mut update_queued),
+        };
+        if queued {
+            return;
+        }
+
+        let overrun = {
+            let mut state = self.state.lock();
+            state.overrun = count - 1;
+            state.overrun
+        };
+        let mut sig = SignalInfo::new(signo, SI_TIMER);
+        sig.0.__bindgen_anon_1.__bindgen_anon_1._sifields._timer = __sifields__bindgen_ty_2 {
+            _tid: self.id,
+            _overrun: overrun as _,
+            _sigval: sigval {
+                sival_ptr: value.unwrap_or(self.id as _) as _,
+            },
+            _sys_private: 0,
+        };
+        match thr_data {
+            Some(thr_data) => {
+                thr_data.signal.send_signal(sig);
+                thr_data.interrupt();
+            }
+            None => {
+                data.signal.send_signal(sig);
+                interrupt_threads(process);
+            }
+        }
+    }
+
+    /// Move the expiration of the timer after its clock was stepped by
+    /// `delta_ns` nanoseconds, if it was armed with an absolute time of a
+    /// settable clock.
+    ///
+    /// Returns whether the expiration was moved.
+    fn clock_was_set(&self, delta_ns: i64) -> bool {
+        if !matches!(self.clock, TimerClock::Realtime | TimerClock::Tai) {
+            return false;
+        }
+        let mut state = self.state.lock();
+        if !state.absolute {
+            return false;
+        }
+        let Some(deadline) = state.deadline else {
+            return false;
+        };
+        let delta = Duration::from_nanos(delta_ns.unsigned_abs());
+        // Expire at once if the time has already passed.
+        state.deadline = Some(if delta_ns > 0 {
+            deadline.saturating_sub(delta).max(Duration::from_nanos(1))
+        } else {
+            deadline + delta
+        });
+        true
+    }
+
+    /// Returns the new overrun count.
+    fn add_overrun(&self, count: u32) -> u32 {
+        let mut state = self.state.lock();
+        state.overrun = state.overrun.saturating_add(count).min(DELAYTIMER_MAX);
+        state.overrun
+    }
+}
+
+struct TimersTask {
+    /// Incremented each time a timer is set or deleted, so that the task
+    /// waiting for the previous deadlines wakes up
+    generation: u64,
+    /// Whether the task waiting for the deadlines was spawned
+    spawned: bool,
+    /// Whether the process exited, so that the task stops
+    exited: bool,
+}
+
+/// The POSIX timers of a process, by ID.
+pub struct PosixTimers {
+    timers: Mutex<BTreeMap<i32, Arc<PosixTimer>>>,
+    next_id: Mutex<i32>,
+    task: Mutex<TimersTask>,
+    wq: WaitQueue,
+}
+
+impl Default for PosixTimers {
+    fn default() -> Self {
+        Self {
+            timers: Mutex::new(BTreeMap::new()),
+            next_id: Mutex::new(0),
+            task: Mutex::new(TimersTask {
+                generation: 0,
+                spawned: false,
+                exited: false,
+            }),
+            wq: WaitQueue::new(),
+        }
+    }
+}
+
+impl PosixTimers {
+    /// Create a disarmed timer of `process`, whose timers these are,
+    /// measuring `clock` and notified with `notify`.
+    ///
+    /// Returns the ID of the new timer, or `EINVAL` if timers cannot measure
+    /// `clock`, or `EAGAIN` if the process has too many timers.
+    pub fn create(
+        &self,
+        process: Weak<Process>,
+        clock: Clock,
+        notify: TimerNotify,
+    ) -> LinuxResult<i32> {
+        let clock = TimerClock::new(clock).ok_or(LinuxError::EINVAL)?;
+        let mut timers = self.timers.lock();
+        if timers.len() >= TIMER_MAX {
+            return Err(LinuxError::EAGAIN);
+        }
+        let mut next_id = self.next_id.lock();
+        let id = loop {
+            let id = *next_id;
+            *next_id = id.checked_add(1).unwrap_or(0);
+            if !timers.contains_key(&id) {
+                break id;
+            }
+        };
+        timers.insert(
+            id,
+            Arc::new(PosixTimer {
+                id,
+                clock,
+                notify,
+                state: Mutex::new(TimerState {
+                    interval: Duration::ZERO,
+                    deadline: None,
+                    absolute: false,
+                    overrun: 0,
+                }),
+            }),
+        );
+        drop(next_id);
+        drop(timers);
+
+        let mut task = self.task.lock();
+        let spawn = !task.spawned && !task.exited;
+        task.spawned |= spawn;
+        drop(task);
+        if spawn {
+            axtask::spawn_raw(
+                move || run_timers(process),
+                "posix-timers".to_string(),
+                axconfig::TASK_STACK_SIZE,
+            );
+        }
+        Ok(id)
+    }
+
+    /// Get the timer `id`.
+    pub fn get(&self, id: i32) -> Option<Arc<PosixTimer>> {
+        self.timers.lock().get(&id).cloned()
+    }
+
+    /// Set the timer `id`, see [`PosixTimer::set`].
+    ///
+    /// Returns the previous setting, or `None` if the timer does not exist.
+    pub fn set(&self, id: i32, timer: ITimer, absolute: bool) -> Option<ITimer> {
+        let old = self.get(id)?.set(timer, absolute);
+        self.wake_task();
+        Some(old)
+    }
+
+    /// Delete the timer `id`, returning whether it existed.
+    pub fn delete(&self, id: i32) -> bool {
+        if self.timers.lock().remove(&id).is_none() {
+            return false;
+        }
+        self.wake_task();
+        true
+    }
+
+    /// Delete all the timers, e.g. when the process calls `execve`.
+    pub fn clear(&self) {
+        self.timers.lock().clear();
+        self.wake_task();
+    }
+
+    /// Delete all the timers and stop their task, when the process exits.
+    pub fn stop(&self) {
+        self.timers.lock().clear();
+        self.task.lock().exited = true;
+        self.wake_task();
+    }
+
+    fn wake_task(&self) {
+        self.task.lock().generation += 1;
+        self.wq.notify_all(false);
+    }
+}
+
+/// Re-arm the timers armed with an absolute time of the realtime clocks,
+/// which were stepped by `delta_ns` nanoseconds, so that they expire at the
+/// time they were set to.
+pub fn clock_was_set(delta_ns: i64) {
+    for process in processes() {
+        let Some(data) = process.data::<ProcessData>() else {
+            continue;
+        };
+        let mut moved = false;
+        for timer in data.timers.timers.lock().values() {
+            moved |= timer.clock_was_set(delta_ns);
+        }
+        if moved {
+            data.timers.wake_task();
+        }
+    }
+}
+
+/// Wait for the expirations of the timers of `process` and notify them,
+/// until the process exits.
+fn run_timers(process: Weak<Process>) {
+    loop {
+        let Some(process) = process.upgrade().filter(|it| !it.is_zombie()) else {
+            return;
+        };
+        let Some(data) = process.data::<ProcessData>() else {
+            return;
+        };
+        let timers = &data.timers;
+        let generation = {
+            let task = timers.task.lock();
+            if task.exited {
+                return;
+            }
+            task.generation
+        };
+
+        let mut wait: Option<Duration> = None;
+        let armed: Vec<_> = timers.timers.lock().values().cloned().collect();
+        for timer in armed {
+            let (count, next) = timer.check();
+            if count > 0 {
+                timer.notify(&process, data, count);
+            }
+            if let Some(next) = next {
+                wait = Some(wait.map_or(next, |wait| wait.min(next)));
+            }
+        }
+        let changed = || timers.task.lock().generation != generation;
+        match wait {
+            None => timers.wq.wait_until(changed),
+            Some(wait) => {
+                timers.wq.wait_timeout_until(wait, changed);
+            }
+        }
+    }
+}
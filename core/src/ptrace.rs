@@ -0,0 +1,145 @@
+//! Tracing of threads by a debugger, see `ptrace(2)`.
+
+use alloc::sync::{Arc, Weak};
+
+use axhal::arch::TrapFrame;
+use axprocess::Process;
+use axsignal::{SignalInfo, Signo};
+use axtask::WaitQueue;
+use spin::{Mutex, MutexGuard};
+
+/// Why a traced thread is stopped.
+#[derive(Clone)]
+pub enum PtraceStop {
+    /// The thread is about to deliver the signal, which the tracer may
+    /// suppress or replace.
+    Signal(SignalInfo),
+    /// The thread is entering a system call.
+    SyscallEntry,
+    /// The thread is leaving a system call.
+    SyscallExit,
+    /// The thread hit the given `PTRACE_EVENT_*` event.
+    Event(u32),
+}
+
+/// How a traced thread runs until its next stop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PtraceResume {
+    /// Only stop for signals and events.
+    #[default]
+    Cont,
+    /// Also stop when entering and leaving system calls.
+    Syscall,
+    /// Also stop after executing one instruction.
+    SingleStep,
+}
+
+/// The tracing state of a thread.
+#[derive(Default)]
+pub struct PtraceState {
+    /// The process tracing the thread
+    pub tracer: Option<Weak<Process>>,
+    /// The `PTRACE_O_*` options
+    pub options: u32,
+    /// Whether the tracer attached with `PTRACE_SEIZE`
+    pub seized: bool,
+    /// The stop the thread is in
+    pub stop: Option<PtraceStop>,
+    /// Whether the stop has been reported to the tracer by `wait4`
+    pub reported: bool,
+    /// The exit status of the thread, if it exited while traced and the
+    /// tracer has not waited for it
+    pub exit_status: Option<i32>,
+    /// How to run after the stop
+    pub resume: PtraceResume,
+    /// The signal to deliver after the stop
+    pub signal: Option<Signo>,
+    /// The message of the last event, as returned by `PTRACE_GETEVENTMSG`
+    pub event_msg: usize,
+    /// The number of the system call the thread is stopped in, which the
+    /// tracer may change at entry
+    pub sysno: usize,
+    /// The registers a new thread enters user space with, while it is
+    /// stopped before running
+    pub start_regs: Option<TrapFrame>,
+}
+
+impl PtraceState {
+    /// The process tracing the thread, if it is still alive.
+    pub fn tracer(&self) -> Option<Arc<Process>> {
+        self.tracer
+            .as_ref()
+            .and_then(Weak::upgrade)
+            .filter(|it| !it.is_zombie())
+    }
+}
+
+/// The tracing of a thread.
+pub struct Ptrace {
+    state: Mutex<PtraceState>,
+    wq: WaitQueue,
+}
+
+impl Default for Ptrace {
+    fn default() -> Self {
+        Self {
+            state: Mutex::new(PtraceState::default()),
+            wq: WaitQueue::new(),
+        }
+    }
+}
+
+impl Ptrace {
+    /// Lock the tracing state.
+    pub fn lock(&self) -> MutexGuard<'_, PtraceState> {
+        self.state.lock()
+    }
+
+    /// The process tracing the thread, if any.
+    pub fn tracer(&self) -> Option<Arc<Process>> {
+        self.lock().tracer()
+    }
+
+    /// Whether the thread is in a stop.
+    pub fn is_stopped(&self) -> bool {
+        self.lock().stop.is_some()
+    }
+
+    /// Suspend the current thread, which is in a stop, until the tracer
+    /// resumes it or `killed` returns `true`.
+    ///
+    /// Returns the signal the tracer asks to deliver.
+    pub fn wait_resumed(&self, killed: impl Fn() -> bool) -> Option<Signo> {
+        self.wq
+            .wait_until(|| self.lock().stop.is_none() || killed());
+        let mut state = self.lock();
+        state.stop = None;
+        state.signal.take()
+    }
+
+    /// Resume the thread from its stop, delivering `signal` if it is not
+    /// `None`.
+    pub fn resume(&self, resume: PtraceResume, signal: Option<Signo>) {
+        let mut state = self.lock();
+        state.resume = resume;
+        state.signal = signal;
+        state.stop = None;
+        self.wq.notify_all(false);
+    }
+
+    /// Stop tracing the thread, resuming it with `signal` if it is stopped.
+    pub fn detach(&self, signal: Option<Signo>) {
+        let mut state = self.lock();
+        state.tracer = None;
+        state.options = 0;
+        state.seized = false;
+        state.exit_status = None;
+        drop(state);
+        self.resume(PtraceResume::Cont, signal);
+    }
+
+    /// Wake up the thread to re-check whether it is killed.
+    pub fn wake(&self) {
+        self.wq.notify_all(false);
+    }
+}
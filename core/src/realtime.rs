@@ -0,0 +1,210 @@
+//! The realtime clock, which can be set with `clock_settime(2)` and adjusted
+//! with `adjtimex(2)`.
+//!
+//! The wall-clock time of the platform is left untouched, as the kernel uses
+//! it for its own deadlines. The realtime clock runs from the monotonic time
+//! at the rate given by the frequency adjustment and the tick length, and
+//! slews the offset requested with `ADJ_OFFSET` at most 500 ppm faster or
+//! slower than that. The PLL of NTP is not emulated.
+
+use core::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use axhal::time::{NANOS_PER_SEC, TimeValue, epochoffset_nanos, monotonic_time_nanos};
+use axtask::WaitQueue;
+use spin::Mutex;
+
+use crate::posix_timer::clock_was_set;
+
+/// The maximum frequency adjustment, in scaled ppm (`MAXFREQ_SCALED`).
+pub const MAX_FREQ: i64 = 500 << 16;
+/// The maximum rate at which offsets are slewed, in ppm.
+pub const SLEW_RATE_PPM: i64 = 500;
+/// The maximum error of an unsynchronized clock, in microseconds
+/// (`NTP_PHASE_LIMIT`).
+pub const NTP_PHASE_LIMIT: i64 = 16_000_000;
+/// The status flag of a clock which is not synchronized.
+pub const STA_UNSYNC: i32 = 0x0040;
+
+/// The parameters of the adjustment of the realtime clock, as reported by
+/// `adjtimex(2)`.
+#[derive(Debug, Clone, Copy)]
+pub struct ClockAdjustment {
+    /// The offset left to slew, in nanoseconds
+    pub offset_ns: i64,
+    /// The frequency adjustment, in ppm with a 16-bit fractional part
+    pub freq: i64,
+    /// The maximum error, in microseconds
+    pub maxerror: i64,
+    /// The estimated error, in microseconds
+    pub esterror: i64,
+    /// The `STA_*` status flags
+    pub status: i32,
+    /// The time constant of the PLL
+    pub constant: i64,
+    /// The length of a tick, in microseconds
+    pub tick: i64,
+    /// The offset of TAI from UTC, in seconds
+    pub tai: i32,
+}
+
+impl ClockAdjustment {
+    /// The length of a tick without adjustment, in microseconds.
+    pub fn default_tick() -> i64 {
+        1_000_000 / axconfig::TICKS_PER_SEC as i64
+    }
+}
+
+struct Realtime {
+    /// The monotonic time of the last update, in nanoseconds
+    base_mono_ns: u64,
+    /// The realtime at the last update, in nanoseconds since the epoch
+    base_real_ns: i64,
+    adjustment: ClockAdjustment,
+}
+
+impl Realtime {
+    /// The realtime at the monotonic time `now_ns`, and the part of the
+    /// offset slewed since the last update.
+    fn at(&self, now_ns: u64) -> (i64, i64) {
+        let elapsed = now_ns.saturating_sub(self.base_mono_ns) as i128;
+        let adj = &self.adjustment;
+        // The rate error in parts per 2^16 million.
+        let tick_error = (adj.tick - ClockAdjustment::default_tick())
+            * axconfig::TICKS_PER_SEC as i64
+            * (1 << 16);
+        let rate = (adj.freq + tick_error) as i128;
+        let drift = elapsed * rate / (1_000_000 << 16);
+        let max_slew = (elapsed * SLEW_RATE_PPM as i128 / 1_000_000) as i64;
+        let slewed = adj.offset_ns.clamp(-max_slew, max_slew);
+        let real = self.base_real_ns as i128 + elapsed + drift + slewed as i128;
+        (real as i64, slewed)
+    }
+
+    /// Start measuring from the current time, so that the adjustment can be
+    /// changed without affecting the time already elapsed.
+    fn update(&mut self) -> i64 {
+        let now_ns = monotonic_time_nanos();
+        let (real, slewed) = self.at(now_ns);
+        self.base_mono_ns = now_ns;
+        self.base_real_ns = real;
+        self.adjustment.offset_ns -= slewed;
+        real
+    }
+}
+
+static REALTIME: Mutex<Option<Realtime>> = Mutex::new(None);
+
+/// The number of times the realtime was set or stepped.
+static REALTIME_SETS: AtomicU64 = AtomicU64::new(0);
+
+/// The tasks waiting for deadlines of the realtime clock, woken when it is
+/// set or stepped.
+static REALTIME_SET_WQ: WaitQueue = WaitQueue::new();
+
+fn with_realtime<R>(f: impl FnOnce(&mut Realtime) -> R) -> R {
+    let mut realtime = REALTIME.lock();
+    let realtime = realtime.get_or_insert_with(|| Realtime {
+        base_mono_ns: 0,
+        base_real_ns: epochoffset_nanos() as i64,
+        adjustment: ClockAdjustment {
+            offset_ns: 0,
+            freq: 0,
+            maxerror: NTP_PHASE_LIMIT,
+            esterror: NTP_PHASE_LIMIT,
+            status: STA_UNSYNC,
+            constant: 2,
+            tick: ClockAdjustment::default_tick(),
+            tai: 0,
+        },
+    });
+    f(realtime)
+}
+
+fn to_time_value(ns: i64) -> TimeValue {
+    Duration::from_nanos(ns.max(0) as u64)
+}
+
+/// The current realtime, as the time since the epoch.
+pub fn realtime() -> TimeValue {
+    let realtime = with_realtime(|it| it.at(monotonic_time_nanos()).0);
+    to_time_value(realtime)
+}
+
+/// Make the filesystems use the realtime clock for the timestamps of files.
+pub fn register_fs_clock() {
+    axfs::set_clock(realtime);
+}
+
+/// The current TAI time, as the time since the epoch.
+pub fn tai_time() -> TimeValue {
+    let (realtime, tai) = with_realtime(|it| (it.at(monotonic_time_nanos()).0, it.adjustment.tai));
+    to_time_value(realtime + tai as i64 * NANOS_PER_SEC as i64)
+}
+
+/// Set the realtime to `time`.
+///
+/// Like Linux, the offset being slewed is dropped and the clock is marked
+/// as not synchronized.
+pub fn set_realtime(time: TimeValue) {
+    let delta = with_realtime(|it| {
+        let old = it.update();
+        it.base_real_ns = time.as_nanos() as i64;
+        let adj = &mut it.adjustment;
+        adj.offset_ns = 0;
+        adj.maxerror = NTP_PHASE_LIMIT;
+        adj.esterror = NTP_PHASE_LIMIT;
+        adj.status |= STA_UNSYNC;
+        it.base_real_ns - old
+    });
+    REALTIME_SETS.fetch_add(1, Ordering::Release);
+    clock_was_set(delta);
+    REALTIME_SET_WQ.notify_all(false);
+}
+
+/// Step the realtime by `delta_ns` nanoseconds, for `ADJ_SETOFFSET`.
+pub fn shift_realtime(delta_ns: i64) {
+    with_realtime(|it| {
+        it.update();
+        it.base_real_ns += delta_ns;
+    });
+    REALTIME_SETS.fetch_add(1, Ordering::Release);
+    clock_was_set(delta_ns);
+    REALTIME_SET_WQ.notify_all(false);
+}
+
+/// The number of times the realtime was set or stepped, which changes when
+/// the clock jumps, as opposed to being slewed.
+pub fn realtime_set_count() -> u64 {
+    REALTIME_SETS.load(Ordering::Acquire)
+}
+
+/// The wait queue of the tasks waiting for deadlines of the realtime clock,
+/// which is notified when the clock is set or stepped.
+pub fn realtime_set_wait_queue() -> &'static WaitQueue {
+    &REALTIME_SET_WQ
+}
+
+/// The current adjustment of the realtime clock.
+pub fn clock_adjustment() -> ClockAdjustment {
+    with_realtime(|it| {
+        it.update();
+        it.adjustment
+    })
+}
+
+/// Change the adjustment of the realtime clock with `f`, which gets the
+/// current one.
+///
+/// Returns the adjustment before the change.
+pub fn adjust_clock(f: impl FnOnce(&mut ClockAdjustment)) -> ClockAdjustment {
+    with_realtime(|it| {
+        it.update();
+        let old = it.adjustment;
+        f(&mut it.adjustment);
+        it.adjustment.freq = it.adjustment.freq.clamp(-MAX_FREQ, MAX_FREQ);
+        old
+    })
+}
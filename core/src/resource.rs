@@ -0,0 +1,60 @@
+//! Resource limits of processes.
+
+use core::ops::{Index, IndexMut};
+
+use linux_raw_sys::general::{
+    RLIM_NLIMITS, RLIMIT_CORE, RLIMIT_MEMLOCK, RLIMIT_MSGQUEUE, RLIMIT_NICE, RLIMIT_NOFILE,
+    RLIMIT_RTPRIO, RLIMIT_STACK,
+};
+
+/// The value of a limit which is not enforced.
+pub const RLIM_INFINITY: u64 = u64::MAX;
+
+/// A resource limit, see `getrlimit(2)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rlimit {
+    /// The soft limit, which is enforced
+    pub current: u64,
+    /// The ceiling of the soft limit
+    pub max: u64,
+}
+
+impl Rlimit {
+    /// Create a new [`Rlimit`].
+    pub const fn new(current: u64, max: u64) -> Self {
+        Self { current, max }
+    }
+}
+
+/// The resource limits of a process, indexed by the `RLIMIT_*` constants.
+#[derive(Debug, Clone)]
+pub struct Rlimits([Rlimit; RLIM_NLIMITS as usize]);
+
+impl Default for Rlimits {
+    /// The limits of the first process, as those of Linux.
+    fn default() -> Self {
+        let mut limits = [Rlimit::new(RLIM_INFINITY, RLIM_INFINITY); RLIM_NLIMITS as usize];
+        limits[RLIMIT_STACK as usize].current = 8 * 1024 * 1024;
+        limits[RLIMIT_CORE as usize].current = 0;
+        limits[RLIMIT_NOFILE as usize] = Rlimit::new(1024, 4096);
+        limits[RLIMIT_MEMLOCK as usize] = Rlimit::new(8 * 1024 * 1024, 8 * 1024 * 1024);
+        limits[RLIMIT_MSGQUEUE as usize] = Rlimit::new(819200, 819200);
+        limits[RLIMIT_NICE as usize] = Rlimit::new(0, 0);
+        limits[RLIMIT_RTPRIO as usize] = Rlimit::new(0, 0);
+        Self(limits)
+    }
+}
+
+impl Index<u32> for Rlimits {
+    type Output = Rlimit;
+
+    fn index(&self, resource: u32) -> &Rlimit {
+        &self.0[resource as usize]
+    }
+}
+
+impl IndexMut<u32> for Rlimits {
+    fn index_mut(&mut self, resource: u32) -> &mut Rlimit {
+        &mut self.0[resource as usize]
+    }
+}
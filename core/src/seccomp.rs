@@ -0,0 +1,534 @@
+//! Filtering of system calls with seccomp, see `seccomp(2)`.
+//!
+//! Filters are classic BPF programs, which are checked and decoded when
+//! installed, and run over [`seccomp_data`] before each system call.
+
+use alloc::{sync::Arc, vec::Vec};
+use core::iter;
+
+use axerrno::{LinuxError, LinuxResult};
+use linux_raw_sys::ptrace::{
+    BPF_A, BPF_ABS, BPF_ADD, BPF_ALU, BPF_AND, BPF_DIV, BPF_IMM, BPF_JA, BPF_JEQ, BPF_JGE, BPF_JGT,
+    BPF_JMP, BPF_JSET, BPF_K, BPF_LD, BPF_LDX, BPF_LEN, BPF_LSH, BPF_MAXINSNS, BPF_MEM,
+    BPF_MEMWORDS, BPF_MISC, BPF_MOD, BPF_MUL, BPF_NEG, BPF_OR, BPF_RET, BPF_RSH, BPF_ST, BPF_STX,
+    BPF_SUB, BPF_TAX, BPF_TXA, BPF_W, BPF_X, BPF_XOR, SECCOMP_RET_ACTION_FULL, seccomp_data,
+    sock_filter,
+};
+
+/// The `AUDIT_ARCH_*` value of the current architecture, as found in
+/// [`seccomp_data`].
+#[cfg(target_arch = "x86_64")]
+pub const SECCOMP_ARCH: u32 = linux_raw_sys::ptrace::AUDIT_ARCH_X86_64;
+/// The `AUDIT_ARCH_*` value of the current architecture, as found in
+/// [`seccomp_data`].
+#[cfg(target_arch = "riscv32")]
+pub const SECCOMP_ARCH: u32 = linux_raw_sys::ptrace::AUDIT_ARCH_RISCV32;
+/// The `AUDIT_ARCH_*` value of the current architecture, as found in
+/// [`seccomp_data`].
+#[cfg(target_arch = "riscv64")]
+pub const SECCOMP_ARCH: u32 = linux_raw_sys::ptrace::AUDIT_ARCH_RISCV64;
+/// The `AUDIT_ARCH_*` value of the current architecture, as found in
+/// [`seccomp_data`].
+#[cfg(target_arch = "aarch64")]
+pub const SECCOMP_ARCH: u32 = linux_raw_sys::ptrace::AUDIT_ARCH_AARCH64;
+/// The `AUDIT_ARCH_*` value of the current architecture, as found in
+/// [`seccomp_data`].
+#[cfg(target_arch = "loongarch64")]
+pub const SECCOMP_ARCH: u32 = linux_raw_sys::ptrace::AUDIT_ARCH_LOONGARCH64;
+
+const DATA_SIZE: usize = size_of::<seccomp_data>();
+
+/// The seccomp state of a thread.
+#[derive(Clone, Default)]
+pub struct Seccomp {
+    /// The `SECCOMP_MODE_*` mode
+    pub mode: u32,
+    /// The last installed filter, which runs along with the earlier ones
+    pub filter: Option<Arc<SeccompFilter>>,
+}
+
+impl Seccomp {
+    /// Run all the filters on `data`.
+    ///
+    /// Returns the `SECCOMP_RET_*` value with the highest precedence, and
+    /// whether the filter returning it asked for logging.
+    pub fn run(&self, data: &seccomp_data) -> Option<(u32, bool)> {
+        let data = data_bytes(data);
+        let mut result: Option<(u32, bool)> = None;
+        let mut filter = self.filter.as_deref();
+        while let Some(it) = filter {
+            let ret = it.eval(&data);
+            // The actions are ordered by precedence as signed integers, with
+            // `SECCOMP_RET_KILL_PROCESS` first.
+            let precedes = |other: u32| {
+                ((ret & SECCOMP_RET_ACTION_FULL) as i32)
+                    < ((other & SECCOMP_RET_ACTION_FULL) as i32)
+            };
+            if result.is_none_or(|(other, _)| precedes(other)) {
+                result = Some((ret, it.log));
+            }
+            filter = it.prev.as_deref();
+        }
+        result
+    }
+
+    /// Whether all the filters of `self` are also run by `other`, so that
+    /// `other` can replace `self` when synchronizing the threads of a
+    /// process.
+    pub fn is_ancestor_of(&self, other: &Seccomp) -> bool {
+        let Some(ancestor) = &self.filter else {
+            return self.mode == 0;
+        };
+        iter::successors(other.filter.as_ref(), |it| it.prev.as_ref())
+            .any(|it| Arc::ptr_eq(it, ancestor))
+    }
+}
+
+fn data_bytes(data: &seccomp_data) -> [u8; DATA_SIZE] {
+    let mut bytes = [0; DATA_SIZE];
+    bytes[0..4].copy_from_slice(&data.nr.to_ne_bytes());
+    bytes[4..8].copy_from_slice(&data.arch.to_ne_bytes());
+    bytes[8..16].copy_from_slice(&data.instruction_pointer.to_ne_bytes());
+    for (chunk, arg) in bytes[16..].chunks_exact_mut(8).zip(data.args) {
+        chunk.copy_from_slice(&arg.to_ne_bytes());
+    }
+    bytes
+}
+
+/// The second operand of an ALU or jump instruction.
+#[derive(Debug, Clone, Copy)]
+enum Src {
+    K(u32),
+    X,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum AluOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Or,
+    And,
+    Xor,
+    Lsh,
+    Rsh,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum JumpOp {
+    Eq,
+    Gt,
+    Ge,
+    Set,
+}
+
+/// A decoded instruction, with absolute jump targets.
+#[derive(Debug, Clone, Copy)]
+enum Insn {
+    /// `A = data[k]`
+    LoadData(usize),
+    /// `A = k`
+    LoadImm(u32),
+    /// `A = M[k]`
+    LoadMem(usize),
+    /// `X = k`
+    LoadXImm(u32),
+    /// `X = M[k]`
+    LoadXMem(usize),
+    /// `M[k] = A`
+    Store(usize),
+    /// `M[k] = X`
+    StoreX(usize),
+    /// `A = A op src`
+    Alu(AluOp, Src),
+    /// `A = -A`
+    Neg,
+    /// `pc = target`
+    Jump(usize),
+    /// `pc = A op src ? jt : jf`
+    JumpIf(JumpOp, Src, usize, usize),
+    /// `return k`
+    Ret(u32),
+    /// `return A`
+    RetA,
+    /// `X = A`
+    Tax,
+    /// `A = X`
+    Txa,
+}
+
+/// Decode the instruction at `pc` of a program of `len` instructions, which
+/// must be allowed for seccomp and only jump forward inside the program.
+fn decode(insn: &sock_filter, pc: usize, len: usize) -> LinuxResult<Insn> {
+    let code = insn.code as u32;
+    let k = insn.k;
+    if code > 0xff {
+        return Err(LinuxError::EINVAL);
+    }
+    let mem = || {
+        let index = k as usize;
+        (index < BPF_MEMWORDS as usize)
+            .then_some(index)
+            .ok_or(LinuxError::EINVAL)
+    };
+    let target = |offset: u32| {
+        let target = pc + 1 + offset as usize;
+        (target < len).then_some(target).ok_or(LinuxError::EINVAL)
+    };
+    let src = || {
+        if code & BPF_X != 0 { Src::X } else { Src::K(k) }
+    };
+
+    let insn = match code & 0x07 {
+        BPF_LD => match code & !0x07 {
+            mode if mode == BPF_W | BPF_ABS => {
+                let offset = k as usize;
+                if !offset.is_multiple_of(4) || offset >= DATA_SIZE {
+                    return Err(LinuxError::EINVAL);
+                }
+                Insn::LoadData(offset)
+            }
+            mode if mode == BPF_W | BPF_LEN => Insn::LoadImm(DATA_SIZE as u32),
+            mode if mode == BPF_W | BPF_IMM => Insn::LoadImm(k),
+            mode if mode == BPF_W | BPF_MEM => Insn::LoadMem(mem()?),
+            _ => return Err(LinuxError::EINVAL),
+        },
+        BPF_LDX => match code & !0x07 {
+            mode if mode == BPF_W | BPF_LEN => Insn::LoadXImm(DATA_SIZE as u32),
+            mode if mode == BPF_W | BPF_IMM => Insn::LoadXImm(k),
+            mode if mode == BPF_W | BPF_MEM => Insn::LoadXMem(mem()?),
+            _ => return Err(LinuxError::EINVAL),
+        },
+        BPF_ST if code == BPF_ST => Insn::Store(mem()?),
+        BPF_STX if code == BPF_STX => Insn::StoreX(mem()?),
+        BPF_ALU => {
+            let op = match code & 0xf0 {
+                BPF_NEG if code == BPF_ALU | BPF_NEG => return Ok(Insn::Neg),
+                BPF_ADD => AluOp::Add,
+                BPF_SUB => AluOp::Sub,
+                BPF_MUL => AluOp::Mul,
+                BPF_DIV => AluOp::Div,
+                BPF_MOD => AluOp::Mod,
+                BPF_OR => AluOp::Or,
+                BPF_AND => AluOp::And,
+                BPF_XOR => AluOp::Xor,
+                BPF_LSH => AluOp::Lsh,
+                BPF_RSH => AluOp::Rsh,
+                _ => return Err(LinuxError::EINVAL),
+            };
+            if matches!(op, AluOp::Div | AluOp::Mod) && matches!(src(), Src::K(0)) {
+                return Err(LinuxError::EINVAL);
+            }
+            Insn::Alu(op, src())
+        }
+        BPF_JMP => {
+            let op = match code & 0xf0 {
+                BPF_JA if code == BPF_JMP | BPF_JA => return Ok(Insn::Jump(target(k)?)),
+                BPF_JEQ => JumpOp::Eq,
+                BPF_JGT => JumpOp::Gt,
+                BPF_JGE => JumpOp::Ge,
+                BPF_JSET => JumpOp::Set,
+                _ => return Err(LinuxError::EINVAL),
+            };
+            Insn::JumpIf(op, src(), target(insn.jt as _)?, target(insn.jf as _)?)
+        }
+        BPF_RET => match code & !0x07 {
+            BPF_K => Insn::Ret(k),
+            BPF_A => Insn::RetA,
+            _ => return Err(LinuxError::EINVAL),
+        },
+        BPF_MISC => match code & !0x07 {
+            BPF_TAX => Insn::Tax,
+            BPF_TXA => Insn::Txa,
+            _ => return Err(LinuxError::EINVAL),
+        },
+        _ => return Err(LinuxError::EINVAL),
+    };
+    Ok(insn)
+}
+
+/// A seccomp filter, installed with `SECCOMP_SET_MODE_FILTER`.
+pub struct SeccompFilter {
+    insns: Vec<Insn>,
+    /// Whether the actions it returns, but `SECCOMP_RET_ALLOW`, are logged
+    pub log: bool,
+    prev: Option<Arc<SeccompFilter>>,
+}
+
+impl SeccompFilter {
+    /// Check and decode the classic BPF program `prog`, as a filter running
+    /// after the filters up to `prev`.
+    ///
+    /// Returns `EINVAL` if the program is empty, too long, not ending with a
+    /// return, or uses instructions not allowed for seccomp.
+    pub fn new(
+        prog: &[sock_filter],
+        log: bool,
+        prev: Option<Arc<SeccompFilter>>,
+    ) -> LinuxResult<Self> {
+        if prog.is_empty() || prog.len() > BPF_MAXINSNS as usize {
+            return Err(LinuxError::EINVAL);
+        }
+        let insns = prog
+            .iter()
+            .enumerate()
+            .map(|(pc, insn)| decode(insn, pc, prog.len()))
+            .collect::<LinuxResult<Vec<_>>>()?;
+        if !matches!(insns.last(), Some(Insn::Ret(_) | Insn::RetA)) {
+            return Err(LinuxError::EINVAL);
+        }
+        Ok(Self { insns, log, prev })
+    }
+
+    fn eval(&self, data: &[u8; DATA_SIZE]) -> u32 {
+        let mut a = 0u32;
+        let mut x = 0u32;
+        let mut mem = [0u32; BPF_MEMWORDS as usize];
+        let mut pc = 0;
+        loop {
+            let insn = self.insns[pc];
+            pc += 1;
+            match insn {
+                Insn::LoadData(offset) => {
+                    a = u32::from_ne_bytes(data[offset..offset + 4].try_into().unwrap());
+                }
+                Insn::LoadImm(k) => a = k,
+                Insn::LoadMem(index) => a = mem[index],
+                Insn::LoadXImm(k) => x = k,
+                Insn::LoadXMem(index) => x = mem[index],
+                Insn::Store(index) => mem[index] = a,
+                Insn::StoreX(index) => mem[index] = x,
+                Insn::Alu(op, src) => {
+                    let src = match src {
+                        Src::K(k) => k,
+                        Src::X => x,
+                    };
+                    a = match op {
+                        AluOp::Add => a.wrapping_add(src),
+                        AluOp::Sub => a.wrapping_sub(src),
+                        AluOp::Mul => a.wrapping_mul(src),
+                        // A division by zero in `X` ends the program with 0.
+                        AluOp::Div => match a.checked_div(src) {
+                            Some(it) => it,
+                            None => return 0,
+                        },
+                        AluOp::Mod => match a.checked_rem(src) {
+                            Some(it) => it,
+                            None => return 0,
+                        },
+                        AluOp::Or => a | src,
+                        AluOp::And => a & src,
+                        AluOp::Xor => a ^ src,
+                        AluOp::Lsh => a.checked_shl(src).unwrap_or(0),
+                        AluOp::Rsh => a.checked_shr(src).unwrap_or(0),
+                    };
+                }
+                Insn::Neg => a = a.wrapping_neg(),
+                Insn::Jump(target) => pc = target,
+                Insn::JumpIf(op, src, jt, jf) => {
+                    let src = match src {
+                        Src::K(k) => k,
+                        Src::X => x,
+                    };
+                    let cond = match op {
+                        JumpOp::Eq => a == src,
+                        JumpOp::Gt => a > src,
+                        JumpOp::Ge => a >= src,
+                        JumpOp::Set => a & src != 0,
+                    };
+                    pc = if cond { jt } else { jf };
+                }
+                Insn::Ret(k) => return k,
+                Insn::RetA => return a,
+                Insn::Tax => x = a,
+                Insn::Txa => a = x,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{sync::Arc, vec, vec::Vec};
+
+    use linux_raw_sys::ptrace::{
+        BPF_A, BPF_ABS, BPF_ALU, BPF_B, BPF_DIV, BPF_IMM, BPF_IND, BPF_JA, BPF_JEQ, BPF_JMP, BPF_K,
+        BPF_LD, BPF_LDX, BPF_MEM, BPF_MEMWORDS, BPF_MSH, BPF_RET, BPF_ST, BPF_W, BPF_X,
+        SECCOMP_MODE_FILTER, SECCOMP_RET_ALLOW, SECCOMP_RET_ERRNO, SECCOMP_RET_KILL_PROCESS,
+        SECCOMP_RET_KILL_THREAD, SECCOMP_RET_LOG, SECCOMP_RET_TRAP, seccomp_data, sock_filter,
+    };
+
+    use super::{SECCOMP_ARCH, Seccomp, SeccompFilter};
+
+    fn stmt(code: u32, k: u32) -> sock_filter {
+        jump(code, k, 0, 0)
+    }
+
+    fn jump(code: u32, k: u32, jt: u8, jf: u8) -> sock_filter {
+        sock_filter {
+            code: code as _,
+            jt,
+            jf,
+            k,
+        }
+    }
+
+    fn ret(k: u32) -> sock_filter {
+        stmt(BPF_RET | BPF_K, k)
+    }
+
+    fn data(nr: i32) -> seccomp_data {
+        seccomp_data {
+            nr,
+            arch: SECCOMP_ARCH,
+            instruction_pointer: 0,
+            args: [0; 6],
+        }
+    }
+
+    /// The filters of `progs` installed in order.
+    fn install(progs: &[Vec<sock_filter>]) -> Seccomp {
+        let filter = progs.iter().fold(None, |prev, prog| {
+            Some(Arc::new(SeccompFilter::new(prog, false, prev).unwrap()))
+        });
+        Seccomp {
+            mode: SECCOMP_MODE_FILTER,
+            filter,
+        }
+    }
+
+    fn run(progs: &[Vec<sock_filter>], nr: i32) -> u32 {
+        install(progs).run(&data(nr)).unwrap().0
+    }
+
+    #[test]
+    fn rejects_opcodes() {
+        let rejected = [
+            // Byte loads and indirect loads are not allowed.
+            BPF_LD | BPF_B | BPF_ABS,
+            BPF_LD | BPF_W | BPF_IND,
+            BPF_LDX | BPF_B | BPF_MSH,
+            // Codes are 8 bits.
+            0x100 | BPF_RET | BPF_K,
+        ];
+        for code in rejected {
+            assert!(SeccompFilter::new(&[stmt(code, 0), ret(0)], false, None).is_err());
+        }
+        // Loads out of `seccomp_data`, or not aligned.
+        for offset in [2, size_of::<seccomp_data>() as u32] {
+            let prog = [stmt(BPF_LD | BPF_W | BPF_ABS, offset), ret(0)];
+            assert!(SeccompFilter::new(&prog, false, None).is_err());
+        }
+        // Constant division by zero.
+        let prog = [stmt(BPF_ALU | BPF_DIV | BPF_K, 0), ret(0)];
+        assert!(SeccompFilter::new(&prog, false, None).is_err());
+    }
+
+    #[test]
+    fn rejects_out_of_range() {
+        let prog = [stmt(BPF_JMP | BPF_JA, 1), ret(0)];
+        assert!(SeccompFilter::new(&prog, false, None).is_err());
+        let prog = [jump(BPF_JMP | BPF_JEQ | BPF_K, 0, 0, 1), ret(0)];
+        assert!(SeccompFilter::new(&prog, false, None).is_err());
+        let prog = [stmt(BPF_ST, BPF_MEMWORDS), ret(0)];
+        assert!(SeccompFilter::new(&prog, false, None).is_err());
+        let prog = [stmt(BPF_LD | BPF_W | BPF_MEM, BPF_MEMWORDS), ret(0)];
+        assert!(SeccompFilter::new(&prog, false, None).is_err());
+
+        let prog = [
+            stmt(BPF_ST, BPF_MEMWORDS - 1),
+            stmt(BPF_JMP | BPF_JA, 0),
+            ret(0),
+        ];
+        assert!(SeccompFilter::new(&prog, false, None).is_ok());
+    }
+
+    #[test]
+    fn requires_final_return() {
+        assert!(SeccompFilter::new(&[], false, None).is_err());
+        let prog = [stmt(BPF_LD | BPF_W | BPF_ABS, 0)];
+        assert!(SeccompFilter::new(&prog, false, None).is_err());
+        let prog = [ret(SECCOMP_RET_ALLOW), stmt(BPF_LD | BPF_W | BPF_IMM, 0)];
+        assert!(SeccompFilter::new(&prog, false, None).is_err());
+    }
+
+    #[test]
+    fn division_by_zero_returns_zero() {
+        let prog = vec![
+            stmt(BPF_LDX | BPF_W | BPF_IMM, 0),
+            stmt(BPF_LD | BPF_W | BPF_IMM, 10),
+            stmt(BPF_ALU | BPF_DIV | BPF_X, 0),
+            stmt(BPF_RET | BPF_A, 0),
+        ];
+        assert_eq!(run(&[prog], 0), SECCOMP_RET_KILL_THREAD);
+
+        let prog = vec![
+            stmt(BPF_LDX | BPF_W | BPF_IMM, 2),
+            stmt(BPF_LD | BPF_W | BPF_IMM, 10),
+            stmt(BPF_ALU | BPF_DIV | BPF_X, 0),
+            stmt(BPF_RET | BPF_A, 0),
+        ];
+        assert_eq!(run(&[prog], 0), 5);
+    }
+
+    #[test]
+    fn matches_system_call() {
+        let prog = vec![
+            stmt(BPF_LD | BPF_W | BPF_ABS, 0),
+            jump(BPF_JMP | BPF_JEQ | BPF_K, 42, 0, 1),
+            ret(SECCOMP_RET_ERRNO | 1),
+            ret(SECCOMP_RET_ALLOW),
+        ];
+        let progs = [prog];
+        assert_eq!(run(&progs, 42), SECCOMP_RET_ERRNO | 1);
+        assert_eq!(run(&progs, 1), SECCOMP_RET_ALLOW);
+    }
+
+    #[test]
+    fn action_precedence() {
+        let progs = [
+            vec![ret(SECCOMP_RET_ALLOW)],
+            vec![ret(SECCOMP_RET_LOG)],
+            vec![ret(SECCOMP_RET_ERRNO | 2)],
+            vec![ret(SECCOMP_RET_TRAP)],
+            vec![ret(SECCOMP_RET_ERRNO | 1)],
+        ];
+        assert_eq!(run(&progs, 0), SECCOMP_RET_TRAP);
+
+        // Equal actions keep the data of the last installed filter.
+        assert_eq!(run(&progs[..3], 0), SECCOMP_RET_ERRNO | 2);
+        let progs = [
+            vec![ret(SECCOMP_RET_ERRNO | 1)],
+            vec![ret(SECCOMP_RET_ERRNO | 2)],
+        ];
+        assert_eq!(run(&progs, 0), SECCOMP_RET_ERRNO | 2);
+
+        // `SECCOMP_RET_KILL_PROCESS` comes first as a negative value.
+        let progs = [
+            vec![ret(SECCOMP_RET_KILL_THREAD)],
+            vec![ret(SECCOMP_RET_KILL_PROCESS)],
+            vec![ret(SECCOMP_RET_ALLOW)],
+        ];
+        assert_eq!(run(&progs, 0), SECCOMP_RET_KILL_PROCESS);
+
+        assert_eq!(Seccomp::default().run(&data(0)), None);
+    }
+
+    #[test]
+    fn ancestors() {
+        let old = install(&[vec![ret(SECCOMP_RET_ALLOW)]]);
+        let new = Seccomp {
+            mode: SECCOMP_MODE_FILTER,
+            filter: Some(Arc::new(
+                SeccompFilter::new(&[ret(SECCOMP_RET_LOG)], false, old.filter.clone()).unwrap(),
+            )),
+        };
+        assert!(Seccomp::default().is_ancestor_of(&new));
+        assert!(old.is_ancestor_of(&new));
+        assert!(new.is_ancestor_of(&new));
+        assert!(!new.is_ancestor_of(&old));
+        assert!(!install(&[vec![ret(SECCOMP_RET_ALLOW)]]).is_ancestor_of(&new));
+    }
+}
@@ -2,33 +2,40 @@
 
 use core::{
     alloc::Layout,
-    cell::RefCell,
-    sync::atomic::{AtomicIsize, AtomicUsize, Ordering},
+    sync::atomic::{AtomicBool, AtomicIsize, AtomicU32, AtomicU64, AtomicUsize, Ordering},
     time::Duration,
 };
 
 use alloc::{
-    string::String, sync::{Arc, Weak}, vec::Vec
+    boxed::Box,
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
 };
 use axerrno::{LinuxError, LinuxResult};
 use axhal::{
-    arch::UspaceContext,
+    arch::{TrapFrame, UspaceContext},
+    mem::VirtAddr,
     time::{NANOS_PER_MICROS, NANOS_PER_SEC, monotonic_time_nanos},
 };
 use axmm::{AddrSpace, kernel_aspace};
 use axns::{AxNamespace, AxNamespaceIf};
 use axprocess::{Pid, Process, ProcessGroup, Session, Thread};
 use axsignal::{
-    Signo,
+    SignalInfo, SignalSet, Signo,
     api::{ProcessSignalManager, SignalActions, ThreadSignalManager},
 };
-use axsync::{Mutex, RawMutex};
-use axtask::{TaskExtRef, TaskInner, WaitQueue, current};
+use axsync::{Mutex, RawMutex, spin::SpinNoIrq};
+use axtask::{AxTaskRef, TaskExtHooks, TaskExtRef, TaskInner, WaitQueue, WeakAxTaskRef, current};
+use linux_raw_sys::general::{SI_KERNEL, SI_USER};
 use memory_addr::VirtAddrRange;
 use spin::{Once, RwLock};
 use weak_map::WeakMap;
 
-use crate::{futex::FutexTable, time::TimeStat};
+use crate::{
+    clock::CpuTime, futex::FutexTable, itimer::ITimers, posix_timer::PosixTimers, ptrace::Ptrace,
+    resource::Rlimits, seccomp::Seccomp, time::TimeStat, trace::SyscallStats,
+};
 
 /// Create a new user task.
 pub fn new_user_task(
@@ -43,6 +50,23 @@ pub fn new_user_task(
                 *tid = curr.id().as_u64() as Pid;
             }
 
+            // A child attached to the tracer of its parent starts in a stop,
+            // where the tracer may change the registers it starts with.
+            let thr_data = curr.task_ext().thread_data();
+            let uctx = if thr_data.ptrace.is_stopped() {
+                let signal = &thr_data.signal;
+                if let Some(signo) = thr_data
+                    .ptrace
+                    .wait_resumed(|| signal.pending().has(Signo::SIGKILL))
+                {
+                    signal.send_signal(SignalInfo::new(signo, SI_USER as _));
+                }
+                let start_regs = thr_data.ptrace.lock().start_regs.take();
+                start_regs.map_or(uctx, |tf| UspaceContext::from(&tf))
+            } else {
+                uctx
+            };
+
             let kstack_top = curr.kernel_stack_top().unwrap();
             info!(
                 "Enter user space: entry={:#x}, ustack={:#x}, kstack={:#x}",
@@ -50,6 +74,7 @@ pub fn new_user_task(
                 uctx.sp(),
                 kstack_top,
             );
+            time_stat_from_kernel_to_user();
             unsafe { uctx.enter_uspace(kstack_top) }
         },
         name.into(),
@@ -60,7 +85,7 @@ pub fn new_user_task(
 /// Task extended data for the monolithic kernel.
 pub struct TaskExt {
     /// The time statistics
-    pub time: RefCell<TimeStat>,
+    pub time: SpinNoIrq<TimeStat>,
     /// The thread
     pub thread: Arc<Thread>,
     /// Page fault statistics
@@ -72,8 +97,10 @@ pub struct TaskExt {
 impl TaskExt {
     /// Create a new [`TaskExt`].
     pub fn new(thread: Arc<Thread>) -> Self {
+        let mut time = TimeStat::new();
+        time.reset(monotonic_time_nanos() as usize);
         Self {
-            time: RefCell::new(TimeStat::new()),
+            time: SpinNoIrq::new(time),
             thread,
             minflt: AtomicIsize::new(0),
             majflt: AtomicIsize::new(0),
@@ -81,15 +108,41 @@ impl TaskExt {
     }
 
     pub(crate) fn time_stat_from_kernel_to_user(&self, current_tick: usize) {
-        self.time.borrow_mut().switch_into_user_mode(current_tick);
+        let stime_ns = self.time.lock().switch_into_user_mode(current_tick);
+        self.account(0, stime_ns);
     }
 
     pub(crate) fn time_stat_from_user_to_kernel(&self, current_tick: usize) {
-        self.time.borrow_mut().switch_into_kernel_mode(current_tick);
+        let utime_ns = self.time.lock().switch_into_kernel_mode(current_tick);
+        self.account(utime_ns, 0);
+    }
+
+    pub(crate) fn time_stat_update(&self, current_tick: usize) {
+        let (utime_ns, stime_ns) = self.time.lock().update(current_tick);
+        self.account(utime_ns, stime_ns);
+    }
+
+    /// Add the user and system time to the CPU time of the thread and of the
+    /// process, and count down the CPU time interval timers of the process.
+    ///
+    /// This may run in IRQ context, so the signals of the timers which expired
+    /// are sent later, by [`send_expired_itimer_signals`].
+    fn account(&self, utime_ns: usize, stime_ns: usize) {
+        if utime_ns == 0 && stime_ns == 0 {
+            return;
+        }
+        let (user_ns, system_ns) = (utime_ns as u64, stime_ns as u64);
+        self.thread_data().cpu_time.add(user_ns, system_ns);
+        let proc_data = self.process_data();
+        proc_data.cpu_time.add(user_ns, system_ns);
+        proc_data.itimers.account(
+            Duration::from_nanos(user_ns),
+            Duration::from_nanos(system_ns),
+        );
     }
 
     pub(crate) fn time_stat_output(&self) -> (usize, usize) {
-        self.time.borrow().output()
+        self.time.lock().output()
     }
 
     /// Get the [`ThreadData`] associated with this task.
@@ -105,6 +158,30 @@ impl TaskExt {
 
 axtask::def_task_ext!(TaskExt);
 
+impl TaskExtHooks for TaskExt {
+    fn on_leave(&self) {
+        let (utime_ns, stime_ns) = self
+            .time
+            .lock()
+            .switch_from_old_task(monotonic_time_nanos() as usize);
+        self.account(utime_ns, stime_ns);
+    }
+
+    fn on_enter(&self) {
+        self.time
+            .lock()
+            .switch_to_new_task(monotonic_time_nanos() as usize);
+        #[cfg(target_arch = "aarch64")]
+        axhal::arch::set_single_step(self.thread_data().single_step.load(Ordering::Relaxed));
+    }
+
+    fn on_tick(&self) {
+        self.time_stat_update(monotonic_time_nanos() as usize);
+    }
+}
+
+axtask::def_task_ext_hooks!(TaskExt);
+
 /// Update the time statistics to reflect a switch from kernel mode to user mode.
 pub fn time_stat_from_kernel_to_user() {
     let curr_task = current();
@@ -121,9 +198,29 @@ pub fn time_stat_from_user_to_kernel() {
         .time_stat_from_user_to_kernel(monotonic_time_nanos() as usize);
 }
 
+/// Update the time statistics with the time spent by the current task so far,
+/// e.g. before reading its CPU time.
+pub fn time_stat_update() {
+    current()
+        .task_ext()
+        .time_stat_update(monotonic_time_nanos() as usize);
+}
+
+/// Send the signals of the CPU time interval timers of the current process
+/// which expired, before returning to user space.
+pub fn send_expired_itimer_signals() {
+    let proc_data = current().task_ext().process_data();
+    for signo in proc_data.itimers.take_expired() {
+        proc_data
+            .signal
+            .send_signal(SignalInfo::new(signo, SI_KERNEL as _));
+    }
+}
+
 /// Get the time statistics for the current task.
 pub fn time_stat_output() -> (usize, usize, usize, usize) {
     let curr_task = current();
+    time_stat_update();
     let (utime_ns, stime_ns) = curr_task.task_ext().time_stat_output();
     (
         utime_ns / NANOS_PER_SEC as usize,
@@ -166,19 +263,100 @@ pub struct ThreadData {
 
     /// The thread-level signal manager
     pub signal: ThreadSignalManager<RawMutex, WaitQueueWrapper>,
+
+    /// The system call interrupted by a signal, which is restarted or fails
+    /// with `EINTR` once the signal is checked
+    pub interrupted: Mutex<Option<InterruptedSyscall>>,
+    /// The continuation of the interrupted system call, run by
+    /// `restart_syscall`
+    pub restart_block: Mutex<Option<RestartBlock>>,
+
+    /// The tracing of the thread by a debugger
+    pub ptrace: Ptrace,
+
+    /// The seccomp filtering of the system calls of the thread
+    pub seccomp: RwLock<Seccomp>,
+    /// Whether the thread and its children may not gain privileges, set with
+    /// `PR_SET_NO_NEW_PRIVS`
+    pub no_new_privs: AtomicBool,
+
+    /// The CPU time spent by the thread
+    pub cpu_time: CpuTime,
+
+    /// Whether the thread is single-stepped by a debugger, which is enabled
+    /// on the CPU it runs on when it is switched in
+    #[cfg(target_arch = "aarch64")]
+    pub single_step: AtomicBool,
+
+    /// The top of the kernel stack, below which the user registers are saved
+    kstack_top: VirtAddr,
+    /// The task running the thread, interrupted by the signals sent to it
+    task: SpinNoIrq<WeakAxTaskRef>,
 }
 
 impl ThreadData {
     /// Create a new [`ThreadData`].
+    ///
+    /// `kstack_top` is the top of the kernel stack of the task running the
+    /// thread.
     #[allow(clippy::new_without_default)]
-    pub fn new(proc: &ProcessData) -> Self {
+    pub fn new(proc: &ProcessData, kstack_top: VirtAddr) -> Self {
         Self {
             clear_child_tid: AtomicUsize::new(0),
 
             signal: ThreadSignalManager::new(proc.signal.clone()),
+
+            interrupted: Mutex::new(None),
+            restart_block: Mutex::new(None),
+
+            ptrace: Ptrace::default(),
+
+            seccomp: RwLock::new(Seccomp::default()),
+            no_new_privs: AtomicBool::new(false),
+
+            cpu_time: CpuTime::default(),
+
+            #[cfg(target_arch = "aarch64")]
+            single_step: AtomicBool::new(false),
+
+            kstack_top,
+            task: SpinNoIrq::new(WeakAxTaskRef::new()),
+        }
+    }
+
+    /// Set the task running the thread, once it is spawned.
+    pub fn set_task(&self, task: &AxTaskRef) {
+        *self.task.lock() = Arc::downgrade(task);
+        // A signal sent before could not interrupt the task.
+        if self
+            .signal
+            .pending()
+            .dequeue(&!SignalSet::default())
+            .is_some()
+        {
+            axtask::interrupt(task);
+        }
+    }
+
+    /// Interrupt the interruptible wait of the thread, after a signal is sent
+    /// to it.
+    pub fn interrupt(&self) {
+        let task = self.task.lock().upgrade();
+        if let Some(task) = task {
+            axtask::interrupt(&task);
         }
     }
 
+    /// The user registers of the thread, saved at the top of its kernel stack
+    /// when it last entered the kernel.
+    ///
+    /// The pointer is valid as long as the thread has not exited. The
+    /// registers are only up to date if the thread is in the kernel, e.g. for
+    /// the current thread or a stopped one.
+    pub fn user_trap_frame(&self) -> *mut TrapFrame {
+        (self.kstack_top.as_usize() - core::mem::size_of::<TrapFrame>()) as *mut TrapFrame
+    }
+
     /// Get the clear child tid field.
     pub fn clear_child_tid(&self) -> usize {
         self.clear_child_tid.load(Ordering::Relaxed)
@@ -191,29 +369,124 @@ impl ThreadData {
     }
 }
 
+/// How a system call interrupted by a signal is restarted, like the
+/// `ERESTART*` codes of Linux.
+///
+/// See <https://man7.org/linux/man-pages/man7/signal.7.html>
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyscallRestart {
+    /// Restart unless the signal runs a handler without `SA_RESTART`.
+    Sys,
+    /// Restart only if the signal does not run a handler.
+    NoHandler,
+    /// Continue with `restart_syscall` if the signal does not run a handler.
+    Block,
+}
+
+/// A system call interrupted by a signal.
+#[derive(Debug, Clone, Copy)]
+pub struct InterruptedSyscall {
+    /// The system call to execute again, which is `restart_syscall` for
+    /// [`SyscallRestart::Block`]
+    pub sysno: usize,
+    /// The first argument, which the return value has overwritten on most
+    /// architectures
+    pub arg0: usize,
+    /// How to restart it
+    pub restart: SyscallRestart,
+}
+
+/// The rest of an interrupted system call, such as sleeping until the original
+/// deadline for `nanosleep`.
+pub type RestartBlock = Box<dyn FnOnce() -> LinuxResult<isize> + Send>;
+
+/// A job control state change of a process, reported to its parent by
+/// `wait4` with `WUNTRACED` or `WCONTINUED`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobEvent {
+    /// The process was stopped by the given signal.
+    Stopped(Signo),
+    /// The process was resumed by `SIGCONT`.
+    Continued,
+}
+
+/// Interrupt the interruptible waits of the threads of `process`, after a
+/// signal is sent to it, as any of them may handle it.
+pub fn interrupt_threads(process: &Process) {
+    for thread in process.threads() {
+        if let Some(data) = thread.data::<ThreadData>() {
+            data.interrupt();
+        }
+    }
+}
+
 /// Extended data for [`Process`].
 pub struct ProcessData {
     /// The executable path
     pub exe_path: RwLock<String>,
-    /// The virtual memory address space.
-    pub aspace: Arc<Mutex<AddrSpace>>,
+    /// The virtual memory address space, shared with the processes created
+    /// with `CLONE_VM`
+    aspace: RwLock<Arc<Mutex<AddrSpace>>>,
     /// The resource namespace
     pub ns: AxNamespace,
     /// The user heap bottom
     heap_bottom: AtomicUsize,
     /// The user heap top
     heap_top: AtomicUsize,
-
-    /// The child exit wait queue
-    pub child_exit_wq: WaitQueue,
+    /// The initial user stack pointer of the program, where `argc`, `argv`,
+    /// `envp` and the auxiliary vector are
+    user_stack: AtomicUsize,
+    /// The resource limits
+    pub rlimits: RwLock<Rlimits>,
+    /// The interval timers
+    pub itimers: ITimers,
+    /// The POSIX timers
+    pub timers: PosixTimers,
+    /// The CPU time spent by all the threads of the process, including those
+    /// which exited
+    pub cpu_time: CpuTime,
+    /// The CPU time spent by the children which were waited for, including
+    /// that of their own waited-for children
+    pub children_cpu_time: CpuTime,
+
+    /// The wait queue for children exiting, stopping or continuing
+    child_exit_wq: WaitQueue,
+    /// The number of times children exited, stopped or continued
+    child_events: AtomicU64,
+    /// The ID of the thread which created the process, for `__WNOTHREAD`
+    creator_tid: AtomicU32,
+    /// Whether the parent is suspended by `vfork` until the process calls
+    /// `execve` or exits
+    vfork_pending: AtomicBool,
+    /// The wait queue for the parent suspended by `vfork`
+    vfork_wq: WaitQueue,
     /// The exit signal of the thread
     pub exit_signal: Option<Signo>,
 
     /// The process signal manager
     pub signal: Arc<ProcessSignalManager<RawMutex, WaitQueueWrapper>>,
 
+    /// The threads traced by the process
+    pub tracees: spin::Mutex<Vec<Arc<Thread>>>,
+
+    /// Whether the system calls of the process are recorded in the trace
+    /// buffer
+    pub syscall_trace: AtomicBool,
+    /// The statistics of the system calls of the process
+    pub syscall_stats: SyscallStats,
+
     /// The futex table.
     pub futex_table: FutexTable,
+
+    /// Whether the process has called `execve`
+    execed: AtomicBool,
+
+    /// Whether the thread group is stopped by a job control signal
+    stopped: AtomicBool,
+    /// The job control state change not yet reported to the parent
+    job_event: spin::Mutex<Option<JobEvent>>,
+    /// The wait queue for threads suspended while the process is stopped
+    stop_wq: WaitQueue,
 }
 
 impl ProcessData {
@@ -226,12 +499,22 @@ impl ProcessData {
     ) -> Self {
         Self {
             exe_path: RwLock::new(exe_path),
-            aspace,
+            aspace: RwLock::new(aspace),
             ns: AxNamespace::new_thread_local(),
             heap_bottom: AtomicUsize::new(axconfig::plat::USER_HEAP_BASE),
             heap_top: AtomicUsize::new(axconfig::plat::USER_HEAP_BASE),
+            user_stack: AtomicUsize::new(0),
+            rlimits: RwLock::new(Rlimits::default()),
+            itimers: ITimers::default(),
+            timers: PosixTimers::default(),
+            cpu_time: CpuTime::default(),
+            children_cpu_time: CpuTime::default(),
 
             child_exit_wq: WaitQueue::new(),
+            child_events: AtomicU64::new(0),
+            creator_tid: AtomicU32::new(0),
+            vfork_pending: AtomicBool::new(false),
+            vfork_wq: WaitQueue::new(),
             exit_signal,
 
             signal: Arc::new(ProcessSignalManager::new(
@@ -239,10 +522,35 @@ impl ProcessData {
                 axconfig::plat::SIGNAL_TRAMPOLINE,
             )),
 
+            tracees: spin::Mutex::new(Vec::new()),
+
+            syscall_trace: AtomicBool::new(false),
+            syscall_stats: SyscallStats::default(),
+
             futex_table: FutexTable::new(),
+
+            execed: AtomicBool::new(false),
+
+            stopped: AtomicBool::new(false),
+            job_event: spin::Mutex::new(None),
+            stop_wq: WaitQueue::new(),
         }
     }
 
+    /// Get the virtual memory address space.
+    ///
+    /// It is cloned out of the lock guarding its replacement, which must not
+    /// be held while the address space is locked.
+    pub fn aspace(&self) -> Arc<Mutex<AddrSpace>> {
+        self.aspace.read().clone()
+    }
+
+    /// Replace the virtual memory address space, e.g. when the process calls
+    /// `execve` while sharing it with other processes.
+    pub fn set_aspace(&self, aspace: Arc<Mutex<AddrSpace>>) {
+        *self.aspace.write() = aspace;
+    }
+
     /// Get the bottom address of the user heap.
     pub fn get_heap_bottom(&self) -> usize {
         self.heap_bottom.load(Ordering::Acquire)
@@ -263,19 +571,160 @@ impl ProcessData {
         self.heap_top.store(top, Ordering::Release)
     }
 
+    /// Get the initial user stack pointer of the program.
+    pub fn get_user_stack(&self) -> usize {
+        self.user_stack.load(Ordering::Acquire)
+    }
+
+    /// Set the initial user stack pointer of the program.
+    pub fn set_user_stack(&self, sp: usize) {
+        self.user_stack.store(sp, Ordering::Release)
+    }
+
+    /// Get the ID of the thread which created the process, or 0 if it was
+    /// not created by a thread of its parent.
+    pub fn creator_tid(&self) -> Pid {
+        self.creator_tid.load(Ordering::Acquire)
+    }
+
+    /// Record the thread of the parent which created the process.
+    pub fn set_creator_tid(&self, tid: Pid) {
+        self.creator_tid.store(tid, Ordering::Release)
+    }
+
+    /// The number of times children exited, stopped or continued, to be
+    /// passed to [`wait_child_event`](Self::wait_child_event).
+    pub fn child_events(&self) -> u64 {
+        self.child_events.load(Ordering::Acquire)
+    }
+
+    /// Wake up the threads waiting for a child to exit, stop or continue.
+    pub fn notify_child_event(&self) {
+        self.child_events.fetch_add(1, Ordering::AcqRel);
+        self.child_exit_wq.notify_all(false);
+    }
+
+    /// Wait until a child exits, stops or continues after
+    /// [`child_events`](Self::child_events) returned `seen`, or a signal is
+    /// sent to the current thread.
+    pub fn wait_child_event(&self, seen: u64) {
+        self.child_exit_wq
+            .wait_until_interruptible(|| self.child_events() != seen);
+    }
+
+    /// Record that the parent is suspended by `vfork` until the process calls
+    /// `execve` or exits.
+    pub fn set_vfork_pending(&self) {
+        self.vfork_pending.store(true, Ordering::Release)
+    }
+
+    /// Wait for the process created by `vfork` to call `execve` or exit, or
+    /// for a signal to be sent to the current thread or its process.
+    ///
+    /// Returns whether the process did.
+    pub fn wait_vfork_done(&self) -> bool {
+        let done = || !self.vfork_pending.load(Ordering::Acquire);
+        self.vfork_wq.wait_until_interruptible(done);
+        done()
+    }
+
+    /// Resume the parent suspended by `vfork`, if any, once the process calls
+    /// `execve` or exits.
+    pub fn vfork_done(&self) {
+        if self.vfork_pending.swap(false, Ordering::AcqRel) {
+            self.vfork_wq.notify_all(false);
+        }
+    }
+
     /// Linux manual: A "clone" child is one which delivers no signal, or a
     /// signal other than SIGCHLD to its parent upon termination.
     pub fn is_clone_child(&self) -> bool {
         self.exit_signal != Some(Signo::SIGCHLD)
     }
+
+    /// Whether the process has called `execve`.
+    ///
+    /// A parent may only change the process group of a child before this.
+    pub fn has_execed(&self) -> bool {
+        self.execed.load(Ordering::Acquire)
+    }
+
+    /// Record that the process has called `execve`.
+    pub fn set_execed(&self) {
+        self.execed.store(true, Ordering::Release)
+    }
+
+    /// Whether the process is stopped by a job control signal.
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::Acquire)
+    }
+
+    /// Stop the process because of `signo`.
+    ///
+    /// Returns `false` if it was already stopped.
+    pub fn stop(&self, signo: Signo) -> bool {
+        if self.stopped.swap(true, Ordering::AcqRel) {
+            return false;
+        }
+        *self.job_event.lock() = Some(JobEvent::Stopped(signo));
+        true
+    }
+
+    /// Resume a stopped process and wake up all its threads.
+    ///
+    /// Returns `false` if it was not stopped.
+    pub fn resume(&self) -> bool {
+        if !self.stopped.swap(false, Ordering::AcqRel) {
+            return false;
+        }
+        *self.job_event.lock() = Some(JobEvent::Continued);
+        self.stop_wq.notify_all(false);
+        true
+    }
+
+    /// Suspend the current thread until the process is resumed or
+    /// `interrupted` returns `true`.
+    pub fn wait_while_stopped(&self, interrupted: impl Fn() -> bool) {
+        self.stop_wq
+            .wait_until(|| !self.is_stopped() || interrupted());
+    }
+
+    /// Wake up the threads suspended by a stop to re-check their condition,
+    /// e.g. when `SIGKILL` arrives.
+    pub fn wake_stopped(&self) {
+        self.stop_wq.notify_all(false);
+    }
+
+    /// Take the job control state change not yet reported to the parent, if
+    /// `filter` accepts it.
+    ///
+    /// With `peek` set, the change is left in place, as for `WNOWAIT`.
+    pub fn take_job_event(
+        &self,
+        peek: bool,
+        filter: impl FnOnce(&JobEvent) -> bool,
+    ) -> Option<JobEvent> {
+        let mut event = self.job_event.lock();
+        let result = event.filter(filter)?;
+        if !peek {
+            *event = None;
+        }
+        Some(result)
+    }
 }
 
 impl Drop for ProcessData {
     fn drop(&mut self) {
         if !cfg!(target_arch = "aarch64") && !cfg!(target_arch = "loongarch64") {
             // See [`crate::new_user_aspace`]
+            let aspace = self.aspace.get_mut();
+            // Processes sharing the address space still use the kernel
+            // mappings, the last one clears them.
+            if Arc::strong_count(aspace) > 1 {
+                return;
+            }
             let kernel = kernel_aspace().lock();
-            self.aspace
+            aspace
                 .lock()
                 .clear_mappings(VirtAddrRange::from_start_size(kernel.base(), kernel.size()));
         }
@@ -323,12 +772,17 @@ pub fn add_thread_to_table(thread: &Arc<Thread>) {
     }
     process_table.insert(process.pid(), process);
 
+    add_process_group_to_table(&process.group());
+}
+
+/// Add the process group and possibly its session to the corresponding
+/// tables, e.g. after `setpgid` or `setsid` created them.
+pub fn add_process_group_to_table(process_group: &Arc<ProcessGroup>) {
     let mut process_group_table = PROCESS_GROUP_TABLE.write();
-    let process_group = process.group();
     if process_group_table.contains_key(&process_group.pgid()) {
         return;
     }
-    process_group_table.insert(process_group.pgid(), &process_group);
+    process_group_table.insert(process_group.pgid(), process_group);
 
     let mut session_table = SESSION_TABLE.write();
     let session = process_group.session();
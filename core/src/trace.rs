@@ -0,0 +1,234 @@
+//! Tracing of system calls and per-process statistics about them.
+//!
+//! Traced system calls are recorded as [`SyscallEvent`]s in a ring buffer
+//! shared by all processes, where the oldest events are overwritten.
+
+use alloc::{
+    collections::{BTreeMap, VecDeque},
+    format,
+    string::String,
+    vec::Vec,
+};
+use core::fmt::{self, Write};
+
+use axerrno::LinuxError;
+use axhal::time::NANOS_PER_SEC;
+use axprocess::Pid;
+use spin::Mutex;
+
+/// The number of events kept in the trace buffer.
+pub const TRACE_BUFFER_CAPACITY: usize = 4096;
+
+/// The statistics of one system call.
+#[derive(Debug, Clone, Copy)]
+pub struct SyscallStat {
+    /// The name of the system call
+    pub name: &'static str,
+    /// The number of calls
+    pub calls: u64,
+    /// The number of calls which failed
+    pub errors: u64,
+    /// The cumulative time spent in the system call, in nanoseconds
+    pub time_ns: u64,
+    /// Whether the system call is not implemented, and always fails with
+    /// `ENOSYS`
+    pub unimplemented: bool,
+}
+
+/// The statistics of the system calls of a process.
+#[derive(Default)]
+pub struct SyscallStats(Mutex<BTreeMap<usize, SyscallStat>>);
+
+impl SyscallStats {
+    /// Count a call to the system call `sysno` named `name`, which returned
+    /// `ret` after `time_ns` nanoseconds.
+    pub fn record(
+        &self,
+        sysno: usize,
+        name: &'static str,
+        ret: isize,
+        time_ns: u64,
+        unimplemented: bool,
+    ) {
+        let mut stats = self.0.lock();
+        let stat = stats.entry(sysno).or_insert(SyscallStat {
+            name,
+            calls: 0,
+            errors: 0,
+            time_ns: 0,
+            unimplemented,
+        });
+        stat.calls += 1;
+        if is_error(ret) {
+            stat.errors += 1;
+        }
+        stat.time_ns += time_ns;
+    }
+
+    /// The statistics of all the system calls made, by system call number.
+    pub fn snapshot(&self) -> Vec<(usize, SyscallStat)> {
+        self.0
+            .lock()
+            .iter()
+            .map(|(&sysno, &stat)| (sysno, stat))
+            .collect()
+    }
+
+    /// The statistics as a table sorted by time, in the format of
+    /// `strace -c`, followed by the system calls which are not implemented.
+    pub fn output(&self) -> Vec<u8> {
+        let mut stats = self.snapshot();
+        stats.sort_by(|(_, a), (_, b)| b.time_ns.cmp(&a.time_ns).then(b.calls.cmp(&a.calls)));
+        let total_ns = stats.iter().map(|(_, it)| it.time_ns).sum::<u64>();
+
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "% time     seconds  usecs/call     calls    errors syscall"
+        );
+        let _ = writeln!(
+            out,
+            "------ ----------- ----------- --------- --------- ----------------"
+        );
+        for (_, stat) in &stats {
+            let percent = (stat.time_ns * 10000).checked_div(total_ns).unwrap_or(0);
+            let _ = writeln!(
+                out,
+                "{:3}.{:02} {:>11} {:11} {:9} {:9} {}",
+                percent / 100,
+                percent % 100,
+                Seconds(stat.time_ns),
+                stat.time_ns / 1000 / stat.calls,
+                stat.calls,
+                stat.errors,
+                stat.name,
+            );
+        }
+
+        let unimplemented = stats
+            .iter()
+            .filter(|(_, it)| it.unimplemented)
+            .map(|(sysno, it)| (*sysno, it.name))
+            .collect::<BTreeMap<_, _>>();
+        if !unimplemented.is_empty() {
+            let _ = writeln!(out, "\nunimplemented:");
+            for (sysno, name) in unimplemented {
+                let _ = writeln!(out, "{sysno:5} {name}");
+            }
+        }
+        out.into_bytes()
+    }
+}
+
+/// Whether `ret` is an error returned by a system call.
+fn is_error(ret: isize) -> bool {
+    (-4095..0).contains(&ret)
+}
+
+/// A duration in nanoseconds, displayed in seconds.
+struct Seconds(u64);
+
+impl fmt::Display for Seconds {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(&format!(
+            "{}.{:06}",
+            self.0 / NANOS_PER_SEC,
+            self.0 % NANOS_PER_SEC / 1000
+        ))
+    }
+}
+
+/// A system call made by a traced process.
+#[derive(Debug, Clone)]
+pub struct SyscallEvent {
+    /// The monotonic time at which the system call was entered, in
+    /// nanoseconds
+    pub timestamp_ns: u64,
+    /// The process making the system call
+    pub pid: Pid,
+    /// The thread making the system call
+    pub tid: Pid,
+    /// The name of the system call
+    pub name: &'static str,
+    /// The arguments, with the strings some of them point to
+    pub args: [(usize, Option<String>); 6],
+    /// The value returned
+    pub ret: isize,
+    /// The time spent in the system call, in nanoseconds
+    pub duration_ns: u64,
+}
+
+impl fmt::Display for SyscallEvent {
+    /// Display the event like `strace` does, with the arguments not known to
+    /// be strings shown as hexadecimal numbers.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[{:>14}] {}/{} {}(",
+            Seconds(self.timestamp_ns),
+            self.pid,
+            self.tid,
+            self.name
+        )?;
+        for (i, (value, string)) in self.args.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            match string {
+                Some(string) => write!(f, "{string:?}")?,
+                None => write!(f, "{value:#x}")?,
+            }
+        }
+        f.write_str(") = ")?;
+        if is_error(self.ret)
+            && let Ok(err) = LinuxError::try_from(-self.ret as i32)
+        {
+            write!(f, "-1 {err:?} ({err})")?;
+        } else {
+            write!(f, "{}", self.ret)?;
+        }
+        write!(f, " <{}>", Seconds(self.duration_ns))
+    }
+}
+
+struct TraceBuffer {
+    events: VecDeque<SyscallEvent>,
+    /// The number of events overwritten since the buffer was cleared
+    lost: u64,
+}
+
+static TRACE_BUFFER: Mutex<TraceBuffer> = Mutex::new(TraceBuffer {
+    events: VecDeque::new(),
+    lost: 0,
+});
+
+/// Add `event` to the trace buffer, overwriting the oldest event if it is
+/// full.
+pub fn push_trace_event(event: SyscallEvent) {
+    let mut buffer = TRACE_BUFFER.lock();
+    if buffer.events.len() >= TRACE_BUFFER_CAPACITY {
+        buffer.events.pop_front();
+        buffer.lost += 1;
+    }
+    buffer.events.push_back(event);
+}
+
+/// Remove all the events from the trace buffer.
+pub fn clear_trace_buffer() {
+    let mut buffer = TRACE_BUFFER.lock();
+    buffer.events.clear();
+    buffer.lost = 0;
+}
+
+/// The events in the trace buffer, one per line from the oldest.
+pub fn trace_buffer_output() -> Vec<u8> {
+    let buffer = TRACE_BUFFER.lock();
+    let mut out = String::new();
+    if buffer.lost > 0 {
+        let _ = writeln!(out, "# {} events lost", buffer.lost);
+    }
+    for event in &buffer.events {
+        let _ = writeln!(out, "{event}");
+    }
+    out.into_bytes()
+}
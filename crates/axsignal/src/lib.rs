@@ -0,0 +1,15 @@
+#![no_std]
+
+#[macro_use]
+extern crate log;
+extern crate alloc;
+
+mod action;
+pub mod api;
+pub mod arch;
+mod pending;
+mod types;
+
+pub use action::*;
+pub use pending::*;
+pub use types::*;
@@ -4,7 +4,7 @@ use axhal::arch::UspaceContext;
 use axprocess::{Pid, init_proc};
 use axsignal::Signo;
 use axsync::Mutex;
-use starry_api::file::FD_TABLE;
+use starry_api::{file::FD_TABLE, terminal::CONSOLE, trace::add_proc_entries};
 use starry_core::{
     mm::{copy_from_kernel, load_user_app, map_trampoline, new_user_aspace_empty},
     task::{ProcessData, TaskExt, ThreadData, add_thread_to_table, new_user_task},
@@ -37,6 +37,7 @@ pub fn run_user_app(args: &[String], envs: &[String]) -> Option<i32> {
         Arc::default(),
         Some(Signo::SIGCHLD),
     );
+    process_data.set_user_stack(ustack_top.as_usize());
 
     FD_TABLE
         .deref_from(&process_data.ns)
@@ -50,16 +51,27 @@ pub fn run_user_app(args: &[String], envs: &[String]) -> Option<i32> {
 
     let tid = task.id().as_u64() as Pid;
     let process = init_proc().fork(tid).data(process_data).build();
+    add_proc_entries(&process);
+    // The first user process leads a new session, with the console as its
+    // controlling terminal.
+    let (session, group) = process
+        .create_session()
+        .expect("Failed to create the session of the first process");
+    CONSOLE.attach(&session, &group);
 
     let thread = process
         .new_thread(tid)
-        .data(ThreadData::new(process.data().unwrap()))
+        .data(ThreadData::new(
+            process.data().unwrap(),
+            task.kernel_stack_top().unwrap(),
+        ))
         .build();
     add_thread_to_table(&thread);
 
-    task.init_task_ext(TaskExt::new(thread));
+    task.init_task_ext(TaskExt::new(thread.clone()));
 
     let task = axtask::spawn_task(task);
+    thread.data::<ThreadData>().unwrap().set_task(&task);
 
     // TODO: we need a way to wait on the process but not only the main task
     task.join()
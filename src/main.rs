@@ -13,6 +13,8 @@ mod syscall;
 
 #[unsafe(no_mangle)]
 fn main() {
+    starry_core::realtime::register_fs_clock();
+
     // Create a init process
     axprocess::Process::new_init(axtask::current().id().as_u64() as _).build();
 
@@ -5,10 +5,12 @@ use axhal::{
     paging::MappingFlags,
     trap::{PAGE_FAULT, register_trap_handler},
 };
+use axsignal::Signo;
 use axtask::{TaskExtRef, current};
-use linux_raw_sys::general::SIGSEGV;
-use starry_api::do_exit;
-use starry_core::mm::is_accessing_user_memory;
+use linux_raw_sys::general::{SEGV_ACCERR, SEGV_MAPERR, SIGSEGV};
+use memory_addr::VirtAddrRange;
+use starry_api::{do_exit, signal::send_fault_signal};
+use starry_core::{mm::is_accessing_user_memory, task::time_stat_from_user_to_kernel};
 
 #[register_trap_handler(PAGE_FAULT)]
 fn handle_page_fault(vaddr: VirtAddr, access_flags: MappingFlags, is_user: bool) -> bool {
@@ -19,12 +21,15 @@ fn handle_page_fault(vaddr: VirtAddr, access_flags: MappingFlags, is_user: bool)
     if !is_user && !is_accessing_user_memory() {
         return false;
     }
+    if is_user {
+        time_stat_from_user_to_kernel();
+    }
 
     let curr = current();
     let result = curr
         .task_ext()
         .process_data()
-        .aspace
+        .aspace()
         .lock()
         .handle_page_fault(vaddr, access_flags);
 
@@ -35,6 +40,22 @@ fn handle_page_fault(vaddr: VirtAddr, access_flags: MappingFlags, is_user: bool)
         } else {  
             curr.task_ext().majflt.fetch_add(1, Ordering::Relaxed);  
         }  
+    } else if is_user {
+        let range = VirtAddrRange::from_start_size(vaddr, 1);
+        let mapped = curr
+            .task_ext()
+            .process_data()
+            .aspace()
+            .lock()
+            .check_region_access(range, MappingFlags::empty());
+        let code = if mapped { SEGV_ACCERR } else { SEGV_MAPERR };
+        info!(
+            "{} ({:?}): segmentation fault at {:#x}",
+            curr.id_name(),
+            curr.task_ext().thread,
+            vaddr
+        );
+        send_fault_signal(Signo::SIGSEGV, code, vaddr.as_usize());
     } else {
         warn!(
             "{} ({:?}): segmentation fault at {:#x}, exit!",
@@ -3,15 +3,90 @@ use axhal::{
     arch::TrapFrame,
     trap::{SYSCALL, register_trap_handler},
 };
-use starry_api::*;
-use starry_core::task::{time_stat_from_kernel_to_user, time_stat_from_user_to_kernel};
+use axtask::{TaskExtRef, current};
+use starry_api::{
+    ptrace::{ptrace_syscall_entry, ptrace_syscall_exit},
+    seccomp::seccomp_syscall_entry,
+    signal::set_interrupted,
+    trace::{trace_syscall_entry, trace_syscall_exit},
+    *,
+};
+use starry_core::task::{SyscallRestart, time_stat_from_user_to_kernel};
 use syscalls::Sysno;
 
+/// How `sysno` is restarted after being interrupted by a signal, see
+/// `signal(7)`. Returns `None` for the system calls which always fail with
+/// `EINTR`.
+fn syscall_restart(sysno: Sysno) -> Option<SyscallRestart> {
+    match sysno {
+        Sysno::nanosleep | Sysno::clock_nanosleep | Sysno::restart_syscall => {
+            Some(SyscallRestart::Block)
+        }
+        #[cfg(target_arch = "x86_64")]
+        Sysno::poll | Sysno::select => Some(SyscallRestart::NoHandler),
+        Sysno::ppoll | Sysno::pselect6 => Some(SyscallRestart::NoHandler),
+        Sysno::rt_sigsuspend | Sysno::rt_sigtimedwait => None,
+        // A futex wait with a timeout continues until its original deadline.
+        Sysno::futex if has_restart_block() => Some(SyscallRestart::Block),
+        _ => Some(SyscallRestart::Sys),
+    }
+}
+
+/// Whether the interrupted system call left the rest of its work to
+/// `restart_syscall`.
+fn has_restart_block() -> bool {
+    current()
+        .task_ext()
+        .thread_data()
+        .restart_block
+        .lock()
+        .is_some()
+}
+
+/// The arguments of `sysno` which are strings, shown when it is traced.
+fn string_args(sysno: Sysno) -> &'static [usize] {
+    match sysno {
+        Sysno::chdir | Sysno::execve | Sysno::umount2 => &[0],
+        #[cfg(target_arch = "x86_64")]
+        Sysno::unlink | Sysno::open | Sysno::stat | Sysno::lstat => &[0],
+        #[cfg(target_arch = "x86_64")]
+        Sysno::link => &[0, 1],
+        #[cfg(target_arch = "x86_64")]
+        Sysno::newfstatat => &[1],
+        #[cfg(not(target_arch = "x86_64"))]
+        Sysno::fstatat => &[1],
+        Sysno::mkdirat
+        | Sysno::unlinkat
+        | Sysno::openat
+        | Sysno::openat2
+        | Sysno::statx
+        | Sysno::readlinkat
+        | Sysno::faccessat
+        | Sysno::fchmodat
+        | Sysno::utimensat => &[1],
+        Sysno::linkat | Sysno::renameat2 => &[1, 3],
+        Sysno::symlinkat => &[0, 2],
+        Sysno::mount => &[0, 1, 2],
+        _ => &[],
+    }
+}
+
 #[register_trap_handler(SYSCALL)]
 fn handle_syscall(tf: &mut TrapFrame, syscall_num: usize) -> isize {
+    time_stat_from_user_to_kernel();
+    let syscall_num = ptrace_syscall_entry(syscall_num);
+    let syscall_num = match seccomp_syscall_entry(tf, syscall_num) {
+        Ok(syscall_num) => syscall_num,
+        Err(ans) => {
+            let ans = ptrace_syscall_exit(tf, ans);
+            info!("Syscall {} skipped, return {}", syscall_num, ans);
+            return ans;
+        }
+    };
     let sysno = Sysno::from(syscall_num as u32);
     info!("Syscall {}", sysno);
-    time_stat_from_user_to_kernel();
+    let trace = trace_syscall_entry(tf, syscall_num, sysno.name(), string_args(sysno));
+    let mut unimplemented = false;
     let result = match sysno {
         // fs ctl
         Sysno::ioctl => sys_ioctl(tf.arg0() as _, tf.arg1() as _, tf.arg2().into()),
@@ -39,14 +114,21 @@ fn handle_syscall(tf: &mut TrapFrame, syscall_num: usize) -> isize {
             tf.arg2() as _,
             tf.arg3() as _,
         ),
+        Sysno::openat2 => sys_openat2(
+            tf.arg0() as _,
+            tf.arg1().into(),
+            tf.arg2().into(),
+            tf.arg3() as _,
+        ),
         #[cfg(target_arch = "x86_64")]
         Sysno::open => sys_open(tf.arg0().into(), tf.arg1() as _, tf.arg2() as _),
         Sysno::close => sys_close(tf.arg0() as _),
         Sysno::dup => sys_dup(tf.arg0() as _),
         #[cfg(target_arch = "x86_64")]
         Sysno::dup2 => sys_dup2(tf.arg0() as _, tf.arg1() as _),
-        Sysno::dup3 => sys_dup2(tf.arg0() as _, tf.arg1() as _),
+        Sysno::dup3 => sys_dup3(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
         Sysno::fcntl => sys_fcntl(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
+        Sysno::flock => sys_flock(tf.arg0() as _, tf.arg1() as _),
         Sysno::renameat2 => sys_renameat2(
             tf.arg0() as _,
             tf.arg1().into(),
@@ -92,6 +174,11 @@ fn handle_syscall(tf: &mut TrapFrame, syscall_num: usize) -> isize {
         #[cfg(target_arch = "x86_64")]
         Sysno::pipe => sys_pipe2(tf.arg0().into(), 0),
 
+        // eventfd
+        Sysno::eventfd2 => sys_eventfd2(tf.arg0() as _, tf.arg1() as _),
+        #[cfg(target_arch = "x86_64")]
+        Sysno::eventfd => sys_eventfd2(tf.arg0() as _, 0),
+
         // fs stat
         #[cfg(target_arch = "x86_64")]
         Sysno::stat => sys_stat(tf.arg0().into(), tf.arg1().into()),
@@ -138,16 +225,28 @@ fn handle_syscall(tf: &mut TrapFrame, syscall_num: usize) -> isize {
         Sysno::getppid => sys_getppid(),
         Sysno::gettid => sys_gettid(),
         Sysno::getpgid => sys_getpgid(tf.arg0() as _),
+        #[cfg(target_arch = "x86_64")]
+        Sysno::getpgrp => sys_getpgrp(),
+        Sysno::setpgid => sys_setpgid(tf.arg0() as _, tf.arg1() as _),
+        Sysno::getsid => sys_getsid(tf.arg0() as _),
+        Sysno::setsid => sys_setsid(),
 
         // task sched
         Sysno::sched_yield => sys_sched_yield(),
         Sysno::nanosleep => sys_nanosleep(tf.arg0().into(), tf.arg1().into()),
+        Sysno::clock_nanosleep => sys_clock_nanosleep(
+            tf.arg0() as _,
+            tf.arg1() as _,
+            tf.arg2().into(),
+            tf.arg3().into(),
+        ),
 
         // task ops
         Sysno::execve => sys_execve(tf, tf.arg0().into(), tf.arg1().into(), tf.arg2().into()),
         Sysno::set_tid_address => sys_set_tid_address(tf.arg0()),
         #[cfg(target_arch = "x86_64")]
         Sysno::arch_prctl => sys_arch_prctl(tf, tf.arg0() as _, tf.arg1() as _),
+        Sysno::prctl => sys_prctl(tf.arg0() as _, tf.arg1(), tf.arg2(), tf.arg3(), tf.arg4()),
 
         // task management
         Sysno::clone => sys_clone(
@@ -158,11 +257,28 @@ fn handle_syscall(tf: &mut TrapFrame, syscall_num: usize) -> isize {
             tf.arg3(),
             tf.arg4(),
         ),
+        Sysno::clone3 => sys_clone3(tf, tf.arg0().into(), tf.arg1() as _),
         #[cfg(target_arch = "x86_64")]
         Sysno::fork => sys_fork(tf),
+        #[cfg(target_arch = "x86_64")]
+        Sysno::vfork => sys_vfork(tf),
         Sysno::exit => sys_exit(tf.arg0() as _),
         Sysno::exit_group => sys_exit_group(tf.arg0() as _),
-        Sysno::wait4 => sys_waitpid(tf.arg0() as _, tf.arg1().into(), tf.arg2() as _),
+        Sysno::wait4 => sys_waitpid(
+            tf.arg0() as _,
+            tf.arg1().into(),
+            tf.arg2() as _,
+            tf.arg3().into(),
+        ),
+        Sysno::waitid => sys_waitid(
+            tf.arg0() as _,
+            tf.arg1() as _,
+            tf.arg2().into(),
+            tf.arg3() as _,
+            tf.arg4().into(),
+        ),
+        Sysno::pidfd_open => sys_pidfd_open(tf.arg0() as _, tf.arg1() as _),
+        Sysno::pidfd_getfd => sys_pidfd_getfd(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
 
         // signal
         Sysno::rt_sigprocmask => sys_rt_sigprocmask(
@@ -179,6 +295,7 @@ fn handle_syscall(tf: &mut TrapFrame, syscall_num: usize) -> isize {
         ),
         Sysno::rt_sigpending => sys_rt_sigpending(tf.arg0().into(), tf.arg1() as _),
         Sysno::rt_sigreturn => sys_rt_sigreturn(tf),
+        Sysno::restart_syscall => sys_restart_syscall(),
         Sysno::rt_sigtimedwait => sys_rt_sigtimedwait(
             tf.arg0().into(),
             tf.arg1().into(),
@@ -202,7 +319,21 @@ fn handle_syscall(tf: &mut TrapFrame, syscall_num: usize) -> isize {
             tf.arg3().into(),
             tf.arg4() as _,
         ),
+        Sysno::pidfd_send_signal => sys_pidfd_send_signal(
+            tf.arg0() as _,
+            tf.arg1() as _,
+            tf.arg2().into(),
+            tf.arg3() as _,
+        ),
         Sysno::sigaltstack => sys_sigaltstack(tf.arg0().into(), tf.arg1().into()),
+        Sysno::signalfd4 => sys_signalfd4(
+            tf.arg0() as _,
+            tf.arg1().into(),
+            tf.arg2() as _,
+            tf.arg3() as _,
+        ),
+        #[cfg(target_arch = "x86_64")]
+        Sysno::signalfd => sys_signalfd4(tf.arg0() as _, tf.arg1().into(), tf.arg2() as _, 0),
         Sysno::futex => sys_futex(
             tf.arg0().into(),
             tf.arg1() as _,
@@ -224,8 +355,35 @@ fn handle_syscall(tf: &mut TrapFrame, syscall_num: usize) -> isize {
 
         // time
         Sysno::gettimeofday => sys_gettimeofday(tf.arg0().into()),
+        Sysno::settimeofday => sys_settimeofday(tf.arg0().into(), tf.arg1().into()),
         Sysno::times => sys_times(tf.arg0().into()),
+        Sysno::getitimer => sys_getitimer(tf.arg0() as _, tf.arg1().into()),
+        Sysno::setitimer => sys_setitimer(tf.arg0() as _, tf.arg1().into(), tf.arg2().into()),
+        #[cfg(target_arch = "x86_64")]
+        Sysno::alarm => sys_alarm(tf.arg0() as _),
         Sysno::clock_gettime => sys_clock_gettime(tf.arg0() as _, tf.arg1().into()),
+        Sysno::clock_getres => sys_clock_getres(tf.arg0() as _, tf.arg1().into()),
+        Sysno::clock_settime => sys_clock_settime(tf.arg0() as _, tf.arg1().into()),
+        Sysno::clock_adjtime => sys_clock_adjtime(tf.arg0() as _, tf.arg1().into()),
+        Sysno::adjtimex => sys_adjtimex(tf.arg0().into()),
+        Sysno::timer_create => sys_timer_create(tf.arg0() as _, tf.arg1().into(), tf.arg2().into()),
+        Sysno::timer_settime => sys_timer_settime(
+            tf.arg0() as _,
+            tf.arg1() as _,
+            tf.arg2().into(),
+            tf.arg3().into(),
+        ),
+        Sysno::timer_gettime => sys_timer_gettime(tf.arg0() as _, tf.arg1().into()),
+        Sysno::timer_getoverrun => sys_timer_getoverrun(tf.arg0() as _),
+        Sysno::timer_delete => sys_timer_delete(tf.arg0() as _),
+        Sysno::timerfd_create => sys_timerfd_create(tf.arg0() as _, tf.arg1() as _),
+        Sysno::timerfd_settime => sys_timerfd_settime(
+            tf.arg0() as _,
+            tf.arg1() as _,
+            tf.arg2().into(),
+            tf.arg3().into(),
+        ),
+        Sysno::timerfd_gettime => sys_timerfd_gettime(tf.arg0() as _, tf.arg1().into()),
 
         // I/O multiplexing
         #[cfg(target_arch = "x86_64")]
@@ -303,13 +461,28 @@ fn handle_syscall(tf: &mut TrapFrame, syscall_num: usize) -> isize {
             tf.arg3() as _,
         ),
 
+        Sysno::ptrace => sys_ptrace(tf.arg0() as _, tf.arg1() as _, tf.arg2(), tf.arg3()),
+        Sysno::seccomp => sys_seccomp(tf.arg0() as _, tf.arg1() as _, tf.arg2()),
+
         _ => {
             error!("Unimplemented syscall: {}", sysno);
+            unimplemented = true;
             Err(LinuxError::ENOSYS)
         }
     };
+    if matches!(result, Err(LinuxError::EINTR)) {
+        if let Some(restart) = syscall_restart(sysno) {
+            let restart_sysno = if restart == SyscallRestart::Block {
+                Sysno::restart_syscall as usize
+            } else {
+                syscall_num
+            };
+            set_interrupted(tf, restart_sysno, restart);
+        }
+    }
     let ans = result.unwrap_or_else(|err| -err.code() as _);
-    time_stat_from_kernel_to_user();
+    trace_syscall_exit(trace, ans, unimplemented);
+    let ans = ptrace_syscall_exit(tf, ans);
     info!("Syscall {:?} return {}", sysno, ans);
     ans
 }